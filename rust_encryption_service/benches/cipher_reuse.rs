@@ -0,0 +1,88 @@
+// ── Small-message encrypt latency, rebuilding the cipher vs reusing it ──
+// Run with `cargo bench --bench cipher_reuse`. The service caches one
+// ChannelCipher per channel; this measures what that saves per request and
+// prints whether reuse still clears REUSE_SPEEDUP_FLOOR.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use aes_gcm::aead::Payload;
+use criterion::{BenchmarkId, Criterion, Throughput};
+
+#[allow(dead_code)]
+#[path = "../src/crypto.rs"]
+mod crypto;
+
+use crypto::{derive_key, Algorithm, ChannelCipher, KeyId};
+
+// AES-GCM reuse is expected to be at least this many times faster for
+// small messages; below it, the cache isn't earning its memory. ChaCha20 has
+// no key schedule to skip, so it is reported without a floor.
+const REUSE_SPEEDUP_FLOOR: f64 = 1.2;
+const MESSAGE_LEN: usize = 64;
+
+fn bench(c: &mut Criterion) {
+    let key = derive_key("bench-master-secret", KeyId::channel(1));
+    let message = [0x42u8; MESSAGE_LEN];
+    let nonce = [7u8; 12];
+
+    let mut group = c.benchmark_group("encrypt_64b");
+    group.throughput(Throughput::Bytes(MESSAGE_LEN as u64));
+    for algorithm in Algorithm::ALL {
+        group.bench_function(BenchmarkId::new("rebuild", algorithm.name()), |b| {
+            b.iter(|| {
+                let cipher = ChannelCipher::new(algorithm, black_box(&key));
+                cipher.encrypt(&nonce, Payload { msg: black_box(&message), aad: b"" })
+            })
+        });
+        let cipher = ChannelCipher::new(algorithm, &key);
+        group.bench_function(BenchmarkId::new("reuse", algorithm.name()), |b| {
+            b.iter(|| cipher.encrypt(&nonce, Payload { msg: black_box(&message), aad: b"" }))
+        });
+    }
+    group.finish();
+}
+
+// Mean time per encrypt over a fixed wall-clock budget
+fn mean_time(mut encrypt: impl FnMut()) -> Duration {
+    let budget = Duration::from_millis(300);
+    let (start, mut runs) = (Instant::now(), 0u32);
+    while start.elapsed() < budget {
+        encrypt();
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+fn report_speedup() {
+    let key = derive_key("bench-master-secret", KeyId::channel(1));
+    let message = [0x42u8; MESSAGE_LEN];
+    let nonce = [7u8; 12];
+
+    for algorithm in Algorithm::ALL {
+        let rebuild = mean_time(|| {
+            let cipher = ChannelCipher::new(algorithm, black_box(&key));
+            black_box(cipher.encrypt(&nonce, Payload { msg: &message, aad: b"" }).unwrap());
+        });
+        let cipher = ChannelCipher::new(algorithm, &key);
+        let reuse = mean_time(|| {
+            black_box(cipher.encrypt(&nonce, Payload { msg: &message, aad: b"" }).unwrap());
+        });
+        let speedup = rebuild.as_secs_f64() / reuse.as_secs_f64();
+        let verdict = match algorithm {
+            Algorithm::Chacha20poly1305 => "no floor".to_string(),
+            _ if speedup >= REUSE_SPEEDUP_FLOOR => format!("ok, floor {:.1}x", REUSE_SPEEDUP_FLOOR),
+            _ => format!("BELOW FLOOR of {:.1}x", REUSE_SPEEDUP_FLOOR),
+        };
+        println!(
+            "{}: rebuild {:?}, reuse {:?}, speedup {:.2}x ({})",
+            algorithm.name(), rebuild, reuse, speedup, verdict
+        );
+    }
+}
+
+fn main() {
+    let mut c = Criterion::default().configure_from_args();
+    bench(&mut c);
+    c.final_summary();
+    report_speedup();
+}
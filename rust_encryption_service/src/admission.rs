@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::crypto::KeyId;
+
+// ── Per-channel share of the crypto slots ──
+// Requests queued for a slot (CRYPTO_QUEUE_TIMEOUT_MS) are served round-robin
+// across channels: only the oldest waiter of each channel queues on the
+// semaphore, the rest wait behind it, so a flood on one channel costs a
+// quiet one at most a turn. With MAX_CRYPTO_PER_CHANNEL set, one channel may
+// also hold at most that many slots, counting requests still queued for
+// one; past it, its requests are shed at once.
+pub struct ChannelShares {
+    pub limit: Option<usize>,
+    in_flight: InFlight,
+    // One per channel with a request waiting; None is work over many channels
+    turns: Mutex<HashMap<Option<KeyId>, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+// Requests holding or queued for a slot, per channel; idle channels absent
+type InFlight = Arc<Mutex<HashMap<KeyId, usize>>>;
+
+// Held for as long as the request counts against its channel
+pub struct ChannelSlot(Option<(InFlight, KeyId)>);
+
+// ── What `AppState::crypto_permit` hands out; dropping it frees both ──
+pub struct CryptoPermit {
+    _slot: ChannelSlot,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl ChannelShares {
+    pub fn from_env() -> Self {
+        ChannelShares::new(
+            std::env::var("MAX_CRYPTO_PER_CHANNEL").ok().and_then(|v| v.parse().ok()),
+        )
+    }
+
+    pub fn new(limit: Option<usize>) -> Self {
+        ChannelShares {
+            limit: limit.filter(|&limit| limit > 0),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            turns: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // ── A slot from `permits`, waiting behind this channel's earlier requests ──
+    // Waits as long as it takes; callers bound it with a timeout.
+    pub async fn acquire(
+        &self,
+        key_id: Option<KeyId>,
+        permits: Arc<Semaphore>,
+    ) -> Option<OwnedSemaphorePermit> {
+        // Nothing is queued while slots are free
+        if let Ok(permit) = permits.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        let turn = Turn::new(self, key_id);
+        let _up = turn.gate.lock().await;
+        permits.acquire_owned().await.ok()
+    }
+
+    // ── No request holding, or in line for, a slot ──
+    #[cfg(test)]
+    pub fn is_idle(&self) -> bool {
+        self.in_flight.lock().unwrap().is_empty() && self.turns.lock().unwrap().is_empty()
+    }
+
+    // ── Count one more request against `key_id`; None past its share ──
+    // Requests tied to no channel, and every request without a limit, always
+    // get a slot.
+    pub fn claim(&self, key_id: Option<KeyId>) -> Option<ChannelSlot> {
+        let (Some(limit), Some(key_id)) = (self.limit, key_id) else {
+            return Some(ChannelSlot(None));
+        };
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.entry(key_id).or_default();
+        if *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(ChannelSlot(Some((self.in_flight.clone(), key_id))))
+    }
+}
+
+// A request's place in its channel's line, from arriving until it has a slot
+// or gives up
+struct Turn<'a> {
+    shares: &'a ChannelShares,
+    key_id: Option<KeyId>,
+    gate: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl<'a> Turn<'a> {
+    fn new(shares: &'a ChannelShares, key_id: Option<KeyId>) -> Self {
+        let gate = shares.turns.lock().unwrap().entry(key_id).or_default().clone();
+        Turn { shares, key_id, gate }
+    }
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        let mut turns = self.shares.turns.lock().unwrap();
+        // Only the map and this request hold it: nobody else is in line
+        if Arc::strong_count(&self.gate) == 2 {
+            turns.remove(&self.key_id);
+        }
+    }
+}
+
+impl CryptoPermit {
+    pub fn new(slot: ChannelSlot, permit: OwnedSemaphorePermit) -> Self {
+        CryptoPermit { _slot: slot, _permit: permit }
+    }
+}
+
+impl Drop for ChannelSlot {
+    fn drop(&mut self) {
+        let Some((in_flight, key_id)) = self.0.take() else {
+            return;
+        };
+        let mut in_flight = in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(&key_id) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&key_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::crypto::KeyId;
+
+// ── One audited use of a channel key ──
+// Which key did what, and when; never message content or key material.
+#[derive(Serialize, Clone)]
+pub struct AuditEntry {
+    // Unix seconds, from the AppState clock
+    pub at: u64,
+    pub action: AuditAction,
+    pub channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_with: Option<i64>,
+}
+
+impl AuditEntry {
+    pub fn new(at: u64, action: AuditAction, key_id: KeyId) -> Self {
+        AuditEntry {
+            at,
+            action,
+            channel_id: key_id.channel_id,
+            org_id: key_id.org_id,
+            shared_with: key_id.shared_with,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Encrypt,
+    Decrypt,
+    // The key itself left the service, wrapped for a recipient
+    Export,
+    // A re-encrypt job moved the channel onto a new master secret
+    Rotate,
+}
+
+impl AuditAction {
+    // Same spelling as the JSON field value
+    pub fn name(self) -> &'static str {
+        match self {
+            AuditAction::Encrypt => "encrypt",
+            AuditAction::Decrypt => "decrypt",
+            AuditAction::Export => "export",
+            AuditAction::Rotate => "rotate",
+        }
+    }
+}
+
+// ── Where audit entries go ──
+// Called inline on the request path, so a sink should be quick; a failed
+// write is logged and the request still succeeds.
+pub trait AuditSink: Send + Sync {
+    // Human-readable sink, for the startup log
+    fn name(&self) -> &'static str;
+    fn record(&self, entry: &AuditEntry) -> Result<(), String>;
+
+    // File the entries can be read back from, for GET /audit/export
+    fn export_path(&self) -> Option<&str> {
+        None
+    }
+
+    // Whether entries carry the AUDIT_CHAIN_KEY hash chain
+    fn chained(&self) -> bool {
+        false
+    }
+}
+
+// ── Pick the sink named by AUDIT_SINK ──
+// Unset means no audit log. As with SECRET_SOURCE, an unknown value or a
+// missing setting is an error rather than a silent fallback.
+pub fn sink_from_env() -> Result<Option<Box<dyn AuditSink>>, String> {
+    match std::env::var("AUDIT_SINK").ok().as_deref() {
+        None | Some("") | Some("off") => Ok(None),
+        Some("stdout") => Ok(Some(Box::new(StdoutAuditSink))),
+        Some("file") => {
+            let path = std::env::var("AUDIT_LOG_PATH")
+                .map_err(|_| "AUDIT_LOG_PATH must be set for AUDIT_SINK=file".to_string())?;
+            let chain_key = std::env::var("AUDIT_CHAIN_KEY").ok().filter(|key| !key.is_empty());
+            Ok(Some(Box::new(FileAuditSink::open(&path, chain_key.map(String::into_bytes))?)))
+        }
+        Some(other) => Err(format!("Unknown AUDIT_SINK {:?}; expected file or stdout", other)),
+    }
+}
+
+// ── Keyed hash chain over the audit file ──
+// With AUDIT_CHAIN_KEY set, each line also carries `prev`, the `chain` of
+// the line before it (64 zeros for the first), and its own `chain`:
+// hex(HMAC-SHA256(key, chain_message(prev, entry))). Every line checks on
+// its own, and an edited, dropped or reordered line breaks the link after
+// it; only a holder of the key could forge a replacement. The chain resumes
+// from the file's last line on restart.
+const CHAIN_START: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// The bytes a line's chain value covers: a domain tag, then one field per line
+fn chain_message(prev: &str, entry: &AuditEntry) -> Vec<u8> {
+    let optional = |id: Option<i64>| id.map(|id| id.to_string()).unwrap_or_default();
+    format!(
+        "freecord/audit/v1\n{}\n{}\n{}\n{}\n{}\n{}\n",
+        prev,
+        entry.at,
+        entry.action.name(),
+        entry.channel_id,
+        optional(entry.org_id),
+        optional(entry.shared_with),
+    )
+    .into_bytes()
+}
+
+#[derive(Serialize)]
+struct ChainedEntry<'a> {
+    #[serde(flatten)]
+    entry: &'a AuditEntry,
+    prev: &'a str,
+    chain: &'a str,
+}
+
+// ── JSON lines appended to AUDIT_LOG_PATH ──
+pub struct FileAuditSink {
+    path: String,
+    // The file, with the chain value of its last line
+    file: Mutex<(File, String)>,
+    chain_key: Option<Vec<u8>>,
+}
+
+impl FileAuditSink {
+    pub fn open(path: &str, chain_key: Option<Vec<u8>>) -> Result<Self, String> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Cannot open AUDIT_LOG_PATH {}: {}", path, e))?;
+        let last = match chain_key {
+            Some(_) => last_chain(&mut file)
+                .map_err(|e| format!("Cannot read AUDIT_LOG_PATH {}: {}", path, e))?,
+            None => String::new(),
+        };
+        Ok(FileAuditSink { path: path.to_owned(), file: Mutex::new((file, last)), chain_key })
+    }
+}
+
+// `chain` of the file's last line; the start value for an empty file, or
+// one written before chaining was turned on
+fn last_chain(file: &mut File) -> std::io::Result<String> {
+    // Lines are a few hundred bytes; the tail always holds the last whole one
+    let len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(len.saturating_sub(4096)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+
+    #[derive(Deserialize)]
+    struct Chained {
+        chain: Option<String>,
+    }
+    let last = tail.split(|&b| b == b'\n').rev().find(|line| !line.is_empty());
+    let chain = last.and_then(|line| serde_json::from_slice::<Chained>(line).ok()?.chain);
+    Ok(chain.unwrap_or_else(|| CHAIN_START.to_owned()))
+}
+
+impl AuditSink for FileAuditSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn record(&self, entry: &AuditEntry) -> Result<(), String> {
+        // Held from computing the link to writing it, so lines land in chain order
+        let mut guard = self.file.lock().unwrap();
+        let (file, last) = &mut *guard;
+        let (mut line, chain) = match &self.chain_key {
+            None => (serde_json::to_vec(entry).map_err(|e| e.to_string())?, None),
+            Some(key) => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+                mac.update(&chain_message(last, entry));
+                let chain = hex::encode(mac.finalize().into_bytes());
+                let chained = ChainedEntry { entry, prev: last, chain: &chain };
+                (serde_json::to_vec(&chained).map_err(|e| e.to_string())?, Some(chain))
+            }
+        };
+        line.push(b'\n');
+        // One write per line, so concurrent entries never interleave
+        file.write_all(&line).map_err(|e| e.to_string())?;
+        if let Some(chain) = chain {
+            *last = chain;
+        }
+        Ok(())
+    }
+
+    fn export_path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+
+    fn chained(&self) -> bool {
+        self.chain_key.is_some()
+    }
+}
+
+// ── Which lines GET /audit/export returns ──
+// `from` is inclusive and `to` exclusive, both unix seconds. Lines are
+// passed through byte for byte, so their chain values still verify.
+#[derive(Deserialize)]
+pub struct ExportFilter {
+    #[serde(default)]
+    pub from: Option<u64>,
+    #[serde(default)]
+    pub to: Option<u64>,
+    #[serde(default)]
+    pub channel_id: Option<i64>,
+    #[serde(default)]
+    pub org_id: Option<i64>,
+}
+
+impl ExportFilter {
+    // A line that does not parse is kept, so an export never hides one
+    pub fn matches(&self, line: &[u8]) -> bool {
+        #[derive(Deserialize)]
+        struct Fields {
+            at: u64,
+            channel_id: i64,
+            org_id: Option<i64>,
+        }
+        let Ok(fields) = serde_json::from_slice::<Fields>(line) else {
+            return true;
+        };
+        self.from.is_none_or(|from| fields.at >= from)
+            && self.to.is_none_or(|to| fields.at < to)
+            && self.channel_id.is_none_or(|id| fields.channel_id == id)
+            && self.org_id.is_none_or(|id| fields.org_id == Some(id))
+    }
+}
+
+// ── JSON lines on stdout, for a container's log collector ──
+pub struct StdoutAuditSink;
+
+impl AuditSink for StdoutAuditSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    fn record(&self, entry: &AuditEntry) -> Result<(), String> {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        writeln!(std::io::stdout().lock(), "{}", line).map_err(|e| e.to_string())
+    }
+}
+
+// ── Keeps every entry in memory, for tests ──
+// A clone of `entries` still sees them once the sink is boxed into AppState.
+#[cfg(test)]
+#[derive(Default)]
+pub struct CollectingAuditSink {
+    pub entries: std::sync::Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+#[cfg(test)]
+impl AuditSink for CollectingAuditSink {
+    fn name(&self) -> &'static str {
+        "collecting"
+    }
+
+    fn record(&self, entry: &AuditEntry) -> Result<(), String> {
+        self.entries.lock().unwrap().push(entry.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh file under the temp dir, unique to this process and test
+    fn temp_log(name: &str) -> String {
+        let file = format!("freecord-{}-{}.log", std::process::id(), name);
+        let path = std::env::temp_dir().join(file);
+        let _ = std::fs::remove_file(&path);
+        path.to_str().unwrap().to_owned()
+    }
+
+    fn lines(path: &str) -> Vec<serde_json::Value> {
+        let text = std::fs::read_to_string(path).unwrap();
+        text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn entries_name_the_key_and_never_more() {
+        let entry = AuditEntry::new(5, AuditAction::Decrypt, KeyId::new(None, 1));
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"at":5,"action":"decrypt","channel_id":1}"#,
+        );
+        let key_id = KeyId::new(Some(2), 1).with_shared(Some(3));
+        let json = serde_json::to_value(AuditEntry::new(5, AuditAction::Rotate, key_id)).unwrap();
+        assert_eq!(json["action"], AuditAction::Rotate.name());
+        assert_eq!((json["org_id"].as_i64(), json["shared_with"].as_i64()), (Some(2), Some(3)));
+    }
+
+    #[test]
+    fn file_sink_appends_one_line_per_entry() {
+        let path = temp_log("append");
+        let sink = FileAuditSink::open(&path, None).unwrap();
+        sink.record(&AuditEntry::new(1, AuditAction::Encrypt, KeyId::new(None, 1))).unwrap();
+        drop(sink);
+        let sink = FileAuditSink::open(&path, None).unwrap();
+        sink.record(&AuditEntry::new(2, AuditAction::Decrypt, KeyId::new(None, 1))).unwrap();
+        assert!(!sink.chained());
+        assert_eq!(sink.export_path(), Some(path.as_str()));
+
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["action"], "encrypt");
+        assert_eq!(lines[1]["at"], 2);
+        assert!(lines[1].get("chain").is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Recomputes every line's link, checking each `prev` against the line before
+    fn chain_holds(path: &str, key: &[u8]) -> bool {
+        use AuditAction::*;
+        let mut prev = CHAIN_START.to_owned();
+        lines(path).iter().all(|line| {
+            let action = [Encrypt, Decrypt, Export, Rotate];
+            let entry = AuditEntry {
+                at: line["at"].as_u64().unwrap(),
+                action: action.into_iter().find(|a| line["action"] == a.name()).unwrap(),
+                channel_id: line["channel_id"].as_i64().unwrap(),
+                org_id: line["org_id"].as_i64(),
+                shared_with: line["shared_with"].as_i64(),
+            };
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+            mac.update(&chain_message(&prev, &entry));
+            let linked = line["prev"] == prev.as_str();
+            prev = line["chain"].as_str().unwrap().to_owned();
+            linked && hex::encode(mac.finalize().into_bytes()) == prev
+        })
+    }
+
+    #[test]
+    fn chain_links_every_line_and_resumes_on_reopen() {
+        let path = temp_log("chain");
+        let sink = FileAuditSink::open(&path, Some(b"chain-key".to_vec())).unwrap();
+        assert!(sink.chained());
+        sink.record(&AuditEntry::new(1, AuditAction::Encrypt, KeyId::new(None, 1))).unwrap();
+        sink.record(&AuditEntry::new(2, AuditAction::Decrypt, KeyId::new(Some(4), 1))).unwrap();
+        drop(sink);
+        let sink = FileAuditSink::open(&path, Some(b"chain-key".to_vec())).unwrap();
+        sink.record(&AuditEntry::new(3, AuditAction::Export, KeyId::new(None, 2))).unwrap();
+
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["prev"], CHAIN_START);
+        assert!(chain_holds(&path, b"chain-key"));
+        assert!(!chain_holds(&path, b"other-key"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn an_edited_line_breaks_the_chain() {
+        let path = temp_log("edited");
+        let sink = FileAuditSink::open(&path, Some(b"chain-key".to_vec())).unwrap();
+        for at in 1..=3 {
+            sink.record(&AuditEntry::new(at, AuditAction::Decrypt, KeyId::new(None, 1))).unwrap();
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, text.replacen(r#""at":2"#, r#""at":9"#, 1)).unwrap();
+        assert!(!chain_holds(&path, b"chain-key"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn chaining_an_unchained_file_starts_afresh() {
+        let path = temp_log("unchained");
+        let sink = FileAuditSink::open(&path, None).unwrap();
+        sink.record(&AuditEntry::new(1, AuditAction::Encrypt, KeyId::new(None, 1))).unwrap();
+        drop(sink);
+        let sink = FileAuditSink::open(&path, Some(b"chain-key".to_vec())).unwrap();
+        sink.record(&AuditEntry::new(2, AuditAction::Encrypt, KeyId::new(None, 1))).unwrap();
+        assert_eq!(lines(&path)[1]["prev"], CHAIN_START);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn export_filter_bounds_time_and_key() {
+        let filter = |query: &str| -> ExportFilter { serde_json::from_str(query).unwrap() };
+        let line = br#"{"at":100,"action":"encrypt","channel_id":1,"org_id":5}"#;
+        assert!(filter("{}").matches(line));
+        assert!(filter(r#"{"from":100,"to":101}"#).matches(line));
+        assert!(!filter(r#"{"from":101}"#).matches(line));
+        assert!(!filter(r#"{"to":100}"#).matches(line));
+        assert!(filter(r#"{"channel_id":1,"org_id":5}"#).matches(line));
+        assert!(!filter(r#"{"channel_id":2}"#).matches(line));
+        assert!(!filter(r#"{"org_id":6}"#).matches(br#"{"at":1,"channel_id":1}"#));
+        // Never hide a line it cannot read
+        assert!(filter(r#"{"channel_id":2}"#).matches(b"not json"));
+    }
+}
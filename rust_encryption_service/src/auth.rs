@@ -0,0 +1,238 @@
+use actix_web::{HttpRequest, HttpResponse};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::crypto::KeyId;
+use crate::{ids, ErrorResponse};
+
+// ── Admin auth: `Authorization: Bearer <ADMIN_TOKEN>` ──
+// With no ADMIN_TOKEN configured, every admin request is refused.
+pub fn require_admin(req: &HttpRequest, admin_token: Option<&str>) -> Result<(), HttpResponse> {
+    let Some(expected) = admin_token else {
+        return Err(unauthorized("Admin API is disabled"));
+    };
+
+    let presented = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+
+    if bool::from(presented.as_bytes().ct_eq(expected.as_bytes())) {
+        Ok(())
+    } else {
+        log::warn!("Rejected admin request to {}", req.path());
+        Err(unauthorized("Invalid admin token"))
+    }
+}
+
+fn unauthorized(error: &str) -> HttpResponse {
+    HttpResponse::Unauthorized().json(ErrorResponse {
+        error: error.into(),
+        code: "unauthorized".into(),
+    })
+}
+
+// ── Optional JWT auth for the encrypt and decrypt endpoints ──
+// Enabled by JWT_PUBLIC_KEY_PATH: every such request then needs
+// `Authorization: Bearer <jwt>` signed by that key (JWT_ALGORITHM, default
+// RS256), unexpired, with JWT_AUDIENCE in `aud` and the endpoint's scope in
+// the space-separated `scope` claim. A `channel_ids` claim, when present,
+// limits the token to those channels, and an `org_id` claim to that org's
+// channels. Admin endpoints keep ADMIN_TOKEN.
+pub struct JwtAuth {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+#[derive(Clone, Copy)]
+pub enum Scope {
+    Encrypt,
+    Decrypt,
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::Encrypt => "crypto:encrypt",
+            Scope::Decrypt => "crypto:decrypt",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    #[serde(default)]
+    scope: String,
+    #[serde(default, deserialize_with = "optional_list")]
+    channel_ids: Option<Vec<i64>>,
+    // The tenant the token was issued to; limits it to that org's channels
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+}
+
+fn optional_list<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<Vec<i64>>, D::Error> {
+    ids::list(d).map(Some)
+}
+
+impl JwtAuth {
+    // None when JWT_PUBLIC_KEY_PATH is unset; a bad key or setting is an error
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let path = std::env::var("JWT_PUBLIC_KEY_PATH").ok().filter(|p| !p.is_empty());
+        let Some(path) = path else {
+            return Ok(None);
+        };
+        let algorithm: Algorithm = std::env::var("JWT_ALGORITHM")
+            .unwrap_or_else(|_| "RS256".into())
+            .parse()
+            .map_err(|_| "JWT_ALGORITHM is not a known JWT algorithm".to_string())?;
+        let pem = std::fs::read(&path)
+            .map_err(|e| format!("Cannot read JWT_PUBLIC_KEY_PATH {}: {}", path, e))?;
+        let key = match algorithm {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => DecodingKey::from_rsa_pem(&pem),
+            Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(&pem),
+            Algorithm::EdDSA => DecodingKey::from_ed_pem(&pem),
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                return Err("JWT_ALGORITHM must be a public-key algorithm".into())
+            }
+        }
+        .map_err(|e| format!("Bad public key in JWT_PUBLIC_KEY_PATH: {}", e))?;
+
+        let audience = std::env::var("JWT_AUDIENCE").ok().filter(|a| !a.is_empty());
+        let Some(audience) = audience else {
+            return Err("JWT_PUBLIC_KEY_PATH requires JWT_AUDIENCE".into());
+        };
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[audience]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+        if let Some(issuer) = std::env::var("JWT_ISSUER").ok().filter(|i| !i.is_empty()) {
+            validation.set_issuer(&[issuer]);
+        }
+        log::info!("JWT auth enabled ({:?})", algorithm);
+        Ok(Some(JwtAuth { key, validation }))
+    }
+
+    // ── Check the request's JWT grants `scope` on `key_id`'s channel ──
+    // 401 for a missing, malformed, badly signed or expired token; 403 for a
+    // valid one without the scope or the channel. A `tenant` key, one derived
+    // from its org's own secret, also needs the token's `org_id` claim.
+    pub fn authorize(
+        &self,
+        req: &HttpRequest,
+        scope: Scope,
+        key_id: KeyId,
+        tenant: bool,
+    ) -> Result<(), HttpResponse> {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let Some(token) = token else {
+            let error = "A bearer JWT is required";
+            return Err(jwt_error(HttpResponse::Unauthorized(), error, "jwt_required"));
+        };
+
+        let claims = match jsonwebtoken::decode::<JwtClaims>(token, &self.key, &self.validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                let (error, code) = match e.kind() {
+                    ErrorKind::ExpiredSignature => ("JWT has expired", "jwt_expired"),
+                    _ => ("Invalid JWT", "jwt_invalid"),
+                };
+                log::warn!("Rejected JWT for {}: {}", req.path(), e);
+                return Err(jwt_error(HttpResponse::Unauthorized(), error, code));
+            }
+        };
+
+        if !claims.scope.split(' ').any(|s| s == scope.as_str()) {
+            log::warn!("Rejected JWT for {}: missing scope {}", req.path(), scope.as_str());
+            return Err(jwt_error(
+                HttpResponse::Forbidden(),
+                &format!("JWT lacks the {} scope", scope.as_str()),
+                "insufficient_scope",
+            ));
+        }
+        // A shared key is both channels' key, so the token must cover both
+        let channels = [Some(key_id.channel_id), key_id.shared_with];
+        let allowed = |ids: Vec<i64>| channels.iter().flatten().all(|id| ids.contains(id));
+        if claims.channel_ids.is_some_and(|ids| !allowed(ids)) {
+            log::warn!("Rejected JWT for {}: not valid for channel {}", req.path(), key_id);
+            return Err(jwt_error(
+                HttpResponse::Forbidden(),
+                "JWT is not valid for this channel",
+                "channel_not_allowed",
+            ));
+        }
+        if tenant && claims.org_id.is_none() {
+            log::warn!("Rejected JWT for {}: no org_id claim for tenant {}", req.path(), key_id);
+            return Err(jwt_error(
+                HttpResponse::Forbidden(),
+                "A tenant's channels need a JWT with its org_id claim",
+                "org_claim_required",
+            ));
+        }
+        if claims.org_id.is_some_and(|org_id| key_id.org_id != Some(org_id)) {
+            log::warn!("Rejected JWT for {}: not valid for org of {}", req.path(), key_id);
+            return Err(jwt_error(
+                HttpResponse::Forbidden(),
+                "JWT is not valid for this org",
+                "org_not_allowed",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// ── EdDSA over a raw public key, for tests that sign their own tokens ──
+#[cfg(test)]
+impl JwtAuth {
+    pub fn ed25519(public_key: &[u8], audience: &str) -> Self {
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.set_audience(&[audience]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+        JwtAuth { key: DecodingKey::from_ed_der(public_key), validation }
+    }
+}
+
+fn jwt_error(mut builder: actix_web::HttpResponseBuilder, error: &str, code: &str) -> HttpResponse {
+    builder.json(ErrorResponse {
+        error: error.into(),
+        code: code.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn with_auth(value: &str) -> HttpRequest {
+        TestRequest::default().insert_header(("Authorization", value)).to_http_request()
+    }
+
+    #[test]
+    fn admin_needs_the_exact_bearer_token() {
+        assert!(require_admin(&with_auth("Bearer s3cret"), Some("s3cret")).is_ok());
+        for presented in ["Bearer s3cre", "Bearer s3cret ", "bearer s3cret", "s3cret"] {
+            let refused = require_admin(&with_auth(presented), Some("s3cret")).unwrap_err();
+            assert_eq!(refused.status(), 401, "{}", presented);
+        }
+        let bare = TestRequest::default().to_http_request();
+        assert!(require_admin(&bare, Some("s3cret")).is_err());
+    }
+
+    #[test]
+    fn without_an_admin_token_nothing_is_admin() {
+        assert!(require_admin(&with_auth("Bearer "), None).is_err());
+        assert!(require_admin(&with_auth("Bearer anything"), None).is_err());
+    }
+}
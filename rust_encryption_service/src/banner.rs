@@ -0,0 +1,138 @@
+use serde::Serialize;
+
+use crate::AppState;
+
+// ── Effective configuration, logged once as a single JSON line at startup ──
+// So the log pipeline records what a running instance actually enabled.
+// Secrets only ever show as "[redacted]" when set, or null when unset; the
+// master secret, keys and tokens themselves never reach this struct.
+#[derive(Serialize)]
+pub struct EffectiveConfig<'a> {
+    bind: &'a str,
+    // Set when ADMIN_PORT moves the admin routes off `bind`
+    admin_bind: Option<&'a str>,
+    tls: bool,
+    mtls: bool,
+    secret_source: &'a str,
+    master_secret: Option<&'static str>,
+    previous_master_secret: Option<&'static str>,
+    tenant_secrets: usize,
+    admin_token: Option<&'static str>,
+    jwt_auth: bool,
+    audit_sink: Option<&'static str>,
+    audit_chain: bool,
+    response_signing_key: Option<&'static str>,
+    sentry_dsn: Option<&'static str>,
+    require_decrypt_token: bool,
+    debug_errors: bool,
+    default_secret_active: bool,
+    metrics_enabled: bool,
+    pin_algorithm_on_first_use: bool,
+    default_algorithm: &'static str,
+    encrypt_timestamps: bool,
+    reject_invalid_unicode: bool,
+    key_cache_ttl_secs: Option<u64>,
+    max_cached_keys: Option<usize>,
+    key_cache_overflow: &'static str,
+    ratchet_window: usize,
+    lock_timeout_ms: u128,
+    max_concurrent_crypto: usize,
+    max_crypto_per_channel: Option<usize>,
+    crypto_queue_timeout_ms: u128,
+    max_decrypted_bytes: usize,
+    reencrypt_batch_size: usize,
+    max_upload_bytes: usize,
+    daily_encrypt_quota: Option<u64>,
+    key_byte_limit: u64,
+    offload_threshold_bytes: usize,
+    max_aad_bytes: Option<usize>,
+    decrypt_cache_capacity: Option<usize>,
+    decrypt_cache_ttl_secs: Option<u64>,
+    max_decrypt_failures: Option<u32>,
+    trusted_proxies: usize,
+    decrypt_lockout_secs: Option<u64>,
+    archive_signing: bool,
+    slo_alert_webhook: bool,
+    slo_window_secs: u64,
+    slo_decrypt_success_threshold: f64,
+    idempotency_cache_capacity: usize,
+    idempotency_ttl_secs: u64,
+}
+
+fn redact(set: bool) -> Option<&'static str> {
+    set.then_some("[redacted]")
+}
+
+// What the process settled on outside AppState
+pub struct Startup<'a> {
+    pub bind: &'a str,
+    pub admin_bind: Option<&'a str>,
+    pub tls: bool,
+    pub mtls: bool,
+    pub secret_source: &'a str,
+    pub signing: bool,
+    pub sentry: bool,
+}
+
+impl<'a> EffectiveConfig<'a> {
+    pub fn new(state: &AppState, startup: Startup<'a>) -> Self {
+        let cache = state.decrypt_cache.as_ref();
+        EffectiveConfig {
+            bind: startup.bind,
+            admin_bind: startup.admin_bind,
+            tls: startup.tls,
+            mtls: startup.mtls,
+            secret_source: startup.secret_source,
+            master_secret: redact(!state.master_secret.is_empty()),
+            previous_master_secret: redact(state.previous_master_secret.is_some()),
+            tenant_secrets: state.tenant_secrets.len(),
+            admin_token: redact(state.admin_token.is_some()),
+            jwt_auth: state.jwt.is_some(),
+            audit_sink: state.audit.as_ref().map(|sink| sink.name()),
+            audit_chain: state.audit.as_ref().is_some_and(|sink| sink.chained()),
+            response_signing_key: redact(startup.signing),
+            sentry_dsn: redact(startup.sentry),
+            require_decrypt_token: state.require_decrypt_token,
+            debug_errors: state.debug_errors,
+            default_secret_active: state.default_secret_active,
+            metrics_enabled: state.metrics_enabled,
+            pin_algorithm_on_first_use: state.pin_on_first_use,
+            default_algorithm: state.default_algorithm.name(),
+            encrypt_timestamps: state.encrypt_timestamps,
+            reject_invalid_unicode: state.reject_invalid_unicode,
+            key_cache_ttl_secs: state.key_cache_ttl.map(|ttl| ttl.as_secs()),
+            max_cached_keys: state.max_cached_keys,
+            key_cache_overflow: state.key_cache_overflow.name(),
+            ratchet_window: state.ratchet_window,
+            lock_timeout_ms: state.lock_timeout.as_millis(),
+            max_concurrent_crypto: state.crypto_permits.available_permits(),
+            max_crypto_per_channel: state.channel_shares.limit,
+            crypto_queue_timeout_ms: state.crypto_queue_timeout.as_millis(),
+            max_decrypted_bytes: state.limits.max_decrypted_bytes,
+            reencrypt_batch_size: state.limits.reencrypt_batch_size,
+            max_upload_bytes: state.limits.max_upload_bytes,
+            daily_encrypt_quota: state.limits.daily_encrypt_quota,
+            key_byte_limit: state.limits.key_byte_limit,
+            offload_threshold_bytes: state.limits.offload_threshold_bytes,
+            max_aad_bytes: state.limits.max_aad_bytes,
+            decrypt_cache_capacity: cache.map(|c| c.capacity),
+            decrypt_cache_ttl_secs: cache.map(|c| c.ttl.as_secs()),
+            max_decrypt_failures: state.decrypt_lockout.as_ref().map(|l| l.max_failures),
+            trusted_proxies: state.trusted_proxies.count(),
+            decrypt_lockout_secs: state.decrypt_lockout.as_ref().map(|l| l.lockout_secs),
+            archive_signing: state.archive_signer.is_some(),
+            slo_alert_webhook: state.slo.webhook_enabled(),
+            slo_window_secs: state.slo.window_secs,
+            slo_decrypt_success_threshold: state.slo.threshold,
+            idempotency_cache_capacity: state.idempotency.capacity,
+            idempotency_ttl_secs: state.idempotency.ttl.as_secs(),
+        }
+    }
+
+    pub fn log(&self) {
+        match serde_json::to_string(self) {
+            Ok(json) => log::info!("Effective config: {}", json),
+            Err(e) => log::warn!("Failed to encode effective config: {}", e),
+        }
+    }
+}
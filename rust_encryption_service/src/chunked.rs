@@ -0,0 +1,354 @@
+use aead::stream::{NewStream, StreamBE32, StreamPrimitive};
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::Payload;
+
+use crate::crypto::ChannelCipher;
+use crate::TAG_LEN;
+
+// ── Chunked envelope for files ──
+//
+// header (FLAG_CHUNKED) || nonce prefix(7) || chunk* || last chunk
+//
+// Each chunk holds up to `chunk_size` plaintext bytes plus its own tag and is
+// sealed by aead::stream's StreamBE32, under nonce = prefix || u32 BE chunk
+// index || last-chunk flag. Every chunk also authenticates the header bytes. Reordering, dropping or duplicating a
+// chunk, or truncating the file before the flagged last chunk, fails to
+// open. Only an empty file has an empty last chunk.
+
+pub const NONCE_PREFIX_LEN: usize = 7;
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+#[derive(Debug)]
+pub enum ChunkError {
+    // Any chunk failed to authenticate
+    Auth,
+    // Input ended before the last chunk
+    Truncated,
+    // More chunks than a u32 index can number
+    TooManyChunks,
+}
+
+impl std::fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkError::Auth => f.write_str("chunk failed to authenticate"),
+            ChunkError::Truncated => f.write_str("file ends before its last chunk"),
+            ChunkError::TooManyChunks => f.write_str("file has too many chunks"),
+        }
+    }
+}
+
+// Chunks are opened at explicit positions rather than through a Decryptor,
+// since the first chunk is tried under several ciphers and `salvage` retries
+// a chunk as both last and middle.
+type Stream = StreamBE32<ChannelCipher>;
+
+fn stream(cipher: ChannelCipher, prefix: &[u8; NONCE_PREFIX_LEN]) -> Stream {
+    Stream::from_aead(cipher, GenericArray::from_slice(prefix))
+}
+
+// ── Seals plaintext pushed in arbitrary pieces into chunks ──
+pub struct ChunkSealer {
+    stream: Stream,
+    aad: Vec<u8>,
+    prefix: [u8; NONCE_PREFIX_LEN],
+    chunk_size: usize,
+    index: u32,
+    pending: Vec<u8>,
+}
+
+impl ChunkSealer {
+    // `header` is the encoded envelope header; it and the prefix must be
+    // written ahead of the first chunk
+    pub fn new(
+        cipher: ChannelCipher,
+        header: Vec<u8>,
+        prefix: [u8; NONCE_PREFIX_LEN],
+        chunk_size: u32,
+    ) -> Self {
+        ChunkSealer {
+            stream: stream(cipher, &prefix),
+            aad: header,
+            prefix,
+            chunk_size: chunk_size as usize,
+            index: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn preamble(&self) -> Vec<u8> {
+        [&self.aad[..], &self.prefix].concat()
+    }
+
+    // Seal every chunk `data` completes. One full chunk is always held back,
+    // since only `finish` knows which chunk is the last.
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, ChunkError> {
+        self.pending.extend_from_slice(data);
+        let mut out = Vec::new();
+        while self.pending.len() > self.chunk_size {
+            let rest = self.pending.split_off(self.chunk_size);
+            let chunk = std::mem::replace(&mut self.pending, rest);
+            out.extend(self.seal(&chunk, false)?);
+        }
+        Ok(out)
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>, ChunkError> {
+        let chunk = std::mem::take(&mut self.pending);
+        self.seal(&chunk, true)
+    }
+
+    fn seal(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>, ChunkError> {
+        let index = self.index;
+        self.index = self.index.checked_add(1).ok_or(ChunkError::TooManyChunks)?;
+        self.stream
+            .encrypt(index, last, Payload { msg: chunk, aad: &self.aad })
+            .map_err(|_| ChunkError::Auth)
+    }
+}
+
+// ── Opens sealed chunks pushed in arbitrary pieces ──
+// Callers may offer several ciphers (e.g. current and previous master
+// secret); the first chunk picks the one that authenticates, and every
+// later chunk must open under that same cipher.
+pub struct ChunkOpener<T> {
+    candidates: Vec<(T, Stream)>,
+    aad: Vec<u8>,
+    sealed_chunk: usize,
+    // First chunk handed to this opener; nonzero only after `seek`
+    start: u32,
+    index: u32,
+    pending: Vec<u8>,
+}
+
+impl<T: Copy> ChunkOpener<T> {
+    pub fn new(
+        candidates: Vec<(T, ChannelCipher)>,
+        header: Vec<u8>,
+        prefix: [u8; NONCE_PREFIX_LEN],
+        chunk_size: u32,
+    ) -> Self {
+        ChunkOpener {
+            candidates: candidates
+                .into_iter()
+                .map(|(label, cipher)| (label, stream(cipher, &prefix)))
+                .collect(),
+            aad: header,
+            sealed_chunk: chunk_size as usize + TAG_LEN,
+            start: 0,
+            index: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    // Sealed bytes per full chunk, tag included
+    pub fn sealed_chunk_len(&self) -> usize {
+        self.sealed_chunk
+    }
+
+    // Start at chunk `index` instead of the first; the caller drops the
+    // sealed bytes of every chunk before it. Nonces follow from the index
+    // alone, so no earlier chunk has to be opened. Only before any push.
+    pub fn seek(&mut self, index: u32) {
+        self.start = index;
+        self.index = index;
+    }
+
+    // Label of the cipher that opened the first chunk, once one has
+    pub fn chosen(&self) -> Option<T> {
+        match self.candidates.as_slice() {
+            [(label, _)] if self.index > self.start => Some(*label),
+            _ => None,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, ChunkError> {
+        let mut out = Vec::new();
+        self.push_into(data, &mut out)?;
+        Ok(out)
+    }
+
+    // Like `push`, but chunks that open before a failure stay in `out`
+    pub fn push_into(&mut self, data: &[u8], out: &mut Vec<u8>) -> Result<(), ChunkError> {
+        self.pending.extend_from_slice(data);
+        while self.pending.len() > self.sealed_chunk {
+            let rest = self.pending.split_off(self.sealed_chunk);
+            let chunk = std::mem::replace(&mut self.pending, rest);
+            out.extend(self.open(&chunk, false)?);
+        }
+        Ok(())
+    }
+
+    // Open the last chunk; also returns the label of the cipher that opened
+    // the file
+    pub fn finish(mut self) -> Result<(T, Vec<u8>), ChunkError> {
+        if self.pending.len() < TAG_LEN {
+            return Err(ChunkError::Truncated);
+        }
+        let chunk = std::mem::take(&mut self.pending);
+        // Past the first chunk the key is known good, so a full chunk that
+        // fails as last means the real last chunk was cut off
+        let cut_off = self.index > self.start && chunk.len() == self.sealed_chunk;
+        let last = self.open(&chunk, true).map_err(|e| match e {
+            ChunkError::Auth if cut_off => ChunkError::Truncated,
+            e => e,
+        })?;
+        Ok((self.candidates[0].0, last))
+    }
+
+    // Best-effort end of input, for recovering a damaged file: the last chunk
+    // if it opens, else the held-back chunk if it opens as a middle one (the
+    // file was cut at a chunk boundary). Returns the opening label, if any
+    // chunk opened, the plaintext and whether the file was complete.
+    pub fn salvage(mut self) -> (Option<T>, Vec<u8>, bool) {
+        let chunk = std::mem::take(&mut self.pending);
+        if let Ok(last) = self.open(&chunk, true) {
+            return (self.chosen(), last, true);
+        }
+        let middle = if chunk.len() == self.sealed_chunk {
+            self.open(&chunk, false).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        (self.chosen(), middle, false)
+    }
+
+    fn open(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>, ChunkError> {
+        let payload = || Payload { msg: chunk, aad: &self.aad };
+        let opened = self.candidates.iter().enumerate().find_map(|(pos, (_, stream))| {
+            stream.decrypt(self.index, last, payload()).ok().map(|plaintext| (pos, plaintext))
+        });
+        let Some((pos, plaintext)) = opened else {
+            return Err(ChunkError::Auth);
+        };
+        if self.candidates.len() > 1 {
+            let chosen = self.candidates.swap_remove(pos);
+            self.candidates = vec![chosen];
+        }
+
+        self.index = self.index.checked_add(1).ok_or(ChunkError::TooManyChunks)?;
+        Ok(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Algorithm;
+
+    const PREFIX: [u8; NONCE_PREFIX_LEN] = [9; NONCE_PREFIX_LEN];
+    const HEADER: &[u8] = b"header";
+    const CHUNK: u32 = 4;
+    const SEALED: usize = CHUNK as usize + TAG_LEN;
+
+    fn cipher(key: u8) -> ChannelCipher {
+        ChannelCipher::new(Algorithm::Aes256gcm, &[key; 32])
+    }
+
+    // Sealed chunks, without the preamble, pushed one byte at a time
+    fn seal(plaintext: &[u8]) -> Vec<u8> {
+        let mut sealer = ChunkSealer::new(cipher(1), HEADER.to_vec(), PREFIX, CHUNK);
+        let mut out = Vec::new();
+        for byte in plaintext {
+            out.extend(sealer.push(&[*byte]).unwrap());
+        }
+        out.extend(sealer.finish().unwrap());
+        out
+    }
+
+    fn opener() -> ChunkOpener<&'static str> {
+        ChunkOpener::new(vec![("right", cipher(1))], HEADER.to_vec(), PREFIX, CHUNK)
+    }
+
+    fn open(sealed: &[u8]) -> Result<Vec<u8>, ChunkError> {
+        let mut opener = opener();
+        let mut out = Vec::new();
+        for piece in sealed.chunks(3) {
+            out.extend(opener.push(piece)?);
+        }
+        let (_, last) = opener.finish()?;
+        out.extend(last);
+        Ok(out)
+    }
+
+    #[test]
+    fn any_length_round_trips_in_any_pieces() {
+        for len in [0, 1, 3, 4, 5, 8, 11] {
+            let plaintext: Vec<u8> = (0..len).collect();
+            let sealed = seal(&plaintext);
+            // Whole chunks, plus a last one that is only empty for an empty file
+            let chunks = (len as usize).div_ceil(CHUNK as usize).max(1);
+            assert_eq!(sealed.len(), len as usize + chunks * TAG_LEN);
+            assert_eq!(open(&sealed).unwrap(), plaintext, "len {}", len);
+        }
+    }
+
+    #[test]
+    fn preamble_is_header_then_prefix() {
+        let sealer = ChunkSealer::new(cipher(1), HEADER.to_vec(), PREFIX, CHUNK);
+        assert_eq!(sealer.preamble(), [HEADER, &PREFIX[..]].concat());
+    }
+
+    #[test]
+    fn reordered_or_repeated_chunks_fail() {
+        let sealed = seal(b"hello world");
+        let (first, rest) = sealed.split_at(SEALED);
+        let (second, last) = rest.split_at(SEALED);
+        let swapped = [second, first, last].concat();
+        assert!(matches!(open(&swapped), Err(ChunkError::Auth)));
+        let repeated = [first, first, second, last].concat();
+        assert!(matches!(open(&repeated), Err(ChunkError::Auth)));
+    }
+
+    #[test]
+    fn a_cut_off_file_is_truncated() {
+        let sealed = seal(b"hello world");
+        // At a chunk boundary, and partway into the last chunk
+        assert!(matches!(open(&sealed[..2 * SEALED]), Err(ChunkError::Truncated)));
+        assert!(matches!(open(&sealed[..2 * SEALED + 5]), Err(ChunkError::Truncated)));
+    }
+
+    #[test]
+    fn the_header_is_authenticated() {
+        let sealed = seal(b"hello");
+        let mut opener = ChunkOpener::new(vec![((), cipher(1))], b"other".to_vec(), PREFIX, CHUNK);
+        assert!(matches!(opener.push(&sealed), Err(ChunkError::Auth)));
+    }
+
+    #[test]
+    fn the_first_chunk_picks_the_cipher() {
+        let sealed = seal(b"hello world");
+        let candidates = vec![("wrong", cipher(2)), ("right", cipher(1))];
+        let mut opener = ChunkOpener::new(candidates, HEADER.to_vec(), PREFIX, CHUNK);
+        assert_eq!(opener.chosen(), None);
+        let mut out = opener.push(&sealed).unwrap();
+        assert_eq!(opener.chosen(), Some("right"));
+        let (label, last) = opener.finish().unwrap();
+        out.extend(last);
+        assert_eq!((label, &out[..]), ("right", &b"hello world"[..]));
+    }
+
+    #[test]
+    fn seek_opens_from_a_later_chunk() {
+        let sealed = seal(b"hello world");
+        let mut opener = opener();
+        opener.seek(1);
+        let mut out = opener.push(&sealed[SEALED..]).unwrap();
+        out.extend(opener.finish().unwrap().1);
+        assert_eq!(out, b"o world");
+    }
+
+    #[test]
+    fn salvage_keeps_what_opens() {
+        let sealed = seal(b"hello world");
+        let mut cut = opener();
+        let mut out = cut.push(&sealed[..2 * SEALED]).unwrap();
+        let (label, rest, complete) = cut.salvage();
+        out.extend(rest);
+        assert_eq!((label, &out[..], complete), (Some("right"), &b"hello wo"[..], false));
+
+        let mut whole = opener();
+        whole.push(&sealed).unwrap();
+        assert!(whole.salvage().2);
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// ── Where time-based features read the current time ──
+// Cipher TTLs, the decrypt cache, daily quotas, token expiry and last-used
+// stamps all go through the clock in AppState, so a test can swap in a
+// MockClock and move time forward without sleeping.
+pub trait Clock: Send + Sync {
+    // Monotonic, for measuring how long something has been held
+    fn now(&self) -> Instant;
+    // Wall-clock seconds since the epoch, for day boundaries and expiry stamps
+    fn unix_now(&self) -> u64;
+}
+
+// ── The real clock ──
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn unix_now(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+    }
+}
+
+// ── A clock that only moves when told to ──
+#[cfg(test)]
+pub struct MockClock {
+    start: Instant,
+    start_unix: u64,
+    offset: std::sync::Mutex<std::time::Duration>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(start_unix: u64) -> Self {
+        MockClock { start: Instant::now(), start_unix, offset: Default::default() }
+    }
+
+    pub fn advance(&self, by: std::time::Duration) {
+        *self.offset.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.offset.lock().unwrap()
+    }
+
+    fn unix_now(&self) -> u64 {
+        self.start_unix + self.offset.lock().unwrap().as_secs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn mock_clock_moves_only_when_advanced() {
+        let clock = MockClock::new(1_700_000_000);
+        let start = clock.now();
+        assert_eq!((clock.now(), clock.unix_now()), (start, 1_700_000_000));
+        clock.advance(Duration::from_millis(1_500));
+        assert_eq!(clock.now() - start, Duration::from_millis(1_500));
+        // Wall-clock seconds only count whole seconds
+        assert_eq!(clock.unix_now(), 1_700_000_001);
+    }
+
+    #[test]
+    fn system_clock_reads_the_wall_clock() {
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let now = SystemClock.unix_now();
+        assert!(now >= before && now <= before + 1);
+    }
+}
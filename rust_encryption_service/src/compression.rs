@@ -0,0 +1,105 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, ContentEncoding};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+
+// ── Optional transport compression of responses ──
+// Enabled by RESPONSE_COMPRESSION=true: actix's Compress middleware picks
+// gzip, brotli or zstd from the client's Accept-Encoding. Only responses of
+// at least RESPONSE_COMPRESSION_MIN_BYTES are compressed, and streamed ones
+// always are.
+//
+// Caveat: compressing a secret next to text an attacker controls leaks its
+// length (BREACH). A decrypt response holds only the sealed plaintext, but
+// a client echoing attacker input into messages it later decrypts should
+// leave this off.
+
+// ── Smallest response body worth compressing ──
+pub struct CompressionThreshold(pub usize);
+
+// None unless RESPONSE_COMPRESSION=true
+pub fn threshold_from_env() -> Option<CompressionThreshold> {
+    if !std::env::var("RESPONSE_COMPRESSION").is_ok_and(|v| v == "true") {
+        return None;
+    }
+    let min_bytes = std::env::var("RESPONSE_COMPRESSION_MIN_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1024);
+    log::info!("Response compression enabled for bodies of {}+ bytes", min_bytes);
+    Some(CompressionThreshold(min_bytes))
+}
+
+// ── Middleware: mark bodies under the threshold so Compress skips them ──
+// Runs inside Compress, which leaves any response that already names a
+// Content-Encoding as it is.
+pub async fn skip_small(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let threshold = req.app_data::<web::Data<CompressionThreshold>>().map(|t| t.0);
+    let mut res = next.call(req).await?;
+    let small = match res.response().body().size() {
+        BodySize::Sized(len) => threshold.is_some_and(|min| len < min as u64),
+        BodySize::None | BodySize::Stream => false,
+    };
+    if small {
+        let identity = ContentEncoding::Identity.to_header_value();
+        res.headers_mut().insert(header::CONTENT_ENCODING, identity);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{middleware, App, HttpResponse};
+
+    // Content-Encoding and body length of GET /{len}, a body of `len` bytes
+    async fn fetch(threshold: Option<usize>, len: usize) -> (Option<String>, usize) {
+        let mut app = App::new();
+        if let Some(min) = threshold {
+            app = app.app_data(web::Data::new(CompressionThreshold(min)));
+        }
+        let app = init_service(
+            app.wrap(middleware::from_fn(skip_small)).wrap(middleware::Compress::default()).route(
+                "/{len}",
+                web::get().to(|len: web::Path<usize>| async move {
+                    HttpResponse::Ok().body("a".repeat(*len))
+                }),
+            ),
+        )
+        .await;
+        let req = TestRequest::get()
+            .uri(&format!("/{}", len))
+            .insert_header(("Accept-Encoding", "gzip"));
+        let resp = call_service(&app, req.to_request()).await;
+        let encoding = resp
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|v| v.to_str().unwrap().to_owned());
+        (encoding, read_body(resp).await.len())
+    }
+
+    #[actix_web::test]
+    async fn bodies_under_the_threshold_are_sent_as_is() {
+        let (encoding, len) = fetch(Some(1024), 1023).await;
+        assert_eq!(encoding.as_deref(), Some("identity"));
+        assert_eq!(len, 1023);
+    }
+
+    #[actix_web::test]
+    async fn bodies_at_the_threshold_are_compressed() {
+        let (encoding, len) = fetch(Some(1024), 1024).await;
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+        assert!(len < 1024);
+    }
+
+    #[actix_web::test]
+    async fn without_a_threshold_nothing_is_marked() {
+        let (encoding, _) = fetch(None, 10).await;
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+    }
+}
@@ -0,0 +1,442 @@
+use aes_gcm::{
+    aead::consts::{U0, U12, U16},
+    aead::{Aead, AeadCore, AeadInPlace, KeyInit, Payload, Tag},
+    Aes256Gcm, Nonce,
+};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use ring::agreement;
+use ring::signature::{self, Ed25519KeyPair};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Domain tag for namespaced derivations; never a prefix of a legacy input,
+// which always starts with the master secret itself
+const ORG_CHANNEL_DOMAIN: &[u8] = b"freecord/key/org-channel/v1";
+const SHARED_DOMAIN: &[u8] = b"freecord/key/shared/v1";
+
+// ── HKDF info label for every key derived from another key ──
+// One label per purpose, kept here so no two sites can expand the same key
+// under the same info. Existing ciphertext depends on these bytes: a label
+// never changes, and a new purpose gets a new label.
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyPurpose {
+    // The key two channels share, from both channel keys
+    Shared,
+    // A channel key of other than 32 bytes; the length is appended
+    KeyLength,
+    // Per-context subkey; the context is appended
+    Context,
+    // Key commitment recorded in the header
+    Commitment,
+    // Signs decrypt tokens, from the master secret
+    DecryptToken,
+    // Blind search-index tokens
+    Index,
+    // First ratchet chain key
+    RatchetInit,
+    // Each later ratchet chain key
+    RatchetStep,
+    // A ratchet message key, from the chain key of its step
+    RatchetMessage,
+    // Wraps channel keys exported to a recipient, from an X25519 secret
+    KeyExport,
+    // Seed of the channel's Ed25519 signing key
+    Signing,
+}
+
+impl KeyPurpose {
+    pub const fn info(self) -> &'static [u8] {
+        match self {
+            KeyPurpose::Shared => b"freecord/shared/v1",
+            KeyPurpose::KeyLength => b"freecord/key-length/v1",
+            KeyPurpose::Context => b"freecord/context/v1:",
+            KeyPurpose::Commitment => b"freecord/commit/v1",
+            KeyPurpose::DecryptToken => b"freecord/decrypt-token/v1",
+            KeyPurpose::Index => b"freecord/index/v1",
+            KeyPurpose::RatchetInit => b"freecord/ratchet/v1",
+            KeyPurpose::RatchetStep => b"freecord/ratchet/step",
+            KeyPurpose::RatchetMessage => b"freecord/ratchet/message",
+            KeyPurpose::KeyExport => b"freecord/key-export/v1",
+            KeyPurpose::Signing => b"freecord/ed25519/v1",
+        }
+    }
+}
+
+// ── What a key belongs to: a bare channel, or a channel inside an org ──
+// With `shared_with`, the key two channels share; see `KeyId::shared`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyId {
+    pub org_id: Option<i64>,
+    pub channel_id: i64,
+    // Partner channel of a shared key, always above `channel_id`
+    pub shared_with: Option<i64>,
+}
+
+impl KeyId {
+    pub fn channel(channel_id: i64) -> Self {
+        KeyId { org_id: None, channel_id, shared_with: None }
+    }
+
+    pub fn new(org_id: Option<i64>, channel_id: i64) -> Self {
+        KeyId { org_id, channel_id, shared_with: None }
+    }
+
+    // The same id whichever order the two channels are given in
+    pub fn shared(org_id: Option<i64>, a: i64, b: i64) -> Self {
+        KeyId { org_id, channel_id: a.min(b), shared_with: Some(a.max(b)) }
+    }
+
+    pub fn with_shared(self, other: Option<i64>) -> Self {
+        match other {
+            Some(other) => KeyId::shared(self.org_id, self.channel_id, other),
+            None => self,
+        }
+    }
+}
+
+impl std::fmt::Display for KeyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.channel_id)?;
+        if let Some(other) = self.shared_with {
+            write!(f, "+{}", other)?;
+        }
+        match self.org_id {
+            Some(org_id) => write!(f, " (org {})", org_id),
+            None => Ok(()),
+        }
+    }
+}
+
+// ── Derive a per-channel 256-bit key from master secret + channel_id ──
+pub fn derive_channel_key(master_secret: &str, channel_id: i64) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(master_secret.as_bytes());
+    hasher.update(channel_id.to_le_bytes());
+    hasher.finalize().to_vec()
+}
+
+// ── Derive the key for any KeyId ──
+// Bare channels keep the original derivation so existing ciphertext still
+// opens. Namespaced ids use a domain tag and length-prefixed fields, so no
+// two (org, channel) pairs can ever feed the hash the same bytes. A shared
+// key is HKDF over both channel keys, lower channel id first, so either
+// channel's key alone does not give it.
+pub fn derive_key(master_secret: &str, key_id: KeyId) -> Vec<u8> {
+    if let Some(other) = key_id.shared_with {
+        let (low, high) = (key_id.channel_id.min(other), key_id.channel_id.max(other));
+        let low = derive_key(master_secret, KeyId { shared_with: None, channel_id: low, ..key_id });
+        let high =
+            derive_key(master_secret, KeyId { shared_with: None, channel_id: high, ..key_id });
+        let ikm = zeroize::Zeroizing::new([low, high].concat());
+        let mut key = vec![0u8; 32];
+        Hkdf::<Sha256>::new(Some(SHARED_DOMAIN), &ikm)
+            .expand(KeyPurpose::Shared.info(), &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        return key;
+    }
+
+    let Some(org_id) = key_id.org_id else {
+        return derive_channel_key(master_secret, key_id.channel_id);
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update((ORG_CHANNEL_DOMAIN.len() as u32).to_be_bytes());
+    hasher.update(ORG_CHANNEL_DOMAIN);
+    hasher.update((master_secret.len() as u32).to_be_bytes());
+    hasher.update(master_secret.as_bytes());
+    hasher.update(org_id.to_be_bytes());
+    hasher.update(key_id.channel_id.to_be_bytes());
+    hasher.finalize().to_vec()
+}
+
+// ── Derive a key of `len` bytes for any KeyId ──
+// 32 bytes is the derive_key output itself, so today's ciphers keep their
+// keys. Any other length is HKDF-Expand over that key with the length bound
+// into the info, so a 16-byte key is never a prefix of the 64-byte one.
+// Panics above HKDF-SHA256's 8160-byte limit.
+pub fn derive_key_len(master_secret: &str, key_id: KeyId, len: usize) -> Vec<u8> {
+    let base = zeroize::Zeroizing::new(derive_key(master_secret, key_id));
+    if len == base.len() {
+        return base.to_vec();
+    }
+    let mut info = KeyPurpose::KeyLength.info().to_vec();
+    info.extend_from_slice(&(len as u32).to_be_bytes());
+    let mut key = vec![0u8; len];
+    Hkdf::<Sha256>::from_prk(&base)
+        .expect("a 32-byte key is a valid HKDF-SHA256 PRK")
+        .expand(&info, &mut key)
+        .expect("key length within HKDF-SHA256 output limit");
+    key
+}
+
+// ── HKDF subkey for one caller-supplied context ──
+// Separates keys per conversation thread: learning one context's subkey says
+// nothing about the channel key or any other context.
+pub fn derive_context_key(channel_key: &[u8], context: &[u8]) -> [u8; 32] {
+    let info = [KeyPurpose::Context.info(), context].concat();
+    let mut subkey = [0u8; 32];
+    Hkdf::<Sha256>::new(None, channel_key)
+        .expand(&info, &mut subkey)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    subkey
+}
+
+// ── Commitment to the exact key a message was sealed under ──
+// GCM and Poly1305 tags are not key-committing: a blob can be crafted to
+// authenticate under two keys. Checking this value before decrypt makes any
+// other key fail, at the cost of 32 header bytes and one HKDF per message.
+pub fn key_commitment(key: &[u8]) -> [u8; 32] {
+    let mut commitment = [0u8; 32];
+    Hkdf::<Sha256>::new(None, key)
+        .expand(KeyPurpose::Commitment.info(), &mut commitment)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    commitment
+}
+
+// ── Key that signs decrypt tokens ──
+// An HKDF expansion of the master secret under its own label, so a token
+// signature reveals nothing about any channel key.
+pub fn derive_token_key(master_secret: &str) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, master_secret.as_bytes())
+        .expand(KeyPurpose::DecryptToken.info(), &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+// ── Per-channel key for blind search-index tokens ──
+// A subkey of the channel key: tokens for the same word differ between
+// channels, and a token reveals nothing about the channel key.
+pub fn derive_index_key(channel_key: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, channel_key)
+        .expand(KeyPurpose::Index.info(), &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+// ── Key that wraps exported channel keys for one recipient ──
+// X25519 between a fresh ephemeral key and the recipient's public key, then
+// HKDF-SHA256 over the shared secret, salted with the ephemeral and then the
+// recipient public key. Returns the ephemeral public key with the wrapping
+// key; None if the recipient's is not a usable X25519 public key.
+pub fn export_wrapping_key(
+    recipient_public: &[u8],
+) -> Option<([u8; 32], zeroize::Zeroizing<[u8; 32]>)> {
+    let rng = ring::rand::SystemRandom::new();
+    let ephemeral = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng).ok()?;
+    let ephemeral_public: [u8; 32] = ephemeral.compute_public_key().ok()?.as_ref().try_into().ok()?;
+    let peer = agreement::UnparsedPublicKey::new(&agreement::X25519, recipient_public);
+    let salt = [&ephemeral_public[..], recipient_public].concat();
+    agreement::agree_ephemeral(ephemeral, &peer, |shared| {
+        let mut key = zeroize::Zeroizing::new([0u8; 32]);
+        Hkdf::<Sha256>::new(Some(&salt), shared)
+            .expand(KeyPurpose::KeyExport.info(), &mut key[..])
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        (ephemeral_public, key)
+    })
+    .ok()
+}
+
+// ── Per-channel Ed25519 key for publicly verifiable signatures ──
+// Seeded by a subkey of the channel key, so a channel keeps one public key
+// until MASTER_SECRET changes, and a signature reveals nothing about the
+// channel key.
+// Built on ring rather than ed25519-dalek: ring is already linked for TLS and
+// the X25519 exchange, and its Ed25519 is the same RFC 8032 scheme, giving
+// byte-identical keys and signatures for a seed, so a second implementation
+// would only add a dependency to audit.
+pub fn derive_signing_key(channel_key: &[u8]) -> Ed25519KeyPair {
+    let mut seed = zeroize::Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, channel_key)
+        .expand(KeyPurpose::Signing.info(), &mut seed[..])
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    Ed25519KeyPair::from_seed_unchecked(&seed[..]).expect("any 32 bytes is an Ed25519 seed")
+}
+
+pub fn verify_ed25519(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(message, signature)
+        .is_ok()
+}
+
+// ── Short, human-comparable fingerprint of a key ──
+// First 8 bytes of a domain-separated SHA-256, as four hex groups. One-way,
+// and under a tag no other derivation uses, so it reveals nothing about the
+// key or any value computed from it.
+pub fn key_fingerprint(key: &[u8]) -> String {
+    let digest = Sha256::new()
+        .chain_update(b"freecord/fingerprint/v1")
+        .chain_update(key)
+        .finalize();
+    digest[..8]
+        .chunks(2)
+        .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// ── AEAD algorithms a channel can encrypt under ──
+// Both take the same 32-byte channel key and a 12-byte nonce, and produce a
+// 16-byte tag, so they share the envelope layout.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Aes256gcm,
+    Chacha20poly1305,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 2] = [Algorithm::Aes256gcm, Algorithm::Chacha20poly1305];
+
+    // Stable id written into the envelope header
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Aes256gcm => 1,
+            Algorithm::Chacha20poly1305 => 2,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Algorithm::ALL.into_iter().find(|algorithm| algorithm.name() == name)
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Algorithm::Aes256gcm),
+            2 => Some(Algorithm::Chacha20poly1305),
+            _ => None,
+        }
+    }
+
+    // Same spelling as the JSON field value
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Aes256gcm => "aes256gcm",
+            Algorithm::Chacha20poly1305 => "chacha20poly1305",
+        }
+    }
+
+    // Key bytes the cipher takes; derive_key_len produces exactly this many
+    pub fn key_len(self) -> usize {
+        match self {
+            Algorithm::Aes256gcm | Algorithm::Chacha20poly1305 => 32,
+        }
+    }
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// ── A fully initialized cipher for one (channel key, algorithm) pair ──
+#[derive(Clone)]
+pub enum ChannelCipher {
+    // Boxed: the expanded AES key schedule dwarfs ChaCha's 32-byte key
+    Aes256gcm(Box<Aes256Gcm>),
+    Chacha20poly1305(ChaCha20Poly1305),
+}
+
+impl ChannelCipher {
+    // Heap bytes behind the larger variant: the boxed AES key schedule
+    pub const MAX_HEAP_BYTES: usize = std::mem::size_of::<Aes256Gcm>();
+
+    pub fn new(algorithm: Algorithm, key: &[u8]) -> Self {
+        match algorithm {
+            Algorithm::Aes256gcm => ChannelCipher::Aes256gcm(Box::new(Aes256Gcm::new(
+                aes_gcm::Key::<Aes256Gcm>::from_slice(key),
+            ))),
+            Algorithm::Chacha20poly1305 => ChannelCipher::Chacha20poly1305(ChaCha20Poly1305::new(
+                chacha20poly1305::Key::from_slice(key),
+            )),
+        }
+    }
+
+    pub fn encrypt(&self, nonce: &[u8], payload: Payload) -> aes_gcm::aead::Result<Vec<u8>> {
+        let nonce = Nonce::from_slice(nonce);
+        match self {
+            ChannelCipher::Aes256gcm(c) => c.encrypt(nonce, payload),
+            ChannelCipher::Chacha20poly1305(c) => c.encrypt(nonce, payload),
+        }
+    }
+
+    pub fn decrypt(&self, nonce: &[u8], payload: Payload) -> aes_gcm::aead::Result<Vec<u8>> {
+        let nonce = Nonce::from_slice(nonce);
+        match self {
+            ChannelCipher::Aes256gcm(c) => c.decrypt(nonce, payload),
+            ChannelCipher::Chacha20poly1305(c) => c.decrypt(nonce, payload),
+        }
+    }
+}
+
+// ── Time to seal a fixed workload under each algorithm, fastest first ──
+// A startup micro-benchmark: on a CPU with AES instructions AES-GCM wins,
+// without them the software ChaCha20 usually does. Throwaway key, no secrets.
+pub fn benchmark_algorithms() -> Vec<(Algorithm, std::time::Duration)> {
+    const ROUNDS: usize = 64;
+    let message = [0u8; 16 * 1024];
+    let nonce = [0u8; 12];
+    let mut timings: Vec<_> = Algorithm::ALL
+        .into_iter()
+        .map(|algorithm| {
+            let cipher = ChannelCipher::new(algorithm, &[0x42; 32]);
+            // One untimed round warms caches and any lazy CPU feature detection
+            let _ = cipher.encrypt(&nonce, Payload { msg: &message, aad: b"" });
+            let start = std::time::Instant::now();
+            for _ in 0..ROUNDS {
+                let sealed = cipher.encrypt(&nonce, Payload { msg: &message, aad: b"" });
+                std::hint::black_box(sealed.ok());
+            }
+            (algorithm, start.elapsed())
+        })
+        .collect();
+    timings.sort_by_key(|&(_, elapsed)| elapsed);
+    timings
+}
+
+// In-place AEAD, so chunked files can be sealed through aead::stream. Both
+// algorithms share nonce and tag sizes, so one set of sizes covers either.
+impl AeadCore for ChannelCipher {
+    type NonceSize = U12;
+    type TagSize = U16;
+    type CiphertextOverhead = U0;
+}
+
+impl AeadInPlace for ChannelCipher {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<U12>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+    ) -> aes_gcm::aead::Result<Tag<Self>> {
+        match self {
+            ChannelCipher::Aes256gcm(c) => {
+                c.encrypt_in_place_detached(nonce, associated_data, buffer)
+            }
+            ChannelCipher::Chacha20poly1305(c) => {
+                c.encrypt_in_place_detached(nonce, associated_data, buffer)
+            }
+        }
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &Nonce<U12>,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tag: &Tag<Self>,
+    ) -> aes_gcm::aead::Result<()> {
+        match self {
+            ChannelCipher::Aes256gcm(c) => {
+                c.decrypt_in_place_detached(nonce, associated_data, buffer, tag)
+            }
+            ChannelCipher::Chacha20poly1305(c) => {
+                c.decrypt_in_place_detached(nonce, associated_data, buffer, tag)
+            }
+        }
+    }
+}
@@ -0,0 +1,428 @@
+// ── Ciphertext envelope ──
+//
+// v0 (legacy, headerless): nonce(12) || ciphertext
+// v1:                      MAGIC || 1 || algorithm || flags || [fields] || nonce(12) || ciphertext
+// v2:                      MAGIC || 2 || algorithm || flags || [flags2] || [fields]
+//                          || nonce(12) || ciphertext
+//
+// v2 is v1 with room for more flags: FLAG_EXTENDED in the first flags byte
+// says a second follows, and `Header::flags` holds that one as its high byte.
+// Optional fields follow the flags in ascending flag-bit order, each
+// present only when its flag is set. In every version each header byte is
+// passed to the AEAD as associated data, so a flipped flag or algorithm id
+// fails authentication like any other tamper.
+//
+// Headers are parsed through `PARSERS`, keyed by the version byte. A new
+// version adds its parser there and becomes FORMAT_VERSION for writes, while
+// blobs in every older listed version keep opening.
+
+pub const MAGIC: u8 = 0xFC;
+pub const FORMAT_VERSION: u8 = 2;
+const FIXED_HEADER_LEN: usize = 4;
+
+// Plaintext is `u32 BE message length || message || metadata JSON`
+pub const FLAG_FRAMED: u16 = 0x01;
+// Field: `u16 BE length || context`; the message key is an HKDF subkey
+pub const FLAG_CONTEXT: u16 = 0x02;
+// Field: `u64 BE ratchet index`; the message key is that ratchet step
+pub const FLAG_RATCHET: u16 = 0x04;
+// Field: 32-byte commitment to the message key, checked before decrypt
+pub const FLAG_COMMIT: u16 = 0x08;
+// No field: the caller-held attachment hash is appended to the AAD
+pub const FLAG_ATTACHMENT: u16 = 0x10;
+// Field: `u32 BE chunk size`; the body is a chunked stream (see chunked.rs)
+pub const FLAG_CHUNKED: u16 = 0x20;
+// Field: `u64 BE sequence`, the channel's count of sequenced encrypts
+pub const FLAG_SEQUENCE: u16 = 0x40;
+// v2: the second flags byte follows the first; never set in `Header::flags`
+const FLAG_EXTENDED: u8 = 0x80;
+// Field (v2): `u64 BE unix seconds` the server sealed the blob at
+pub const FLAG_PRODUCED_AT: u16 = 0x0100;
+
+// Flags each version can carry; a header setting any other does not parse,
+// so a blob from a newer writer is refused rather than misread
+const KNOWN_FLAGS_V1: u16 = FLAG_FRAMED
+    | FLAG_CONTEXT
+    | FLAG_RATCHET
+    | FLAG_COMMIT
+    | FLAG_ATTACHMENT
+    | FLAG_CHUNKED
+    | FLAG_SEQUENCE;
+const KNOWN_FLAGS_V2: u16 = KNOWN_FLAGS_V1 | FLAG_PRODUCED_AT;
+
+pub const COMMITMENT_LEN: usize = 32;
+
+// Longest possible header: fixed part, second flags byte and every field at
+// its maximum
+pub const MAX_HEADER_LEN: usize =
+    FIXED_HEADER_LEN + 1 + 2 + u16::MAX as usize + 8 + COMMITMENT_LEN + 4 + 8 + 8;
+
+#[derive(Clone, Copy)]
+pub struct Header<'a> {
+    pub algorithm: u8,
+    pub flags: u16,
+    pub context: Option<&'a [u8]>,
+    pub ratchet_index: Option<u64>,
+    pub commitment: Option<&'a [u8]>,
+    pub chunk_size: Option<u32>,
+    pub sequence: Option<u64>,
+    pub produced_at: Option<u64>,
+}
+
+impl<'a> Header<'a> {
+    pub fn new(algorithm: u8) -> Self {
+        Header {
+            algorithm,
+            flags: 0,
+            context: None,
+            ratchet_index: None,
+            commitment: None,
+            chunk_size: None,
+            sequence: None,
+            produced_at: None,
+        }
+    }
+
+    pub fn with_context(mut self, context: &'a [u8]) -> Self {
+        self.flags |= FLAG_CONTEXT;
+        self.context = Some(context);
+        self
+    }
+
+    pub fn with_ratchet(mut self, index: u64) -> Self {
+        self.flags |= FLAG_RATCHET;
+        self.ratchet_index = Some(index);
+        self
+    }
+
+    pub fn with_commitment(mut self, commitment: &'a [u8; COMMITMENT_LEN]) -> Self {
+        self.flags |= FLAG_COMMIT;
+        self.commitment = Some(commitment);
+        self
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let [high, low] = self.flags.to_be_bytes();
+        let mut out = match high {
+            0 => vec![MAGIC, FORMAT_VERSION, self.algorithm, low],
+            _ => vec![MAGIC, FORMAT_VERSION, self.algorithm, low | FLAG_EXTENDED, high],
+        };
+        if let Some(context) = self.context {
+            out.extend_from_slice(&(context.len() as u16).to_be_bytes());
+            out.extend_from_slice(context);
+        }
+        if let Some(index) = self.ratchet_index {
+            out.extend_from_slice(&index.to_be_bytes());
+        }
+        if let Some(commitment) = self.commitment {
+            out.extend_from_slice(commitment);
+        }
+        if let Some(chunk_size) = self.chunk_size {
+            out.extend_from_slice(&chunk_size.to_be_bytes());
+        }
+        if let Some(sequence) = self.sequence {
+            out.extend_from_slice(&sequence.to_be_bytes());
+        }
+        if let Some(produced_at) = self.produced_at {
+            out.extend_from_slice(&produced_at.to_be_bytes());
+        }
+        out
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.flags |= FLAG_CHUNKED;
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    pub fn with_sequence(mut self, sequence: u64) -> Self {
+        self.flags |= FLAG_SEQUENCE;
+        self.sequence = Some(sequence);
+        self
+    }
+
+    pub fn with_produced_at(mut self, produced_at: u64) -> Self {
+        self.flags |= FLAG_PRODUCED_AT;
+        self.produced_at = Some(produced_at);
+        self
+    }
+
+    pub fn has(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+
+    // Parse a header in any readable version, returning it and its encoded
+    // length. None for a blob with no header, an unknown version, or only
+    // part of its header.
+    pub fn parse(blob: &'a [u8]) -> Option<(Self, usize)> {
+        let (&MAGIC, [version, ..]) = blob.split_first()? else {
+            return None;
+        };
+        let (_, parse) = PARSERS.iter().find(|(v, _)| v == version)?;
+        parse(blob)
+    }
+}
+
+type ParseFn = for<'a> fn(&'a [u8]) -> Option<(Header<'a>, usize)>;
+
+// ── Header parser for each readable format version ──
+// Every version authenticates its header as AAD, so only parsing differs
+// between them; what the fields mean to decrypt is carried by the flags.
+const PARSERS: &[(u8, ParseFn)] = &[(1, parse_v1), (2, parse_v2)];
+
+fn parse_v1(blob: &[u8]) -> Option<(Header<'_>, usize)> {
+    let fixed = blob.get(..FIXED_HEADER_LEN)?;
+    let mut header = Header::new(fixed[2]);
+    header.flags = fixed[3] as u16;
+    if header.flags & !KNOWN_FLAGS_V1 != 0 {
+        return None;
+    }
+    parse_fields(blob, header, FIXED_HEADER_LEN)
+}
+
+fn parse_v2(blob: &[u8]) -> Option<(Header<'_>, usize)> {
+    let fixed = blob.get(..FIXED_HEADER_LEN)?;
+    let mut header = Header::new(fixed[2]);
+    let mut pos = FIXED_HEADER_LEN;
+    header.flags = (fixed[3] & !FLAG_EXTENDED) as u16;
+    if fixed[3] & FLAG_EXTENDED != 0 {
+        header.flags |= (*blob.get(pos)? as u16) << 8;
+        pos += 1;
+    }
+    if header.flags & !KNOWN_FLAGS_V2 != 0 {
+        return None;
+    }
+    parse_fields(blob, header, pos)
+}
+
+// The optional fields after the flags, the same in every version
+fn parse_fields<'a>(
+    blob: &'a [u8],
+    mut header: Header<'a>,
+    mut pos: usize,
+) -> Option<(Header<'a>, usize)> {
+    if header.has(FLAG_CONTEXT) {
+        let len = u16::from_be_bytes(blob.get(pos..pos + 2)?.try_into().ok()?) as usize;
+        pos += 2;
+        header.context = Some(blob.get(pos..pos + len)?);
+        pos += len;
+    }
+    if header.has(FLAG_RATCHET) {
+        header.ratchet_index = Some(u64::from_be_bytes(blob.get(pos..pos + 8)?.try_into().ok()?));
+        pos += 8;
+    }
+    if header.has(FLAG_COMMIT) {
+        header.commitment = Some(blob.get(pos..pos + COMMITMENT_LEN)?);
+        pos += COMMITMENT_LEN;
+    }
+    if header.has(FLAG_CHUNKED) {
+        header.chunk_size = Some(u32::from_be_bytes(blob.get(pos..pos + 4)?.try_into().ok()?));
+        pos += 4;
+    }
+    if header.has(FLAG_SEQUENCE) {
+        header.sequence = Some(u64::from_be_bytes(blob.get(pos..pos + 8)?.try_into().ok()?));
+        pos += 8;
+    }
+    if header.has(FLAG_PRODUCED_AT) {
+        header.produced_at = Some(u64::from_be_bytes(blob.get(pos..pos + 8)?.try_into().ok()?));
+        pos += 8;
+    }
+    Some((header, pos))
+}
+
+// A blob split into its parts; `aad` is empty for headerless blobs
+pub struct Envelope<'a> {
+    pub header: Option<Header<'a>>,
+    pub aad: &'a [u8],
+    pub nonce: &'a [u8],
+    pub ciphertext: &'a [u8],
+}
+
+// ── Possible readings of a blob, most specific first ──
+// A legacy blob whose random nonce happens to start with MAGIC and a known
+// version also parses as v1, so callers fall back to the headerless reading
+// when the v1 one fails to authenticate.
+pub fn candidates(
+    blob: &[u8],
+    nonce_len: usize,
+    tag_len: usize,
+    headered: bool,
+) -> Vec<Envelope<'_>> {
+    let mut out = Vec::with_capacity(2);
+
+    let parsed = if headered { Header::parse(blob) } else { None };
+    if let Some((header, header_len)) = parsed {
+        if blob.len() >= header_len + nonce_len + tag_len {
+            let (aad, rest) = blob.split_at(header_len);
+            let (nonce, ciphertext) = rest.split_at(nonce_len);
+            out.push(Envelope { header: Some(header), aad, nonce, ciphertext });
+        }
+    }
+
+    if blob.len() >= nonce_len + tag_len {
+        let (nonce, ciphertext) = blob.split_at(nonce_len);
+        out.push(Envelope { header: None, aad: &[], nonce, ciphertext });
+    }
+
+    out
+}
+
+// ── Message + metadata framing ──
+
+pub fn frame(message: &[u8], metadata: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + message.len() + metadata.len());
+    out.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    out.extend_from_slice(message);
+    out.extend_from_slice(metadata);
+    out
+}
+
+pub fn unframe(plaintext: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = plaintext.split_first_chunk::<4>()?;
+    let len = u32::from_be_bytes(*len) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMITMENT: [u8; COMMITMENT_LEN] = [7; COMMITMENT_LEN];
+
+    fn full_header() -> Header<'static> {
+        let mut header = Header::new(2)
+            .with_context(b"ctx")
+            .with_ratchet(9)
+            .with_commitment(&COMMITMENT)
+            .with_chunk_size(4096)
+            .with_sequence(3)
+            .with_produced_at(1_700_000_000);
+        header.flags |= FLAG_FRAMED | FLAG_ATTACHMENT;
+        header
+    }
+
+    #[test]
+    fn every_field_round_trips() {
+        let encoded = full_header().encode();
+        let (header, len) = Header::parse(&encoded).unwrap();
+        assert_eq!(len, encoded.len());
+        assert_eq!(header.algorithm, 2);
+        assert_eq!(header.flags, KNOWN_FLAGS_V2);
+        assert_eq!(header.context, Some(&b"ctx"[..]));
+        assert_eq!(header.ratchet_index, Some(9));
+        assert_eq!(header.commitment, Some(&COMMITMENT[..]));
+        assert_eq!(header.chunk_size, Some(4096));
+        assert_eq!(header.sequence, Some(3));
+        assert_eq!(header.produced_at, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn fields_follow_the_fixed_header_in_flag_order() {
+        let encoded = Header::new(1).with_sequence(1).with_ratchet(2).encode();
+        let mut expected = vec![MAGIC, FORMAT_VERSION, 1, (FLAG_RATCHET | FLAG_SEQUENCE) as u8];
+        expected.extend_from_slice(&2u64.to_be_bytes());
+        expected.extend_from_slice(&1u64.to_be_bytes());
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn only_known_versions_parse() {
+        assert!(Header::parse(&[]).is_none());
+        assert!(Header::parse(&[MAGIC]).is_none());
+        assert!(Header::parse(&[0x00, FORMAT_VERSION, 1, 0]).is_none());
+        assert!(Header::parse(&[MAGIC, 0, 1, 0]).is_none());
+        assert!(Header::parse(&[MAGIC, FORMAT_VERSION + 1, 1, 0]).is_none());
+        assert_eq!(Header::parse(&[MAGIC, FORMAT_VERSION, 1, 0]).unwrap().1, 4);
+    }
+
+    #[test]
+    fn unknown_flags_do_not_parse() {
+        // v1 has no extension byte, so its top bit is simply unknown
+        assert!(Header::parse(&[MAGIC, 1, 1, FLAG_EXTENDED]).is_none());
+        assert!(Header::parse(&[MAGIC, 2, 1, FLAG_EXTENDED, 0x02]).is_none());
+        assert_eq!(Header::parse(&[MAGIC, 2, 1, FLAG_EXTENDED, 0x00]).unwrap().1, 5);
+        assert!(Header::parse(&[MAGIC, 2, 1, FLAG_EXTENDED]).is_none());
+    }
+
+    #[test]
+    fn produced_at_is_written_as_v2_with_an_extension_byte() {
+        let encoded = Header::new(1).with_produced_at(7).encode();
+        assert_eq!(encoded[..5], [MAGIC, 2, 1, FLAG_EXTENDED, 0x01]);
+        assert_eq!(encoded[5..], 7u64.to_be_bytes());
+    }
+
+    #[test]
+    fn a_cut_off_header_does_not_parse() {
+        let encoded = full_header().encode();
+        for len in 0..encoded.len() {
+            assert!(Header::parse(&encoded[..len]).is_none(), "parsed {} bytes", len);
+        }
+        // A context length running past the blob
+        let context = FLAG_CONTEXT as u8;
+        assert!(Header::parse(&[MAGIC, FORMAT_VERSION, 1, context, 0, 5, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn a_headered_blob_also_reads_as_legacy() {
+        let mut blob = Header::new(1).encode();
+        blob.extend_from_slice(&[0xAB; 12 + 16]);
+        let readings = candidates(&blob, 12, 16, true);
+        assert_eq!(readings.len(), 2);
+        assert!(readings[0].header.is_some());
+        assert_eq!(readings[0].aad, &blob[..4]);
+        assert_eq!(readings[0].nonce, &[0xAB; 12]);
+        assert!(readings[1].header.is_none());
+        assert!(readings[1].aad.is_empty());
+        assert_eq!(readings[1].nonce, &blob[..12]);
+    }
+
+    #[test]
+    fn candidates_skip_readings_too_short_for_a_tag() {
+        let mut blob = Header::new(1).encode();
+        blob.extend_from_slice(&[0xAB; 12 + 15]);
+        let readings = candidates(&blob, 12, 16, true);
+        assert_eq!(readings.len(), 1);
+        assert!(readings[0].header.is_none());
+        assert!(candidates(&[0; 27], 12, 16, true).is_empty());
+        // Formats without a header are only read raw
+        let readings = candidates(&blob, 12, 16, false);
+        assert!(readings.iter().all(|r| r.header.is_none()));
+    }
+
+    #[test]
+    fn framing_round_trips() {
+        let framed = frame(b"hello", br#"{"k":1}"#);
+        assert_eq!(&framed[..4], &5u32.to_be_bytes());
+        assert_eq!(unframe(&framed), Some((&b"hello"[..], &br#"{"k":1}"#[..])));
+        assert_eq!(unframe(&frame(b"", b"")), Some((&b""[..], &b""[..])));
+    }
+
+    #[test]
+    fn unframe_rejects_a_length_past_the_end() {
+        assert!(unframe(&[0, 0, 0]).is_none());
+        assert!(unframe(&[0, 0, 0, 6, b'h', b'e', b'l', b'l', b'o']).is_none());
+    }
+
+    #[test]
+    fn the_written_version_has_exactly_one_parser() {
+        let versions: Vec<u8> = PARSERS.iter().map(|&(version, _)| version).collect();
+        assert_eq!(versions.iter().filter(|&&v| v == FORMAT_VERSION).count(), 1);
+        for (i, version) in versions.iter().enumerate() {
+            assert!(!versions[i + 1..].contains(version), "version {} listed twice", version);
+        }
+        assert_eq!(full_header().encode()[1], FORMAT_VERSION);
+    }
+
+    #[test]
+    fn parse_dispatches_on_the_version_byte() {
+        let encoded = full_header().encode();
+        for &(version, parse) in PARSERS {
+            let mut blob = encoded.clone();
+            blob[1] = version;
+            assert_eq!(Header::parse(&blob).map(|(_, len)| len), parse(&blob).map(|(_, len)| len));
+        }
+    }
+}
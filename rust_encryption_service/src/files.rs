@@ -0,0 +1,686 @@
+use std::pin::Pin;
+
+use actix_multipart::{Field, Multipart};
+use actix_web::body::{BodyStream, MessageBody};
+use actix_web::error::{
+    ErrorBadRequest, ErrorConflict, ErrorInternalServerError, ErrorPayloadTooLarge,
+};
+use actix_web::http::header;
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+use aes_gcm::aead::OsRng;
+use futures_util::{stream, Stream, StreamExt};
+use rand::RngCore;
+use serde::Deserialize;
+
+use crate::audit::AuditAction;
+use crate::auth::Scope;
+use crate::chunked::{ChunkError, ChunkOpener, ChunkSealer, DEFAULT_CHUNK_SIZE, NONCE_PREFIX_LEN};
+use crate::crypto::{Algorithm, KeyId};
+use crate::envelope::{self, Header};
+use crate::ids;
+use crate::reporting;
+use crate::{
+    charge_key_bytes, charge_quota, client_id, get_cipher, record_use, resolve_algorithm, AppState,
+    ErrorResponse, KeyCacheError,
+    SecretVersion, TAG_LEN,
+};
+
+// ── Backpressure ──
+// The streaming endpoints are pull-driven: the response stream reads the
+// next body chunk only when actix asks it for more output, which happens
+// once the socket has taken the last. A client sending faster than chunks
+// are sealed, or reading slower, is throttled by TCP flow control, and a
+// request holds about one chunk in memory; there is no queue to fill.
+// /decrypt/range and best-effort recovery buffer their output, bounded by
+// MAX_DECRYPTED_BYTES and MAX_UPLOAD_BYTES.
+
+// Largest chunk size accepted from a file header, so a forged header cannot
+// make the opener buffer without bound
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+// Header flags a file may carry; the rest change how the key is derived,
+// which files do not support
+const FILE_FLAGS: u16 = envelope::FLAG_CHUNKED;
+
+fn bad_request(error: &str, code: &str) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .json(ErrorResponse {
+            error: error.into(),
+            code: code.into(),
+        })
+}
+
+fn upload_too_large(max: usize) -> HttpResponse {
+    HttpResponse::PayloadTooLarge()
+        .json(ErrorResponse {
+            error: format!("File exceeds {} bytes", max),
+            code: "file_too_large".into(),
+        })
+}
+
+// Content-Length, when the client sent one
+fn declared_len(req: &actix_web::HttpRequest) -> Option<usize> {
+    req.headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+async fn read_text_field(field: &mut Field) -> Option<String> {
+    let mut buf = Vec::new();
+    while let Some(Ok(bytes)) = field.next().await {
+        buf.extend_from_slice(&bytes);
+        if buf.len() > 64 {
+            return None;
+        }
+    }
+    String::from_utf8(buf).ok()
+}
+
+// ── POST /encrypt/file ──
+// multipart/form-data: `channel_id` (and optionally `org_id`) fields, then
+// a `file` part. The file is sealed chunk by chunk as it arrives and the
+// chunked envelope streams back as application/octet-stream, so neither
+// side ever holds the whole file.
+pub async fn encrypt_file(
+    req: actix_web::HttpRequest,
+    data: web::Data<AppState>,
+    mut form: Multipart,
+) -> HttpResponse {
+    if let Err(resp) = data.reject_in_maintenance() {
+        return resp;
+    }
+    let max = data.limits.max_upload_bytes;
+    if declared_len(&req).is_some_and(|len| len > max) {
+        return upload_too_large(max);
+    }
+
+    let mut channel_id = None;
+    let mut org_id = None;
+    let file = loop {
+        let mut field = match form.next().await {
+            Some(Ok(field)) => field,
+            Some(Err(e)) => {
+                log::error!("Malformed multipart upload: {}", e);
+                return bad_request("Malformed multipart body", "invalid_multipart");
+            }
+            None => return bad_request("Missing file part", "missing_file"),
+        };
+        match field.name() {
+            Some("channel_id") => {
+                channel_id = read_text_field(&mut field).await.and_then(|v| v.trim().parse().ok());
+                if channel_id.is_none() {
+                    return bad_request("channel_id must be an integer", "invalid_channel_id");
+                }
+            }
+            Some("org_id") => {
+                org_id = read_text_field(&mut field).await.and_then(|v| v.trim().parse().ok());
+                if org_id.is_none() {
+                    return bad_request("org_id must be an integer", "invalid_org_id");
+                }
+            }
+            Some("file") => break field,
+            _ => {}
+        }
+    };
+    let Some(channel_id) = channel_id else {
+        return bad_request("channel_id must precede the file part", "missing_channel_id");
+    };
+
+    let key_id = KeyId { org_id, channel_id, shared_with: None };
+    if let Err(resp) = data.authorize(&req, Scope::Encrypt, key_id) {
+        return resp;
+    }
+    // Claimed once the channel is known; the fields before the file are small
+    let permit = match data.crypto_permit(Some(key_id)).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+    let algorithm = match resolve_algorithm(&data, key_id, None, false) {
+        Ok(a) => a,
+        Err(resp) => return resp,
+    };
+    let cipher = match get_cipher(&data, SecretVersion::Current, algorithm, key_id) {
+        Ok(c) => c,
+        Err(e) => return e.response(),
+    };
+    let charge = match charge_quota(&data, key_id) {
+        Ok(charge) => charge,
+        Err(resp) => return resp,
+    };
+    let filename = file
+        .content_disposition()
+        .and_then(|cd| cd.get_filename())
+        .unwrap_or("file")
+        .replace(['"', '\\'], "_");
+
+    let header = Header::new(algorithm.id()).with_chunk_size(DEFAULT_CHUNK_SIZE).encode();
+    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+    OsRng.fill_bytes(&mut prefix);
+    let sealer = ChunkSealer::new(cipher, header, prefix, DEFAULT_CHUNK_SIZE);
+    let preamble = Bytes::from(sealer.preamble());
+
+    log::info!("Encrypting file upload for channel {}", key_id);
+    // The form rides along with the field: dropping it would end the field
+    // early. The permit is held until the last chunk is out, and the quota
+    // charge is only kept once that chunk is sealed.
+    let state = (form, file, sealer, 0usize, (permit, charge), data.clone());
+    let chunks = stream::unfold(Some(state), move |state| async move {
+        let (form, mut file, mut sealer, mut read, (permit, charge), data) = state?;
+        loop {
+            let bytes = match file.next().await {
+                Some(Ok(bytes)) => bytes,
+                Some(Err(e)) => return Some((Err(ErrorBadRequest(e.to_string())), None)),
+                None => {
+                    let last = sealer.finish().map(Bytes::from).map_err(|e| {
+                        log::error!("File encryption failed for channel {}: {}", key_id, e);
+                        reporting::capture_failure("encryption_failed", Some(key_id), "File encryption failed");
+                        ErrorInternalServerError(e)
+                    });
+                    if last.is_ok() {
+                        charge.keep();
+                    }
+                    return Some((last, None));
+                }
+            };
+            read += bytes.len();
+            if read > max {
+                log::warn!("Aborted file upload for channel {}: over {} bytes", key_id, max);
+                return Some((Err(ErrorPayloadTooLarge("file too large")), None));
+            }
+            if !charge_key_bytes(&data, key_id, bytes.len()) {
+                return Some((Err(ErrorConflict("channel key data limit reached")), None));
+            }
+            match sealer.push(&bytes) {
+                Ok(out) if out.is_empty() => continue,
+                Ok(out) => {
+                    let state = (form, file, sealer, read, (permit, charge), data);
+                    return Some((Ok(Bytes::from(out)), Some(state)));
+                }
+                Err(e) => {
+                    log::error!("File encryption failed for channel {}: {}", key_id, e);
+                    reporting::capture_failure("encryption_failed", Some(key_id), "File encryption failed");
+                    return Some((Err(ErrorInternalServerError(e)), None));
+                }
+            }
+        }
+    });
+
+    record_use(&data, key_id, AuditAction::Encrypt);
+    data.metrics.encrypt_total.with_label_values(&[algorithm.name()]).inc();
+    HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.enc\"", filename),
+        ))
+        .streaming(stream::once(async move { Ok(preamble) }).chain(chunks))
+}
+
+#[derive(Deserialize)]
+pub struct FileQuery {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    // Recover the intact leading chunks of a damaged file; see `recover`
+    #[serde(default)]
+    best_effort: bool,
+}
+
+// ── POST /decrypt/file?channel_id=… ──
+// Body: the raw chunked envelope from /encrypt/file. The first chunk is
+// opened before responding, so a wrong channel or key is a clean 400; a
+// later bad chunk aborts the stream, which the client sees as truncation.
+pub async fn decrypt_file(
+    req: actix_web::HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<FileQuery>,
+    mut body: web::Payload,
+) -> HttpResponse {
+    let key_id = KeyId { org_id: query.org_id, channel_id: query.channel_id, shared_with: None };
+    if let Err(resp) = data.authorize(&req, Scope::Decrypt, key_id) {
+        return resp;
+    }
+    if let Err(resp) = data.check_decrypt_token(&req, key_id) {
+        return resp;
+    }
+    let client = client_id(&data, &req);
+    if let Err(resp) = data.check_lockout(key_id, &client) {
+        return resp;
+    }
+    let max = data.limits.max_upload_bytes;
+    if declared_len(&req).is_some_and(|len| len > max + envelope::MAX_HEADER_LEN) {
+        return upload_too_large(max);
+    }
+    let permit = match data.crypto_permit(Some(key_id)).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let (mut opener, mut pending) = match read_preamble(&data, key_id, &mut body).await {
+        Ok(read) => read,
+        Err(resp) => return resp,
+    };
+    if query.best_effort {
+        return recover(&data, key_id, &client, opener, pending, body).await;
+    }
+
+    // Open the first chunk (or the whole file, if it is that small) up front
+    let mut written = 0usize;
+    let first = loop {
+        match opener.push(&std::mem::take(&mut pending)) {
+            Ok(out) if out.is_empty() => {}
+            Ok(out) => break out,
+            Err(e) => {
+                log::error!("File decryption failed for channel {}: {}", key_id, e);
+                data.record_decrypt(key_id, &client, false);
+                return bad_request("Decryption failed", "decryption_failed");
+            }
+        }
+        match body.next().await {
+            Some(Ok(bytes)) => pending = bytes.to_vec(),
+            Some(Err(e)) => {
+                log::error!("File body read failed: {}", e);
+                return bad_request("Failed to read body", "invalid_body");
+            }
+            None => {
+                return match opener.finish() {
+                    Ok((secret_version, out)) if out.len() <= max => {
+                        data.record_decrypt(key_id, &client, true);
+                        record_use(&data, key_id, AuditAction::Decrypt);
+                        log::info!("Decrypted file for channel {}", key_id);
+                        file_response(secret_version).body(out)
+                    }
+                    Ok(_) => upload_too_large(max),
+                    Err(e) => {
+                        log::error!("File decryption failed for channel {}: {}", key_id, e);
+                        data.record_decrypt(key_id, &client, false);
+                        bad_request("Decryption failed", "decryption_failed")
+                    }
+                };
+            }
+        }
+    };
+    written += first.len();
+    // A later chunk failing is damage, not a guessed key: the first opened
+    data.record_decrypt(key_id, &client, true);
+    record_use(&data, key_id, AuditAction::Decrypt);
+    let secret_version = opener.chosen().unwrap_or(SecretVersion::Current);
+
+    log::info!("Decrypting file for channel {}", key_id);
+    let rest = stream::unfold(Some((body, opener, written, permit)), move |state| async move {
+        let (mut body, mut opener, mut written, permit) = state?;
+        loop {
+            let bytes = match body.next().await {
+                Some(Ok(bytes)) => bytes,
+                Some(Err(e)) => return Some((Err(ErrorBadRequest(e.to_string())), None)),
+                None => {
+                    let last = opener.finish().map(|(_, last)| Bytes::from(last)).map_err(|e| {
+                        log::error!("File decryption failed for channel {}: {}", key_id, e);
+                        ErrorBadRequest(e)
+                    });
+                    return Some((last, None));
+                }
+            };
+            match opener.push(&bytes) {
+                Ok(out) if out.is_empty() => continue,
+                Ok(out) => {
+                    written += out.len();
+                    if written > max {
+                        return Some((Err(ErrorPayloadTooLarge("file too large")), None));
+                    }
+                    return Some((Ok(Bytes::from(out)), Some((body, opener, written, permit))));
+                }
+                Err(e) => {
+                    log::error!("File decryption failed for channel {}: {}", key_id, e);
+                    return Some((Err(ErrorBadRequest(e)), None));
+                }
+            }
+        }
+    });
+
+    file_response(secret_version)
+        .streaming(stream::once(async move { Ok(Bytes::from(first)) }).chain(rest))
+}
+
+// ── POST /decrypt/stream?channel_id=… ──
+// /decrypt/file for newline-delimited text: the same chunk-by-chunk open,
+// served as text/plain with each piece cut just after a line break, so a
+// client can feed every piece straight to a line reader. A line longer
+// than MAX_LINE_CARRY is sent in parts rather than buffered whole.
+pub async fn decrypt_stream(
+    req: actix_web::HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<FileQuery>,
+    body: web::Payload,
+) -> HttpResponse {
+    let resp = decrypt_file(req, data, query, body).await;
+    if !resp.status().is_success() {
+        return resp;
+    }
+    let (mut resp, mut body) = resp.into_parts();
+    let pieces = stream::poll_fn(move |cx| Pin::new(&mut body).poll_next(cx));
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    resp.set_body(BodyStream::new(whole_lines(pieces))).map_into_boxed_body()
+}
+
+const MAX_LINE_CARRY: usize = 1024 * 1024;
+
+// Regroups a byte stream so each item ends just after a '\n'; the bytes
+// past the last one wait for the next item, or go out at the end
+fn whole_lines<S, E>(input: S) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    stream::unfold(Some((input, Vec::new())), |state| async move {
+        let (mut input, mut carry) = state?;
+        loop {
+            match input.next().await {
+                Some(Ok(bytes)) => {
+                    carry.extend_from_slice(&bytes);
+                    let cut = match carry.iter().rposition(|&b| b == b'\n') {
+                        Some(i) => i + 1,
+                        None if carry.len() >= MAX_LINE_CARRY => carry.len(),
+                        None => continue,
+                    };
+                    let rest = carry.split_off(cut);
+                    let out = std::mem::replace(&mut carry, rest);
+                    return Some((Ok(Bytes::from(out)), Some((input, carry))));
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None if carry.is_empty() => return None,
+                None => return Some((Ok(Bytes::from(carry)), None)),
+            }
+        }
+    })
+}
+
+// ── Read a chunked envelope's header and nonce prefix off the body ──
+// Buffers just enough of it; returns an opener trying each decrypt secret,
+// and whatever of the chunks arrived along with the preamble.
+async fn read_preamble(
+    data: &AppState,
+    key_id: KeyId,
+    body: &mut web::Payload,
+) -> Result<(ChunkOpener<SecretVersion>, Vec<u8>), HttpResponse> {
+    let mut buf = Vec::new();
+    let (header, header_len) = loop {
+        if let Some((header, len)) = Header::parse(&buf) {
+            if buf.len() >= len + NONCE_PREFIX_LEN {
+                break (header, len);
+            }
+        } else if buf.len() >= envelope::MAX_HEADER_LEN {
+            return Err(bad_request("Not a chunked file envelope", "invalid_file"));
+        }
+        match body.next().await {
+            Some(Ok(bytes)) => buf.extend_from_slice(&bytes),
+            Some(Err(e)) => {
+                log::error!("File body read failed: {}", e);
+                return Err(bad_request("Failed to read body", "invalid_body"));
+            }
+            None => return Err(bad_request("Not a chunked file envelope", "invalid_file")),
+        }
+    };
+
+    let (Some(chunk_size), Some(algorithm)) = (header.chunk_size, Algorithm::from_id(header.algorithm))
+    else {
+        return Err(bad_request("Not a chunked file envelope", "invalid_file"));
+    };
+    if header.flags & !FILE_FLAGS != 0 || chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(bad_request("Unsupported file envelope options", "invalid_file"));
+    }
+
+    let candidates = data
+        .decrypt_versions()
+        .into_iter()
+        .map(|version| Ok((version, get_cipher(data, version, algorithm, key_id)?)))
+        .collect::<Result<Vec<_>, KeyCacheError>>()
+        .map_err(KeyCacheError::response)?;
+    let prefix: [u8; NONCE_PREFIX_LEN] =
+        buf[header_len..header_len + NONCE_PREFIX_LEN].try_into().unwrap();
+    let opener = ChunkOpener::new(candidates, buf[..header_len].to_vec(), prefix, chunk_size);
+    Ok((opener, buf.split_off(header_len + NONCE_PREFIX_LEN)))
+}
+
+#[derive(Deserialize)]
+pub struct RangeQuery {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    // Plaintext byte offset and length of the slice wanted
+    start: u64,
+    length: u64,
+}
+
+// ── POST /decrypt/range?channel_id=…&start=…&length=… ──
+// Body: a chunked envelope from /encrypt/file. Chunks are a fixed size with
+// nonces derived from their index, so the ones before `start` are skipped
+// unread and only those overlapping the range are opened; the body is not
+// read past the range. A range running past the end is cut short, and one
+// starting past it is a 416.
+pub async fn decrypt_range(
+    req: actix_web::HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<RangeQuery>,
+    mut body: web::Payload,
+) -> HttpResponse {
+    let key_id = KeyId { org_id: query.org_id, channel_id: query.channel_id, shared_with: None };
+    if let Err(resp) = data.authorize(&req, Scope::Decrypt, key_id) {
+        return resp;
+    }
+    if let Err(resp) = data.check_decrypt_token(&req, key_id) {
+        return resp;
+    }
+    let client = client_id(&data, &req);
+    if let Err(resp) = data.check_lockout(key_id, &client) {
+        return resp;
+    }
+    let max = data.limits.max_decrypted_bytes;
+    if query.length == 0 {
+        return bad_request("length must be at least 1", "invalid_range");
+    }
+    if query.length > max as u64 {
+        return HttpResponse::PayloadTooLarge()
+            .json(ErrorResponse {
+                error: format!("Range exceeds {} bytes", max),
+                code: "range_too_large".into(),
+            });
+    }
+    let _permit = match data.crypto_permit(Some(key_id)).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let (mut opener, mut pending) = match read_preamble(&data, key_id, &mut body).await {
+        Ok(read) => read,
+        Err(resp) => return resp,
+    };
+    let sealed_chunk = opener.sealed_chunk_len() as u64;
+    let chunk_size = sealed_chunk - TAG_LEN as u64;
+    let Ok(first) = u32::try_from(query.start / chunk_size) else {
+        return range_not_satisfiable();
+    };
+    opener.seek(first);
+    let mut skip = first as u64 * sealed_chunk;
+    // Plaintext from the start of chunk `first`; enough once it reaches `want`
+    let offset = (query.start - first as u64 * chunk_size) as usize;
+    let want = offset + query.length as usize;
+    let decryption_failed = |e: ChunkError| {
+        log::error!("Range decryption failed for channel {}: {}", key_id, e);
+        data.record_decrypt(key_id, &client, false);
+        bad_request("Decryption failed", "decryption_failed")
+    };
+
+    let mut out = Vec::new();
+    let secret_version = loop {
+        let dropped = skip.min(pending.len() as u64);
+        pending.drain(..dropped as usize);
+        skip -= dropped;
+        if let Err(e) = opener.push_into(&std::mem::take(&mut pending), &mut out) {
+            return decryption_failed(e);
+        }
+        if out.len() >= want {
+            break opener.chosen().unwrap_or(SecretVersion::Current);
+        }
+        match body.next().await {
+            Some(Ok(bytes)) => pending = bytes.to_vec(),
+            Some(Err(e)) => {
+                log::error!("File body read failed: {}", e);
+                return bad_request("Failed to read body", "invalid_body");
+            }
+            None if skip > 0 => return range_not_satisfiable(),
+            None => match opener.finish() {
+                Ok((secret_version, last)) => {
+                    out.extend(last);
+                    break secret_version;
+                }
+                Err(e) => return decryption_failed(e),
+            },
+        }
+    };
+    data.record_decrypt(key_id, &client, true);
+    if offset >= out.len() {
+        return range_not_satisfiable();
+    }
+
+    record_use(&data, key_id, AuditAction::Decrypt);
+    log::info!("Decrypted a {}-byte range of file for channel {}", query.length, key_id);
+    file_response(secret_version).body(out[offset..want.min(out.len())].to_vec())
+}
+
+fn range_not_satisfiable() -> HttpResponse {
+    HttpResponse::RangeNotSatisfiable()
+        .json(ErrorResponse {
+            error: "Range starts past the end of the file".into(),
+            code: "range_not_satisfiable".into(),
+        })
+}
+
+// ── Best-effort decrypt of a damaged file ──
+// Buffers the body and keeps every leading chunk whose tag verifies,
+// stopping at the first that does not. X-Truncated says whether the file
+// was whole. A file whose first chunk fails is still a 400: that is
+// indistinguishable from the wrong key.
+async fn recover(
+    data: &AppState,
+    key_id: KeyId,
+    client: &str,
+    mut opener: ChunkOpener<SecretVersion>,
+    pending: Vec<u8>,
+    mut body: web::Payload,
+) -> HttpResponse {
+    let max = data.limits.max_upload_bytes;
+    let mut out = Vec::new();
+    let mut next = Some(pending);
+    let truncated = loop {
+        let Some(bytes) = next.take() else {
+            break false;
+        };
+        if let Err(e) = opener.push_into(&bytes, &mut out) {
+            log::warn!("Recovery for channel {} stopped: {}", key_id, e);
+            break true;
+        }
+        if out.len() > max {
+            return upload_too_large(max);
+        }
+        match body.next().await {
+            Some(Ok(bytes)) => next = Some(bytes.to_vec()),
+            Some(Err(e)) => {
+                log::error!("File body read failed: {}", e);
+                return bad_request("Failed to read body", "invalid_body");
+            }
+            None => {}
+        }
+    };
+
+    let (secret_version, truncated) = if truncated {
+        (opener.chosen(), true)
+    } else {
+        let (secret_version, last, complete) = opener.salvage();
+        out.extend(last);
+        (secret_version, !complete)
+    };
+    let Some(secret_version) = secret_version else {
+        log::error!("File decryption failed for channel {}: no chunk authenticated", key_id);
+        data.record_decrypt(key_id, client, false);
+        return bad_request("Decryption failed", "decryption_failed");
+    };
+    data.record_decrypt(key_id, client, true);
+    if out.len() > max {
+        return upload_too_large(max);
+    }
+
+    record_use(data, key_id, AuditAction::Decrypt);
+    log::info!(
+        "Recovered {} bytes of file for channel {}{}",
+        out.len(), key_id, if truncated { " (truncated)" } else { "" }
+    );
+    file_response(secret_version)
+        .insert_header(("X-Truncated", if truncated { "true" } else { "false" }))
+        .body(out)
+}
+
+fn file_response(secret_version: SecretVersion) -> actix_web::HttpResponseBuilder {
+    let mut builder = HttpResponse::Ok();
+    builder.content_type("application/octet-stream").insert_header((
+        "X-Secret-Version",
+        match secret_version {
+            SecretVersion::Current => "current",
+            SecretVersion::Previous => "previous",
+        },
+    ));
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn regroup(pieces: &[&str]) -> Vec<String> {
+        let input = stream::iter(pieces.iter().map(|p| Ok::<_, ()>(Bytes::from(p.to_string()))));
+        whole_lines(input)
+            .map(|item| String::from_utf8(item.unwrap().to_vec()).unwrap())
+            .collect()
+            .await
+    }
+
+    #[actix_web::test]
+    async fn pieces_end_after_a_line_break() {
+        let out = regroup(&["one\ntw", "o\nthr", "ee", "\nfour"]).await;
+        assert_eq!(out, ["one\n", "two\n", "three\n", "four"]);
+    }
+
+    #[actix_web::test]
+    async fn several_lines_in_one_piece_stay_together() {
+        let out = regroup(&["a\nb\nc", "\n"]).await;
+        assert_eq!(out, ["a\nb\n", "c\n"]);
+    }
+
+    #[actix_web::test]
+    async fn empty_input_gives_nothing() {
+        assert!(regroup(&[]).await.is_empty());
+        assert!(regroup(&["", ""]).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn an_overlong_line_is_sent_in_parts() {
+        let long = "x".repeat(MAX_LINE_CARRY);
+        let out = regroup(&[&long, "y\n"]).await;
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].len(), MAX_LINE_CARRY);
+        assert_eq!(out[1], "y\n");
+    }
+
+    #[actix_web::test]
+    async fn errors_pass_through() {
+        let input = stream::iter([Ok(Bytes::from_static(b"a\nb")), Err("broken")]);
+        let out: Vec<_> = whole_lines(input).collect().await;
+        assert_eq!(out, [Ok(Bytes::from_static(b"a\n")), Err("broken")]);
+    }
+}
@@ -0,0 +1,108 @@
+use serde::de::{self, Deserializer, Unexpected, Visitor};
+use serde::Deserialize;
+
+// ── Channel and org ids from JSON, as numbers or decimal strings ──
+// JavaScript numbers lose precision above 2^53, silently turning a large id
+// into a neighbouring one and so a different key. Clients that can't hold
+// an i64 exactly should send ids as strings: `"channel_id": "9007199254740993"`.
+pub struct Id(pub i64);
+
+struct IdVisitor;
+
+impl Visitor<'_> for IdVisitor {
+    type Value = Id;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("an integer id, as a number or a decimal string")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Id, E> {
+        Ok(Id(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Id, E> {
+        i64::try_from(v).map(Id).map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Id, E> {
+        v.parse().map(Id).map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(IdVisitor)
+    }
+}
+
+// ── Field helpers: `#[serde(deserialize_with = "ids::id")]` and friends ──
+pub fn id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    Id::deserialize(deserializer).map(|Id(v)| v)
+}
+
+pub fn optional<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    Option::<Id>::deserialize(deserializer).map(|id| id.map(|Id(v)| v))
+}
+
+pub fn list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<i64>, D::Error> {
+    Vec::<Id>::deserialize(deserializer).map(|ids| ids.into_iter().map(|Id(v)| v).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Body {
+        #[serde(deserialize_with = "id")]
+        channel_id: i64,
+        #[serde(default, deserialize_with = "optional")]
+        org_id: Option<i64>,
+        #[serde(default, deserialize_with = "list")]
+        channel_ids: Vec<i64>,
+    }
+
+    fn body(value: serde_json::Value) -> Result<Body, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+
+    #[test]
+    fn ids_read_as_numbers_or_strings() {
+        let parsed = body(json!({ "channel_id": 7, "org_id": "-3", "channel_ids": [1, "2"] }));
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.channel_id, 7);
+        assert_eq!(parsed.org_id, Some(-3));
+        assert_eq!(parsed.channel_ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn ids_past_two_to_the_53_stay_exact_as_strings() {
+        let parsed = body(json!({ "channel_id": "9007199254740993" })).unwrap();
+        assert_eq!(parsed.channel_id, 9_007_199_254_740_993);
+        let max = body(json!({ "channel_id": i64::MAX.to_string() })).unwrap();
+        assert_eq!(max.channel_id, i64::MAX);
+    }
+
+    #[test]
+    fn optional_ids_may_be_null_or_absent() {
+        assert_eq!(body(json!({ "channel_id": 1, "org_id": null })).unwrap().org_id, None);
+        assert_eq!(body(json!({ "channel_id": 1 })).unwrap().org_id, None);
+    }
+
+    #[test]
+    fn anything_but_an_integer_id_is_refused() {
+        for bad in [
+            json!("twelve"),
+            json!(""),
+            json!(" 1"),
+            json!(1.5),
+            json!(u64::MAX),
+            json!("9223372036854775808"),
+            json!(true),
+            json!(null),
+        ] {
+            assert!(body(json!({ "channel_id": bad })).is_err(), "accepted {}", bad);
+        }
+    }
+}
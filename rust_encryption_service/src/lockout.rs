@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::crypto::KeyId;
+
+// ── Lockout after repeated failed decrypts ──
+// Off unless MAX_DECRYPT_FAILURES is set. Failures are counted per channel
+// and client; reaching the limit within DECRYPT_FAILURE_WINDOW_SECS locks
+// that pair out for DECRYPT_LOCKOUT_SECS. A successful decrypt clears the
+// count. Only authentication failures count, not malformed input.
+pub struct DecryptLockout {
+    pub max_failures: u32,
+    pub window_secs: u64,
+    pub lockout_secs: u64,
+    entries: Mutex<HashMap<(KeyId, String), Failures>>,
+}
+
+#[derive(Default)]
+struct Failures {
+    window_start: u64,
+    count: u32,
+    locked_until: u64,
+}
+
+// Past this many tracked pairs, stale ones are dropped on the next failure
+const PRUNE_AT: usize = 10_000;
+
+fn env(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+impl DecryptLockout {
+    pub fn from_env() -> Option<Self> {
+        let max_failures = std::env::var("MAX_DECRYPT_FAILURES").ok()?.parse().ok()?;
+        Some(DecryptLockout::new(
+            max_failures,
+            env("DECRYPT_FAILURE_WINDOW_SECS", 60),
+            env("DECRYPT_LOCKOUT_SECS", 300),
+        ))
+    }
+
+    pub fn new(max_failures: u32, window_secs: u64, lockout_secs: u64) -> Self {
+        DecryptLockout {
+            max_failures,
+            window_secs,
+            lockout_secs,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // ── Unix time the pair is locked until, if it is locked at `now` ──
+    pub fn locked_until(&self, key_id: KeyId, client: &str, now: u64) -> Option<u64> {
+        let entries = self.entries.lock().unwrap();
+        let until = entries.get(&(key_id, client.to_owned()))?.locked_until;
+        (until > now).then_some(until)
+    }
+
+    // ── Count one failure; returns the lock's end if this one set it ──
+    pub fn record_failure(&self, key_id: KeyId, client: &str, now: u64) -> Option<u64> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= PRUNE_AT {
+            entries.retain(|_, f| {
+                f.locked_until > now || now.saturating_sub(f.window_start) < self.window_secs
+            });
+        }
+        let failures = entries.entry((key_id, client.to_owned())).or_default();
+        if now.saturating_sub(failures.window_start) >= self.window_secs {
+            failures.window_start = now;
+            failures.count = 0;
+        }
+        failures.count += 1;
+        if failures.count < self.max_failures {
+            return None;
+        }
+        failures.count = 0;
+        failures.locked_until = now + self.lockout_secs;
+        Some(failures.locked_until)
+    }
+
+    pub fn record_success(&self, key_id: KeyId, client: &str) {
+        self.entries.lock().unwrap().remove(&(key_id, client.to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: &str = "192.0.2.1";
+
+    #[test]
+    fn locks_at_the_limit_and_unlocks_after() {
+        let lockout = DecryptLockout::new(3, 60, 300);
+        let key_id = KeyId::channel(1);
+        assert_eq!(lockout.record_failure(key_id, CLIENT, 100), None);
+        assert_eq!(lockout.record_failure(key_id, CLIENT, 101), None);
+        assert_eq!(lockout.record_failure(key_id, CLIENT, 102), Some(402));
+        assert_eq!(lockout.locked_until(key_id, CLIENT, 401), Some(402));
+        assert_eq!(lockout.locked_until(key_id, CLIENT, 402), None);
+    }
+
+    #[test]
+    fn failures_outside_the_window_start_over() {
+        let lockout = DecryptLockout::new(2, 60, 300);
+        let key_id = KeyId::channel(1);
+        assert_eq!(lockout.record_failure(key_id, CLIENT, 100), None);
+        assert_eq!(lockout.record_failure(key_id, CLIENT, 160), None);
+        assert_eq!(lockout.record_failure(key_id, CLIENT, 161), Some(461));
+    }
+
+    #[test]
+    fn success_clears_the_count() {
+        let lockout = DecryptLockout::new(2, 60, 300);
+        let key_id = KeyId::channel(1);
+        lockout.record_failure(key_id, CLIENT, 100);
+        lockout.record_success(key_id, CLIENT);
+        assert_eq!(lockout.record_failure(key_id, CLIENT, 101), None);
+    }
+
+    #[test]
+    fn pairs_are_counted_apart() {
+        let lockout = DecryptLockout::new(1, 60, 300);
+        assert!(lockout.record_failure(KeyId::channel(1), CLIENT, 100).is_some());
+        assert_eq!(lockout.locked_until(KeyId::channel(2), CLIENT, 100), None);
+        assert_eq!(lockout.locked_until(KeyId::channel(1), "192.0.2.2", 100), None);
+    }
+}
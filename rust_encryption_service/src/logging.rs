@@ -0,0 +1,88 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::str::FromStr;
+use std::sync::{OnceLock, RwLock};
+
+// ── env_logger behind a swappable filter ──
+// env_logger fixes its filter when built, so changing verbosity at runtime
+// means building a new logger from the new spec and swapping it in.
+struct ReloadableLogger {
+    inner: RwLock<env_logger::Logger>,
+}
+
+static LOGGER: OnceLock<ReloadableLogger> = OnceLock::new();
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.read().unwrap().log(record)
+    }
+
+    fn flush(&self) {
+        self.inner.read().unwrap().flush()
+    }
+}
+
+// ── Install the logger, starting from RUST_LOG ──
+pub fn init() {
+    let logger = env_logger::Builder::from_default_env().build();
+    let max_level = logger.filter();
+    let logger = LOGGER.get_or_init(|| ReloadableLogger { inner: RwLock::new(logger) });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+// ── Replace the active filter with a RUST_LOG-style spec ──
+// Accepts `debug` or per-module directives such as
+// `rust_encryption_service=debug,actix_web=warn`. Returns the new max level.
+pub fn set_filter(spec: &str) -> Result<LevelFilter, String> {
+    validate(spec)?;
+    let Some(active) = LOGGER.get() else {
+        return Err("Logger is not installed".into());
+    };
+
+    let logger = env_logger::Builder::new().parse_filters(spec).build();
+    let max_level = logger.filter();
+    *active.inner.write().unwrap() = logger;
+    log::set_max_level(max_level);
+    Ok(max_level)
+}
+
+// env_logger only warns on stderr about a bad spec, so check it up front
+fn validate(spec: &str) -> Result<(), String> {
+    if spec.trim().is_empty() {
+        return Err("Log filter is empty".into());
+    }
+    // A bare directive is either a global level or a module at every level
+    for directive in spec.split(',').map(str::trim) {
+        let Some((_, level)) = directive.split_once('=') else {
+            continue;
+        };
+        if LevelFilter::from_str(level).is_err() {
+            return Err(format!("Invalid log level in directive {:?}", directive));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_and_module_directives_are_accepted() {
+        for spec in ["debug", "rust_encryption_service=debug,actix_web=warn", "actix_web, info"] {
+            assert!(validate(spec).is_ok(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn bad_levels_and_empty_specs_are_refused() {
+        assert_eq!(validate("  ").unwrap_err(), "Log filter is empty");
+        let err = validate("info,actix_web=loud").unwrap_err();
+        assert_eq!(err, "Invalid log level in directive \"actix_web=loud\"");
+    }
+}
@@ -1,186 +1,4656 @@
+// Handlers bail out early with a ready-made HttpResponse as the error value
+#![allow(clippy::result_large_err)]
+
 use actix_cors::Cors;
-use actix_web::{web, App, HttpResponse, HttpServer, middleware};
-use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng},
-    Aes256Gcm, Nonce,
+use actix_web::body::MessageBody;
+use actix_web::error::{
+    ErrorBadRequest, ErrorInternalServerError, ErrorPayloadTooLarge, InternalError,
+    JsonPayloadError, QueryPayloadError,
+};
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::{
+    guard, middleware, web, App, FromRequest, Handler, HttpRequest, HttpResponse, HttpServer, Resource,
+    Responder,
 };
-use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use base64::engine::general_purpose::{
+    GeneralPurpose, GeneralPurposeConfig, STANDARD as BASE64, STANDARD_NO_PAD as BASE64_NO_PAD,
+};
+use base64::engine::DecodePaddingMode;
+use base64::{alphabet, Engine};
+use futures_util::{stream, StreamExt};
+use crypto_secretbox::XSalsa20Poly1305;
 use rand::RngCore;
+use rayon::prelude::*;
+use ring::signature::KeyPair;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncBufReadExt;
+use tokio::sync::Semaphore;
+use unicode_normalization::UnicodeNormalization;
+use zeroize::Zeroizing;
+
+mod admission;
+mod audit;
+mod auth;
+mod banner;
+mod chunked;
+mod clock;
+mod compression;
+mod crypto;
+mod envelope;
+mod files;
+mod ids;
+mod lockout;
+mod logging;
+mod metrics;
+mod nonce;
+mod nonce_guard;
+mod proxy;
+mod ratchet;
+mod response_cache;
+mod reporting;
+mod search;
+mod secrets;
+mod selftest;
+mod shutdown;
+mod signing;
+mod slo;
+#[cfg(test)]
+mod tests;
+mod text;
+mod tls;
+mod tokens;
+
+use admission::{ChannelShares, CryptoPermit};
+use audit::{AuditAction, AuditEntry, AuditSink};
+use auth::Scope;
+use crypto::{
+    benchmark_algorithms, derive_context_key, derive_index_key, derive_key, derive_key_len,
+    derive_signing_key, derive_token_key, export_wrapping_key, key_commitment, key_fingerprint,
+    verify_ed25519, Algorithm, ChannelCipher, KeyId,
+};
+use clock::{Clock, SystemClock};
+use envelope::Header;
+use lockout::DecryptLockout;
+use metrics::Metrics;
+use nonce::{NonceStrategy, RandomNonce};
+use nonce_guard::NonceGuard;
+use proxy::TrustedProxies;
+use shutdown::{Phase, ShutdownDiagnostics};
+use slo::SloMonitor;
+use ratchet::Ratchet;
+use response_cache::{DecryptCache, ResponseCache};
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+// Channel keys are 256-bit for every algorithm
+const KEY_LEN: usize = 32;
+const BIND_ADDR: &str = "127.0.0.1:8001";
+const MAX_CANARY_SAMPLES: usize = 100;
+// Channels per /keys/fingerprints request
+const MAX_FINGERPRINT_CHANNELS: usize = 1000;
+// Channels per /keys/preload request; each one costs a key derivation
+const MAX_PRELOAD_CHANNELS: usize = 1000;
+// Decrypt token lifetimes: the default, and the longest /tokens/issue grants
+const DEFAULT_TOKEN_TTL_SECS: u64 = 5 * 60;
+const MAX_TOKEN_TTL_SECS: u64 = 24 * 60 * 60;
+
+// ── Size limits, configurable via env ──
+struct Limits {
+    // Ceiling on the plaintext a single decrypt may produce
+    max_decrypted_bytes: usize,
+    // Items handled per /jobs/reencrypt call
+    reencrypt_batch_size: usize,
+    // Ceiling on a file streamed through /encrypt/file or /decrypt/file
+    max_upload_bytes: usize,
+    // Encrypts a channel may make per UTC day; channels can override it
+    daily_encrypt_quota: Option<u64>,
+    // Plaintext bytes one channel key may seal, safety margin already taken off
+    key_byte_limit: u64,
+    // Messages and blobs at least this long are sealed or opened on the
+    // blocking pool rather than the async worker; see `offload`
+    offload_threshold_bytes: usize,
+    // Ceiling on the associated data one seal authenticates: the header,
+    // context included, plus any attachment hash. Unset leaves only the
+    // request body limit.
+    max_aad_bytes: Option<usize>,
+}
+
+impl Limits {
+    fn from_env() -> Self {
+        let max_decrypted_bytes = std::env::var("MAX_DECRYPTED_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1024 * 1024);
+        let reencrypt_batch_size = std::env::var("REENCRYPT_BATCH_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(500);
+        let max_upload_bytes = std::env::var("MAX_UPLOAD_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(100 * 1024 * 1024);
+
+        let daily_encrypt_quota = std::env::var("DAILY_ENCRYPT_QUOTA")
+            .ok()
+            .and_then(|v| v.parse().ok());
+
+        // AES-GCM's bound on data under one key is 64 GiB; stop short of it by
+        // KEY_BYTE_LIMIT_MARGIN_PERCENT so tracking slop never crosses it
+        let key_byte_limit: u64 = std::env::var("KEY_BYTE_LIMIT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(64 << 30);
+        let margin: u64 = std::env::var("KEY_BYTE_LIMIT_MARGIN_PERCENT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10)
+            .min(100);
+        let key_byte_limit = (key_byte_limit as u128 * (100 - margin) as u128 / 100) as u64;
+
+        let offload_threshold_bytes = std::env::var("OFFLOAD_THRESHOLD_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(64 * 1024);
+
+        let max_aad_bytes = std::env::var("MAX_AAD_BYTES").ok().and_then(|v| v.parse().ok());
+
+        Limits {
+            max_decrypted_bytes,
+            reencrypt_batch_size,
+            max_upload_bytes,
+            daily_encrypt_quota,
+            key_byte_limit,
+            offload_threshold_bytes,
+            max_aad_bytes,
+        }
+    }
+}
+
+// ── Which master secret a key was derived from ──
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+enum SecretVersion {
+    // MASTER_SECRET: used for all new encryptions
+    Current,
+    // MASTER_SECRET_PREVIOUS: accepted for decrypt only while rolling the secret
+    Previous,
+}
+
+// ── Where a channel key comes from ──
+#[derive(Clone, Copy, PartialEq)]
+enum KeySource<'a> {
+    // Derived from a master secret, and cached
+    Secret(SecretVersion),
+    // Derived from a master secret for one request, leaving the cache and its
+    // metrics untouched; for read-only checks such as the master canary
+    Uncached(SecretVersion),
+    // Supplied by the caller for one request; never cached
+    Raw(&'a [u8]),
+}
+
+impl KeySource<'_> {
+    // The master secret the key comes from; None for a caller's raw key
+    fn version(self) -> Option<SecretVersion> {
+        match self {
+            KeySource::Secret(version) | KeySource::Uncached(version) => Some(version),
+            KeySource::Raw(_) => None,
+        }
+    }
+}
+
+// A cipher slot that is filled exactly once, however many requests race on it
+type CipherCell = Arc<OnceLock<ChannelCipher>>;
+
+// Every input to a cached key's derivation: the secret (by version, which
+// with the tenant in KeyId names exactly one secret, fixed for the process'
+// life), the algorithm (for its key length) and the KeyId. A new derivation
+// input must join this key, or two derivations would share one entry.
+type CipherCacheKey = (SecretVersion, Algorithm, KeyId);
+type CipherMap = HashMap<CipherCacheKey, CachedCipher>;
+
+// ── A cache slot and when it was created, for TTL expiry ──
+struct CachedCipher {
+    cell: CipherCell,
+    created: Instant,
+    // Last lookup, for LRU eviction at MAX_CACHED_KEYS
+    used: Instant,
+}
+
+impl CachedCipher {
+    fn empty(now: Instant) -> Self {
+        CachedCipher { cell: CipherCell::default(), created: now, used: now }
+    }
 
-// ── App state: holds per-channel encryption keys ──
+    fn is_expired(&self, ttl: Option<Duration>, now: Instant) -> bool {
+        ttl.is_some_and(|ttl| now.saturating_duration_since(self.created) >= ttl)
+    }
+}
+
+// ── What a cold channel does once MAX_CACHED_KEYS are cached ──
+#[derive(Clone, Copy, PartialEq)]
+enum KeyCacheOverflow {
+    // Evict the least recently used cipher to make room
+    Lru,
+    // Refuse the request with 503 cache_full
+    Reject,
+    // Ignore the limit
+    Unbounded,
+}
+
+impl KeyCacheOverflow {
+    fn from_env() -> Result<Self, String> {
+        match std::env::var("KEY_CACHE_OVERFLOW").ok().as_deref() {
+            None | Some("") | Some("lru") => Ok(KeyCacheOverflow::Lru),
+            Some("reject") => Ok(KeyCacheOverflow::Reject),
+            Some("unbounded") => Ok(KeyCacheOverflow::Unbounded),
+            Some(other) => Err(format!(
+                "Unknown KEY_CACHE_OVERFLOW {:?}; expected lru, reject or unbounded",
+                other
+            )),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            KeyCacheOverflow::Lru => "lru",
+            KeyCacheOverflow::Reject => "reject",
+            KeyCacheOverflow::Unbounded => "unbounded",
+        }
+    }
+}
+
+// ── Algorithm for channels with no pin, policy or per-request choice ──
+// DEFAULT_ALGORITHM names it outright and wins; otherwise
+// AUTO_SELECT_ALGORITHM=true benchmarks both ciphers on this host and takes
+// the faster. Neither set keeps AES-256-GCM.
+fn default_algorithm_from_env() -> Result<Algorithm, String> {
+    choose_default_algorithm(
+        std::env::var("DEFAULT_ALGORITHM").ok(),
+        std::env::var("AUTO_SELECT_ALGORITHM").is_ok_and(|v| v == "true"),
+        benchmark_algorithms,
+    )
+}
+
+// The choice itself; `benchmark` gives timings fastest first and only runs
+// when auto-selection decides
+fn choose_default_algorithm(
+    named: Option<String>,
+    auto_select: bool,
+    benchmark: impl FnOnce() -> Vec<(Algorithm, Duration)>,
+) -> Result<Algorithm, String> {
+    if let Some(name) = named {
+        let algorithm = Algorithm::from_name(&name).ok_or_else(|| {
+            format!("Unknown DEFAULT_ALGORITHM {:?}; expected aes256gcm or chacha20poly1305", name)
+        })?;
+        log::info!("Default algorithm: {} (DEFAULT_ALGORITHM)", algorithm);
+        return Ok(algorithm);
+    }
+    if !auto_select {
+        return Ok(Algorithm::default());
+    }
+    let timings = benchmark();
+    let summary: Vec<String> = timings
+        .iter()
+        .map(|(algorithm, elapsed)| format!("{} {}us", algorithm, elapsed.as_micros()))
+        .collect();
+    let fastest = timings[0].0;
+    log::info!("Default algorithm: {} (benchmarked: {})", fastest, summary.join(", "));
+    Ok(fastest)
+}
+
+// ── Per-channel key metadata ──
+#[derive(Default)]
+struct KeyMetadata {
+    // Once set, encrypts under any other algorithm are refused unless overridden
+    pinned_algorithm: Option<Algorithm>,
+    // Overrides DAILY_ENCRYPT_QUOTA for this channel
+    daily_quota: Option<u64>,
+    usage: DailyUsage,
+    policy: ChannelPolicy,
+    // Next number for an encrypt with `"sequence": true`. In memory, like
+    // the ratchet chains: a restart starts every channel over at 0.
+    next_sequence: u64,
+    // Oldest first; see `record_rotation`
+    rotations: Vec<Rotation>,
+}
+
+// ── One change of a channel's key generation ──
+// `generation` is the key's fingerprint, as in a signed envelope. Kept in
+// memory with the rest of the metadata; with an audit sink, each entry is
+// also recorded there as a `rotate`.
+#[derive(Serialize, Clone)]
+struct Rotation {
+    generation: String,
+    // Fingerprint under MASTER_SECRET_PREVIOUS, which the data moved off
+    previous_generation: Option<String>,
+    rotated_at: u64,
+    reason: RotationReason,
+}
+
+// Why a rolled-over master secret was migrated to; given on the re-encrypt job
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum RotationReason {
+    #[default]
+    Manual,
+    Scheduled,
+    // The key neared KEY_BYTE_LIMIT
+    Usage,
+    Incident,
+}
+
+// ── Per-channel encrypt defaults, set with POST /channels/{id}/policy ──
+// Each field applies only when an encrypt request leaves it unset. A pin
+// still wins over the policy's algorithm.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+struct ChannelPolicy {
+    algorithm: Option<Algorithm>,
+    commit: Option<bool>,
+    ratchet: Option<bool>,
+    output: Option<BlobEncoding>,
+}
+
+// ── How often a channel key is used ──
+// Shared behind an Arc so the hot path bumps these under a read lock on the
+// map; only a channel's first use takes the write lock.
+#[derive(Default)]
+struct KeyUsage {
+    // Successful encrypts and decrypts
+    use_count: AtomicU64,
+    // Unix time of the latest one
+    last_used_at: AtomicU64,
+    // Plaintext sealed under the current master secret's key; see `charge_key_bytes`
+    bytes_sealed: AtomicU64,
+}
+
+// ── Encrypts a channel has made on one UTC day ──
+#[derive(Default)]
+struct DailyUsage {
+    // Days since the Unix epoch
+    day: u64,
+    count: u64,
+}
+
+// ── App state: holds per-channel ciphers, built once from the derived key ──
 struct AppState {
-    keys: Mutex<HashMap<i64, Vec<u8>>>,
+    // Taken with `lock_ciphers`, which gives up after `lock_timeout`
+    ciphers: parking_lot::Mutex<CipherMap>,
+    lock_timeout: Duration,
+    // Cached ciphers older than this are dropped and re-derived on next use
+    key_cache_ttl: Option<Duration>,
+    // Unset means no limit; KEY_CACHE_OVERFLOW decides what happens at it
+    max_cached_keys: Option<usize>,
+    key_cache_overflow: KeyCacheOverflow,
+    key_meta: Mutex<HashMap<KeyId, KeyMetadata>>,
+    key_usage: RwLock<HashMap<KeyId, Arc<KeyUsage>>>,
+    pin_on_first_use: bool,
+    // For new channels; see default_algorithm_from_env
+    default_algorithm: Algorithm,
+    // Encrypts without a `timestamp` field bind produced_at when set;
+    // ENCRYPT_TIMESTAMPS=true
+    encrypt_timestamps: bool,
+    // Refuse a message holding a lone surrogate escape, rather than sealing
+    // it with U+FFFD in its place; REJECT_INVALID_UNICODE=false turns it off
+    reject_invalid_unicode: bool,
+    ratchets: Mutex<HashMap<KeyId, Ratchet>>,
+    // Past ratchet steps kept per channel for out-of-order decrypt
+    ratchet_window: usize,
     master_secret: String,
+    previous_master_secret: Option<String>,
+    // Orgs with a master secret of their own; see secrets::tenant_secrets_from_env
+    tenant_secrets: HashMap<i64, String>,
+    limits: Limits,
+    metrics: Metrics,
+    metrics_enabled: bool,
+    admin_token: Option<String>,
+    // Bearer JWTs with per-endpoint scopes; None unless JWT_PUBLIC_KEY_PATH is set
+    jwt: Option<auth::JwtAuth>,
+    // Refuse decrypts that carry no X-Decrypt-Token; see `check_decrypt_token`
+    require_decrypt_token: bool,
+    // Name likely causes in decrypt errors; off in production, since a hint
+    // tells a caller more than "failed" does
+    debug_errors: bool,
+    // Running on the built-in fallback secret; with debug_errors, decrypt
+    // failures under it say so instead of just "failed"
+    default_secret_active: bool,
+    // While set, requests that produce new ciphertext are refused; in memory
+    // only, so a restart always comes back out of maintenance
+    maintenance: AtomicBool,
+    // Panics on a repeated nonce in debug builds; a no-op in release
+    nonce_guard: NonceGuard,
+    // Known-answer result from startup, which only gets this far on a pass
+    crypto_selftest: selftest::SelfTestReport,
+    // Supplies each /encrypt nonce; RandomNonce outside tests
+    nonces: Box<dyn NonceStrategy>,
+    // One permit per in-flight encrypt/decrypt; see `crypto_permit`
+    crypto_permits: Arc<Semaphore>,
+    // How long a request may wait for a permit; zero sheds at once
+    crypto_queue_timeout: Duration,
+    // Caps how many of those permits one channel may hold
+    channel_shares: ChannelShares,
+    // Recent decrypt responses; None unless DECRYPT_CACHE_ENABLED=true
+    decrypt_cache: Option<DecryptCache>,
+    // None unless MAX_DECRYPT_FAILURES is set
+    decrypt_lockout: Option<DecryptLockout>,
+    // Rolling decrypt success ratio behind the SLO alert webhook
+    slo: SloMonitor,
+    // Peers whose Forwarded / X-Forwarded-For headers name the client
+    trusted_proxies: TrustedProxies,
+    // Signs archival envelopes; None unless ARCHIVE_SIGNING_KEY is set
+    archive_signer: Option<signing::ArchiveSigner>,
+    // /encrypt responses by Idempotency-Key, so a retry gets the original
+    // ciphertext rather than a second one
+    idempotency: ResponseCache<EncryptReply>,
+    // Every time-based check reads this rather than the OS directly
+    clock: Arc<dyn Clock>,
+    // One entry per encrypt or decrypt under a channel key; None unless AUDIT_SINK is set
+    audit: Option<Box<dyn AuditSink>>,
+}
+
+impl AppState {
+    fn secret(&self, version: SecretVersion) -> &str {
+        match version {
+            SecretVersion::Current => &self.master_secret,
+            SecretVersion::Previous => self
+                .previous_master_secret
+                .as_deref()
+                .unwrap_or(&self.master_secret),
+        }
+    }
+
+    // ── Secret a channel key is derived from ──
+    // A tenant's own secret has no previous generation, so both versions
+    // resolve to it.
+    fn key_secret(&self, version: SecretVersion, key_id: KeyId) -> &str {
+        match key_id.org_id.and_then(|org_id| self.tenant_secrets.get(&org_id)) {
+            Some(secret) => secret,
+            None => self.secret(version),
+        }
+    }
+
+    fn current_key(&self, key_id: KeyId) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(derive_key(self.key_secret(SecretVersion::Current, key_id), key_id))
+    }
+
+    fn reject_in_maintenance(&self) -> Result<(), HttpResponse> {
+        if !self.maintenance.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        Err(HttpResponse::ServiceUnavailable()
+            .json(ErrorResponse {
+                error: "Service is in maintenance mode; only decrypts are served".into(),
+                code: "maintenance_mode".into(),
+            }))
+    }
+
+    // ── Claim a crypto slot, or shed the request with a 503 ──
+    // Past MAX_CONCURRENT_CRYPTO in-flight operations, extra requests are
+    // refused rather than allowed to slow every request down. A request for
+    // one channel also needs room in that channel's share, and queues in
+    // turn with other channels; admin work over many channels passes None.
+    async fn crypto_permit(&self, channel: Option<KeyId>) -> Result<CryptoPermit, HttpResponse> {
+        let Some(slot) = self.channel_shares.claim(channel) else {
+            self.metrics.crypto_requests_shed.inc();
+            log::warn!("Shed a crypto request: channel {} is at its share", channel.unwrap());
+            return Err(HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, "1"))
+                .json(ErrorResponse {
+                    error: "Too many concurrent crypto requests on this channel; retry shortly"
+                        .into(),
+                    code: "channel_overloaded".into(),
+                }));
+        };
+        let permits = self.crypto_permits.clone();
+        let permit = if self.crypto_queue_timeout.is_zero() {
+            permits.try_acquire_owned().ok()
+        } else {
+            let queued = self.channel_shares.acquire(channel, permits);
+            tokio::time::timeout(self.crypto_queue_timeout, queued).await.ok().flatten()
+        };
+        permit.map(|permit| CryptoPermit::new(slot, permit)).ok_or_else(|| {
+            self.metrics.crypto_requests_shed.inc();
+            log::warn!("Shed a crypto request: all slots busy");
+            HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, "1"))
+                .json(ErrorResponse {
+                    error: "Too many concurrent crypto requests; retry shortly".into(),
+                    code: "overloaded".into(),
+                })
+        })
+    }
+
+    // ── Enforce the decrypt token, if any, on a request for `key_id` ──
+    // A presented token is always checked; a missing one is refused only with
+    // REQUIRE_DECRYPT_TOKEN. Tokens signed under the previous secret still
+    // verify while it is configured.
+    fn check_decrypt_token(&self, req: &HttpRequest, key_id: KeyId) -> Result<(), HttpResponse> {
+        let token = req.headers().get("x-decrypt-token").map(|v| v.to_str().unwrap_or(""));
+        let Some(token) = token else {
+            if !self.require_decrypt_token {
+                return Ok(());
+            }
+            return Err(HttpResponse::Forbidden()
+                .json(ErrorResponse {
+                    error: "A decrypt token is required".into(),
+                    code: "token_required".into(),
+                }));
+        };
+
+        let keys: Vec<[u8; 32]> = self
+            .decrypt_versions()
+            .into_iter()
+            .map(|version| derive_token_key(self.secret(version)))
+            .collect();
+        let (error, code) = match tokens::verify(&keys, token, key_id, self.clock.unix_now()) {
+            Ok(()) => return Ok(()),
+            Err(tokens::TokenError::Invalid) => ("Invalid decrypt token", "token_invalid"),
+            Err(tokens::TokenError::Expired) => ("Decrypt token has expired", "token_expired"),
+            Err(tokens::TokenError::WrongScope) => {
+                ("Decrypt token is not valid for this channel", "token_wrong_scope")
+            }
+        };
+        log::warn!("Rejected decrypt token for channel {}: {}", key_id, code);
+        Err(HttpResponse::Forbidden()
+            .json(ErrorResponse {
+                error: error.into(),
+                code: code.into(),
+            }))
+    }
+
+    // ── Refuse a client locked out of `key_id` by failed decrypts ──
+    fn check_lockout(&self, key_id: KeyId, client: &str) -> Result<(), HttpResponse> {
+        let lockout = self.decrypt_lockout.as_ref();
+        match lockout.and_then(|l| l.locked_until(key_id, client, self.clock.unix_now())) {
+            Some(until) => Err(locked_out(self, key_id, until)),
+            None => Ok(()),
+        }
+    }
+
+    // ── Count how one decrypt went against the lockout ──
+    // Every route that opens a blob reports here, so failures on one count
+    // toward a lockout on all. `opened` is false only for a blob that failed
+    // to authenticate.
+    fn record_decrypt(&self, key_id: KeyId, client: &str, opened: bool) {
+        let Some(lockout) = &self.decrypt_lockout else {
+            return;
+        };
+        if opened {
+            lockout.record_success(key_id, client);
+        } else if let Some(until) = lockout.record_failure(key_id, client, self.clock.unix_now()) {
+            log::warn!(
+                "Security: channel {} locked for client {} until {} after {} failed decrypts",
+                key_id,
+                client,
+                until,
+                lockout.max_failures
+            );
+        }
+    }
+
+    // ── Check the caller's JWT for `scope` on this channel, when JWT auth is on ──
+    // A tenant's keys are only handed to a caller whose token names its org;
+    // without JWT auth nothing can, so they are refused outright.
+    fn authorize(
+        &self,
+        req: &HttpRequest,
+        scope: Scope,
+        key_id: KeyId,
+    ) -> Result<(), HttpResponse> {
+        let tenant = self.is_tenant(key_id);
+        match &self.jwt {
+            Some(jwt) => jwt.authorize(req, scope, key_id, tenant),
+            None if tenant => {
+                log::warn!("Rejected {} for tenant {}: JWT auth is off", req.path(), key_id);
+                Err(HttpResponse::Forbidden()
+                    .json(ErrorResponse {
+                        error: "A tenant's channels need JWT auth with an org_id claim".into(),
+                        code: "org_claim_required".into(),
+                    }))
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Secrets to try on decrypt, newest first
+    fn decrypt_versions(&self) -> Vec<SecretVersion> {
+        let mut versions = vec![SecretVersion::Current];
+        if self.previous_master_secret.is_some() {
+            versions.push(SecretVersion::Previous);
+        }
+        versions
+    }
+
+    // Channel key generations to try on decrypt; a tenant's secret has one
+    fn key_versions(&self, key_id: KeyId) -> Vec<SecretVersion> {
+        let mut versions = self.decrypt_versions();
+        if self.is_tenant(key_id) {
+            versions.truncate(1);
+        }
+        versions
+    }
+
+    fn is_tenant(&self, key_id: KeyId) -> bool {
+        key_id.org_id.is_some_and(|org_id| self.tenant_secrets.contains_key(&org_id))
+    }
+}
+
+// ── Request / Response types ──
+
+// Serialize gives the canonical body an Idempotency-Key is bound to
+#[derive(Serialize, Deserialize)]
+struct EncryptRequest {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    // Namespaces the channel key under an organization
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    // Seal under the key shared with this channel instead
+    #[serde(default, deserialize_with = "ids::optional")]
+    shared_with: Option<i64>,
+    message: text::Text,
+    // `message` is base64 of raw bytes, which are sealed in its place
+    #[serde(default)]
+    binary: bool,
+    // Sealed together with the message and returned by /decrypt
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+    // Defaults to the channel's pinned algorithm, else its policy's, else AES-256-GCM
+    #[serde(default)]
+    algorithm: Option<Algorithm>,
+    // Encrypt under `algorithm` even if the channel is pinned to another
+    #[serde(default)]
+    override_pin: bool,
+    // Encrypt under an HKDF subkey for this context; recorded in the header
+    #[serde(default)]
+    context: Option<String>,
+    // Encrypt under the channel's next ratchet key, for forward secrecy;
+    // unset follows the channel policy
+    #[serde(default)]
+    ratchet: Option<bool>,
+    // Add size statistics to the response
+    #[serde(default)]
+    verbose: bool,
+    // Record a key commitment so only the exact sealing key can open it;
+    // unset follows the channel policy
+    #[serde(default)]
+    commit: Option<bool>,
+    // Content hash of a referenced attachment, bound into the AAD; the same
+    // value must be supplied to decrypt
+    #[serde(default)]
+    attachment_hash: Option<String>,
+    // Encoding of the returned blob; without it, `Accept:
+    // application/octet-stream` selects raw
+    #[serde(default)]
+    output: Option<BlobEncoding>,
+    // Unicode normalization applied to `message` before it is sealed
+    #[serde(default)]
+    normalize: Normalization,
+    // Record the channel's next sequence number in the header, so readers
+    // can spot dropped or reordered messages
+    #[serde(default)]
+    sequence: bool,
+    // Record the encrypt time in the header, authenticated like the rest of
+    // it; unset follows ENCRYPT_TIMESTAMPS
+    #[serde(default)]
+    timestamp: Option<bool>,
+    // Also return a signed envelope for archiving; see `ArchiveEnvelope`
+    #[serde(default)]
+    envelope: bool,
+}
+
+// ── Unicode normalization of message text before sealing ──
+// Under NFC, composed and decomposed spellings of the same visible text
+// seal the same bytes.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Normalization {
+    #[default]
+    None,
+    Nfc,
+}
+
+// ── How a blob travels over the wire ──
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum BlobEncoding {
+    #[default]
+    Base64,
+    // Standard base64 with the `=` padding stripped; as input, the same as base64
+    Base64Nopad,
+    Hex,
+    // Bare bytes in an application/octet-stream body
+    Raw,
+}
+
+// Padding is optional on input: either `=` run is accepted, or none at all
+const PADDING_INDIFFERENT: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const TOLERANT_BASE64: GeneralPurpose =
+    GeneralPurpose::new(&alphabet::STANDARD, PADDING_INDIFFERENT);
+const TOLERANT_BASE64_URL: GeneralPurpose =
+    GeneralPurpose::new(&alphabet::URL_SAFE, PADDING_INDIFFERENT);
+
+// ── Decode base64 blob input, padded or not, standard or URL-safe ──
+// ASCII whitespace is dropped first, so line-wrapped (PEM-style) blobs
+// decode too. Output stays padded standard base64 unless the caller asks
+// otherwise.
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let unwrapped: String;
+    let encoded = if encoded.bytes().any(|b| b.is_ascii_whitespace()) {
+        unwrapped = encoded.split_ascii_whitespace().collect();
+        &unwrapped
+    } else {
+        encoded
+    };
+    TOLERANT_BASE64.decode(encoded).or_else(|_| TOLERANT_BASE64_URL.decode(encoded)).ok()
+}
+
+// ── Decode a blob sent as a JSON string ──
+fn decode_blob(encoding: BlobEncoding, encoded: &str) -> Result<Vec<u8>, HttpResponse> {
+    let (decoded, name, code) = match encoding {
+        BlobEncoding::Base64 | BlobEncoding::Base64Nopad => {
+            (decode_base64(encoded), "base64", "invalid_base64")
+        }
+        BlobEncoding::Hex => (hex::decode(encoded).ok(), "hex", "invalid_hex"),
+        BlobEncoding::Raw => {
+            return Err(HttpResponse::BadRequest()
+                .json(ErrorResponse {
+                    error: "Raw blobs must be sent as an application/octet-stream body".into(),
+                    code: "invalid_input_encoding".into(),
+                }))
+        }
+    };
+    decoded.ok_or_else(|| {
+        log::error!("Failed to decode {} blob", name);
+        HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("Invalid {}", name),
+                code: code.into(),
+            })
+    })
+}
+
+#[derive(Serialize)]
+struct EncryptResponse {
+    encrypted: String,
+    // Set when the message was normalized before sealing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normalized: Option<Normalization>,
+    // Set for a sequenced encrypt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+    // Unix seconds recorded in the header, for a timestamped encrypt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    produced_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    envelope: Option<ArchiveEnvelope>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    stats: Option<EncryptStats>,
+}
+
+// ── Encrypt result signed for long-term storage ──
+// `signature` is base64 Ed25519, under the key at GET /archive/public-key,
+// over `signing::archive_message` of the other four fields. `generation` is
+// the fingerprint of the channel key it was sealed under, which outlives
+// rotation; /channels/{id}/fingerprint reports the same value.
+#[derive(Serialize)]
+struct ArchiveEnvelope {
+    ciphertext: String,
+    generation: String,
+    algorithm: Algorithm,
+    produced_at: u64,
+    signature: String,
+}
+
+#[derive(Serialize)]
+struct EncryptStats {
+    // Plaintext as sealed, including framed metadata
+    plaintext_bytes: usize,
+    // Decoded blob: header, nonce, ciphertext and tag
+    ciphertext_bytes: usize,
+}
+
+// ── Body for /encrypt/estimate ──
+// Sizes don't depend on the channel or algorithm, so neither is needed; any
+// /encrypt field not listed here is ignored.
+#[derive(Deserialize)]
+struct EstimateRequest {
+    // Exactly one of `message` or its UTF-8 `length` in bytes
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    length: Option<usize>,
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    context: Option<String>,
+    #[serde(default)]
+    ratchet: bool,
+    #[serde(default)]
+    commit: bool,
+    #[serde(default)]
+    sequence: bool,
+    #[serde(default)]
+    timestamp: bool,
+    #[serde(default)]
+    output: BlobEncoding,
+}
+
+#[derive(Serialize)]
+struct EstimateResponse {
+    plaintext_bytes: usize,
+    ciphertext_bytes: usize,
+    // Length of `encrypted` in the chosen output encoding; exact, since
+    // nothing is compressed
+    encoded_bytes: usize,
+}
+
+#[derive(Deserialize)]
+struct DecryptRequest {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    #[serde(default, deserialize_with = "ids::optional")]
+    shared_with: Option<i64>,
+    encrypted: String,
+    // Encoding of `encrypted`; raw blobs go in the body instead
+    #[serde(default)]
+    input: BlobEncoding,
+    #[serde(default)]
+    format: CiphertextFormat,
+    // Required exactly when the message was sealed with one
+    #[serde(default)]
+    attachment_hash: Option<String>,
+    // Add how the blob was sealed to the response; admin only
+    #[serde(default)]
+    verbose: bool,
+}
+
+// ── Query string for /decrypt with an application/octet-stream body ──
+#[derive(Deserialize)]
+struct DecryptQuery {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    #[serde(default, deserialize_with = "ids::optional")]
+    shared_with: Option<i64>,
+    #[serde(default)]
+    format: CiphertextFormat,
+    #[serde(default)]
+    attachment_hash: Option<String>,
+    #[serde(default)]
+    verbose: bool,
+}
+
+impl TokenRequest {
+    fn key_id(&self) -> KeyId {
+        KeyId::new(self.org_id, self.channel_id).with_shared(self.shared_with)
+    }
+}
+
+impl EncryptRequest {
+    fn key_id(&self) -> KeyId {
+        KeyId::new(self.org_id, self.channel_id).with_shared(self.shared_with)
+    }
+}
+
+impl DecryptRequest {
+    fn key_id(&self) -> KeyId {
+        KeyId::new(self.org_id, self.channel_id).with_shared(self.shared_with)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawDecryptRequest {
+    // Base64 channel key, used in place of the derived one
+    key: Zeroizing<String>,
+    #[serde(flatten)]
+    decrypt: DecryptRequest,
+}
+
+// ── Why a single reading of a blob failed to open ──
+#[derive(Clone, Copy, PartialEq)]
+enum OpenError {
+    // Tag mismatch: wrong key, wrong channel or tampered data
+    Auth,
+    // The blob commits to a different key than this one: a wrong channel or
+    // org id, or a different master secret
+    WrongKey,
+    // Ratchet step no longer (or never) held in the channel's window
+    RatchetWindow,
+    // Sealed with an attachment hash, and none was supplied
+    AttachmentRequired,
+    // The header names an algorithm id this build doesn't know
+    UnsupportedAlgorithm(u8),
+    // No cipher: the cache lock timed out, or the cache is full
+    KeyCache(KeyCacheError),
+}
+
+// ── Wire format of the blob handed to /decrypt ──
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum CiphertextFormat {
+    // Our own AES-256-GCM: 12-byte nonce + ciphertext
+    #[default]
+    Aes256gcm,
+    // Legacy libsodium crypto_secretbox_easy: 24-byte nonce + MAC + ciphertext
+    Secretbox,
+}
+
+impl CiphertextFormat {
+    fn nonce_len(self) -> usize {
+        match self {
+            CiphertextFormat::Aes256gcm => NONCE_LEN,
+            CiphertextFormat::Secretbox => 24,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DecryptResponse {
+    message: String,
+    // Set when the plaintext is not UTF-8 and `message` is its base64
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    binary: bool,
+    // Set when an authenticated blob held an empty message; a failed
+    // decrypt is always an error, never an empty `message`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    empty: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<String>,
+    // Set when the blob was sealed with `"sequence": true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+    // When the blob was sealed, if it was timestamped; authenticated, so it
+    // cannot be changed without failing decryption
+    #[serde(skip_serializing_if = "Option::is_none")]
+    produced_at: Option<u64>,
+    // Absent when the caller supplied the key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret_version: Option<SecretVersion>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    details: Option<DecryptDetails>,
+}
+
+// ── How a blob was sealed, returned with `"verbose": true` ──
+// Only reported for a blob that opened, so `authenticated` is always true
+#[derive(Serialize)]
+struct DecryptDetails {
+    authenticated: bool,
+    algorithm: &'static str,
+    // Null for a legacy headerless blob
+    format_version: Option<u8>,
+    // Whether the header was bound into the tag as associated data
+    header_authenticated: bool,
+    attachment_bound: bool,
+    commitment_verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ratchet_index: Option<u64>,
+    // Base64
+    nonce: String,
+}
+
+#[derive(Deserialize)]
+struct ReencryptJobRequest {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    #[serde(default)]
+    format: CiphertextFormat,
+    // Items with an id at or below this were handled by an earlier call
+    #[serde(default)]
+    cursor: Option<i64>,
+    // Admin only: reseal under this algorithm instead of the channel's own
+    #[serde(default)]
+    algorithm: Option<Algorithm>,
+    // Recorded in the channel's rotation history if the job migrates it
+    #[serde(default)]
+    reason: RotationReason,
+    items: Vec<ReencryptItem>,
+}
+
+impl ReencryptJobRequest {
+    fn key_id(&self) -> KeyId {
+        KeyId { org_id: self.org_id, channel_id: self.channel_id, shared_with: None }
+    }
+}
+
+// ── Query string for /jobs/reencrypt with an application/x-ndjson body ──
+#[derive(Deserialize)]
+struct ReencryptJobQuery {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    #[serde(default)]
+    format: CiphertextFormat,
+    #[serde(default)]
+    cursor: Option<i64>,
+    #[serde(default)]
+    algorithm: Option<Algorithm>,
+    #[serde(default)]
+    reason: RotationReason,
+}
+
+impl ReencryptJobQuery {
+    fn key_id(&self) -> KeyId {
+        KeyId { org_id: self.org_id, channel_id: self.channel_id, shared_with: None }
+    }
+}
+
+#[derive(Deserialize)]
+struct ReencryptItem {
+    id: i64,
+    encrypted: String,
+    #[serde(default)]
+    attachment_hash: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ReencryptResult {
+    id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encrypted: Option<String>,
+    // Error code when this item could not be re-encrypted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    // Opened under MASTER_SECRET_PREVIOUS; not sent
+    #[serde(skip)]
+    migrated: bool,
+}
+
+#[derive(Serialize)]
+struct ReencryptJobResponse {
+    reencrypted: usize,
+    failed: usize,
+    // Items past this batch, still to be sent again with `next_cursor`
+    remaining: usize,
+    // Absent once every item sent has been handled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<i64>,
+    results: Vec<ReencryptResult>,
+}
+
+#[derive(Deserialize)]
+struct PreloadRequest {
+    #[serde(deserialize_with = "ids::list")]
+    channel_ids: Vec<i64>,
+}
+
+#[derive(Serialize)]
+struct PreloadResponse {
+    preloaded: usize,
+}
+
+#[derive(Deserialize)]
+struct KeyExistsRequest {
+    #[serde(deserialize_with = "ids::list")]
+    channel_ids: Vec<i64>,
+}
+
+#[derive(Serialize)]
+struct KeyExists {
+    channel_id: i64,
+    exists: bool,
+}
+
+#[derive(Deserialize)]
+struct FingerprintsRequest {
+    #[serde(deserialize_with = "ids::list")]
+    channel_ids: Vec<i64>,
+    // Applies to every channel listed
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct PinRequest {
+    algorithm: Algorithm,
+}
+
+#[derive(Serialize)]
+struct PinResponse {
+    channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shared_with: Option<i64>,
+    algorithm: Algorithm,
+}
+
+// ── Which key a per-channel admin setting applies to ──
+// Same fields as on /encrypt; without them, the bare channel's key.
+#[derive(Deserialize)]
+struct ChannelKeyQuery {
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    #[serde(default, deserialize_with = "ids::optional")]
+    shared_with: Option<i64>,
+}
+
+impl ChannelKeyQuery {
+    fn key_id(&self, channel_id: i64) -> KeyId {
+        KeyId::new(self.org_id, channel_id).with_shared(self.shared_with)
+    }
+}
+
+#[derive(Deserialize)]
+struct SharedKeyRequest {
+    // Exactly two distinct channels, in either order
+    #[serde(deserialize_with = "ids::list")]
+    channel_ids: Vec<i64>,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct SharedKeyResponse {
+    // Use as `channel_id` and `shared_with` on /encrypt and /decrypt
+    channel_id: i64,
+    shared_with: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    fingerprint: String,
+}
+
+#[derive(Serialize)]
+struct IndexTokensResponse {
+    tokens: Vec<String>,
+}
+
+// ── Body for /sign/ed25519, and without `signature` for /verify/ed25519 ──
+#[derive(Deserialize)]
+struct Ed25519Request {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    // Signed as its UTF-8 bytes
+    message: String,
+    // Base64; only read by /verify/ed25519
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+impl Ed25519Request {
+    fn key_id(&self) -> KeyId {
+        KeyId::new(self.org_id, self.channel_id)
+    }
+}
+
+#[derive(Serialize)]
+struct Ed25519SignResponse {
+    signature: String,
+    // Base64 of the raw 32-byte key; the same for every message on the channel
+    public_key: String,
+}
+
+#[derive(Serialize)]
+struct Ed25519VerifyResponse {
+    valid: bool,
+    public_key: String,
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    #[serde(deserialize_with = "ids::id")]
+    channel_id: i64,
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    #[serde(default, deserialize_with = "ids::optional")]
+    shared_with: Option<i64>,
+    // Lifetime in seconds; defaults to DEFAULT_TOKEN_TTL_SECS
+    #[serde(default)]
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    token: String,
+    expires_at: u64,
+}
+
+#[derive(Serialize)]
+struct PolicyResponse {
+    channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shared_with: Option<i64>,
+    policy: ChannelPolicy,
+}
+
+impl PolicyResponse {
+    fn new(key_id: KeyId, policy: ChannelPolicy) -> Self {
+        PolicyResponse {
+            channel_id: key_id.channel_id,
+            org_id: key_id.org_id,
+            shared_with: key_id.shared_with,
+            policy,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RotationsResponse {
+    channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shared_with: Option<i64>,
+    rotations: Vec<Rotation>,
+}
+
+#[derive(Deserialize)]
+struct QuotaRequest {
+    // Encrypts per UTC day; null falls back to DAILY_ENCRYPT_QUOTA
+    daily_limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct QuotaResponse {
+    channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shared_with: Option<i64>,
+    daily_limit: Option<u64>,
+    used: u64,
+    // Unix time of the next UTC midnight, when `used` resets
+    resets_at: u64,
+}
+
+#[derive(Deserialize)]
+struct FingerprintQuery {
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct UsageResponse {
+    channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    use_count: u64,
+    // Unix time; absent until the key is first used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_used_at: Option<u64>,
+    // Encrypts are refused with key_exhausted once this reaches byte_limit
+    bytes_sealed: u64,
+    byte_limit: u64,
+}
+
+#[derive(Serialize)]
+struct FingerprintResponse {
+    channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    fingerprint: String,
+}
+
+#[derive(Serialize)]
+struct ArchivePublicKeyResponse {
+    algorithm: &'static str,
+    // Base64 of the raw 32-byte key
+    public_key: String,
+}
+
+#[derive(Serialize)]
+struct FingerprintPairResponse {
+    channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    current: String,
+    // Both null unless MASTER_SECRET_PREVIOUS is set
+    previous: Option<String>,
+    // False means the rollover would leave this channel's key unchanged
+    changed: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct KeyExportQuery {
+    #[serde(default, deserialize_with = "ids::optional")]
+    org_id: Option<i64>,
+    // Base64 X25519 public key the channel keys are wrapped for
+    recipient_key: String,
+}
+
+#[derive(Serialize)]
+struct KeyExportResponse {
+    channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<i64>,
+    // X25519, HKDF-SHA256 and AES-256-GCM; see `export_wrapping_key`
+    scheme: &'static str,
+    // Base64; the recipient's half of the key agreement
+    ephemeral_public_key: String,
+    keys: Vec<ExportedKey>,
+}
+
+#[derive(Serialize)]
+struct ExportedKey {
+    secret_version: SecretVersion,
+    fingerprint: String,
+    // Base64 nonce + ciphertext + tag of the 32-byte channel key
+    wrapped: String,
+}
+
+#[derive(Deserialize)]
+struct MaintenanceRequest {
+    enabled: bool,
+}
+
+#[derive(Serialize)]
+struct MaintenanceResponse {
+    maintenance: bool,
+}
+
+#[derive(Serialize)]
+struct FlushKeysResponse {
+    cleared: usize,
+}
+
+#[derive(Deserialize)]
+struct CanaryRequest {
+    // Each sample is a /decrypt body
+    samples: Vec<DecryptRequest>,
+}
+
+#[derive(Serialize)]
+struct CanaryResponse {
+    previous_configured: bool,
+    results: Vec<CanaryResult>,
+}
+
+#[derive(Serialize)]
+struct CanaryResult {
+    index: usize,
+    current: bool,
+    // Null when MASTER_SECRET_PREVIOUS is unset
+    previous: Option<bool>,
+    // Set when the sample could not be decoded, so neither was tried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+#[derive(Deserialize)]
+struct TestSecretRequest {
+    candidate: Zeroizing<String>,
+    // Each sample is a /decrypt body
+    samples: Vec<DecryptRequest>,
+}
+
+#[derive(Serialize)]
+struct TestSecretResponse {
+    // The candidate is the secret already in use
+    matches_current: bool,
+    results: Vec<TestSecretResult>,
+}
+
+#[derive(Serialize)]
+struct TestSecretResult {
+    index: usize,
+    opens: bool,
+    // Set when the sample could not be decoded, so it was not tried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+#[derive(Deserialize)]
+struct LogLevelRequest {
+    // RUST_LOG syntax: a level, or per-module `target=level` directives
+    level: String,
+}
+
+#[derive(Serialize)]
+struct LogLevelResponse {
+    level: String,
+    max_level: String,
+}
+
+#[derive(Serialize)]
+struct AlgorithmInfo {
+    name: &'static str,
+    // As written into the envelope header
+    id: u8,
+    key_bytes: usize,
+    nonce_bytes: usize,
+    tag_bytes: usize,
+    // Neither AEAD here survives a repeated nonce; nonces are always random
+    nonce_misuse_resistant: bool,
+    // What /encrypt uses when neither the request nor a policy picks one
+    recommended: bool,
+}
+
+#[derive(Serialize)]
+struct RngHealthResponse {
+    source: &'static str,
+    healthy: bool,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+    code: String,
+}
+
+#[derive(Serialize)]
+struct QuotaExceededResponse {
+    error: String,
+    code: String,
+    resets_at: u64,
+}
+
+// ── No cipher could be had from the cache ──
+#[derive(Clone, Copy, PartialEq)]
+enum KeyCacheError {
+    // The lock was held past LOCK_TIMEOUT_MS. Only a bug holds it that long,
+    // so the request fails fast rather than hanging every request behind it.
+    LockTimeout,
+    // MAX_CACHED_KEYS reached under KEY_CACHE_OVERFLOW=reject
+    Full,
+}
+
+impl KeyCacheError {
+    fn code(self) -> &'static str {
+        match self {
+            KeyCacheError::LockTimeout => "lock_timeout",
+            KeyCacheError::Full => "cache_full",
+        }
+    }
+
+    fn response(self) -> HttpResponse {
+        let error = match self {
+            KeyCacheError::LockTimeout => "Timed out waiting for the key cache",
+            KeyCacheError::Full => "Key cache is full; try again later",
+        };
+        HttpResponse::ServiceUnavailable()
+            .json(ErrorResponse { error: error.into(), code: self.code().into() })
+    }
+}
+
+// ── Rough bytes one cipher cache entry holds ──
+// The map slot (key, CachedCipher and a control byte), the Arc'd OnceLock
+// with its two counts, and the heap behind the larger of the two ciphers.
+const CACHED_CIPHER_BYTES: usize = std::mem::size_of::<(CipherCacheKey, CachedCipher)>()
+    + 1
+    + 2 * std::mem::size_of::<usize>()
+    + std::mem::size_of::<OnceLock<ChannelCipher>>()
+    + ChannelCipher::MAX_HEAP_BYTES;
+
+// ── Cached cipher count and estimated footprint ──
+// Cheap: the count times a fixed per-entry size, never a walk of the map.
+fn key_cache_estimate(state: &AppState) -> Result<(usize, usize), KeyCacheError> {
+    let entries = lock_ciphers(state)?.len();
+    Ok((entries, entries * CACHED_CIPHER_BYTES))
+}
+
+fn lock_ciphers(
+    state: &AppState,
+) -> Result<parking_lot::MutexGuard<'_, CipherMap>, KeyCacheError> {
+    state.ciphers.try_lock_for(state.lock_timeout).ok_or_else(|| {
+        log::error!(
+            "Cipher cache lock not acquired within {:?}; it is held far too long",
+            state.lock_timeout
+        );
+        reporting::capture_failure("lock_timeout", None, "Cipher cache lock timed out");
+        KeyCacheError::LockTimeout
+    })
+}
+
+// ── Get the cipher slot for a channel, creating an empty one if needed ──
+// The map lock is only held for the lookup, never during derivation.
+fn cipher_cell(
+    state: &AppState,
+    version: SecretVersion,
+    algorithm: Algorithm,
+    key_id: KeyId,
+) -> Result<CipherCell, KeyCacheError> {
+    let mut ciphers = lock_ciphers(state)?;
+    let now = state.clock.now();
+    let key = (version, algorithm, key_id);
+    if !ciphers.contains_key(&key) {
+        make_room(state, &mut ciphers, now)?;
+    }
+    let slot = ciphers.entry(key).or_insert_with(|| CachedCipher::empty(now));
+    if slot.is_expired(state.key_cache_ttl, now) {
+        *slot = CachedCipher::empty(now);
+    }
+    slot.used = now;
+    Ok(slot.cell.clone())
+}
+
+// ── Free a slot for a new channel once MAX_CACHED_KEYS are cached ──
+// Expired ciphers go first; after that KEY_CACHE_OVERFLOW decides.
+fn make_room(
+    state: &AppState,
+    ciphers: &mut CipherMap,
+    now: Instant,
+) -> Result<(), KeyCacheError> {
+    let Some(max) = state.max_cached_keys else { return Ok(()) };
+    if ciphers.len() < max || state.key_cache_overflow == KeyCacheOverflow::Unbounded {
+        return Ok(());
+    }
+    ciphers.retain(|_, slot| !slot.is_expired(state.key_cache_ttl, now));
+    while ciphers.len() >= max {
+        if state.key_cache_overflow == KeyCacheOverflow::Reject {
+            return Err(KeyCacheError::Full);
+        }
+        let Some(oldest) = ciphers.iter().min_by_key(|(_, slot)| slot.used).map(|(k, _)| *k)
+        else {
+            break;
+        };
+        ciphers.remove(&oldest);
+    }
+    Ok(())
+}
+
+// ── Drop every cached cipher past its TTL ──
+// The AES and ChaCha key schedules zeroize themselves on drop; a cipher a
+// request is still holding goes when that request finishes.
+fn evict_expired_ciphers(state: &AppState) -> Result<usize, KeyCacheError> {
+    let mut ciphers = lock_ciphers(state)?;
+    let before = ciphers.len();
+    let now = state.clock.now();
+    ciphers.retain(|_, slot| !slot.is_expired(state.key_cache_ttl, now));
+    Ok(before - ciphers.len())
+}
+
+// ── Drop every cached cipher at once ──
+// Returns how many built ciphers went; each channel re-derives on next use.
+fn purge_ciphers(state: &AppState) -> Result<usize, KeyCacheError> {
+    let mut ciphers = lock_ciphers(state)?;
+    let built = ciphers.values().filter(|slot| slot.cell.get().is_some()).count();
+    ciphers.clear();
+    Ok(built)
+}
+
+// ── Fill a cipher slot, returning the cipher and whether this call built it ──
+// Concurrent cold requests for the same channel wait on one derivation.
+fn init_cipher(
+    state: &AppState,
+    cell: &OnceLock<ChannelCipher>,
+    version: SecretVersion,
+    algorithm: Algorithm,
+    key_id: KeyId,
+) -> (ChannelCipher, bool) {
+    let mut built = false;
+    let cipher = cell.get_or_init(|| {
+        built = true;
+        let timer = state.metrics.key_derivation_seconds.start_timer();
+        let secret = state.key_secret(version, key_id);
+        let key = Zeroizing::new(derive_key_len(secret, key_id, algorithm.key_len()));
+        timer.observe_duration();
+
+        let _timer = state.metrics.cipher_init_seconds.start_timer();
+        ChannelCipher::new(algorithm, &key)
+    });
+    (cipher.clone(), built)
+}
+
+// ── Get the cached cipher for a channel, building it on first use ──
+fn get_cipher(
+    state: &AppState,
+    version: SecretVersion,
+    algorithm: Algorithm,
+    key_id: KeyId,
+) -> Result<ChannelCipher, KeyCacheError> {
+    let cell = cipher_cell(state, version, algorithm, key_id)?;
+    let (cipher, built) = init_cipher(state, &cell, version, algorithm, key_id);
+    if built {
+        state.metrics.cipher_cache_misses.inc();
+    } else {
+        state.metrics.cipher_cache_hits.inc();
+    }
+    Ok(cipher)
+}
+
+// ── Cipher for one message ──
+// Plain messages use the cached channel cipher. A ratchet step replaces the
+// channel key and a context derives a subkey on top; both are per message
+// and never cached.
+fn message_cipher(
+    state: &AppState,
+    source: KeySource,
+    algorithm: Algorithm,
+    key_id: KeyId,
+    ratchet_key: Option<&[u8]>,
+    context: Option<&[u8]>,
+) -> Result<ChannelCipher, KeyCacheError> {
+    if let (KeySource::Secret(version), None, None) = (source, ratchet_key, context) {
+        return get_cipher(state, version, algorithm, key_id);
+    }
+    Ok(ChannelCipher::new(algorithm, &message_key(state, source, key_id, ratchet_key, context)))
+}
+
+// ── Raw key a message is sealed under, derived without the cipher cache ──
+fn message_key(
+    state: &AppState,
+    source: KeySource,
+    key_id: KeyId,
+    ratchet_key: Option<&[u8]>,
+    context: Option<&[u8]>,
+) -> Zeroizing<Vec<u8>> {
+    let base = match (ratchet_key, source) {
+        (Some(key), _) | (None, KeySource::Raw(key)) => Zeroizing::new(key.to_vec()),
+        (None, KeySource::Secret(version) | KeySource::Uncached(version)) => {
+            Zeroizing::new(derive_key(state.key_secret(version, key_id), key_id))
+        }
+    };
+    match context {
+        Some(context) => Zeroizing::new(derive_context_key(&base, context).to_vec()),
+        None => base,
+    }
+}
+
+// ── Take the next ratchet step for a channel, starting its chain if new ──
+fn advance_ratchet(state: &AppState, key_id: KeyId) -> (u64, Zeroizing<[u8; 32]>) {
+    let mut ratchets = state.ratchets.lock().unwrap();
+    ratchets
+        .entry(key_id)
+        .or_insert_with(|| {
+            Ratchet::new(ratchet::first_index(state.clock.unix_now()), state.ratchet_window)
+        })
+        .advance()
+}
+
+// ── Open one reading of a blob under one channel key ──
+fn open_sealed(
+    state: &AppState,
+    format: CiphertextFormat,
+    source: KeySource,
+    algorithm: Algorithm,
+    key_id: KeyId,
+    sealed: &envelope::Envelope,
+    attachment_hash: Option<&[u8]>,
+) -> Result<Vec<u8>, OpenError> {
+    if let CiphertextFormat::Secretbox = format {
+        // Same channel key, so secretbox data migrates without re-keying
+        let key = message_key(state, source, key_id, None, None);
+        return XSalsa20Poly1305::new(crypto_secretbox::Key::from_slice(&key))
+            .decrypt(crypto_secretbox::Nonce::from_slice(sealed.nonce), sealed.ciphertext)
+            .map_err(|_| OpenError::Auth);
+    }
+
+    // Ratchet chains only ever advance under the current master secret, and
+    // a caller's raw key has no chain at all
+    let ratchet_key = match sealed.header.and_then(|h| h.ratchet_index) {
+        Some(_) if source.version() != Some(SecretVersion::Current) => {
+            return Err(OpenError::Auth)
+        }
+        Some(index) => {
+            let ratchets = state.ratchets.lock().unwrap();
+            let key = ratchets.get(&key_id).and_then(|r| r.key_for(index));
+            Some(key.ok_or(OpenError::RatchetWindow)?)
+        }
+        None => None,
+    };
+
+    let context = sealed.header.and_then(|h| h.context);
+    let ratchet_key = ratchet_key.as_ref().map(|k| &k[..]);
+    if let Some(commitment) = sealed.header.and_then(|h| h.commitment) {
+        let key = message_key(state, source, key_id, ratchet_key, context);
+        if !bool::from(key_commitment(&key).ct_eq(commitment)) {
+            return Err(OpenError::WrongKey);
+        }
+    }
+
+    // A wrong attachment hash is just different AAD, so it fails the tag
+    let bound;
+    let aad = match sealed.header {
+        Some(h) if h.has(envelope::FLAG_ATTACHMENT) => {
+            bound = [sealed.aad, attachment_hash.ok_or(OpenError::AttachmentRequired)?].concat();
+            &bound[..]
+        }
+        _ => sealed.aad,
+    };
+
+    message_cipher(state, source, algorithm, key_id, ratchet_key, context)
+        .map_err(OpenError::KeyCache)?
+        .decrypt(sealed.nonce, Payload { msg: sealed.ciphertext, aad })
+        .map_err(|_| OpenError::Auth)
+}
+
+// ── Build and store a channel's cipher ahead of its first request ──
+// Returns false if the cipher was already cached.
+fn precompute_cipher(state: &AppState, key_id: KeyId) -> Result<bool, KeyCacheError> {
+    let algorithm = pinned_algorithm(state, key_id).unwrap_or(state.default_algorithm);
+    let cell = cipher_cell(state, SecretVersion::Current, algorithm, key_id)?;
+    Ok(init_cipher(state, &cell, SecretVersion::Current, algorithm, key_id).1)
+}
+
+// ── Whether a channel already has a built cipher under the current secret ──
+// Pure read: unlike `cipher_cell`, absent channels are never inserted.
+fn has_cached_key(state: &AppState, key_id: KeyId) -> Result<bool, KeyCacheError> {
+    let ciphers = lock_ciphers(state)?;
+    let now = state.clock.now();
+    Ok(Algorithm::ALL.iter().any(|&algorithm| {
+        ciphers
+            .get(&(SecretVersion::Current, algorithm, key_id))
+            .is_some_and(|slot| {
+                !slot.is_expired(state.key_cache_ttl, now) && slot.cell.get().is_some()
+            })
+    }))
+}
+
+fn pinned_algorithm(state: &AppState, key_id: KeyId) -> Option<Algorithm> {
+    let meta = state.key_meta.lock().unwrap();
+    meta.get(&key_id).and_then(|m| m.pinned_algorithm)
+}
+
+// ── Take a channel's next sequence number ──
+// Taken before the seal, so a failed encrypt leaves a gap rather than a
+// reused number.
+fn next_sequence(state: &AppState, key_id: KeyId) -> u64 {
+    let mut meta = state.key_meta.lock().unwrap();
+    let entry = meta.entry(key_id).or_default();
+    let sequence = entry.next_sequence;
+    entry.next_sequence += 1;
+    sequence
+}
+
+// ── Count one successful encrypt or decrypt under a channel key ──
+fn record_use(state: &AppState, key_id: KeyId, action: AuditAction) {
+    let usage = key_usage(state, key_id);
+    usage.use_count.fetch_add(1, Ordering::Relaxed);
+    usage.last_used_at.fetch_max(state.clock.unix_now(), Ordering::Relaxed);
+    audit(state, key_id, action);
+}
+
+// ── Hand one entry to the audit sink, if there is one ──
+fn audit(state: &AppState, key_id: KeyId, action: AuditAction) {
+    let Some(sink) = &state.audit else {
+        return;
+    };
+    let entry = AuditEntry::new(state.clock.unix_now(), action, key_id);
+    if let Err(e) = sink.record(&entry) {
+        log::error!("Audit {} sink failed for channel {}: {}", sink.name(), key_id, e);
+    }
+}
+
+fn key_usage(state: &AppState, key_id: KeyId) -> Arc<KeyUsage> {
+    let usage = state.key_usage.read().unwrap().get(&key_id).cloned();
+    usage.unwrap_or_else(|| state.key_usage.write().unwrap().entry(key_id).or_default().clone())
+}
+
+// ── Count plaintext against the channel key's data limit ──
+// False, counting nothing, once `len` more bytes would pass KEY_BYTE_LIMIT.
+// The key is then exhausted: encrypts stay refused until MASTER_SECRET is
+// rotated (old value in MASTER_SECRET_PREVIOUS) and the service restarted,
+// which gives every channel a fresh key. Decrypts are never refused. The
+// count is in memory, so it also starts over on a plain restart.
+fn charge_key_bytes(state: &AppState, key_id: KeyId, len: usize) -> bool {
+    let limit = state.limits.key_byte_limit;
+    let charged = key_usage(state, key_id).bytes_sealed.fetch_update(
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+        |sealed| sealed.checked_add(len as u64).filter(|&total| total <= limit),
+    );
+    if charged.is_err() {
+        log::error!(
+            "Channel {} key reached its {}-byte data limit; rotate MASTER_SECRET",
+            key_id, limit
+        );
+    }
+    charged.is_ok()
+}
+
+fn key_exhausted() -> HttpResponse {
+    HttpResponse::Conflict()
+        .json(ErrorResponse {
+            error: "Channel key has reached its data limit; rotate the master secret".into(),
+            code: "key_exhausted".into(),
+        })
+}
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+// ── Count an encrypt against the channel's daily quota ──
+// Usage resets at UTC midnight and is tracked even without a quota, so one
+// set mid-day applies to what was already sent. Refused encrypts don't count.
+fn charge_quota(state: &web::Data<AppState>, key_id: KeyId) -> Result<QuotaCharge, HttpResponse> {
+    let now = state.clock.unix_now();
+    let today = now / SECS_PER_DAY;
+    let mut meta = state.key_meta.lock().unwrap();
+    let entry = meta.entry(key_id).or_default();
+    if entry.usage.day != today {
+        entry.usage = DailyUsage { day: today, count: 0 };
+    }
+
+    let quota = entry.daily_quota.or(state.limits.daily_encrypt_quota);
+    if quota.is_some_and(|quota| entry.usage.count >= quota) {
+        let resets_at = (today + 1) * SECS_PER_DAY;
+        log::warn!("Channel {} hit its daily encrypt quota", key_id);
+        return Err(HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, (resets_at - now).to_string()))
+            .json(QuotaExceededResponse {
+                error: "Daily encrypt quota exceeded for this channel".into(),
+                code: "quota_exceeded".into(),
+                resets_at,
+            }));
+    }
+    entry.usage.count += 1;
+    Ok(QuotaCharge { state: state.clone(), key_id, day: today, kept: false })
+}
+
+// ── One encrypt counted against its channel's daily quota ──
+// Taken before sealing, so concurrent encrypts cannot overshoot the quota,
+// and refunded when dropped without `keep`: an encrypt that fails after
+// the check costs the channel nothing.
+#[must_use]
+struct QuotaCharge {
+    state: web::Data<AppState>,
+    key_id: KeyId,
+    day: u64,
+    kept: bool,
+}
+
+impl QuotaCharge {
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for QuotaCharge {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        let mut meta = self.state.key_meta.lock().unwrap();
+        // A charge from before midnight went with the old day's count
+        if let Some(entry) = meta.get_mut(&self.key_id).filter(|e| e.usage.day == self.day) {
+            entry.usage.count = entry.usage.count.saturating_sub(1);
+        }
+    }
+}
+
+// ── Pick the algorithm for an encrypt, enforcing the channel's pin ──
+fn resolve_algorithm(
+    state: &AppState,
+    key_id: KeyId,
+    requested: Option<Algorithm>,
+    override_pin: bool,
+) -> Result<Algorithm, HttpResponse> {
+    let mut meta = state.key_meta.lock().unwrap();
+    let entry = meta.entry(key_id).or_default();
+
+    match (entry.pinned_algorithm, requested) {
+        (Some(pinned), Some(requested)) if pinned != requested && !override_pin => {
+            log::warn!(
+                "Rejected {} encrypt for channel {} pinned to {}",
+                requested, key_id, pinned
+            );
+            Err(HttpResponse::Conflict().json(ErrorResponse {
+                error: format!("Channel is pinned to {}", pinned),
+                code: "algorithm_mismatch".into(),
+            }))
+        }
+        (Some(pinned), None) => Ok(pinned),
+        (pinned, requested) => {
+            let algorithm =
+                requested.or(entry.policy.algorithm).unwrap_or(state.default_algorithm);
+            if pinned.is_none() && state.pin_on_first_use {
+                entry.pinned_algorithm = Some(algorithm);
+            }
+            Ok(algorithm)
+        }
+    }
+}
+
+// ── Everything a message is sealed with besides its text ──
+struct SealOptions<'a> {
+    algorithm: Algorithm,
+    metadata: Option<&'a serde_json::Value>,
+    context: Option<&'a [u8]>,
+    ratchet: bool,
+    commit: bool,
+    attachment_hash: Option<&'a [u8]>,
+    sequence: Sequence,
+    produced_at: Option<u64>,
+}
+
+// ── Where a sealed message's sequence number comes from ──
+#[derive(Clone, Copy)]
+enum Sequence {
+    Unnumbered,
+    // The channel's next one, taken only once nothing can refuse the seal
+    Next,
+    // Carried over from the message being re-encrypted
+    Kept(u64),
+}
+
+// A sealed blob, the size of the plaintext that went into it, and the
+// sequence number it was given
+struct Sealed {
+    blob: Vec<u8>,
+    plaintext_len: usize,
+    sequence: Option<u64>,
+}
+
+// ── Seal one message under the current master secret ──
+// Everything that can refuse the request runs before a ratchet index or a
+// sequence number is taken, so a refused seal leaves no gap in either.
+fn seal(
+    state: &AppState,
+    key_id: KeyId,
+    message: &[u8],
+    opts: &SealOptions,
+) -> Result<Sealed, HttpResponse> {
+    let context = opts.context;
+    if context.is_some_and(|c| c.len() > u16::MAX as usize) {
+        return Err(HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("Context exceeds {} bytes", u16::MAX),
+                code: "context_too_long".into(),
+            }));
+    }
+
+    // Stand-ins have the same encoded length as the real values
+    let stand_in = [0; envelope::COMMITMENT_LEN];
+    let sequence = match opts.sequence {
+        Sequence::Unnumbered => None,
+        Sequence::Next | Sequence::Kept(_) => Some(0),
+    };
+    let header_len = seal_header(opts, opts.ratchet.then_some(0), &stand_in, sequence)
+        .encode()
+        .len();
+    let aad_len = header_len + opts.attachment_hash.map_or(0, <[u8]>::len);
+    if let Some(max) = state.limits.max_aad_bytes.filter(|&max| aad_len > max) {
+        return Err(HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("Associated data is {} bytes; the limit is {}", aad_len, max),
+                code: "aad_too_large".into(),
+            }));
+    }
+    let framed;
+    let plaintext = match opts.metadata {
+        Some(metadata) => {
+            framed = envelope::frame(message, metadata.to_string().as_bytes());
+            &framed[..]
+        }
+        None => message,
+    };
+    if !charge_key_bytes(state, key_id, plaintext.len()) {
+        return Err(key_exhausted());
+    }
+
+    let ratchet = opts.ratchet.then(|| advance_ratchet(state, key_id));
+    let ratchet_index = ratchet.as_ref().map(|(index, _)| *index);
+    let ratchet_key = ratchet.as_ref().map(|(_, key)| &key[..]);
+    let sequence = match opts.sequence {
+        Sequence::Unnumbered => None,
+        Sequence::Next => Some(next_sequence(state, key_id)),
+        Sequence::Kept(sequence) => Some(sequence),
+    };
+    let current = KeySource::Secret(SecretVersion::Current);
+    let commitment = match opts.commit {
+        true => key_commitment(&message_key(state, current, key_id, ratchet_key, context)),
+        false => stand_in,
+    };
+    let header = seal_header(opts, ratchet_index, &commitment, sequence).encode();
+    let cipher = message_cipher(state, current, opts.algorithm, key_id, ratchet_key, context)
+        .map_err(KeyCacheError::response)?;
+    let bound;
+    let aad = match opts.attachment_hash {
+        Some(hash) => {
+            bound = [&header[..], hash].concat();
+            &bound[..]
+        }
+        None => &header[..],
+    };
+
+    let nonce_bytes = state.nonces.next_nonce(key_id, plaintext, aad);
+    state.nonce_guard.record(key_id, context, ratchet_index, &nonce_bytes);
+
+    match cipher.encrypt(&nonce_bytes, Payload { msg: plaintext, aad }) {
+        Ok(ciphertext) => {
+            // Pack as: header + nonce + ciphertext
+            let mut blob = header;
+            blob.extend_from_slice(&nonce_bytes);
+            blob.extend_from_slice(&ciphertext);
+            record_use(state, key_id, AuditAction::Encrypt);
+            state.metrics.encrypt_total.with_label_values(&[opts.algorithm.name()]).inc();
+            Ok(Sealed { blob, plaintext_len: plaintext.len(), sequence })
+        }
+        Err(e) => {
+            log::error!("Encryption failed: {}", e);
+            reporting::tag_failure("encryption_failed", key_id);
+            Err(HttpResponse::InternalServerError()
+                .json(ErrorResponse {
+                    error: "Encryption failed".into(),
+                    code: "encryption_failed".into(),
+                }))
+        }
+    }
+}
+
+// ── The header `seal` writes; `commitment` is only used with opts.commit ──
+fn seal_header<'h>(
+    opts: &SealOptions<'h>,
+    ratchet_index: Option<u64>,
+    commitment: &'h [u8; envelope::COMMITMENT_LEN],
+    sequence: Option<u64>,
+) -> Header<'h> {
+    let mut header = Header::new(opts.algorithm.id());
+    if let Some(context) = opts.context {
+        header = header.with_context(context);
+    }
+    if let Some(index) = ratchet_index {
+        header = header.with_ratchet(index);
+    }
+    if opts.commit {
+        header = header.with_commitment(commitment);
+    }
+    if opts.attachment_hash.is_some() {
+        header.flags |= envelope::FLAG_ATTACHMENT;
+    }
+    if opts.metadata.is_some() {
+        header.flags |= envelope::FLAG_FRAMED;
+    }
+    if let Some(sequence) = sequence {
+        header = header.with_sequence(sequence);
+    }
+    if let Some(produced_at) = opts.produced_at {
+        header = header.with_produced_at(produced_at);
+    }
+    header
+}
+
+// Longest Idempotency-Key accepted
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+// ── A successful /encrypt response, as replayed for a repeated Idempotency-Key ──
+#[derive(Clone)]
+struct EncryptReply {
+    content_type: &'static str,
+    body: web::Bytes,
+}
+
+impl EncryptReply {
+    fn response(&self) -> HttpResponse {
+        HttpResponse::Ok().content_type(self.content_type).body(self.body.clone())
+    }
+}
+
+// ── POST /encrypt ──
+// With an Idempotency-Key header, a repeat of the same request within
+// IDEMPOTENCY_TTL_SECS returns the first response byte for byte, and is not
+// charged to the quota again. Two copies racing in at once may both seal.
+async fn encrypt(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<EncryptRequest>,
+) -> HttpResponse {
+    if let Err(resp) = data.authorize(&req, Scope::Encrypt, body.key_id()) {
+        return resp;
+    }
+    if let Err(resp) = data.reject_in_maintenance() {
+        return resp;
+    }
+    if body.message.replaced {
+        if data.reject_invalid_unicode {
+            return HttpResponse::BadRequest()
+                .json(ErrorResponse {
+                    error: "message holds a lone surrogate escape or invalid UTF-8".into(),
+                    code: "invalid_unicode".into(),
+                });
+        }
+        log::warn!("Sealing U+FFFD for invalid Unicode in channel {} message", body.key_id());
+    }
+
+    let idempotency = match req.headers().get("idempotency-key") {
+        None => None,
+        Some(key) => match key.to_str() {
+            Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN => {
+                let accept = req.headers().get(header::ACCEPT).map_or(&[][..], |v| v.as_bytes());
+                let accept = String::from_utf8_lossy(accept);
+                let canonical = serde_json::to_vec(&*body).unwrap_or_default();
+                Some(response_cache::idempotency_key(key, &accept, &canonical))
+            }
+            _ => {
+                return HttpResponse::BadRequest()
+                    .json(ErrorResponse {
+                        error: format!(
+                            "Idempotency-Key must be 1 to {} visible ASCII characters",
+                            MAX_IDEMPOTENCY_KEY_LEN
+                        ),
+                        code: "invalid_idempotency_key".into(),
+                    })
+            }
+        },
+    };
+    if let Some(reply) = idempotency.as_ref().and_then(|key| data.idempotency.get(key)) {
+        log::info!("Replayed encrypt for channel {} by idempotency key", body.key_id());
+        return reply.response();
+    }
+
+    let reply = match seal_request(&req, &data, body.into_inner()).await {
+        Ok(reply) => reply,
+        Err(resp) => return resp,
+    };
+    if let Some(key) = idempotency {
+        data.idempotency.insert(key, reply.clone());
+    }
+    reply.response()
+}
+
+// ── The body of /encrypt, from the permit on ──
+async fn seal_request(
+    req: &HttpRequest,
+    data: &web::Data<AppState>,
+    mut body: EncryptRequest,
+) -> Result<EncryptReply, HttpResponse> {
+    let _permit = data.crypto_permit(Some(body.key_id())).await?;
+    if body.normalize == Normalization::Nfc {
+        body.message.text = body.message.nfc().collect();
+    }
+    // Checked before anything is charged. Text that happens to be valid
+    // base64 cannot be told from a binary message, so only this direction
+    // of mismatch is caught.
+    let decoded = match body.binary {
+        true => match decode_base64(&body.message) {
+            Some(bytes) => Some(Zeroizing::new(bytes)),
+            None => {
+                return Err(HttpResponse::BadRequest()
+                    .json(ErrorResponse {
+                        error: "binary is set but message is not base64".into(),
+                        code: "encoding_mismatch".into(),
+                    }))
+            }
+        },
+        false => None,
+    };
+
+    let key_id = body.key_id();
+    let algorithm = resolve_algorithm(data, key_id, body.algorithm, body.override_pin)?;
+    let charge = charge_quota(data, key_id)?;
+
+    let policy = channel_policy(data, key_id);
+    let output = body.output.unwrap_or_else(|| {
+        let accept = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok());
+        if accept.is_some_and(|a| a.contains("application/octet-stream")) {
+            BlobEncoding::Raw
+        } else {
+            policy.output.unwrap_or_default()
+        }
+    });
+    if body.envelope {
+        archive_signer(data, output)?;
+    }
+    let ratchet = body.ratchet.or(policy.ratchet).unwrap_or(false);
+    let commit = body.commit.or(policy.commit).unwrap_or(false);
+    let sequence = match body.sequence {
+        true => Sequence::Next,
+        false => Sequence::Unnumbered,
+    };
+    let produced_at = body
+        .timestamp
+        .unwrap_or(data.encrypt_timestamps)
+        .then(|| data.clock.unix_now());
+    let body = Arc::new(body);
+    let request = body.clone();
+    let sealed = offload(data, body.message.len(), move |state| {
+        let message = decoded.as_deref().map_or(request.message.as_bytes(), Vec::as_slice);
+        let opts = SealOptions {
+            algorithm,
+            metadata: request.metadata.as_ref(),
+            context: request.context.as_deref().map(str::as_bytes),
+            ratchet,
+            commit,
+            attachment_hash: request.attachment_hash.as_deref().map(str::as_bytes),
+            sequence,
+            produced_at,
+        };
+        seal(state, key_id, message, &opts)
+    })
+    .await?;
+    charge.keep();
+
+    log::info!("Encrypted message for channel {}", key_id);
+    let encrypted = match output {
+        // Stats only travel in the JSON responses
+        BlobEncoding::Raw => {
+            return Ok(EncryptReply {
+                content_type: "application/octet-stream",
+                body: sealed.blob.into(),
+            })
+        }
+        BlobEncoding::Hex => hex::encode(&sealed.blob),
+        BlobEncoding::Base64 => BASE64.encode(&sealed.blob),
+        BlobEncoding::Base64Nopad => BASE64_NO_PAD.encode(&sealed.blob),
+    };
+    let stats = body.verbose.then_some(EncryptStats {
+        plaintext_bytes: sealed.plaintext_len,
+        ciphertext_bytes: sealed.blob.len(),
+    });
+    let normalized = (body.normalize != Normalization::None).then_some(body.normalize);
+    let envelope = match body.envelope {
+        true => Some(archive_envelope(data, key_id, algorithm, &encrypted, produced_at)?),
+        false => None,
+    };
+    let sequence = sealed.sequence;
+    let response =
+        EncryptResponse { encrypted, normalized, sequence, produced_at, envelope, stats };
+    let json = serde_json::to_vec(&response).unwrap_or_default();
+    Ok(EncryptReply { content_type: "application/json", body: json.into() })
+}
+
+// ── The archive signer, if an envelope can be returned at all ──
+// Checked before sealing, so a refused request is not charged to the quota.
+fn archive_signer(
+    data: &AppState,
+    output: BlobEncoding,
+) -> Result<&signing::ArchiveSigner, HttpResponse> {
+    let Some(signer) = &data.archive_signer else {
+        return Err(HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: "Signed envelopes need ARCHIVE_SIGNING_KEY".into(),
+                code: "archive_signing_disabled".into(),
+            }));
+    };
+    if output == BlobEncoding::Raw {
+        return Err(HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: "Signed envelopes only travel in JSON responses".into(),
+                code: "envelope_requires_json".into(),
+            }));
+    }
+    Ok(signer)
+}
+
+fn archive_envelope(
+    data: &AppState,
+    key_id: KeyId,
+    algorithm: Algorithm,
+    ciphertext: &str,
+    produced_at: Option<u64>,
+) -> Result<ArchiveEnvelope, HttpResponse> {
+    let signer = archive_signer(data, BlobEncoding::Base64)?;
+    let generation = key_fingerprint(&data.current_key(key_id));
+    // The same instant as the header's, when the blob is timestamped
+    let produced_at = produced_at.unwrap_or_else(|| data.clock.unix_now());
+    let message =
+        signing::archive_message(ciphertext, &generation, algorithm.name(), produced_at);
+    Ok(ArchiveEnvelope {
+        ciphertext: ciphertext.to_owned(),
+        generation,
+        algorithm,
+        produced_at,
+        signature: signer.sign(&message),
+    })
+}
+
+// ── Run one seal or open, off the async worker when its input is large ──
+// AEAD over a big payload would hold the worker and stall every other
+// connection on it; below OFFLOAD_THRESHOLD_BYTES the thread hop costs more
+// than it saves, so the work runs inline.
+async fn offload<T: Send + 'static>(
+    data: &web::Data<AppState>,
+    len: usize,
+    work: impl FnOnce(&AppState) -> Result<T, HttpResponse> + Send + 'static,
+) -> Result<T, HttpResponse> {
+    if len < data.limits.offload_threshold_bytes {
+        return work(data);
+    }
+    let state = data.clone();
+    let result = web::block(move || work(&state).map_err(DetachedResponse::new)).await;
+    match result {
+        Ok(result) => result.map_err(DetachedResponse::into_response),
+        Err(e) => {
+            log::error!("Blocking pool failed to run crypto work: {}", e);
+            Err(HttpResponse::InternalServerError()
+                .json(ErrorResponse {
+                    error: "Crypto work could not be scheduled".into(),
+                    code: "offload_failed".into(),
+                }))
+        }
+    }
+}
+
+// ── An error response taken apart to cross back from the blocking pool ──
+// HttpResponse itself isn't Send; error bodies here are always plain bytes.
+struct DetachedResponse {
+    status: StatusCode,
+    headers: Vec<(header::HeaderName, header::HeaderValue)>,
+    body: web::Bytes,
+}
+
+impl DetachedResponse {
+    fn new(resp: HttpResponse) -> Self {
+        let status = resp.status();
+        let headers = resp.headers().iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        let body = resp.into_body().try_into_bytes().unwrap_or_default();
+        DetachedResponse { status, headers, body }
+    }
+
+    fn into_response(self) -> HttpResponse {
+        let mut builder = HttpResponse::build(self.status);
+        for header in self.headers {
+            builder.insert_header(header);
+        }
+        builder.body(self.body)
+    }
+}
+
+// ── POST /encrypt/estimate ──
+// Exact size of the blob /encrypt would return for the same options, without
+// touching keys, quotas, ratchets or pins.
+async fn estimate_encrypt(body: web::Json<EstimateRequest>) -> HttpResponse {
+    let message_len = match (&body.message, body.length) {
+        (Some(message), None) => message.len(),
+        (None, Some(length)) => length,
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(ErrorResponse {
+                    error: "Give exactly one of message or length".into(),
+                    code: "invalid_estimate".into(),
+                })
+        }
+    };
+    let context = body.context.as_deref().map(str::as_bytes);
+    if context.is_some_and(|c| c.len() > u16::MAX as usize) {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("Context exceeds {} bytes", u16::MAX),
+                code: "context_too_long".into(),
+            });
+    }
+
+    // Same header fields as `seal` writes; only their lengths matter
+    let mut header = Header::new(Algorithm::default().id());
+    if let Some(context) = context {
+        header = header.with_context(context);
+    }
+    if body.ratchet {
+        header = header.with_ratchet(0);
+    }
+    let commitment = [0u8; envelope::COMMITMENT_LEN];
+    if body.commit {
+        header = header.with_commitment(&commitment);
+    }
+    if body.sequence {
+        header = header.with_sequence(0);
+    }
+    if body.timestamp {
+        header = header.with_produced_at(0);
+    }
+    let plaintext_bytes = match &body.metadata {
+        Some(metadata) => 4 + message_len + metadata.to_string().len(),
+        None => message_len,
+    };
+
+    let ciphertext_bytes = header.encode().len() + NONCE_LEN + plaintext_bytes + TAG_LEN;
+    let encoded_bytes = match body.output {
+        BlobEncoding::Base64 => ciphertext_bytes.div_ceil(3) * 4,
+        BlobEncoding::Base64Nopad => (ciphertext_bytes * 4).div_ceil(3),
+        BlobEncoding::Hex => ciphertext_bytes * 2,
+        BlobEncoding::Raw => ciphertext_bytes,
+    };
+    HttpResponse::Ok().json(EstimateResponse { plaintext_bytes, ciphertext_bytes, encoded_bytes })
+}
+
+// ── Why a blob could not be opened ──
+#[derive(Clone, Copy)]
+enum DecryptError {
+    TooShort,
+    TooLarge(usize),
+    RatchetWindow,
+    Failed,
+    // Only with DEBUG_ERRORS: the key commitment proved the key wrong
+    PossibleWrongChannel,
+    // Only with DEBUG_ERRORS: failed while the fallback master secret is in use
+    DefaultSecretActive,
+    Malformed,
+    AttachmentRequired,
+    AttachmentNotBound,
+    UnsupportedAlgorithm(u8),
+    KeyCache(KeyCacheError),
+}
+
+impl DecryptError {
+    fn code(self) -> &'static str {
+        match self {
+            DecryptError::TooShort => "ciphertext_too_short",
+            DecryptError::TooLarge(_) => "plaintext_too_large",
+            DecryptError::RatchetWindow => "ratchet_window_exceeded",
+            DecryptError::Failed => "decryption_failed",
+            DecryptError::PossibleWrongChannel => "possible_wrong_channel",
+            DecryptError::DefaultSecretActive => "default_secret_active",
+            DecryptError::Malformed => "malformed_payload",
+            DecryptError::AttachmentRequired => "attachment_hash_required",
+            DecryptError::AttachmentNotBound => "attachment_hash_not_bound",
+            DecryptError::UnsupportedAlgorithm(_) => "unsupported_algorithm",
+            DecryptError::KeyCache(e) => e.code(),
+        }
+    }
+
+    fn response(self) -> HttpResponse {
+        let (mut builder, error) = match self {
+            DecryptError::TooShort => {
+                (HttpResponse::BadRequest(), "Ciphertext too short".to_string())
+            }
+            DecryptError::TooLarge(max) => (
+                HttpResponse::PayloadTooLarge(),
+                format!("Decrypted plaintext exceeds {} bytes", max),
+            ),
+            DecryptError::RatchetWindow => (
+                HttpResponse::Gone(),
+                "Ratchet key for this message is no longer retained".to_string(),
+            ),
+            DecryptError::Failed => (HttpResponse::BadRequest(), "Decryption failed".to_string()),
+            DecryptError::PossibleWrongChannel => (
+                HttpResponse::BadRequest(),
+                "Decryption failed: sealed under a different key; check channel_id and org_id"
+                    .to_string(),
+            ),
+            DecryptError::DefaultSecretActive => (
+                HttpResponse::BadRequest(),
+                "Decryption failed: the service is running on the default master secret; \
+                 set MASTER_SECRET"
+                    .to_string(),
+            ),
+            DecryptError::Malformed => {
+                (HttpResponse::BadRequest(), "Malformed framed payload".to_string())
+            }
+            DecryptError::AttachmentRequired => (
+                HttpResponse::BadRequest(),
+                "Message is bound to an attachment; attachment_hash is required".to_string(),
+            ),
+            DecryptError::AttachmentNotBound => (
+                HttpResponse::BadRequest(),
+                "Message is not bound to an attachment".to_string(),
+            ),
+            DecryptError::UnsupportedAlgorithm(id) => (
+                HttpResponse::BadRequest(),
+                format!("Unsupported algorithm id {} in ciphertext header", id),
+            ),
+            DecryptError::KeyCache(e) => return e.response(),
+        };
+        builder.json(ErrorResponse { error, code: self.code().into() })
+    }
+}
+
+// ── A decrypted blob split back into what was sealed ──
+struct Opened {
+    message: Vec<u8>,
+    metadata: Option<serde_json::Value>,
+    context: Option<Vec<u8>>,
+    ratchet: bool,
+    commit: bool,
+    // None when opened under a caller-supplied key
+    secret_version: Option<SecretVersion>,
+    algorithm: &'static str,
+    // None for a headerless blob
+    format_version: Option<u8>,
+    nonce: Vec<u8>,
+    ratchet_index: Option<u64>,
+    sequence: Option<u64>,
+    produced_at: Option<u64>,
+    attachment_bound: bool,
+}
+
+// ── Open one blob under any accepted master secret ──
+fn open(
+    state: &AppState,
+    key_id: KeyId,
+    format: CiphertextFormat,
+    combined: &[u8],
+    attachment_hash: Option<&[u8]>,
+) -> Result<Opened, DecryptError> {
+    let sources: Vec<KeySource> =
+        state.key_versions(key_id).into_iter().map(KeySource::Secret).collect();
+    open_with(state, key_id, &sources, format, combined, attachment_hash)
+}
+
+// ── Open one blob under the first of `sources` that authenticates ──
+// Every attempt is counted by the blob's algorithm and how it ended.
+fn open_with(
+    state: &AppState,
+    key_id: KeyId,
+    sources: &[KeySource],
+    format: CiphertextFormat,
+    combined: &[u8],
+    attachment_hash: Option<&[u8]>,
+) -> Result<Opened, DecryptError> {
+    let result = open_blob(state, key_id, sources, format, combined, attachment_hash);
+    let algorithm = match format {
+        CiphertextFormat::Secretbox => "xsalsa20poly1305",
+        _ if matches!(result, Err(DecryptError::UnsupportedAlgorithm(_))) => "unknown",
+        CiphertextFormat::Aes256gcm => Header::parse(combined)
+            .and_then(|(h, _)| Algorithm::from_id(h.algorithm))
+            .unwrap_or(Algorithm::Aes256gcm)
+            .name(),
+    };
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(
+            DecryptError::Failed
+            | DecryptError::PossibleWrongChannel
+            | DecryptError::DefaultSecretActive,
+        ) => "auth_failure",
+        Err(e) => e.code(),
+    };
+    state.metrics.decrypt_total.with_label_values(&[algorithm, outcome]).inc();
+    match outcome {
+        "success" => state.slo.record(true, state.clock.unix_now()),
+        "auth_failure" => state.slo.record(false, state.clock.unix_now()),
+        _ => {}
+    }
+    result
+}
+
+fn open_blob(
+    state: &AppState,
+    key_id: KeyId,
+    sources: &[KeySource],
+    format: CiphertextFormat,
+    combined: &[u8],
+    attachment_hash: Option<&[u8]>,
+) -> Result<Opened, DecryptError> {
+    let nonce_len = format.nonce_len();
+    let headered = matches!(format, CiphertextFormat::Aes256gcm);
+    let candidates = envelope::candidates(combined, nonce_len, TAG_LEN, headered);
+    if candidates.is_empty() {
+        return Err(DecryptError::TooShort);
+    }
+
+    // GCM plaintext is never longer than ciphertext minus tag, so oversized
+    // blobs are rejected before any plaintext buffer is allocated
+    let max = state.limits.max_decrypted_bytes;
+    if combined.len() - nonce_len - TAG_LEN > max {
+        log::warn!("Rejected decrypt for channel {}: plaintext exceeds {} bytes", key_id, max);
+        return Err(DecryptError::TooLarge(max));
+    }
+
+    // Try each reading of the blob under each key in turn: for derived keys,
+    // the current master secret first, then the previous one if rolling. A
+    // specific failure on the headered reading beats the generic auth
+    // failure of the legacy fallback.
+    let mut failure = OpenError::Auth;
+    let mut opened = None;
+    'readings: for sealed in &candidates {
+        // Headerless blobs predate algorithm choice and are always AES-GCM.
+        // An unknown id is never guessed at; it is reported unless the
+        // legacy reading opens.
+        let algorithm = match sealed.header {
+            Some(h) => match Algorithm::from_id(h.algorithm) {
+                Some(a) => a,
+                None => {
+                    if failure == OpenError::Auth {
+                        failure = OpenError::UnsupportedAlgorithm(h.algorithm);
+                    }
+                    continue;
+                }
+            },
+            None => Algorithm::Aes256gcm,
+        };
+        for &source in sources {
+            let attempt =
+                open_sealed(state, format, source, algorithm, key_id, sealed, attachment_hash);
+            match attempt {
+                Ok(plaintext) => {
+                    opened = Some((sealed, algorithm, source, plaintext));
+                    break 'readings;
+                }
+                // Every other attempt would hit the same stuck lock or full cache
+                Err(OpenError::KeyCache(e)) => return Err(DecryptError::KeyCache(e)),
+                Err(e) if failure == OpenError::Auth => failure = e,
+                Err(_) => {}
+            }
+        }
+    }
+
+    let Some((sealed, algorithm, source, plaintext)) = opened else {
+        log::error!("Decryption failed for channel {}", key_id);
+        // The likeliest cause outranks any other hint, but only when a
+        // derived key was tried; a caller's raw key doesn't depend on it
+        let default_secret = state.debug_errors
+            && state.default_secret_active
+            && sources.iter().any(|s| matches!(s, KeySource::Secret(_)));
+        return Err(match failure {
+            OpenError::RatchetWindow => DecryptError::RatchetWindow,
+            OpenError::AttachmentRequired => DecryptError::AttachmentRequired,
+            OpenError::UnsupportedAlgorithm(id) => DecryptError::UnsupportedAlgorithm(id),
+            OpenError::Auth | OpenError::WrongKey if default_secret => {
+                DecryptError::DefaultSecretActive
+            }
+            OpenError::WrongKey if state.debug_errors => DecryptError::PossibleWrongChannel,
+            OpenError::Auth | OpenError::WrongKey => DecryptError::Failed,
+            OpenError::KeyCache(e) => DecryptError::KeyCache(e),
+        });
+    };
+    let header = sealed.header;
+    // A caller's raw key is not the channel key, so it isn't counted
+    if let KeySource::Secret(_) = source {
+        record_use(state, key_id, AuditAction::Decrypt);
+    }
+
+    // A hash the blob never bound would be silently unverified
+    let bound = header.is_some_and(|h| h.has(envelope::FLAG_ATTACHMENT));
+    if attachment_hash.is_some() && !bound {
+        return Err(DecryptError::AttachmentNotBound);
+    }
+
+    if plaintext.len() > max {
+        log::warn!("Rejected decrypt for channel {}: plaintext exceeds {} bytes", key_id, max);
+        return Err(DecryptError::TooLarge(max));
+    }
+
+    let (message, metadata) = match header {
+        Some(h) if h.has(envelope::FLAG_FRAMED) => {
+            let parsed = envelope::unframe(&plaintext).and_then(|(message, metadata)| {
+                serde_json::from_slice(metadata).ok().map(|m| (message.to_vec(), Some(m)))
+            });
+            match parsed {
+                Some(parts) => parts,
+                None => {
+                    log::error!("Malformed framed payload for channel {}", key_id);
+                    return Err(DecryptError::Malformed);
+                }
+            }
+        }
+        _ => (plaintext, None),
+    };
+
+    Ok(Opened {
+        message,
+        metadata,
+        context: header.and_then(|h| h.context).map(<[u8]>::to_vec),
+        ratchet: header.is_some_and(|h| h.has(envelope::FLAG_RATCHET)),
+        commit: header.is_some_and(|h| h.has(envelope::FLAG_COMMIT)),
+        secret_version: source.version(),
+        algorithm: match format {
+            CiphertextFormat::Secretbox => "xsalsa20poly1305",
+            CiphertextFormat::Aes256gcm => algorithm.name(),
+        },
+        format_version: header.and(sealed.aad.get(1).copied()),
+        nonce: sealed.nonce.to_vec(),
+        ratchet_index: header.and_then(|h| h.ratchet_index),
+        sequence: header.and_then(|h| h.sequence),
+        produced_at: header.and_then(|h| h.produced_at),
+        attachment_bound: bound,
+    })
+}
+
+// ── POST /decrypt ──
+async fn decrypt(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<DecryptRequest>,
+) -> HttpResponse {
+    if let Err(resp) = data.authorize(&req, Scope::Decrypt, body.key_id()) {
+        return resp;
+    }
+    if body.verbose {
+        if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+            return resp;
+        }
+    }
+    if let Err(resp) = data.check_decrypt_token(&req, body.key_id()) {
+        return resp;
+    }
+    let _permit = match data.crypto_permit(Some(body.key_id())).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let combined = match decode_blob(body.input, &body.encrypted) {
+        Ok(d) => d,
+        Err(resp) => return resp,
+    };
+
+    let key_id = body.key_id();
+    let attachment_hash = body.attachment_hash.clone();
+    let blob = combined.into();
+    let client = client_id(&data, &req);
+    open_cached(&data, &client, key_id, body.format, blob, attachment_hash, body.verbose).await
+}
+
+// ── POST /decrypt?channel_id=… with an application/octet-stream body ──
+// The raw-bytes counterpart of `"output": "raw"` on /encrypt.
+async fn decrypt_bytes(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<DecryptQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let key_id = KeyId::new(query.org_id, query.channel_id).with_shared(query.shared_with);
+    if let Err(resp) = data.authorize(&req, Scope::Decrypt, key_id) {
+        return resp;
+    }
+    if query.verbose {
+        if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+            return resp;
+        }
+    }
+    if let Err(resp) = data.check_decrypt_token(&req, key_id) {
+        return resp;
+    }
+    let _permit = match data.crypto_permit(Some(key_id)).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let attachment_hash = query.attachment_hash.clone();
+    let client = client_id(&data, &req);
+    open_cached(&data, &client, key_id, query.format, body, attachment_hash, query.verbose).await
+}
+
+// ── Who is calling, for the decrypt lockout ──
+// The mTLS client identity when there is one, else the client address,
+// looking through any TRUSTED_PROXIES.
+fn client_id(state: &AppState, req: &HttpRequest) -> String {
+    if let Some(client) = req.conn_data::<tls::ClientIdentity>() {
+        return client.0.clone();
+    }
+    req.peer_addr().map_or_else(
+        || "-".to_owned(),
+        |addr| state.trusted_proxies.client_ip(addr.ip(), req.headers()).to_string(),
+    )
+}
+
+fn locked_out(state: &AppState, key_id: KeyId, until: u64) -> HttpResponse {
+    let retry_after = until.saturating_sub(state.clock.unix_now());
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json(QuotaExceededResponse {
+            error: format!("Too many failed decrypts for channel {}; try again later", key_id),
+            code: "too_many_failures".into(),
+            resets_at: until,
+        })
+}
+
+// ── Open a blob for /decrypt, through the decrypt cache when enabled ──
+// Only successful responses are cached, keyed by channel, format,
+// attachment hash and blob, so a hit is exactly what opening would return.
+// Verbose decrypts always open the blob.
+async fn open_cached(
+    state: &web::Data<AppState>,
+    client: &str,
+    key_id: KeyId,
+    format: CiphertextFormat,
+    combined: web::Bytes,
+    attachment: Option<String>,
+    verbose: bool,
+) -> HttpResponse {
+    if let Err(resp) = state.check_lockout(key_id, client) {
+        return resp;
+    }
+
+    let attachment_hash = attachment.as_deref().map(str::as_bytes);
+    let cache = state.decrypt_cache.as_ref().filter(|_| !verbose);
+    let cached = cache.map(|cache| {
+        let key = response_cache::decrypt_key(key_id, format as u8, attachment_hash, &combined);
+        (cache, key)
+    });
+    if let Some((cache, key)) = &cached {
+        if let Some(body) = cache.get(key) {
+            state.metrics.decrypt_cache_hits.inc();
+            // Still a decrypt as far as usage and the audit log are concerned
+            record_use(state, key_id, AuditAction::Decrypt);
+            log::info!("Decrypted message for channel {} from cache", key_id);
+            return HttpResponse::Ok().content_type("application/json").body(body.to_vec());
+        }
+        state.metrics.decrypt_cache_misses.inc();
+    }
+
+    let blob = combined.clone();
+    let opened = offload(state, combined.len(), move |state| {
+        let attachment_hash = attachment.as_deref().map(str::as_bytes);
+        Ok(open(state, key_id, format, &blob, attachment_hash))
+    })
+    .await;
+    let opened = match opened {
+        Ok(Ok(o)) => o,
+        Ok(Err(
+            e @ (DecryptError::Failed
+            | DecryptError::PossibleWrongChannel
+            | DecryptError::DefaultSecretActive),
+        )) => {
+            state.record_decrypt(key_id, client, false);
+            return e.response();
+        }
+        Ok(Err(e)) => return e.response(),
+        Err(resp) => return resp,
+    };
+    state.record_decrypt(key_id, client, true);
+    log::info!("Decrypted message for channel {}", key_id);
+
+    let Some((cache, key)) = cached else {
+        return decrypted(opened, verbose);
+    };
+    let body = match serde_json::to_vec(&decrypted_response(opened, false)) {
+        Ok(body) => Zeroizing::new(body),
+        Err(_) => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorResponse {
+                    error: "Failed to encode decrypt response".into(),
+                    code: "decrypt_failed".into(),
+                });
+        }
+    };
+    let resp = HttpResponse::Ok().content_type("application/json").body(body.to_vec());
+    cache.insert(key, body);
+    resp
+}
+
+fn decrypted(opened: Opened, verbose: bool) -> HttpResponse {
+    HttpResponse::Ok().json(decrypted_response(opened, verbose))
+}
+
+fn decrypted_response(opened: Opened, verbose: bool) -> DecryptResponse {
+    let details = verbose.then(|| DecryptDetails {
+        authenticated: true,
+        algorithm: opened.algorithm,
+        format_version: opened.format_version,
+        header_authenticated: opened.format_version.is_some(),
+        attachment_bound: opened.attachment_bound,
+        commitment_verified: opened.commit,
+        ratchet_index: opened.ratchet_index,
+        nonce: BASE64.encode(&opened.nonce),
+    });
+    // Non-text plaintext comes back base64-encoded rather than lossily decoded
+    let (message, binary) = match String::from_utf8(opened.message) {
+        Ok(text) => (text, false),
+        Err(e) => (BASE64.encode(e.as_bytes()), true),
+    };
+    let context = opened.context.map(|c| String::from_utf8_lossy(&c).into_owned());
+    DecryptResponse {
+        empty: message.is_empty(),
+        message,
+        binary,
+        metadata: opened.metadata,
+        context,
+        sequence: opened.sequence,
+        produced_at: opened.produced_at,
+        secret_version: opened.secret_version,
+        details,
+    }
+}
+
+// ── POST /decrypt/raw ──
+// Forensic recovery with a channel key exported elsewhere: the caller's key
+// replaces derivation for this one request and is zeroized afterwards, never
+// cached. Ratcheted messages cannot be opened this way.
+async fn decrypt_raw(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<RawDecryptRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+    let _permit = match data.crypto_permit(None).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let key = match BASE64.decode(body.key.as_bytes()) {
+        Ok(k) => Zeroizing::new(k),
+        Err(_) => {
+            return HttpResponse::BadRequest()
+                .json(ErrorResponse {
+                    error: "Invalid base64 key".into(),
+                    code: "invalid_key".into(),
+                });
+        }
+    };
+    if key.len() != KEY_LEN {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("Key must be {} bytes, got {}", KEY_LEN, key.len()),
+                code: "invalid_key_length".into(),
+            });
+    }
+
+    let combined = match decode_blob(body.decrypt.input, &body.decrypt.encrypted) {
+        Ok(d) => d,
+        Err(resp) => return resp,
+    };
+
+    let body = &body.decrypt;
+    let key_id = body.key_id();
+    let attachment_hash = body.attachment_hash.as_deref().map(str::as_bytes);
+    let sources = [KeySource::Raw(&key)];
+    let opened = match open_with(&data, key_id, &sources, body.format, &combined, attachment_hash) {
+        Ok(o) => o,
+        Err(e) => return e.response(),
+    };
+
+    log::warn!("Decrypted message for channel {} with a caller-supplied key", key_id);
+    decrypted(opened, body.verbose)
+}
+
+// Re-encrypt batches at least this long are spread across CPU cores
+const PARALLEL_REENCRYPT_MIN: usize = 64;
+
+// ── POST /jobs/reencrypt ──
+// One bounded step of a caller-driven migration onto the current master
+// secret and the channel's current algorithm, or the `algorithm` given. The job keeps no state: the
+// caller sends a page of items and the cursor from the previous call, and
+// gets back the cursor to resume from, so a crash costs at most one batch.
+async fn reencrypt_job(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<ReencryptJobRequest>,
+) -> HttpResponse {
+    // Opens and reseals under the same channel, so needs both scopes
+    for scope in [Scope::Decrypt, Scope::Encrypt] {
+        if let Err(resp) = data.authorize(&req, scope, body.key_id()) {
+            return resp;
+        }
+    }
+    if let Err(resp) = data.reject_in_maintenance() {
+        return resp;
+    }
+    let _permit = match data.crypto_permit(Some(body.key_id())).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+
+    let key_id = body.key_id();
+    let algorithm = match reencrypt_algorithm(&req, &data, key_id, body.algorithm) {
+        Ok(a) => a,
+        Err(resp) => return resp,
+    };
+
+    let ReencryptJobRequest { format, cursor, reason, items, .. } = body.into_inner();
+    let mut pending: Vec<ReencryptItem> =
+        items.into_iter().filter(|item| cursor.is_none_or(|c| item.id > c)).collect();
+    pending.sort_by_key(|item| item.id);
+    let batch_size = data.limits.reencrypt_batch_size;
+    let remaining = pending.len().saturating_sub(batch_size);
+    pending.truncate(batch_size);
+    let next_cursor = if remaining > 0 { pending.last().map(|item| item.id) } else { None };
+
+    // Large batches run on rayon's pool from a blocking thread, leaving the
+    // worker free; results come back in input order either way, and one
+    // item failing never affects another
+    let results: Vec<ReencryptResult> = if pending.len() < PARALLEL_REENCRYPT_MIN {
+        pending.iter().map(|item| reencrypt_result(&data, key_id, algorithm, format, item)).collect()
+    } else {
+        let state = data.clone();
+        let parallel = web::block(move || {
+            pending
+                .par_iter()
+                .map(|item| reencrypt_result(&state, key_id, algorithm, format, item))
+                .collect()
+        });
+        match parallel.await {
+            Ok(results) => results,
+            Err(e) => {
+                log::error!("Re-encrypt batch for channel {} failed: {}", key_id, e);
+                reporting::tag_failure("reencrypt_failed", key_id);
+                return HttpResponse::InternalServerError()
+                    .json(ErrorResponse {
+                        error: "Re-encrypt batch failed".into(),
+                        code: "reencrypt_failed".into(),
+                    });
+            }
+        }
+    };
+
+    if results.iter().any(|r| r.migrated) {
+        record_rotation(&data, key_id, reason);
+    }
+    let failed = results.iter().filter(|r| r.code.is_some()).count();
+    log::info!(
+        "Re-encrypt batch for channel {}: {} done, {} failed, {} remaining",
+        key_id, results.len() - failed, failed, remaining
+    );
+    HttpResponse::Ok().json(ReencryptJobResponse {
+        reencrypted: results.len() - failed,
+        failed,
+        remaining,
+        next_cursor,
+        results,
+    })
+}
+
+// ── The algorithm a re-encrypt job reseals under ──
+// The channel's own unless `target` names one, which turns the job into an
+// algorithm migration and so needs the admin token. A target its pin
+// disagrees with is refused; re-pin the channel first.
+fn reencrypt_algorithm(
+    req: &HttpRequest,
+    state: &AppState,
+    key_id: KeyId,
+    target: Option<Algorithm>,
+) -> Result<Algorithm, HttpResponse> {
+    if let Some(target) = target {
+        auth::require_admin(req, state.admin_token.as_deref())?;
+        log::info!("Re-encrypt job migrating channel {} to {}", key_id, target);
+    }
+    resolve_algorithm(state, key_id, target, false)
+}
+
+// JSON overhead allowed on an NDJSON item line past its base64 blob
+const NDJSON_LINE_SLACK: usize = 64 * 1024;
+
+// ── POST /jobs/reencrypt?channel_id=… with an application/x-ndjson body ──
+// The streaming form of the job, for batches too big to hold in memory:
+// each body line is one item, and each response line is its result, written
+// as soon as that item is done. Items run in arrival order with no batch
+// limit, so there is no paging; `cursor` still skips ids already handled. A
+// line that isn't an item gets an invalid_item line and the rest carry on.
+async fn reencrypt_ndjson(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<ReencryptJobQuery>,
+    body: web::Payload,
+) -> HttpResponse {
+    let key_id = query.key_id();
+    for scope in [Scope::Decrypt, Scope::Encrypt] {
+        if let Err(resp) = data.authorize(&req, scope, key_id) {
+            return resp;
+        }
+    }
+    if let Err(resp) = data.reject_in_maintenance() {
+        return resp;
+    }
+    let permit = match data.crypto_permit(Some(key_id)).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
+    let algorithm = match reencrypt_algorithm(&req, &data, key_id, query.algorithm) {
+        Ok(a) => a,
+        Err(resp) => return resp,
+    };
+
+    let (format, cursor) = (query.format, query.cursor);
+    let max_line = data.limits.max_decrypted_bytes.div_ceil(3) * 4 + NDJSON_LINE_SLACK;
+    log::info!("Streaming re-encrypt job for channel {}", key_id);
+    // The permit is held until the last result line is out. The rotation is
+    // taken by the first line that migrates, so it is recorded once.
+    let rotation = Some(query.reason);
+    let state = (body, Vec::new(), false, data.clone(), permit, rotation);
+    let lines = stream::unfold(Some(state), move |state| async move {
+        let (mut body, mut buf, mut ended, data, permit, mut rotation) = state?;
+        loop {
+            if let Some(end) = buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buf.drain(..=end).collect();
+                let out =
+                    reencrypt_line(&data, key_id, algorithm, format, cursor, &mut rotation, &line);
+                if let Some(out) = out {
+                    return Some((Ok(out), Some((body, buf, ended, data, permit, rotation))));
+                }
+                continue;
+            }
+            // A last line without a trailing newline still counts
+            if ended {
+                if buf.is_empty() {
+                    return None;
+                }
+                buf.push(b'\n');
+                continue;
+            }
+            if buf.len() > max_line {
+                log::warn!("Aborted re-encrypt stream for channel {}: line too long", key_id);
+                return Some((Err(ErrorPayloadTooLarge("NDJSON line too long")), None));
+            }
+            match body.next().await {
+                Some(Ok(bytes)) => buf.extend_from_slice(&bytes),
+                Some(Err(e)) => return Some((Err(ErrorBadRequest(e.to_string())), None)),
+                None => ended = true,
+            }
+        }
+    });
+
+    HttpResponse::Ok().content_type("application/x-ndjson").streaming(lines)
+}
+
+// One NDJSON result line for one body line; None for blank or skipped lines
+fn reencrypt_line(
+    state: &AppState,
+    key_id: KeyId,
+    algorithm: Algorithm,
+    format: CiphertextFormat,
+    cursor: Option<i64>,
+    rotation: &mut Option<RotationReason>,
+    line: &[u8],
+) -> Option<web::Bytes> {
+    if line.trim_ascii().is_empty() {
+        return None;
+    }
+    let encoded = match serde_json::from_slice::<ReencryptItem>(line) {
+        Ok(item) if cursor.is_some_and(|c| item.id <= c) => return None,
+        Ok(item) => {
+            let result = reencrypt_result(state, key_id, algorithm, format, &item);
+            if let Some(reason) = rotation.take_if(|_| result.migrated) {
+                record_rotation(state, key_id, reason);
+            }
+            serde_json::to_vec(&result)
+        }
+        Err(_) => serde_json::to_vec(&ErrorResponse {
+            error: "Line is not a re-encrypt item".into(),
+            code: "invalid_item".into(),
+        }),
+    };
+    let mut out = encoded.unwrap_or_default();
+    out.push(b'\n');
+    Some(out.into())
+}
+
+fn reencrypt_result(
+    state: &AppState,
+    key_id: KeyId,
+    algorithm: Algorithm,
+    format: CiphertextFormat,
+    item: &ReencryptItem,
+) -> ReencryptResult {
+    match reencrypt_one(state, key_id, algorithm, format, item) {
+        Ok((encrypted, migrated)) => {
+            ReencryptResult { id: item.id, encrypted: Some(encrypted), code: None, migrated }
+        }
+        Err(code) => {
+            ReencryptResult { id: item.id, encrypted: None, code: Some(code), migrated: false }
+        }
+    }
+}
+
+// Re-seal one item with the options it was sealed with, or name why not;
+// also whether it came off the previous master secret
+fn reencrypt_one(
+    state: &AppState,
+    key_id: KeyId,
+    algorithm: Algorithm,
+    format: CiphertextFormat,
+    item: &ReencryptItem,
+) -> Result<(String, bool), &'static str> {
+    let combined = decode_base64(&item.encrypted).ok_or("invalid_base64")?;
+    let attachment_hash = item.attachment_hash.as_deref().map(str::as_bytes);
+    let opened =
+        open(state, key_id, format, &combined, attachment_hash).map_err(DecryptError::code)?;
+
+    let opts = SealOptions {
+        algorithm,
+        metadata: opened.metadata.as_ref(),
+        context: opened.context.as_deref(),
+        ratchet: opened.ratchet,
+        commit: opened.commit,
+        attachment_hash,
+        sequence: opened.sequence.map_or(Sequence::Unnumbered, Sequence::Kept),
+        produced_at: opened.produced_at,
+    };
+    let sealed = seal(state, key_id, &opened.message, &opts).map_err(|_| "encryption_failed")?;
+    let migrated = opened.secret_version == Some(SecretVersion::Previous);
+    Ok((BASE64.encode(&sealed.blob), migrated))
+}
+
+// ── Note that a channel's data moved onto the current key generation ──
+// Once per generation: later batches of the same migration find the entry
+// already there.
+fn record_rotation(state: &AppState, key_id: KeyId, reason: RotationReason) {
+    let generation = key_fingerprint(&state.current_key(key_id));
+    let previous_generation = state
+        .previous_master_secret
+        .as_deref()
+        .map(|secret| key_fingerprint(&Zeroizing::new(derive_key(secret, key_id))));
+    {
+        let mut meta = state.key_meta.lock().unwrap();
+        let rotations = &mut meta.entry(key_id).or_default().rotations;
+        if rotations.last().is_some_and(|r| r.generation == generation) {
+            return;
+        }
+        rotations.push(Rotation {
+            generation,
+            previous_generation,
+            rotated_at: state.clock.unix_now(),
+            reason,
+        });
+    }
+    log::info!("Channel {} rotated onto the current master secret", key_id);
+    audit(state, key_id, AuditAction::Rotate);
+}
+
+// ── POST /keys/preload ──
+// Admin only: every id listed is derived and held in the cipher cache.
+async fn preload_keys(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<PreloadRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+    if body.channel_ids.len() > MAX_PRELOAD_CHANNELS {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("At most {} channels per request", MAX_PRELOAD_CHANNELS),
+                code: "too_many_channels".into(),
+            });
+    }
+    let mut preloaded = 0;
+    for &channel_id in &body.channel_ids {
+        match precompute_cipher(&data, KeyId::channel(channel_id)) {
+            Ok(built) => preloaded += built as usize,
+            Err(e) => return e.response(),
+        }
+    }
+
+    log::info!("Preloaded ciphers for {} channel(s)", preloaded);
+    HttpResponse::Ok().json(PreloadResponse { preloaded })
+}
+
+// ── POST /keys/exists ──
+async fn keys_exist(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<KeyExistsRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+    let results: Result<Vec<KeyExists>, KeyCacheError> = body
+        .channel_ids
+        .iter()
+        .map(|&channel_id| {
+            let exists = has_cached_key(&data, KeyId::channel(channel_id))?;
+            Ok(KeyExists { channel_id, exists })
+        })
+        .collect();
+
+    match results {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(e) => e.response(),
+    }
+}
+
+// ── POST /keys/fingerprints ──
+// Current-generation fingerprints for many channels at once, in request
+// order. Replicas sharing a master secret report the same values, so
+// polling each and comparing spots one that missed a rotation.
+async fn key_fingerprints(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<FingerprintsRequest>,
+) -> HttpResponse {
+    if body.channel_ids.len() > MAX_FINGERPRINT_CHANNELS {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("At most {} channels per request", MAX_FINGERPRINT_CHANNELS),
+                code: "too_many_channels".into(),
+            });
+    }
+    for &channel_id in &body.channel_ids {
+        let key_id = KeyId::new(body.org_id, channel_id);
+        if let Err(resp) = data.authorize(&req, Scope::Decrypt, key_id) {
+            return resp;
+        }
+    }
+
+    let fingerprints: Vec<FingerprintResponse> = body
+        .channel_ids
+        .iter()
+        .map(|&channel_id| {
+            let key_id = KeyId::new(body.org_id, channel_id);
+            FingerprintResponse {
+                channel_id,
+                org_id: body.org_id,
+                fingerprint: key_fingerprint(&data.current_key(key_id)),
+            }
+        })
+        .collect();
+    HttpResponse::Ok().json(fingerprints)
+}
+
+// ── POST /channels/{id}/algorithm ──
+async fn pin_algorithm(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<ChannelKeyQuery>,
+    body: web::Json<PinRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let key_id = query.key_id(path.into_inner());
+    let mut meta = data.key_meta.lock().unwrap();
+    meta.entry(key_id).or_default().pinned_algorithm = Some(body.algorithm);
+
+    log::info!("Pinned channel {} to {}", key_id, body.algorithm);
+    HttpResponse::Ok().json(PinResponse {
+        channel_id: key_id.channel_id,
+        org_id: key_id.org_id,
+        shared_with: key_id.shared_with,
+        algorithm: body.algorithm,
+    })
+}
+
+// ── GET /channels/{id}/policy ──
+async fn get_policy(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<ChannelKeyQuery>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let key_id = query.key_id(path.into_inner());
+    let policy = channel_policy(&data, key_id);
+    HttpResponse::Ok().json(PolicyResponse::new(key_id, policy))
+}
+
+// ── POST /channels/{id}/policy ──
+// Replaces the channel's whole policy; `{}` clears it.
+async fn set_policy(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<ChannelKeyQuery>,
+    body: web::Json<ChannelPolicy>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let key_id = query.key_id(path.into_inner());
+    let policy = body.into_inner();
+    let mut meta = data.key_meta.lock().unwrap();
+    meta.entry(key_id).or_default().policy = policy;
+
+    log::info!("Set encrypt policy for channel {}", key_id);
+    HttpResponse::Ok().json(PolicyResponse::new(key_id, policy))
+}
+
+fn channel_policy(state: &AppState, key_id: KeyId) -> ChannelPolicy {
+    let meta = state.key_meta.lock().unwrap();
+    meta.get(&key_id).map(|entry| entry.policy).unwrap_or_default()
+}
+
+// ── POST /channels/{id}/quota ──
+async fn set_quota(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<ChannelKeyQuery>,
+    body: web::Json<QuotaRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let key_id = query.key_id(path.into_inner());
+    let today = data.clock.unix_now() / SECS_PER_DAY;
+    let mut meta = data.key_meta.lock().unwrap();
+    let entry = meta.entry(key_id).or_default();
+    entry.daily_quota = body.daily_limit;
+
+    log::info!("Set daily encrypt quota for channel {} to {:?}", key_id, body.daily_limit);
+    HttpResponse::Ok().json(QuotaResponse {
+        channel_id: key_id.channel_id,
+        org_id: key_id.org_id,
+        shared_with: key_id.shared_with,
+        daily_limit: body.daily_limit.or(data.limits.daily_encrypt_quota),
+        used: if entry.usage.day == today { entry.usage.count } else { 0 },
+        resets_at: (today + 1) * SECS_PER_DAY,
+    })
+}
+
+// ── GET /channels/{id}/rotations ──
+// When and why the channel's key generation changed, oldest first.
+async fn channel_rotations(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<ChannelKeyQuery>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let key_id = query.key_id(path.into_inner());
+    let meta = data.key_meta.lock().unwrap();
+    let rotations = meta.get(&key_id).map(|entry| entry.rotations.clone()).unwrap_or_default();
+    HttpResponse::Ok().json(RotationsResponse {
+        channel_id: key_id.channel_id,
+        org_id: key_id.org_id,
+        shared_with: key_id.shared_with,
+        rotations,
+    })
+}
+
+// ── POST /tokens/issue ──
+// Mints a decrypt token for one channel; see tokens.rs for the format.
+async fn issue_token(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<TokenRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let ttl = body.ttl_secs.unwrap_or(DEFAULT_TOKEN_TTL_SECS);
+    if ttl == 0 || ttl > MAX_TOKEN_TTL_SECS {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("ttl_secs must be between 1 and {}", MAX_TOKEN_TTL_SECS),
+                code: "invalid_ttl".into(),
+            });
+    }
+
+    let key_id = body.key_id();
+    let expires_at = data.clock.unix_now() + ttl;
+    let claims = tokens::Claims {
+        channel_id: key_id.channel_id,
+        org_id: key_id.org_id,
+        shared_with: key_id.shared_with,
+        exp: expires_at,
+    };
+    let token = tokens::issue(&derive_token_key(&data.master_secret), &claims);
+
+    log::info!("Issued decrypt token for channel {} until {}", key_id, expires_at);
+    HttpResponse::Ok().json(TokenResponse { token, expires_at })
 }
 
-// ── Request / Response types ──
+// ── GET /channels/{id}/fingerprint ──
+// Fingerprint of the key new messages are sealed under; it changes whenever
+// MASTER_SECRET does.
+async fn channel_fingerprint(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<FingerprintQuery>,
+) -> HttpResponse {
+    let key_id =
+        KeyId { org_id: query.org_id, channel_id: path.into_inner(), shared_with: None };
+    if let Err(resp) = data.authorize(&req, Scope::Decrypt, key_id) {
+        return resp;
+    }
+    let key = data.current_key(key_id);
 
-#[derive(Deserialize)]
-struct EncryptRequest {
-    channel_id: i64,
-    message: String,
+    HttpResponse::Ok().json(FingerprintResponse {
+        channel_id: key_id.channel_id,
+        org_id: key_id.org_id,
+        fingerprint: key_fingerprint(&key),
+    })
 }
 
-#[derive(Serialize)]
-struct EncryptResponse {
-    encrypted: String,
+// ── GET /archive/public-key ──
+// What a signed envelope is checked against; safe to publish and to store
+// alongside the archive.
+async fn archive_public_key(data: web::Data<AppState>) -> HttpResponse {
+    let Some(signer) = &data.archive_signer else {
+        return HttpResponse::NotFound()
+            .json(ErrorResponse {
+                error: "Archive signing is not configured".into(),
+                code: "archive_signing_disabled".into(),
+            });
+    };
+    HttpResponse::Ok().json(ArchivePublicKeyResponse {
+        algorithm: "ed25519",
+        public_key: signer.public_key(),
+    })
 }
 
-#[derive(Deserialize)]
-struct DecryptRequest {
-    channel_id: i64,
-    encrypted: String,
+// ── GET /channels/{id}/fingerprints ──
+// Before migrating a channel: fingerprints of its key under the current and
+// previous master secrets, so an operator can see the rollover really
+// changes it. Admin only; the keys themselves never leave this function.
+async fn channel_fingerprints(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<FingerprintQuery>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let key_id =
+        KeyId { org_id: query.org_id, channel_id: path.into_inner(), shared_with: None };
+    let current = key_fingerprint(&data.current_key(key_id));
+    let previous = data
+        .previous_master_secret
+        .as_deref()
+        .filter(|_| !data.is_tenant(key_id))
+        .map(|secret| key_fingerprint(&Zeroizing::new(derive_key(secret, key_id))));
+
+    HttpResponse::Ok().json(FingerprintPairResponse {
+        channel_id: key_id.channel_id,
+        org_id: key_id.org_id,
+        changed: previous.as_ref().map(|previous| *previous != current),
+        current,
+        previous,
+    })
 }
 
-#[derive(Serialize)]
-struct DecryptResponse {
-    message: String,
+// ── GET /keys/{channel_id}/export ──
+// A controlled migration of one channel: its key under each master secret
+// held, wrapped for the caller's X25519 public key, so the key is never
+// returned in the clear. Each wrapped key's AAD is
+// `freecord/key-export/v1:{channel_id}:{org_id or empty}:{secret_version}`.
+// An unwrapped key opens the channel's messages through /decrypt/raw. Admin
+// only, and always audited.
+async fn export_channel_key(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<KeyExportQuery>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let key_id =
+        KeyId { org_id: query.org_id, channel_id: path.into_inner(), shared_with: None };
+    let wrapping = decode_base64(&query.recipient_key)
+        .and_then(|recipient| export_wrapping_key(&recipient));
+    let Some((ephemeral_public, wrapping_key)) = wrapping else {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: "recipient_key must be a base64 32-byte X25519 public key".into(),
+                code: "invalid_recipient_key".into(),
+            });
+    };
+    let wrapper = ChannelCipher::new(Algorithm::Aes256gcm, &wrapping_key[..]);
+
+    let versions = data.key_versions(key_id);
+    let mut keys = Vec::with_capacity(versions.len());
+    for version in versions {
+        let key = Zeroizing::new(derive_key(data.key_secret(version, key_id), key_id));
+        let aad = format!(
+            "freecord/key-export/v1:{}:{}:{}",
+            key_id.channel_id,
+            key_id.org_id.map(|id| id.to_string()).unwrap_or_default(),
+            match version {
+                SecretVersion::Current => "current",
+                SecretVersion::Previous => "previous",
+            }
+        );
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let Ok(sealed) = wrapper.encrypt(&nonce, Payload { msg: &key, aad: aad.as_bytes() }) else {
+            log::error!("Wrapping exported key failed for channel {}", key_id);
+            return HttpResponse::InternalServerError()
+                .json(ErrorResponse {
+                    error: "Key export failed".into(),
+                    code: "export_failed".into(),
+                });
+        };
+        keys.push(ExportedKey {
+            secret_version: version,
+            fingerprint: key_fingerprint(&key),
+            wrapped: BASE64.encode([&nonce[..], &sealed].concat()),
+        });
+    }
+
+    audit(&data, key_id, AuditAction::Export);
+    log::warn!("Exported {} key(s) for channel {} on admin request", keys.len(), key_id);
+    HttpResponse::Ok().json(KeyExportResponse {
+        channel_id: key_id.channel_id,
+        org_id: key_id.org_id,
+        scheme: "x25519-hkdf-sha256-aes256gcm",
+        ephemeral_public_key: BASE64.encode(ephemeral_public),
+        keys,
+    })
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
+// ── GET /channels/{id}/usage ──
+// Encrypts and decrypts since startup; counters live in memory only.
+async fn channel_usage(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    path: web::Path<i64>,
+    query: web::Query<FingerprintQuery>,
+) -> HttpResponse {
+    let key_id =
+        KeyId { org_id: query.org_id, channel_id: path.into_inner(), shared_with: None };
+    if let Err(resp) = data.authorize(&req, Scope::Decrypt, key_id) {
+        return resp;
+    }
+    let usage = data.key_usage.read().unwrap().get(&key_id).cloned();
+    let (use_count, last_used_at, bytes_sealed) = match usage {
+        Some(u) => (
+            u.use_count.load(Ordering::Relaxed),
+            Some(u.last_used_at.load(Ordering::Relaxed)),
+            u.bytes_sealed.load(Ordering::Relaxed),
+        ),
+        None => (0, None, 0),
+    };
+
+    HttpResponse::Ok().json(UsageResponse {
+        channel_id: key_id.channel_id,
+        org_id: key_id.org_id,
+        use_count,
+        last_used_at,
+        bytes_sealed,
+        byte_limit: data.limits.key_byte_limit,
+    })
 }
 
-// ── Derive a per-channel 256-bit key from master secret + channel_id ──
-fn derive_channel_key(master_secret: &str, channel_id: i64) -> Vec<u8> {
-    let mut hasher = Sha256::new();
-    hasher.update(master_secret.as_bytes());
-    hasher.update(channel_id.to_le_bytes());
-    hasher.finalize().to_vec()
+// ── GET /metrics ──
+async fn metrics(data: web::Data<AppState>) -> HttpResponse {
+    if !data.metrics_enabled {
+        return HttpResponse::NotFound().finish();
+    }
+
+    // A stuck lock leaves the gauges at their last value
+    if let Ok((entries, bytes)) = key_cache_estimate(&data) {
+        data.metrics.cached_keys.set(entries as i64);
+        data.metrics.key_cache_bytes.set(bytes as i64);
+    }
+    let ratio = data.slo.ratio(data.clock.unix_now());
+    data.metrics.decrypt_success_ratio.set(ratio.unwrap_or(f64::NAN));
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(data.metrics.render())
+}
+
+// ── POST /admin/maintenance ──
+// Freezes writes during a migration window; decrypt and health keep serving.
+async fn set_maintenance(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<MaintenanceRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let was = data.maintenance.swap(body.enabled, Ordering::AcqRel);
+    if was != body.enabled {
+        log::warn!("Maintenance mode {}", if body.enabled { "enabled" } else { "disabled" });
+    }
+    HttpResponse::Ok().json(MaintenanceResponse { maintenance: body.enabled })
 }
 
-// ── Get or create the key for a channel ──
-fn get_or_create_key(state: &AppState, channel_id: i64) -> Vec<u8> {
-    let mut keys = state.keys.lock().unwrap();
-    keys.entry(channel_id)
-        .or_insert_with(|| derive_channel_key(&state.master_secret, channel_id))
-        .clone()
+// ── POST /admin/flush-keys ──
+// Incident response: evicts every cached channel cipher, zeroizing its key,
+// so no derived key stays resident. Channels re-derive on their next
+// request. Ratchet chains are kept, since their keys cannot be re-derived.
+async fn flush_keys(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let cleared = match purge_ciphers(&data) {
+        Ok(cleared) => cleared,
+        Err(e) => return e.response(),
+    };
+    log::warn!("Flushed {} cached channel key(s) on admin request", cleared);
+    if let Some(cache) = &data.decrypt_cache {
+        // Outside the log call, whose arguments are skipped when warn is off
+        let responses = cache.clear();
+        log::warn!("Flushed {} cached decrypt response(s)", responses);
+    }
+    HttpResponse::Ok().json(FlushKeysResponse { cleared })
 }
 
-// ── POST /encrypt ──
-async fn encrypt(
+// ── POST /admin/master-canary ──
+// Before a master-secret rollover: reports which of the current and
+// previous secrets opens each sample. Plaintext is never returned, and
+// nothing is recorded: no usage, metrics, tokens or ratchet steps. Keys are
+// derived per sample, so the cipher cache is neither filled nor counted.
+async fn master_canary(
+    req: HttpRequest,
     data: web::Data<AppState>,
-    body: web::Json<EncryptRequest>,
+    body: web::Json<CanaryRequest>,
 ) -> HttpResponse {
-    let key_bytes = get_or_create_key(&data, body.channel_id);
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+    if body.samples.len() > MAX_CANARY_SAMPLES {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("At most {} samples per canary", MAX_CANARY_SAMPLES),
+                code: "too_many_samples".into(),
+            });
+    }
+    let _permit = match data.crypto_permit(None).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
+    };
 
-    let cipher = match Aes256Gcm::new_from_slice(&key_bytes) {
-        Ok(c) => c,
-        Err(e) => {
-            log::error!("Failed to create cipher: {}", e);
-            return HttpResponse::InternalServerError()
-                .json(ErrorResponse { error: "Encryption init failed".into() });
-        }
+    let previous_configured = data.previous_master_secret.is_some();
+    let mut results = Vec::with_capacity(body.samples.len());
+    for (index, sample) in body.samples.iter().enumerate() {
+        let Ok(combined) = decode_blob(sample.input, &sample.encrypted) else {
+            results.push(CanaryResult {
+                index,
+                current: false,
+                previous: None,
+                code: Some("invalid_encoding"),
+            });
+            continue;
+        };
+        let opens = |version| {
+            let attachment_hash = sample.attachment_hash.as_deref().map(str::as_bytes);
+            let source = KeySource::Uncached(version);
+            opens_under(&data, source, sample.key_id(), sample.format, &combined, attachment_hash)
+        };
+        let current = match opens(SecretVersion::Current) {
+            Ok(ok) => ok,
+            Err(e) => return e.response(),
+        };
+        let has_previous = previous_configured && !data.is_tenant(sample.key_id());
+        let previous = match has_previous.then(|| opens(SecretVersion::Previous)) {
+            Some(Ok(ok)) => Some(ok),
+            Some(Err(e)) => return e.response(),
+            None => None,
+        };
+        results.push(CanaryResult { index, current, previous, code: None });
+    }
+
+    log::info!("Master canary checked {} sample(s)", results.len());
+    HttpResponse::Ok().json(CanaryResponse { previous_configured, results })
+}
+
+// ── POST /admin/test-secret ──
+// Before rolling out a new master secret: reports whether a candidate opens
+// each sample. The candidate is only held for this request, zeroized on drop
+// and never logged; its channel keys bypass the cipher cache.
+async fn test_secret(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<TestSecretRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+    if body.candidate.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: "candidate must not be empty".into(),
+                code: "invalid_candidate".into(),
+            });
+    }
+    if body.samples.len() > MAX_CANARY_SAMPLES {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: format!("At most {} samples per test", MAX_CANARY_SAMPLES),
+                code: "too_many_samples".into(),
+            });
+    }
+    let _permit = match data.crypto_permit(None).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
     };
 
-    // Generate a random 12-byte nonce
-    let mut nonce_bytes = [0u8; 12];
-    OsRng.fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
+    let mut results = Vec::with_capacity(body.samples.len());
+    for (index, sample) in body.samples.iter().enumerate() {
+        let Ok(combined) = decode_blob(sample.input, &sample.encrypted) else {
+            results.push(TestSecretResult { index, opens: false, code: Some("invalid_encoding") });
+            continue;
+        };
+        let key = Zeroizing::new(derive_key(&body.candidate, sample.key_id()));
+        let attachment_hash = sample.attachment_hash.as_deref().map(str::as_bytes);
+        let source = KeySource::Raw(&key);
+        match opens_under(&data, source, sample.key_id(), sample.format, &combined, attachment_hash)
+        {
+            Ok(opens) => results.push(TestSecretResult { index, opens, code: None }),
+            Err(e) => return e.response(),
+        }
+    }
 
-    match cipher.encrypt(nonce, body.message.as_bytes()) {
-        Ok(ciphertext) => {
-            // Pack as: base64(nonce + ciphertext)
-            let mut combined = nonce_bytes.to_vec();
-            combined.extend_from_slice(&ciphertext);
-            let encoded = BASE64.encode(&combined);
+    log::info!("Candidate master secret tested against {} sample(s)", results.len());
+    HttpResponse::Ok().json(TestSecretResponse {
+        matches_current: bool::from(body.candidate.as_bytes().ct_eq(data.master_secret.as_bytes())),
+        results,
+    })
+}
 
-            log::info!("Encrypted message for channel {}", body.channel_id);
-            HttpResponse::Ok().json(EncryptResponse { encrypted: encoded })
+// Whether any reading of the blob authenticates under one channel key
+fn opens_under(
+    state: &AppState,
+    source: KeySource,
+    key_id: KeyId,
+    format: CiphertextFormat,
+    combined: &[u8],
+    attachment_hash: Option<&[u8]>,
+) -> Result<bool, KeyCacheError> {
+    let headered = matches!(format, CiphertextFormat::Aes256gcm);
+    for sealed in envelope::candidates(combined, format.nonce_len(), TAG_LEN, headered) {
+        let algorithm = match sealed.header {
+            Some(h) => match Algorithm::from_id(h.algorithm) {
+                Some(a) => a,
+                None => continue,
+            },
+            None => Algorithm::Aes256gcm,
+        };
+        match open_sealed(state, format, source, algorithm, key_id, &sealed, attachment_hash) {
+            Ok(_) => return Ok(true),
+            Err(OpenError::KeyCache(e)) => return Err(e),
+            Err(_) => {}
         }
-        Err(e) => {
-            log::error!("Encryption failed: {}", e);
-            HttpResponse::InternalServerError()
-                .json(ErrorResponse { error: "Encryption failed".into() })
+    }
+    Ok(false)
+}
+
+// ── POST /channels/derive-shared ──
+// Derives and caches the key two channels share; either channel id may be
+// given first. Only its fingerprint is returned.
+async fn derive_shared_key(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<SharedKeyRequest>,
+) -> HttpResponse {
+    let &[a, b] = body.channel_ids.as_slice() else {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: "channel_ids must hold exactly two channels".into(),
+                code: "invalid_channel_ids".into(),
+            });
+    };
+    if a == b {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: "A channel cannot share a key with itself".into(),
+                code: "invalid_channel_ids".into(),
+            });
+    }
+    // The key seals and opens for both channels, so the caller needs both
+    // scopes on each of them
+    for channel_id in [a, b] {
+        for scope in [Scope::Decrypt, Scope::Encrypt] {
+            if let Err(resp) = data.authorize(&req, scope, KeyId::new(body.org_id, channel_id)) {
+                return resp;
+            }
         }
     }
+
+    let key_id = KeyId::shared(body.org_id, a, b);
+    if let Err(e) = precompute_cipher(&data, key_id) {
+        return e.response();
+    }
+    let key = data.current_key(key_id);
+
+    log::info!("Derived shared key for channels {}", key_id);
+    HttpResponse::Ok().json(SharedKeyResponse {
+        channel_id: key_id.channel_id,
+        shared_with: a.max(b),
+        org_id: key_id.org_id,
+        fingerprint: key_fingerprint(&key),
+    })
 }
 
-// ── POST /decrypt ──
-async fn decrypt(
+// ── POST /index-tokens ──
+// For the search service: decrypts a message and returns only the blind
+// index tokens of its words (see search.rs); the plaintext never leaves.
+// Tokens follow the channel key, so they change along with MASTER_SECRET.
+async fn index_tokens(
+    req: HttpRequest,
     data: web::Data<AppState>,
     body: web::Json<DecryptRequest>,
 ) -> HttpResponse {
-    let key_bytes = get_or_create_key(&data, body.channel_id);
-
-    let cipher = match Aes256Gcm::new_from_slice(&key_bytes) {
-        Ok(c) => c,
-        Err(e) => {
-            log::error!("Failed to create cipher: {}", e);
-            return HttpResponse::InternalServerError()
-                .json(ErrorResponse { error: "Decryption init failed".into() });
-        }
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+    let _permit = match data.crypto_permit(Some(body.key_id())).await {
+        Ok(p) => p,
+        Err(resp) => return resp,
     };
 
-    let combined = match BASE64.decode(&body.encrypted) {
+    let combined = match decode_blob(body.input, &body.encrypted) {
         Ok(d) => d,
-        Err(e) => {
-            log::error!("Base64 decode failed: {}", e);
-            return HttpResponse::BadRequest()
-                .json(ErrorResponse { error: "Invalid base64".into() });
-        }
+        Err(resp) => return resp,
     };
 
-    if combined.len() < 12 {
+    let key_id = body.key_id();
+    let attachment_hash = body.attachment_hash.as_deref().map(str::as_bytes);
+    let opened = match open(&data, key_id, body.format, &combined, attachment_hash) {
+        Ok(o) => o,
+        Err(e) => return e.response(),
+    };
+    let message = Zeroizing::new(opened.message);
+    let Ok(text) = std::str::from_utf8(&message) else {
         return HttpResponse::BadRequest()
-            .json(ErrorResponse { error: "Ciphertext too short".into() });
+            .json(ErrorResponse {
+                error: "Message is not text, so has no terms to index".into(),
+                code: "not_text".into(),
+            });
+    };
+
+    let channel_key = data.current_key(key_id);
+    let index_key = Zeroizing::new(derive_index_key(&channel_key));
+    let tokens = search::index_tokens(&index_key[..], text);
+
+    log::info!("Computed {} index token(s) for channel {}", tokens.len(), key_id);
+    HttpResponse::Ok().json(IndexTokensResponse { tokens })
+}
+
+// ── POST /sign/ed25519 ──
+// Signs under the channel's Ed25519 key (see `derive_signing_key`). Unlike
+// X-Signature, anyone holding the returned public key can check the result,
+// offline or through /verify/ed25519, without being able to sign.
+async fn sign_ed25519(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<Ed25519Request>,
+) -> HttpResponse {
+    let key_id = body.key_id();
+    if let Err(resp) = data.authorize(&req, Scope::Encrypt, key_id) {
+        return resp;
+    }
+
+    let pair = derive_signing_key(&data.current_key(key_id));
+    let signature = pair.sign(body.message.as_bytes());
+
+    log::info!("Signed message for channel {}", key_id);
+    HttpResponse::Ok().json(Ed25519SignResponse {
+        signature: BASE64.encode(signature.as_ref()),
+        public_key: BASE64.encode(pair.public_key().as_ref()),
+    })
+}
+
+// ── POST /verify/ed25519 ──
+// Checks a signature against the channel's current public key. The key is
+// no secret, but it is the channel's, so the caller needs decrypt scope.
+async fn verify_ed25519_signature(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<Ed25519Request>,
+) -> HttpResponse {
+    let key_id = body.key_id();
+    if let Err(resp) = data.authorize(&req, Scope::Decrypt, key_id) {
+        return resp;
     }
+    let Some(signature) = body.signature.as_deref().and_then(decode_base64) else {
+        return HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: "signature is required, as base64".into(),
+                code: "invalid_signature".into(),
+            });
+    };
+
+    let pair = derive_signing_key(&data.current_key(key_id));
+    let public_key = pair.public_key().as_ref();
+    HttpResponse::Ok().json(Ed25519VerifyResponse {
+        valid: verify_ed25519(public_key, body.message.as_bytes(), &signature),
+        public_key: BASE64.encode(public_key),
+    })
+}
 
-    // Split nonce (first 12 bytes) from ciphertext
-    let (nonce_bytes, ciphertext) = combined.split_at(12);
-    let nonce = Nonce::from_slice(nonce_bytes);
+// ── POST /admin/log-level ──
+// Changes verbosity in place, so the cipher cache survives the change.
+async fn set_log_level(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    body: web::Json<LogLevelRequest>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
 
-    match cipher.decrypt(nonce, ciphertext) {
-        Ok(plaintext) => {
-            let message = String::from_utf8_lossy(&plaintext).to_string();
-            log::info!("Decrypted message for channel {}", body.channel_id);
-            HttpResponse::Ok().json(DecryptResponse { message })
+    match logging::set_filter(&body.level) {
+        Ok(max_level) => {
+            log::warn!("Log filter changed to {:?}", body.level);
+            HttpResponse::Ok().json(LogLevelResponse {
+                level: body.level.clone(),
+                max_level: max_level.to_string(),
+            })
         }
+        Err(e) => HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: e,
+                code: "invalid_log_level".into(),
+            }),
+    }
+}
+
+// ── Monobit check over a fresh OsRng sample ──
+// FIPS 140-2 bounds: 20,000 bits must contain between 9,725 and 10,275 ones.
+fn rng_is_healthy() -> bool {
+    let mut sample = [0u8; 2500];
+    if let Err(e) = OsRng.try_fill_bytes(&mut sample) {
+        log::error!("OsRng failed: {}", e);
+        return false;
+    }
+    monobit_passes(&sample)
+}
+
+fn monobit_passes(sample: &[u8; 2500]) -> bool {
+    let ones: u32 = sample.iter().map(|b| b.count_ones()).sum();
+    (9725..=10275).contains(&ones)
+}
+
+// ── GET /diag/rng ──
+// Reports only the verdict; the sampled bytes never leave this function.
+async fn diag_rng(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    let healthy = rng_is_healthy();
+    if !healthy {
+        log::error!("RNG health check failed");
+    }
+    HttpResponse::Ok().json(RngHealthResponse { source: "os", healthy })
+}
+
+// ── GET /admin/crypto-selftest ──
+// The startup known-answer run, not a fresh one; a build that failed it
+// never starts serving.
+async fn crypto_selftest(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    HttpResponse::Ok().json(&data.crypto_selftest)
+}
+
+// ── GET /audit/export?from=…&to=…&channel_id=…&org_id=… ──
+// The AUDIT_SINK=file log as NDJSON, read a line at a time so the whole
+// log is never held; see `audit::ExportFilter`. Lines appended while the
+// export runs may or may not be included, and a line still being written
+// ends it. Admin only.
+async fn export_audit(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<audit::ExportFilter>,
+) -> HttpResponse {
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+    let Some(path) = data.audit.as_ref().and_then(|sink| sink.export_path()) else {
+        return HttpResponse::NotFound()
+            .json(ErrorResponse {
+                error: "Audit export needs AUDIT_SINK=file".into(),
+                code: "audit_export_unavailable".into(),
+            });
+    };
+    let file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
         Err(e) => {
-            log::error!("Decryption failed: {}", e);
-            HttpResponse::BadRequest()
-                .json(ErrorResponse { error: "Decryption failed".into() })
+            log::error!("Cannot open audit log for export: {}", e);
+            return HttpResponse::InternalServerError()
+                .json(ErrorResponse {
+                    error: "Cannot read the audit log".into(),
+                    code: "audit_export_failed".into(),
+                });
         }
+    };
+
+    log::info!("Streaming audit export");
+    let filter = query.into_inner();
+    let state = (tokio::io::BufReader::new(file), filter);
+    let lines = stream::unfold(Some(state), |state| async move {
+        let (mut reader, filter) = state?;
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) => return None,
+                Ok(_) if line.last() != Some(&b'\n') => return None,
+                Ok(_) if filter.matches(&line) => {
+                    return Some((Ok(web::Bytes::from(line)), Some((reader, filter))))
+                }
+                Ok(_) => {}
+                Err(e) => return Some((Err(ErrorInternalServerError(e)), None)),
+            }
+        }
+    });
+
+    HttpResponse::Ok().content_type("application/x-ndjson").streaming(lines)
+}
+
+// ── GET /metrics/snapshot ──
+// Counters since the last reset, for test runs against a long-lived instance.
+async fn metrics_snapshot(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    if !data.metrics_enabled {
+        return HttpResponse::NotFound().finish();
+    }
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
     }
+
+    HttpResponse::Ok().json(data.metrics.snapshot())
+}
+
+// ── POST /metrics/reset ──
+async fn metrics_reset(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    if !data.metrics_enabled {
+        return HttpResponse::NotFound().finish();
+    }
+    if let Err(resp) = auth::require_admin(&req, data.admin_token.as_deref()) {
+        return resp;
+    }
+
+    data.metrics.reset();
+    log::info!("Metrics reset");
+    HttpResponse::Ok().json(data.metrics.snapshot())
+}
+
+// ── GET /algorithms ──
+// Unauthenticated: describes only what the server supports, not any key.
+async fn list_algorithms(data: web::Data<AppState>) -> HttpResponse {
+    let algorithms: Vec<AlgorithmInfo> = Algorithm::ALL
+        .iter()
+        .map(|&algorithm| AlgorithmInfo {
+            name: algorithm.name(),
+            id: algorithm.id(),
+            key_bytes: algorithm.key_len(),
+            nonce_bytes: NONCE_LEN,
+            tag_bytes: TAG_LEN,
+            nonce_misuse_resistant: false,
+            recommended: algorithm == data.default_algorithm,
+        })
+        .collect();
+    HttpResponse::Ok().json(algorithms)
 }
 
 // ── Health check ──
-async fn health() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+// The key cache figures are null if its lock is stuck; health still answers.
+async fn health(data: web::Data<AppState>) -> HttpResponse {
+    let estimate = key_cache_estimate(&data).ok();
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "cached_keys": estimate.map(|(entries, _)| entries),
+        "key_cache_bytes": estimate.map(|(_, bytes)| bytes),
+    }))
+}
+
+// ── Fallbacks, so every error carries a parseable ErrorResponse body ──
+
+async fn not_found() -> HttpResponse {
+    HttpResponse::NotFound()
+        .json(ErrorResponse {
+            error: "No such route".into(),
+            code: "not_found".into(),
+        })
+}
+
+fn method_not_allowed(allowed: &[Method]) -> HttpResponse {
+    let allowed = allowed.iter().map(Method::as_str).collect::<Vec<_>>().join(", ");
+    HttpResponse::MethodNotAllowed()
+        .insert_header((header::ALLOW, allowed.as_str()))
+        .json(ErrorResponse {
+            error: format!("Method not allowed; use {}", allowed),
+            code: "method_not_allowed".into(),
+        })
+}
+
+// ── JSON body rejections as ErrorResponse, with the content type called out ──
+fn json_error(err: JsonPayloadError, req: &HttpRequest) -> actix_web::Error {
+    let response = match &err {
+        JsonPayloadError::ContentType => {
+            log::warn!("Rejected non-JSON body to {}", req.path());
+            HttpResponse::BadRequest()
+                .json(ErrorResponse {
+                    error: "Content-Type must be application/json".into(),
+                    code: "invalid_content_type".into(),
+                })
+        }
+        JsonPayloadError::Overflow { limit }
+        | JsonPayloadError::OverflowKnownLength { limit, .. } => HttpResponse::PayloadTooLarge()
+            .json(ErrorResponse {
+                error: format!("JSON body exceeds {} bytes", limit),
+                code: "payload_too_large".into(),
+            }),
+        _ => HttpResponse::BadRequest()
+            .json(ErrorResponse {
+                error: err.to_string(),
+                code: "invalid_json".into(),
+            }),
+    };
+    InternalError::from_response(err, response).into()
+}
+
+// ── Query string rejections as ErrorResponse ──
+fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let response = HttpResponse::BadRequest()
+        .json(ErrorResponse {
+            error: err.to_string(),
+            code: "invalid_query".into(),
+        });
+    InternalError::from_response(err, response).into()
+}
+
+// ── One route on one method; any other method on the path gets a 405 ──
+fn endpoint<F, Args>(path: &str, method: Method, handler: F) -> Resource
+where
+    F: Handler<Args>,
+    Args: FromRequest + 'static,
+    F::Output: Responder + 'static,
+{
+    let allowed = method.clone();
+    web::resource(path)
+        .route(web::method(method).to(handler))
+        .default_service(web::to(move || {
+            let response = method_not_allowed(std::slice::from_ref(&allowed));
+            async move { response }
+        }))
+}
+
+// ── A GET and a POST on one path, e.g. reading and replacing a setting ──
+fn get_post_endpoint<G, GArgs, P, PArgs>(path: &str, get: G, post: P) -> Resource
+where
+    G: Handler<GArgs>,
+    GArgs: FromRequest + 'static,
+    G::Output: Responder + 'static,
+    P: Handler<PArgs>,
+    PArgs: FromRequest + 'static,
+    P::Output: Responder + 'static,
+{
+    web::resource(path)
+        .route(web::get().to(get))
+        .route(web::post().to(post))
+        .default_service(web::to(|| {
+            let response = method_not_allowed(&[Method::GET, Method::POST]);
+            async move { response }
+        }))
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
-    env_logger::init();
+    logging::init();
+    let diagnostics = ShutdownDiagnostics::new();
+    let result = run(&diagnostics).await;
+    diagnostics.finish(&result);
+    result
+}
+
+async fn run(diagnostics: &ShutdownDiagnostics) -> std::io::Result<()> {
+    let _sentry = reporting::init();
+
+    let crypto_selftest = selftest::run();
+    if !crypto_selftest.passed {
+        let failed: Vec<&str> =
+            crypto_selftest.vectors.iter().filter(|v| !v.passed).map(|v| v.name).collect();
+        let e = format!("Crypto self-test failed: {}", failed.join(", "));
+        log::error!("{}", e);
+        reporting::capture_failure("crypto_selftest_failed", None, &e);
+        return Err(std::io::Error::other(e));
+    }
+    log::info!("Crypto self-test passed ({} vectors)", crypto_selftest.vectors.len());
+
+    // Fetched once, before anything can serve; a failed fetch is fatal
+    let provider = match secrets::provider_from_env() {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!("Invalid master secret configuration: {}", e);
+            reporting::capture_failure("invalid_secret_config", None, "Invalid master secret configuration");
+            return Err(std::io::Error::other(e));
+        }
+    };
+    let master_secret = match provider.fetch() {
+        Ok(secret) => secret,
+        Err(e) => {
+            log::error!("Failed to load master secret from {}: {}", provider.name(), e);
+            reporting::capture_failure(
+                "secret_fetch_failed",
+                None,
+                &format!("Failed to load master secret from {}", provider.name()),
+            );
+            return Err(std::io::Error::other(e));
+        }
+    };
+    log::info!("Loaded master secret from {}", provider.name());
+    let tenant_secrets = match secrets::tenant_secrets_from_env() {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            log::error!("Invalid tenant secret configuration: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+    if !tenant_secrets.is_empty() {
+        log::info!("Loaded master secrets for {} tenant(s)", tenant_secrets.len());
+    }
+    let default_secret_active = secrets::is_default(&master_secret);
+    if default_secret_active {
+        log::warn!("MASTER_SECRET is not set; running on the default secret, which is public");
+    }
+
+    let signing_key = if std::env::var("RESPONSE_SIGNING").is_ok_and(|v| v == "true") {
+        match std::env::var("RESPONSE_SIGNING_KEY") {
+            Ok(key) if !key.is_empty() => Some(web::Data::new(signing::SigningKey(key.into_bytes()))),
+            _ => {
+                log::error!("RESPONSE_SIGNING=true requires RESPONSE_SIGNING_KEY");
+                return Err(std::io::Error::other("RESPONSE_SIGNING_KEY is not set"));
+            }
+        }
+    } else {
+        None
+    };
+    let archive_signer = match signing::ArchiveSigner::from_env() {
+        Ok(signer) => signer,
+        Err(e) => {
+            log::error!("Invalid archive signing key: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+    let compress_threshold = compression::threshold_from_env().map(web::Data::new);
+
+    let jwt = match auth::JwtAuth::from_env() {
+        Ok(jwt) => jwt,
+        Err(e) => {
+            log::error!("Invalid JWT configuration: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+    if jwt.is_none() && !tenant_secrets.is_empty() {
+        log::warn!("TENANT_SECRETS_FILE is set without JWT auth; tenant channels refuse all use");
+    }
+
+    let audit = match audit::sink_from_env() {
+        Ok(sink) => sink,
+        Err(e) => {
+            log::error!("Invalid audit configuration: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+    if let Some(sink) = &audit {
+        log::info!("Audit log enabled: {}", sink.name());
+    }
 
-    let master_secret = std::env::var("MASTER_SECRET")
-        .unwrap_or_else(|_| "default-secret-change-me".to_string());
+    let trusted_proxies = match TrustedProxies::from_env() {
+        Ok(proxies) => proxies,
+        Err(e) => {
+            log::error!("Invalid proxy configuration: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+
+    let default_algorithm = match default_algorithm_from_env() {
+        Ok(algorithm) => algorithm,
+        Err(e) => {
+            log::error!("Invalid algorithm configuration: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+
+    let key_cache_overflow = match KeyCacheOverflow::from_env() {
+        Ok(overflow) => overflow,
+        Err(e) => {
+            log::error!("Invalid key cache configuration: {}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
 
     log::info!("Starting encryption service on port 8001");
 
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
     let state = web::Data::new(AppState {
-        keys: Mutex::new(HashMap::new()),
+        ciphers: parking_lot::Mutex::new(HashMap::new()),
+        lock_timeout: Duration::from_millis(
+            std::env::var("LOCK_TIMEOUT_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1000),
+        ),
+        key_cache_ttl: std::env::var("KEY_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs),
+        max_cached_keys: std::env::var("MAX_CACHED_KEYS").ok().and_then(|v| v.parse().ok()),
+        key_cache_overflow,
+        key_meta: Mutex::new(HashMap::new()),
+        key_usage: RwLock::new(HashMap::new()),
+        pin_on_first_use: std::env::var("PIN_ALGORITHM_ON_FIRST_USE").is_ok_and(|v| v == "true"),
+        default_algorithm,
+        encrypt_timestamps: std::env::var("ENCRYPT_TIMESTAMPS").is_ok_and(|v| v == "true"),
+        reject_invalid_unicode: std::env::var("REJECT_INVALID_UNICODE")
+            .map_or(true, |v| v != "false"),
+        ratchets: Mutex::new(HashMap::new()),
+        ratchet_window: std::env::var("RATCHET_WINDOW")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(64),
         master_secret,
+        previous_master_secret: std::env::var("MASTER_SECRET_PREVIOUS").ok(),
+        tenant_secrets,
+        limits: Limits::from_env(),
+        metrics: Metrics::new(),
+        metrics_enabled: std::env::var("METRICS_ENABLED").is_ok_and(|v| v == "true"),
+        // Empty would match a request with no Authorization header at all
+        admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        jwt,
+        require_decrypt_token: std::env::var("REQUIRE_DECRYPT_TOKEN").is_ok_and(|v| v == "true"),
+        debug_errors: std::env::var("DEBUG_ERRORS").is_ok_and(|v| v == "true"),
+        default_secret_active,
+        maintenance: AtomicBool::new(false),
+        nonce_guard: NonceGuard::new(),
+        crypto_selftest,
+        nonces: Box::new(RandomNonce),
+        crypto_permits: Arc::new(Semaphore::new(
+            std::env::var("MAX_CONCURRENT_CRYPTO")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_else(|| {
+                    2 * std::thread::available_parallelism().map_or(1, |n| n.get())
+                }),
+        )),
+        crypto_queue_timeout: Duration::from_millis(
+            std::env::var("CRYPTO_QUEUE_TIMEOUT_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+        ),
+        channel_shares: ChannelShares::from_env(),
+        decrypt_cache: DecryptCache::from_env(clock.clone()),
+        decrypt_lockout: DecryptLockout::from_env(),
+        slo: SloMonitor::from_env(),
+        trusted_proxies,
+        archive_signer,
+        idempotency: ResponseCache::new(
+            std::env::var("IDEMPOTENCY_CACHE_CAPACITY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1024),
+            Duration::from_secs(
+                std::env::var("IDEMPOTENCY_TTL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(300),
+            ),
+            clock.clone(),
+        ),
+        clock,
+        audit,
     });
+    diagnostics.attach(state.clone());
 
-    HttpServer::new(move || {
-        let cors = Cors::permissive();
+    // Lookups already ignore expired slots; the sweeper bounds how long an
+    // unused one lingers in memory to the TTL plus one sweep period
+    if let Some(ttl) = state.key_cache_ttl {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let period = ttl.clamp(Duration::from_millis(100), Duration::from_secs(1));
+            let mut sweep = tokio::time::interval(period);
+            loop {
+                sweep.tick().await;
+                // A timed-out sweep is logged and retried next period
+                let evicted = evict_expired_ciphers(&state).unwrap_or(0);
+                if evicted > 0 {
+                    log::debug!("Evicted {} expired cipher(s)", evicted);
+                }
+            }
+        });
+    }
 
-        App::new()
-            .wrap(cors)
-            .wrap(middleware::Logger::default())
-            .app_data(state.clone())
-            .route("/health", web::get().to(health))
-            .route("/encrypt", web::post().to(encrypt))
-            .route("/decrypt", web::post().to(decrypt))
-    })
-    .bind("127.0.0.1:8001")?
-    .run()
-    .await
+    let tls_config = match tls::server_config() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(std::io::Error::other(e));
+        }
+    };
+
+    // Loopback only: the admin routes are never meant to face the network
+    let admin_addr = match std::env::var("ADMIN_PORT").ok().filter(|v| !v.is_empty()) {
+        None => None,
+        Some(port) => match port.parse::<u16>() {
+            Ok(port) => Some(format!("127.0.0.1:{}", port)),
+            Err(_) => {
+                let e = format!("Invalid ADMIN_PORT {:?}; expected a port number", port);
+                log::error!("{}", e);
+                return Err(std::io::Error::other(e));
+            }
+        },
+    };
+
+    let client_ca = std::env::var("CLIENT_CA_PATH").is_ok_and(|v| !v.is_empty());
+    banner::EffectiveConfig::new(
+        &state,
+        banner::Startup {
+            bind: BIND_ADDR,
+            admin_bind: admin_addr.as_deref(),
+            tls: tls_config.is_some(),
+            mtls: tls_config.is_some() && client_ca,
+            secret_source: provider.name(),
+            signing: signing_key.is_some(),
+            sentry: _sentry.as_ref().is_some_and(|guard| guard.is_enabled()),
+        },
+    )
+    .log();
+
+    // Binds one server hosting `routes`; both share the state and middleware
+    let serve = |routes: fn(&mut web::ServiceConfig), addr: &str| {
+        let state = state.clone();
+        let signing_key = signing_key.clone();
+        let compress_threshold = compress_threshold.clone();
+        let server = HttpServer::new(move || {
+            let cors = Cors::permissive();
+
+            App::new()
+                // Innermost, so a panic's 500 is signed, reported and logged like any other
+                .wrap(middleware::from_fn(reporting::catch_panics))
+                .wrap(middleware::from_fn(signing::sign_response))
+                // Outside signing, so X-Signature covers the uncompressed body
+                .wrap(middleware::Condition::new(
+                    compress_threshold.is_some(),
+                    middleware::from_fn(compression::skip_small),
+                ))
+                .wrap(middleware::Condition::new(
+                    compress_threshold.is_some(),
+                    middleware::Compress::default(),
+                ))
+                .wrap(middleware::from_fn(reporting::report_server_errors))
+                .wrap(cors)
+                .wrap(
+                    // Logger::default's format, plus the mTLS client, or "-"
+                    middleware::Logger::new(
+                        r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T client=%{client}xi"#,
+                    )
+                    .custom_request_replace("client", |req| {
+                        let client = req.conn_data::<tls::ClientIdentity>();
+                        client.map_or_else(|| "-".to_owned(), |c| c.0.clone())
+                    }),
+                )
+                .app_data(state.clone())
+                .app_data(web::JsonConfig::default().error_handler(json_error))
+                .app_data(web::QueryConfig::default().error_handler(query_error))
+                .configure(|cfg| {
+                    if let Some(key) = &signing_key {
+                        cfg.app_data(key.clone());
+                    }
+                    if let Some(threshold) = &compress_threshold {
+                        cfg.app_data(threshold.clone());
+                    }
+                })
+                .configure(routes)
+                .default_service(web::to(not_found))
+        })
+        .on_connect(tls::record_client);
+        match tls_config.clone() {
+            Some(config) => {
+                log::info!("Serving HTTPS on {}", addr);
+                server.bind_rustls_0_23(addr, config).map(|s| s.run())
+            }
+            None => server.bind(addr).map(|s| s.run()),
+        }
+    };
+
+    diagnostics.set_phase(Phase::Binding);
+    let Some(admin_addr) = &admin_addr else {
+        let server = serve(all_routes, BIND_ADDR)?;
+        diagnostics.set_phase(Phase::Serving);
+        return server.await;
+    };
+    log::info!("Admin routes served only on {}", admin_addr);
+    let public = serve(public_routes, BIND_ADDR)?;
+    let admin = serve(admin_routes, admin_addr)?;
+    diagnostics.set_phase(Phase::Serving);
+    tokio::try_join!(public, admin).map(|_| ())
+}
+
+// ── Every route, when ADMIN_PORT is unset ──
+fn all_routes(cfg: &mut web::ServiceConfig) {
+    public_routes(cfg);
+    admin_routes(cfg);
+}
+
+// ── Routes for the services calling encrypt and decrypt ──
+// Guarded resources come before the plain endpoint on the same path.
+// Besides sealing and opening, clients need: the ed25519 routes, which sign
+// and verify under their channel's key; derive-shared, before sealing to a
+// shared channel; a single channel's fingerprint, to check two replicas
+// agree; and the archive public key, to check envelopes.
+fn public_routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(endpoint("/health", Method::GET, health))
+        .service(endpoint("/algorithms", Method::GET, list_algorithms))
+        .service(endpoint("/encrypt", Method::POST, encrypt))
+        .service(endpoint("/encrypt/estimate", Method::POST, estimate_encrypt))
+        .service(
+            web::resource("/decrypt")
+                .guard(guard::Header("content-type", "application/octet-stream"))
+                .route(web::post().to(decrypt_bytes)),
+        )
+        .service(endpoint("/decrypt", Method::POST, decrypt))
+        .service(endpoint("/encrypt/file", Method::POST, files::encrypt_file))
+        .service(endpoint("/decrypt/file", Method::POST, files::decrypt_file))
+        .service(endpoint("/decrypt/stream", Method::POST, files::decrypt_stream))
+        .service(endpoint("/decrypt/range", Method::POST, files::decrypt_range))
+        .service(endpoint("/sign/ed25519", Method::POST, sign_ed25519))
+        .service(endpoint("/verify/ed25519", Method::POST, verify_ed25519_signature))
+        .service(endpoint("/archive/public-key", Method::GET, archive_public_key))
+        .service(endpoint("/channels/derive-shared", Method::POST, derive_shared_key))
+        .service(endpoint("/channels/{id}/fingerprint", Method::GET, channel_fingerprint));
+}
+
+// ── Management routes; only on ADMIN_PORT when it is set ──
+// Also whatever reads across channels or takes a caller's raw key: the
+// scrape endpoint, re-encrypt jobs, raw-key decrypts and index tokens. And
+// the operators' views of the key cache and of channel usage.
+fn admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(endpoint("/metrics", Method::GET, metrics))
+        .service(endpoint("/decrypt/raw", Method::POST, decrypt_raw))
+        .service(
+            web::resource("/jobs/reencrypt")
+                .guard(guard::Header("content-type", "application/x-ndjson"))
+                .route(web::post().to(reencrypt_ndjson)),
+        )
+        .service(endpoint("/jobs/reencrypt", Method::POST, reencrypt_job))
+        .service(endpoint("/index-tokens", Method::POST, index_tokens))
+        .service(endpoint("/tokens/issue", Method::POST, issue_token))
+        .service(endpoint("/metrics/snapshot", Method::GET, metrics_snapshot))
+        .service(endpoint("/metrics/reset", Method::POST, metrics_reset))
+        .service(endpoint("/channels/{id}/algorithm", Method::POST, pin_algorithm))
+        .service(get_post_endpoint("/channels/{id}/policy", get_policy, set_policy))
+        .service(endpoint("/channels/{id}/quota", Method::POST, set_quota))
+        .service(endpoint("/channels/{id}/fingerprints", Method::GET, channel_fingerprints))
+        .service(endpoint("/channels/{id}/rotations", Method::GET, channel_rotations))
+        .service(endpoint("/channels/{id}/usage", Method::GET, channel_usage))
+        .service(endpoint("/keys/preload", Method::POST, preload_keys))
+        .service(endpoint("/keys/exists", Method::POST, keys_exist))
+        .service(endpoint("/keys/fingerprints", Method::POST, key_fingerprints))
+        .service(endpoint("/keys/{channel_id}/export", Method::GET, export_channel_key))
+        .service(endpoint("/diag/rng", Method::GET, diag_rng))
+        .service(endpoint("/admin/crypto-selftest", Method::GET, crypto_selftest))
+        .service(endpoint("/audit/export", Method::GET, export_audit))
+        .service(endpoint("/admin/log-level", Method::POST, set_log_level))
+        .service(endpoint("/admin/maintenance", Method::POST, set_maintenance))
+        .service(endpoint("/admin/flush-keys", Method::POST, flush_keys))
+        .service(endpoint("/admin/master-canary", Method::POST, master_canary))
+        .service(endpoint("/admin/test-secret", Method::POST, test_secret));
 }
@@ -0,0 +1,241 @@
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// ── A Prometheus counter that can also be read relative to a reset point ──
+// The underlying counter stays monotonic for scrapers; resetting only moves
+// the baseline used by the resettable view.
+pub struct ResettableCounter {
+    name: &'static str,
+    counter: IntCounter,
+    baseline: AtomicU64,
+}
+
+impl ResettableCounter {
+    fn new(name: &'static str, help: &str) -> Self {
+        ResettableCounter {
+            name,
+            counter: IntCounter::new(name, help).unwrap(),
+            baseline: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.counter.inc();
+    }
+
+    pub fn since_reset(&self) -> u64 {
+        self.counter.get().saturating_sub(self.baseline.load(Ordering::Acquire))
+    }
+
+    fn reset(&self) {
+        self.baseline.store(self.counter.get(), Ordering::Release);
+    }
+}
+
+// ── Prometheus metrics for the encryption service ──
+pub struct Metrics {
+    registry: Registry,
+    pub cipher_cache_hits: ResettableCounter,
+    pub cipher_cache_misses: ResettableCounter,
+    pub crypto_requests_shed: ResettableCounter,
+    // Only move while DECRYPT_CACHE_ENABLED=true
+    pub decrypt_cache_hits: ResettableCounter,
+    pub decrypt_cache_misses: ResettableCounter,
+    // Labeled by algorithm, and for decrypts by outcome; never by channel,
+    // so cardinality stays fixed. Not part of the resettable snapshot.
+    pub encrypt_total: IntCounterVec,
+    pub decrypt_total: IntCounterVec,
+    // Cold-start cost of a cache miss, split into its two steps
+    pub key_derivation_seconds: Histogram,
+    pub cipher_init_seconds: Histogram,
+    // Set from the cipher cache on each scrape; see `key_cache_estimate`
+    pub cached_keys: IntGauge,
+    pub key_cache_bytes: IntGauge,
+    // Set from the SLO monitor on each scrape; NaN with no decrypts in the window
+    pub decrypt_success_ratio: Gauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let cipher_cache_hits = ResettableCounter::new(
+            "freecord_cipher_cache_hits_total",
+            "Encrypt/decrypt requests served by an already-built cipher",
+        );
+        let cipher_cache_misses = ResettableCounter::new(
+            "freecord_cipher_cache_misses_total",
+            "Encrypt/decrypt requests that had to build the channel cipher",
+        );
+        let crypto_requests_shed = ResettableCounter::new(
+            "freecord_crypto_requests_shed_total",
+            "Encrypt/decrypt requests refused with 503 because every crypto slot was busy",
+        );
+        let decrypt_cache_hits = ResettableCounter::new(
+            "freecord_decrypt_cache_hits_total",
+            "Decrypts answered from the decrypt cache",
+        );
+        let decrypt_cache_misses = ResettableCounter::new(
+            "freecord_decrypt_cache_misses_total",
+            "Decrypts the decrypt cache could not answer",
+        );
+
+        let encrypt_total = IntCounterVec::new(
+            Opts::new("freecord_encrypt_total", "Messages and files sealed"),
+            &["algorithm"],
+        )
+        .unwrap();
+        let decrypt_total = IntCounterVec::new(
+            Opts::new("freecord_decrypt_total", "Decrypt attempts by result"),
+            &["algorithm", "outcome"],
+        )
+        .unwrap();
+
+        let key_derivation_seconds = histogram(
+            "freecord_key_derivation_seconds",
+            "Time to derive a channel key from the master secret",
+        );
+        let cipher_init_seconds = histogram(
+            "freecord_cipher_init_seconds",
+            "Time to build a cipher from a derived channel key",
+        );
+
+        let cached_keys =
+            IntGauge::new("freecord_cached_keys", "Channel ciphers held in the key cache").unwrap();
+        let key_cache_bytes = IntGauge::new(
+            "freecord_key_cache_bytes",
+            "Estimated bytes held by the key cache, from its entry count",
+        )
+        .unwrap();
+        let decrypt_success_ratio = Gauge::new(
+            "freecord_decrypt_success_ratio",
+            "Decrypts that authenticated, of those attempted, over SLO_WINDOW_SECS",
+        )
+        .unwrap();
+
+        let metrics = Metrics {
+            registry,
+            cipher_cache_hits,
+            cipher_cache_misses,
+            crypto_requests_shed,
+            decrypt_cache_hits,
+            decrypt_cache_misses,
+            encrypt_total,
+            decrypt_total,
+            key_derivation_seconds,
+            cipher_init_seconds,
+            cached_keys,
+            key_cache_bytes,
+            decrypt_success_ratio,
+        };
+        for counter in metrics.counters() {
+            metrics.registry.register(Box::new(counter.counter.clone())).unwrap();
+        }
+        for vec in [&metrics.encrypt_total, &metrics.decrypt_total] {
+            metrics.registry.register(Box::new(vec.clone())).unwrap();
+        }
+        for histogram in [&metrics.key_derivation_seconds, &metrics.cipher_init_seconds] {
+            metrics.registry.register(Box::new(histogram.clone())).unwrap();
+        }
+        for gauge in [&metrics.cached_keys, &metrics.key_cache_bytes] {
+            metrics.registry.register(Box::new(gauge.clone())).unwrap();
+        }
+        metrics.registry.register(Box::new(metrics.decrypt_success_ratio.clone())).unwrap();
+        metrics
+    }
+
+    fn counters(&self) -> [&ResettableCounter; 5] {
+        [
+            &self.cipher_cache_hits,
+            &self.cipher_cache_misses,
+            &self.crypto_requests_shed,
+            &self.decrypt_cache_hits,
+            &self.decrypt_cache_misses,
+        ]
+    }
+
+    // ── Render all registered metrics in the Prometheus text format ──
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            log::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+
+    // ── Counter values since the last reset, keyed by metric name ──
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.counters()
+            .iter()
+            .map(|c| (c.name.to_string(), c.since_reset()))
+            .collect()
+    }
+
+    // ── Encrypts and decrypts since startup, summed over every label ──
+    pub fn operations(&self) -> (u64, u64) {
+        let total = |vec: &IntCounterVec| -> u64 {
+            vec.collect()
+                .iter()
+                .flat_map(|family| family.get_metric())
+                .map(|metric| metric.get_counter().get_value() as u64)
+                .sum()
+        };
+        (total(&self.encrypt_total), total(&self.decrypt_total))
+    }
+
+    pub fn reset(&self) {
+        for counter in self.counters() {
+            counter.reset();
+        }
+    }
+}
+
+// Both steps are microseconds when healthy, so the buckets start far below
+// Prometheus' 5ms default
+fn histogram(name: &str, help: &str) -> Histogram {
+    let buckets = prometheus::exponential_buckets(1e-6, 4.0, 10).unwrap();
+    Histogram::with_opts(HistogramOpts::new(name, help).buckets(buckets)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_moves_only_the_baseline() {
+        let metrics = Metrics::new();
+        for _ in 0..3 {
+            metrics.cipher_cache_hits.inc();
+        }
+        metrics.reset();
+        metrics.cipher_cache_hits.inc();
+        assert_eq!(metrics.cipher_cache_hits.since_reset(), 1);
+        assert!(metrics.render().contains("freecord_cipher_cache_hits_total 4"));
+    }
+
+    #[test]
+    fn snapshot_names_every_resettable_counter() {
+        let metrics = Metrics::new();
+        metrics.decrypt_cache_misses.inc();
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 5);
+        assert_eq!(snapshot["freecord_decrypt_cache_misses_total"], 1);
+        assert_eq!(snapshot["freecord_cipher_cache_misses_total"], 0);
+    }
+
+    #[test]
+    fn operations_sum_over_every_label() {
+        let metrics = Metrics::new();
+        assert_eq!(metrics.operations(), (0, 0));
+        metrics.encrypt_total.with_label_values(&["aes256gcm"]).inc();
+        metrics.encrypt_total.with_label_values(&["chacha20poly1305"]).inc();
+        metrics.decrypt_total.with_label_values(&["aes256gcm", "success"]).inc_by(3);
+        metrics.decrypt_total.with_label_values(&["aes256gcm", "auth_failure"]).inc();
+        assert_eq!(metrics.operations(), (2, 4));
+    }
+}
@@ -0,0 +1,77 @@
+use aes_gcm::aead::OsRng;
+use rand::RngCore;
+
+use crate::crypto::KeyId;
+use crate::NONCE_LEN;
+
+// ── Where a message's nonce comes from ──
+// Every /encrypt seal asks the strategy in AppState, so a test can swap in
+// a MockNonceStrategy and get known nonces for known-answer checks. Given
+// the message key's channel, the plaintext and the AAD, in case a strategy
+// derives the nonce from them.
+pub trait NonceStrategy: Send + Sync {
+    fn next_nonce(&self, key_id: KeyId, plaintext: &[u8], aad: &[u8]) -> [u8; NONCE_LEN];
+}
+
+// ── 96 random bits per message ──
+// Safe up to about 2^32 messages per key; KEY_BYTE_LIMIT rotates well before.
+pub struct RandomNonce;
+
+impl NonceStrategy for RandomNonce {
+    fn next_nonce(&self, _key_id: KeyId, _plaintext: &[u8], _aad: &[u8]) -> [u8; NONCE_LEN] {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        nonce
+    }
+}
+
+// ── Hands out the given nonces in order, then repeats the last ──
+#[cfg(test)]
+pub struct MockNonceStrategy {
+    nonces: std::sync::Mutex<std::collections::VecDeque<[u8; NONCE_LEN]>>,
+}
+
+#[cfg(test)]
+impl MockNonceStrategy {
+    pub fn new(nonces: impl IntoIterator<Item = [u8; NONCE_LEN]>) -> Self {
+        MockNonceStrategy { nonces: std::sync::Mutex::new(nonces.into_iter().collect()) }
+    }
+}
+
+#[cfg(test)]
+impl NonceStrategy for MockNonceStrategy {
+    fn next_nonce(&self, _key_id: KeyId, _plaintext: &[u8], _aad: &[u8]) -> [u8; NONCE_LEN] {
+        let mut nonces = self.nonces.lock().unwrap();
+        match nonces.len() {
+            0 => [0u8; NONCE_LEN],
+            1 => nonces[0],
+            _ => nonces.pop_front().unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_hands_out_nonces_in_order_then_repeats_the_last() {
+        let mock = MockNonceStrategy::new([[1; NONCE_LEN], [2; NONCE_LEN]]);
+        let key_id = KeyId::channel(1);
+        let nonces: Vec<_> = (0..4).map(|_| mock.next_nonce(key_id, b"", b"")).collect();
+        assert_eq!(nonces, [[1; NONCE_LEN], [2; NONCE_LEN], [2; NONCE_LEN], [2; NONCE_LEN]]);
+    }
+
+    #[test]
+    fn empty_mock_gives_zeros() {
+        let mock = MockNonceStrategy::new([]);
+        assert_eq!(mock.next_nonce(KeyId::channel(1), b"", b""), [0; NONCE_LEN]);
+    }
+
+    #[test]
+    fn random_nonces_differ() {
+        let a = RandomNonce.next_nonce(KeyId::channel(1), b"m", b"");
+        let b = RandomNonce.next_nonce(KeyId::channel(1), b"m", b"");
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::crypto::KeyId;
+
+// ── Debug-build tripwire for nonce reuse ──
+//
+// Remembers the last `PER_KEY` nonces sealed under each message key and
+// panics on a repeat, so a regression in nonce generation fails loudly in
+// tests and dev runs. A message key is identified by what it is derived
+// from: channel, context and ratchet step. Release builds compile it out.
+#[cfg(debug_assertions)]
+pub struct NonceGuard {
+    seen: std::sync::Mutex<std::collections::HashMap<Scope, Recent>>,
+}
+
+#[cfg(not(debug_assertions))]
+pub struct NonceGuard;
+
+#[cfg(debug_assertions)]
+const PER_KEY: usize = 4096;
+
+#[cfg(debug_assertions)]
+type Scope = (KeyId, Option<Vec<u8>>, Option<u64>);
+
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct Recent {
+    set: std::collections::HashSet<Vec<u8>>,
+    order: std::collections::VecDeque<Vec<u8>>,
+}
+
+#[cfg(debug_assertions)]
+impl NonceGuard {
+    pub fn new() -> Self {
+        NonceGuard { seen: Default::default() }
+    }
+
+    pub fn record(
+        &self,
+        key_id: KeyId,
+        context: Option<&[u8]>,
+        ratchet_index: Option<u64>,
+        nonce: &[u8],
+    ) {
+        let mut seen = self.seen.lock().unwrap();
+        let recent = seen.entry((key_id, context.map(<[u8]>::to_vec), ratchet_index)).or_default();
+
+        if !recent.set.insert(nonce.to_vec()) {
+            // Unlock first so the panic does not poison the guard for every key
+            drop(seen);
+            log::error!("NONCE REUSE under channel {} (ratchet {:?})", key_id, ratchet_index);
+            panic!("nonce reused under channel {}", key_id);
+        }
+        recent.order.push_back(nonce.to_vec());
+        if recent.order.len() > PER_KEY {
+            if let Some(oldest) = recent.order.pop_front() {
+                recent.set.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(not(debug_assertions))]
+impl NonceGuard {
+    pub fn new() -> Self {
+        NonceGuard
+    }
+
+    #[inline]
+    pub fn record(&self, _: KeyId, _: Option<&[u8]>, _: Option<u64>, _: &[u8]) {}
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+
+    fn nonce(n: u32) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[..4].copy_from_slice(&n.to_be_bytes());
+        nonce
+    }
+
+    #[test]
+    #[should_panic(expected = "nonce reused under channel 1")]
+    fn a_repeat_under_one_key_panics() {
+        let guard = NonceGuard::new();
+        guard.record(KeyId::new(None, 1), None, None, &nonce(1));
+        guard.record(KeyId::new(None, 1), None, None, &nonce(1));
+    }
+
+    #[test]
+    fn the_same_nonce_under_another_key_is_fine() {
+        let guard = NonceGuard::new();
+        guard.record(KeyId::new(None, 1), None, None, &nonce(1));
+        guard.record(KeyId::new(None, 2), None, None, &nonce(1));
+        guard.record(KeyId::new(Some(5), 1), None, None, &nonce(1));
+        guard.record(KeyId::new(None, 1), Some(b"thread-7"), None, &nonce(1));
+        guard.record(KeyId::new(None, 1), None, Some(0), &nonce(1));
+        guard.record(KeyId::new(None, 1), None, Some(1), &nonce(1));
+    }
+
+    #[test]
+    fn only_the_last_per_key_nonces_are_remembered() {
+        let guard = NonceGuard::new();
+        let key_id = KeyId::new(None, 1);
+        for n in 0..=PER_KEY as u32 {
+            guard.record(key_id, None, None, &nonce(n));
+        }
+        guard.record(key_id, None, None, &nonce(0));
+    }
+
+    #[test]
+    fn a_panic_leaves_the_guard_usable() {
+        let guard = NonceGuard::new();
+        guard.record(KeyId::new(None, 1), None, None, &nonce(1));
+        let repeat = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            guard.record(KeyId::new(None, 1), None, None, &nonce(1));
+        }));
+        assert!(repeat.is_err());
+        guard.record(KeyId::new(None, 2), None, None, &nonce(1));
+    }
+}
@@ -0,0 +1,214 @@
+use std::net::IpAddr;
+
+use actix_web::http::header::HeaderMap;
+
+// ── Which peers may name the client in Forwarded / X-Forwarded-For ──
+// TRUSTED_PROXIES is a comma-separated list of addresses or CIDR ranges,
+// e.g. `10.0.0.0/8, 192.0.2.7`. From any other peer the headers are ignored,
+// so a client cannot spoof its address by sending them. Unset trusts no one.
+#[derive(Default)]
+pub struct TrustedProxies {
+    nets: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    pub fn from_env() -> Result<Self, String> {
+        let Ok(list) = std::env::var("TRUSTED_PROXIES") else {
+            return Ok(TrustedProxies::default());
+        };
+        TrustedProxies::parse(&list)
+    }
+
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let nets = list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                parse_net(entry).ok_or_else(|| format!("Invalid TRUSTED_PROXIES entry {:?}", entry))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(TrustedProxies { nets })
+    }
+
+    pub fn count(&self) -> usize {
+        self.nets.len()
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|&(net, prefix)| in_net(ip, net, prefix))
+    }
+
+    // ── The client's address, as seen through any trusted proxies ──
+    // Walks the forwarding chain from the nearest hop outward and stops at the
+    // first address not in TRUSTED_PROXIES; everything left of it could have
+    // been written by the client. An entry that is not an address (`unknown`,
+    // an obfuscated id) ends the walk at the last proxy that named it.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.trusts(peer) {
+            return peer;
+        }
+        let mut client = peer;
+        for hop in forwarded_chain(headers).iter().rev() {
+            let Some(ip) = parse_hop(hop) else {
+                break;
+            };
+            client = ip;
+            if !self.trusts(ip) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+// Forwarded (RFC 7239) `for=` values when present, else X-Forwarded-For,
+// client first; repeated headers are joined in order
+fn forwarded_chain(headers: &HeaderMap) -> Vec<String> {
+    let forwarded: Vec<String> = headers
+        .get_all("forwarded")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                name.eq_ignore_ascii_case("for").then(|| value.trim_matches('"').to_owned())
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+    headers
+        .get_all("x-forwarded-for")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|hop| hop.trim().to_owned())
+        .collect()
+}
+
+// An address, optionally with a port; IPv6 with a port is bracketed
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    if let Ok(ip) = hop.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = hop.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    hop.rsplit_once(':')?.0.parse().ok()
+}
+
+fn parse_net(entry: &str) -> Option<(IpAddr, u8)> {
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr.parse().ok()?, Some(prefix.parse().ok()?)),
+        None => (entry.parse().ok()?, None),
+    };
+    let max = if matches!(addr, IpAddr::V4(_)) { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max);
+    (prefix <= max).then_some((addr, prefix))
+}
+
+fn in_net(ip: IpAddr, net: IpAddr, prefix: u8) -> bool {
+    let (ip, net, bits) = match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => (u32::from(ip) as u128, u32::from(net) as u128, 32),
+        (IpAddr::V6(ip), IpAddr::V6(net)) => (u128::from(ip), u128::from(net), 128),
+        // An IPv4 peer on a dual-stack socket arrives as ::ffff:a.b.c.d
+        (IpAddr::V6(ip), IpAddr::V4(_)) => match ip.to_ipv4_mapped() {
+            Some(v4) => return in_net(IpAddr::V4(v4), net, prefix),
+            None => return false,
+        },
+        (IpAddr::V4(_), IpAddr::V6(_)) => return false,
+    };
+    let shift = bits - prefix as u32;
+    shift >= bits || (ip >> shift) == (net >> shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn proxies(list: &[&str]) -> TrustedProxies {
+        TrustedProxies::parse(&list.join(",")).unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(HeaderName::from_static(name), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn an_untrusted_peer_is_the_client() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        let spoofed = headers(&[("x-forwarded-for", "198.51.100.1")]);
+        assert_eq!(trusted.client_ip(ip("203.0.113.9"), &spoofed), ip("203.0.113.9"));
+        let none = TrustedProxies::default();
+        assert_eq!(none.client_ip(ip("10.0.0.1"), &spoofed), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn the_walk_stops_at_the_first_untrusted_hop() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        let chain = headers(&[("x-forwarded-for", "192.0.2.1, 198.51.100.7, 10.1.1.1")]);
+        assert_eq!(trusted.client_ip(ip("10.0.0.1"), &chain), ip("198.51.100.7"));
+        // Repeated headers join in order
+        let split = headers(&[
+            ("x-forwarded-for", "192.0.2.1"),
+            ("x-forwarded-for", "198.51.100.7, 10.1.1.1"),
+        ]);
+        assert_eq!(trusted.client_ip(ip("10.0.0.1"), &split), ip("198.51.100.7"));
+    }
+
+    #[test]
+    fn forwarded_wins_over_x_forwarded_for() {
+        let trusted = proxies(&["10.0.0.1"]);
+        let both = headers(&[
+            ("forwarded", r#"for=192.0.2.60;proto=https, For="[2001:db8::1]:4711""#),
+            ("x-forwarded-for", "198.51.100.7"),
+        ]);
+        assert_eq!(trusted.client_ip(ip("10.0.0.1"), &both), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn a_hop_that_is_not_an_address_ends_the_walk() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        let chain = headers(&[("forwarded", "for=192.0.2.60, for=unknown, for=10.2.2.2")]);
+        assert_eq!(trusted.client_ip(ip("10.0.0.1"), &chain), ip("10.2.2.2"));
+    }
+
+    #[test]
+    fn a_bad_entry_is_an_error() {
+        assert_eq!(TrustedProxies::parse(" 10.0.0.0/8 ,, 192.0.2.7").unwrap().count(), 2);
+        assert!(TrustedProxies::parse("10.0.0.0/8, proxy.internal").is_err());
+    }
+
+    #[test]
+    fn hops_may_carry_ports() {
+        assert_eq!(parse_hop("192.0.2.1:8080"), Some(ip("192.0.2.1")));
+        assert_eq!(parse_hop("[2001:db8::1]:443"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_hop("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_hop("_hidden"), None);
+    }
+
+    #[test]
+    fn nets_match_by_prefix() {
+        let (net, prefix) = parse_net("192.0.2.0/24").unwrap();
+        assert!(in_net(ip("192.0.2.255"), net, prefix));
+        assert!(!in_net(ip("192.0.3.0"), net, prefix));
+        assert!(in_net(ip("::ffff:192.0.2.9"), net, prefix));
+        assert!(!in_net(ip("2001:db8::1"), net, prefix));
+        let (any, zero) = parse_net("0.0.0.0/0").unwrap();
+        assert!(in_net(ip("203.0.113.1"), any, zero));
+        assert_eq!(parse_net("192.0.2.7"), Some((ip("192.0.2.7"), 32)));
+        assert_eq!(parse_net("192.0.2.0/33"), None);
+        assert_eq!(parse_net("2001:db8::/129"), None);
+        assert_eq!(parse_net("not-an-ip"), None);
+    }
+}
@@ -0,0 +1,139 @@
+use aes_gcm::aead::OsRng;
+use hkdf::Hkdf;
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::VecDeque;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::crypto::KeyPurpose;
+
+type RatchetKey = Zeroizing<[u8; 32]>;
+
+// ── Per-channel symmetric hash ratchet ──
+//
+// c_0 = HKDF(random root, "freecord/ratchet/v1"), c_{n+1} = HKDF(c_n), and
+// message n is sealed under m_n = HKDF(c_n, "freecord/ratchet/message").
+// The root comes from the OS RNG and is never stored, so neither the master
+// secret nor the channel key can rebuild a chain. Only the live chain key
+// and the last `capacity` message keys are held; each chain key is zeroized
+// as soon as the next one exists, and message keys as they leave the window.
+// Ratchet-mode ciphertext stops opening once it falls out of the window or
+// the process restarts: that loss is the forward secrecy.
+pub struct Ratchet {
+    next_index: u64,
+    chain_key: RatchetKey,
+    window: VecDeque<(u64, RatchetKey)>,
+    capacity: usize,
+}
+
+impl Ratchet {
+    // Indices count up from `first_index`; see `first_index` for why a
+    // fresh chain doesn't start at 0
+    pub fn new(first_index: u64, capacity: usize) -> Self {
+        let mut root = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(&mut root[..]);
+        Ratchet {
+            next_index: first_index,
+            chain_key: expand(&root[..], KeyPurpose::RatchetInit),
+            window: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // ── Take the key for the next message and advance the chain ──
+    pub fn advance(&mut self) -> (u64, RatchetKey) {
+        let index = self.next_index;
+        let key = expand(&self.chain_key[..], KeyPurpose::RatchetMessage);
+
+        let next = expand(&self.chain_key[..], KeyPurpose::RatchetStep);
+        self.chain_key.zeroize();
+        self.chain_key = next;
+        self.next_index += 1;
+
+        if self.capacity > 0 {
+            if self.window.len() == self.capacity {
+                self.window.pop_front();
+            }
+            self.window.push_back((index, key.clone()));
+        }
+        (index, key)
+    }
+
+    // ── Key for an already-issued message, if still inside the window ──
+    pub fn key_for(&self, index: u64) -> Option<RatchetKey> {
+        self.window
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, key)| key.clone())
+    }
+}
+
+// ── Index a chain started at `unix_now` counts up from ──
+// Each start second owns 2^32 indices, so a chain started after a restart
+// never reissues an index an earlier chain handed out; old ciphertext then
+// fails with ratchet_window_exceeded instead of trying a stranger's key.
+pub fn first_index(unix_now: u64) -> u64 {
+    unix_now << 32
+}
+
+fn expand(ikm: &[u8], purpose: KeyPurpose) -> RatchetKey {
+    let mut out = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, ikm)
+        .expand(purpose.info(), &mut out[..])
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn restart_never_reissues_an_index_or_key() {
+        let mut issued = HashSet::new();
+        let mut keys = HashSet::new();
+        for started_at in [1_700_000_000, 1_700_000_001, 1_700_000_500] {
+            let mut ratchet = Ratchet::new(first_index(started_at), 4);
+            for _ in 0..100 {
+                let (index, key) = ratchet.advance();
+                assert!(issued.insert(index), "index {index} issued twice");
+                assert!(keys.insert(*key), "key issued twice");
+            }
+        }
+    }
+
+    #[test]
+    fn chains_started_together_differ() {
+        let (_, a) = Ratchet::new(0, 0).advance();
+        let (_, b) = Ratchet::new(0, 0).advance();
+        assert_ne!(*a, *b);
+    }
+
+    #[test]
+    fn message_key_is_not_the_chain_key() {
+        let mut ratchet = Ratchet::new(0, 1);
+        let chain_key = *ratchet.chain_key;
+        let (_, key) = ratchet.advance();
+        assert_ne!(*key, chain_key);
+        assert_ne!(*key, *ratchet.chain_key);
+        assert_eq!(*ratchet.chain_key, *expand(&chain_key, KeyPurpose::RatchetStep));
+    }
+
+    #[test]
+    fn window_keeps_only_the_last_keys() {
+        let mut ratchet = Ratchet::new(10, 2);
+        let issued: Vec<_> = (0..3).map(|_| ratchet.advance()).collect();
+        assert!(ratchet.key_for(10).is_none());
+        assert_eq!(*ratchet.key_for(11).unwrap(), *issued[1].1);
+        assert_eq!(*ratchet.key_for(12).unwrap(), *issued[2].1);
+        assert!(ratchet.key_for(13).is_none());
+    }
+
+    #[test]
+    fn zero_window_keeps_nothing() {
+        let mut ratchet = Ratchet::new(0, 0);
+        let (index, _) = ratchet.advance();
+        assert!(ratchet.key_for(index).is_none());
+    }
+}
@@ -0,0 +1,243 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, ResponseError};
+use futures_util::FutureExt;
+use rand::RngCore;
+use serde::Serialize;
+use sentry::{Hub, Level, SentryFutureExt};
+
+use crate::crypto::KeyId;
+use crate::tls::ClientIdentity;
+
+// ── Optional Sentry error reporting ──
+// Enabled by SENTRY_DSN. Events carry only what this module puts on them:
+// route, status, error code, channel, request id and mTLS client. Plaintext, ciphertext,
+// keys and request bodies never reach Sentry.
+
+// ── Start the client; the guard flushes pending events when dropped ──
+pub fn init() -> Option<sentry::ClientInitGuard> {
+    let dsn = std::env::var("SENTRY_DSN").ok().filter(|dsn| !dsn.is_empty())?;
+    let mut options = sentry::ClientOptions::default();
+    options.release = sentry::release_name!();
+    options.environment = std::env::var("SENTRY_ENVIRONMENT").ok().map(Into::into);
+    options.send_default_pii = false;
+    let guard = sentry::init((dsn, options));
+    if guard.is_enabled() {
+        log::info!("Sentry error reporting enabled");
+    } else {
+        log::warn!("SENTRY_DSN is set but invalid; error reporting is off");
+    }
+    Some(guard)
+}
+
+fn enabled() -> bool {
+    Hub::main().client().is_some_and(|client| client.is_enabled())
+}
+
+fn tag_key(scope: &mut sentry::Scope, key_id: KeyId) {
+    scope.set_tag("channel_id", key_id.channel_id);
+    if let Some(other) = key_id.shared_with {
+        scope.set_tag("shared_with", other);
+    }
+    if let Some(org_id) = key_id.org_id {
+        scope.set_tag("org_id", org_id);
+    }
+}
+
+// ── Describe why the current request is about to fail with a 5xx ──
+// The tags land on the request's hub and go out with the event the
+// middleware captures for the response.
+pub fn tag_failure(code: &str, key_id: KeyId) {
+    Hub::current().configure_scope(|scope| {
+        scope.set_tag("error_code", code);
+        tag_key(scope, key_id);
+    });
+}
+
+// ── Capture a failure outside any request's response ──
+// For errors with no 5xx to carry them: startup, or a stream that fails
+// after its 200 is sent.
+pub fn capture_failure(code: &str, key_id: Option<KeyId>, message: &str) {
+    Hub::current().with_scope(
+        |scope| {
+            scope.set_tag("error_code", code);
+            if let Some(key_id) = key_id {
+                tag_key(scope, key_id);
+            }
+        },
+        || sentry::capture_message(message, Level::Error),
+    );
+}
+
+// ── Middleware: one Sentry event per 5xx response ──
+// Each request runs on its own hub, tagged with the caller's X-Request-Id
+// (or a fresh one), so tags set by handlers never leak across requests.
+pub async fn report_server_errors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if !enabled() {
+        return next.call(req).await;
+    }
+
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned)
+        .unwrap_or_else(|| format!("{:016x}", rand::rngs::OsRng.next_u64()));
+    let route = req.match_pattern().unwrap_or_else(|| req.path().to_owned());
+    let client = req.conn_data::<ClientIdentity>().map(|c| c.0.clone());
+    let method = req.method().clone();
+
+    let hub = Arc::new(Hub::new_from_top(Hub::main()));
+    hub.configure_scope(|scope| {
+        scope.set_tag("request_id", &request_id);
+        scope.set_tag("route", &route);
+        if let Some(client) = &client {
+            scope.set_tag("client", client);
+        }
+    });
+
+    let res = next.call(req).bind_hub(hub.clone()).await;
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    if status.is_server_error() {
+        hub.capture_message(&format!("{} {} returned {}", method, route, status), Level::Error);
+    }
+    res
+}
+
+#[derive(Serialize)]
+struct PanicResponse {
+    error: String,
+    code: String,
+    // Matches the server-side log line for this panic
+    incident_id: String,
+}
+
+// Returned as an Err so the server builds the 500 without the request,
+// which the panicked handler took with it
+#[derive(Debug)]
+struct HandlerPanic {
+    incident_id: String,
+}
+
+impl std::fmt::Display for HandlerPanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "handler panicked (incident {})", self.incident_id)
+    }
+}
+
+impl ResponseError for HandlerPanic {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::InternalServerError()
+            .json(PanicResponse {
+                error: "Internal error".into(),
+                code: "internal_error".into(),
+                incident_id: self.incident_id.clone(),
+            })
+    }
+}
+
+// ── Middleware: a handler panic becomes a 500, not a dropped connection ──
+// The panic message is logged under a fresh incident id; the client only
+// gets the id, never the message itself.
+pub async fn catch_panics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let route = req.match_pattern().unwrap_or_else(|| req.path().to_owned());
+    AssertUnwindSafe(next.call(req)).catch_unwind().await.unwrap_or_else(|panic| {
+        let incident_id = format!("{:016x}", rand::rngs::OsRng.next_u64());
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "non-string panic payload".into());
+        log::error!("Handler for {} panicked (incident {}): {}", route, incident_id, message);
+        Err(HandlerPanic { incident_id }.into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, try_call_service, TestRequest};
+    use actix_web::{middleware, web, App};
+
+    fn tags<'a>(event: &'a sentry::protocol::Event<'static>) -> Vec<(&'a str, &'a str)> {
+        event.tags.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+    }
+
+    #[test]
+    fn captured_failures_carry_only_the_tags() {
+        let events = sentry::test::with_captured_events(|| {
+            capture_failure("stream_failed", Some(KeyId::new(Some(5), 1)), "Stream broke");
+            capture_failure("startup_failed", None, "No secret");
+        });
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].message.as_deref(), Some("Stream broke"));
+        assert_eq!(
+            tags(&events[0]),
+            [("channel_id", "1"), ("error_code", "stream_failed"), ("org_id", "5")]
+        );
+        assert_eq!(tags(&events[1]), [("error_code", "startup_failed")]);
+    }
+
+    #[test]
+    fn a_failure_tags_the_next_event_on_the_hub() {
+        let events = sentry::test::with_captured_events(|| {
+            tag_failure("key_cache_error", KeyId::shared(None, 3, 4));
+            sentry::capture_message("POST /encrypt returned 500", Level::Error);
+        });
+        assert_eq!(
+            tags(&events[0]),
+            [("channel_id", "3"), ("error_code", "key_cache_error"), ("shared_with", "4")]
+        );
+    }
+
+    async fn boom() -> HttpResponse {
+        panic!("channel 7 key was 0x1234")
+    }
+
+    #[actix_web::test]
+    async fn a_panicking_handler_answers_500_with_an_incident_id() {
+        let app = init_service(
+            App::new()
+                .wrap(middleware::from_fn(catch_panics))
+                .route("/boom", web::get().to(boom))
+                .route("/fine", web::get().to(|| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+
+        // The server builds the 500 from the error; a test service hands it back
+        let req = TestRequest::get().uri("/boom").to_request();
+        let Err(err) = try_call_service(&app, req).await else {
+            panic!("a panicking handler should answer with an error");
+        };
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "internal_error");
+        let incident_id = body["incident_id"].as_str().unwrap();
+        assert!(incident_id.len() == 16 && incident_id.bytes().all(|b| b.is_ascii_hexdigit()));
+        // The panic message stays in the server log
+        assert!(!body.to_string().contains("0x1234"));
+
+        let resp = call_service(&app, TestRequest::get().uri("/fine").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
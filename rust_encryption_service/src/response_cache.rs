@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::clock::Clock;
+use crate::crypto::KeyId;
+
+// ── Bounded cache of recent responses, keyed by a request hash ──
+// Holds at most `capacity` entries, least recently used evicted first;
+// entries live for at most `ttl`. Used for decrypt responses and for
+// /encrypt replays under an Idempotency-Key.
+pub struct ResponseCache<V> {
+    entries: Mutex<Entries<V>>,
+    pub capacity: usize,
+    pub ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+struct Entries<V> {
+    map: HashMap<[u8; 32], Entry<V>>,
+    // Bumped on every hit or insert; the lowest `used` is least recent
+    clock: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    used: u64,
+}
+
+pub type CacheKey = [u8; 32];
+
+// ── Optional cache of recent decrypt responses ──
+// For hot reads such as a pinned message rendered to many viewers. Off unless
+// DECRYPT_CACHE_ENABLED=true, since it holds plaintext in memory. Every
+// stored body is zeroized when it leaves the cache.
+pub type DecryptCache = ResponseCache<Zeroizing<Vec<u8>>>;
+
+// ── Hash of everything that decides a decrypt's result ──
+// Fields are length-prefixed, or fixed width, so no two requests can feed
+// the hash the same bytes.
+pub fn decrypt_key(
+    key_id: KeyId,
+    format: u8,
+    attachment_hash: Option<&[u8]>,
+    blob: &[u8],
+) -> CacheKey {
+    let mut hasher = Sha256::new();
+    hasher.update(b"freecord/decrypt-cache/v1");
+    hasher.update(key_id.channel_id.to_be_bytes());
+    for id in [key_id.org_id, key_id.shared_with] {
+        hasher.update([id.is_some() as u8]);
+        hasher.update(id.unwrap_or(0).to_be_bytes());
+    }
+    hasher.update([format]);
+    match attachment_hash {
+        Some(hash) => {
+            hasher.update([1]);
+            hasher.update((hash.len() as u64).to_be_bytes());
+            hasher.update(hash);
+        }
+        None => hasher.update([0]),
+    }
+    hasher.update(blob);
+    hasher.finalize().into()
+}
+
+// ── Hash of an /encrypt request under an Idempotency-Key ──
+// The same key with a different body or Accept header is a different entry,
+// so a reused key can never return another request's ciphertext.
+pub fn idempotency_key(idempotency_key: &str, accept: &str, body: &[u8]) -> CacheKey {
+    let mut hasher = Sha256::new();
+    hasher.update(b"freecord/idempotency/v1");
+    for field in [idempotency_key.as_bytes(), accept.as_bytes()] {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field);
+    }
+    hasher.update(body);
+    hasher.finalize().into()
+}
+
+fn env(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+impl DecryptCache {
+    // None unless DECRYPT_CACHE_ENABLED=true
+    pub fn from_env(clock: Arc<dyn Clock>) -> Option<Self> {
+        if !std::env::var("DECRYPT_CACHE_ENABLED").is_ok_and(|v| v == "true") {
+            return None;
+        }
+        let cache = ResponseCache::new(
+            env("DECRYPT_CACHE_CAPACITY", 1024) as usize,
+            Duration::from_secs(env("DECRYPT_CACHE_TTL_SECS", 60)),
+            clock,
+        );
+        log::warn!(
+            "Decrypt cache enabled: up to {} plaintext response(s) held for {:?}",
+            cache.capacity,
+            cache.ttl
+        );
+        Some(cache)
+    }
+}
+
+impl<V: Clone> ResponseCache<V> {
+    pub fn new(capacity: usize, ttl: Duration, clock: Arc<dyn Clock>) -> Self {
+        ResponseCache {
+            entries: Mutex::new(Entries { map: HashMap::new(), clock: 0 }),
+            capacity: capacity.max(1),
+            ttl,
+            clock,
+        }
+    }
+
+    // ── The cached value for `key`, if present and fresh ──
+    pub fn get(&self, key: &CacheKey) -> Option<V> {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let entry = entries.map.get_mut(key)?;
+        if now.saturating_duration_since(entry.inserted) >= self.ttl {
+            entries.map.remove(key);
+            return None;
+        }
+        entry.used = clock;
+        Some(entry.value.clone())
+    }
+
+    // ── Store a value, evicting expired entries, then the LRU one ──
+    pub fn insert(&self, key: CacheKey, value: V) {
+        let now = self.clock.now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let used = entries.clock;
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(&key) {
+            entries.map.retain(|_, e| now.saturating_duration_since(e.inserted) < self.ttl);
+            if entries.map.len() >= self.capacity {
+                let oldest = entries.map.iter().min_by_key(|(_, e)| e.used).map(|(k, _)| *k);
+                if let Some(oldest) = oldest {
+                    entries.map.remove(&oldest);
+                }
+            }
+        }
+        entries.map.insert(key, Entry { value, inserted: now, used });
+    }
+
+    // ── Drop every entry; returns how many went ──
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let cleared = entries.map.len();
+        entries.map.clear();
+        cleared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    fn cache(capacity: usize, ttl_secs: u64) -> (ResponseCache<u32>, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        (ResponseCache::new(capacity, Duration::from_secs(ttl_secs), clock.clone()), clock)
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let (cache, clock) = cache(4, 60);
+        cache.insert([1; 32], 1);
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(cache.get(&[1; 32]), Some(1));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get(&[1; 32]), None);
+    }
+
+    #[test]
+    fn the_least_recently_used_entry_is_evicted() {
+        let (cache, _) = cache(2, 60);
+        cache.insert([1; 32], 1);
+        cache.insert([2; 32], 2);
+        assert_eq!(cache.get(&[1; 32]), Some(1));
+        cache.insert([3; 32], 3);
+        assert_eq!(cache.get(&[2; 32]), None);
+        assert_eq!(cache.get(&[1; 32]), Some(1));
+        assert_eq!(cache.get(&[3; 32]), Some(3));
+    }
+
+    #[test]
+    fn expired_entries_go_before_fresh_ones() {
+        let (cache, clock) = cache(2, 60);
+        cache.insert([1; 32], 1);
+        clock.advance(Duration::from_secs(30));
+        cache.insert([2; 32], 2);
+        assert_eq!(cache.get(&[1; 32]), Some(1));
+        clock.advance(Duration::from_secs(30));
+        // [1] is the most recently used but has expired; [2] stays
+        cache.insert([3; 32], 3);
+        assert_eq!(cache.get(&[2; 32]), Some(2));
+        assert_eq!(cache.get(&[3; 32]), Some(3));
+    }
+
+    #[test]
+    fn replacing_a_key_evicts_nothing() {
+        let (cache, _) = cache(2, 60);
+        cache.insert([1; 32], 1);
+        cache.insert([2; 32], 2);
+        cache.insert([1; 32], 10);
+        assert_eq!(cache.get(&[1; 32]), Some(10));
+        assert_eq!(cache.get(&[2; 32]), Some(2));
+    }
+
+    #[test]
+    fn clear_reports_what_went() {
+        let (cache, _) = cache(0, 60);
+        assert_eq!(cache.capacity, 1);
+        cache.insert([1; 32], 1);
+        assert_eq!(cache.clear(), 1);
+        assert_eq!(cache.get(&[1; 32]), None);
+    }
+
+    #[test]
+    fn decrypt_key_covers_every_input() {
+        let base = decrypt_key(KeyId::new(None, 1), 0, None, b"blob");
+        let others = [
+            decrypt_key(KeyId::new(None, 2), 0, None, b"blob"),
+            decrypt_key(KeyId::new(Some(0), 1), 0, None, b"blob"),
+            decrypt_key(KeyId::new(None, 1).with_shared(Some(0)), 0, None, b"blob"),
+            decrypt_key(KeyId::new(None, 1), 1, None, b"blob"),
+            decrypt_key(KeyId::new(None, 1), 0, Some(b""), b"blob"),
+            decrypt_key(KeyId::new(None, 1), 0, None, b"blob!"),
+        ];
+        assert!(others.iter().all(|key| *key != base));
+        let hashed = decrypt_key(KeyId::new(None, 1), 0, Some(b"x"), b"blob");
+        assert_ne!(decrypt_key(KeyId::new(None, 1), 0, Some(b""), b"xblob"), hashed);
+        assert_eq!(decrypt_key(KeyId::new(None, 1), 0, None, b"blob"), base);
+    }
+
+    #[test]
+    fn idempotency_key_covers_key_accept_and_body() {
+        let base = idempotency_key("k", "application/json", b"{}");
+        assert_ne!(idempotency_key("k2", "application/json", b"{}"), base);
+        assert_ne!(idempotency_key("k", "application/octet-stream", b"{}"), base);
+        assert_ne!(idempotency_key("k", "application/json", b"{ }"), base);
+        // Length prefixes keep the key and Accept from running together
+        assert_ne!(idempotency_key("ka", "b", b""), idempotency_key("k", "ab", b""));
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeSet;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// ── Blind index tokens for encrypted search ──
+// token = hex(first 16 bytes of HMAC-SHA256(index key, normalized term))
+// A search service matches equal tokens without seeing any word. Terms are
+// runs of alphanumerics, lowercased; each distinct term yields one token and
+// tokens are returned sorted, so neither word order nor repetition leaks.
+
+const TOKEN_LEN: usize = 16;
+
+fn terms(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+pub fn index_tokens(index_key: &[u8], text: &str) -> Vec<String> {
+    let tokens: BTreeSet<String> = terms(text)
+        .iter()
+        .map(|term| {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(index_key).expect("HMAC accepts keys of any length");
+            mac.update(term.as_bytes());
+            hex::encode(&mac.finalize().into_bytes()[..TOKEN_LEN])
+        })
+        .collect();
+    tokens.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [1; 32];
+
+    #[test]
+    fn tokens_match_hmac_sha256_of_each_term() {
+        // Independently computed HMAC-SHA256(KEY, term), first 16 bytes
+        assert_eq!(
+            index_tokens(&KEY, "hello world"),
+            vec!["3a4d75699f355339725ecf666241ddd9", "f60f27661b9e2c61482a0b3a2693e0cd"],
+        );
+    }
+
+    #[test]
+    fn case_punctuation_order_and_repeats_do_not_show() {
+        let plain = index_tokens(&KEY, "hello world");
+        assert_eq!(index_tokens(&KEY, "World, HELLO! hello... world?"), plain);
+    }
+
+    #[test]
+    fn terms_are_unicode_alphanumeric_runs() {
+        let expected = ["42", "ok", "text", "ünïcode"].map(String::from).into();
+        assert_eq!(terms("Ünïcode—text_42 ok"), expected);
+        assert!(index_tokens(&KEY, " ,.!? ").is_empty());
+    }
+
+    #[test]
+    fn the_index_key_changes_every_token() {
+        let a = index_tokens(&KEY, "hello");
+        let b = index_tokens(&[2; 32], "hello");
+        assert_eq!(a[0].len(), 2 * TOKEN_LEN);
+        assert_ne!(a, b);
+    }
+}
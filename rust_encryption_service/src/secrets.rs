@@ -0,0 +1,463 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_SECRET: &str = "default-secret-change-me";
+
+// ── Whether `secret` is the built-in fallback, i.e. MASTER_SECRET was never set ──
+pub fn is_default(secret: &str) -> bool {
+    secret == DEFAULT_SECRET
+}
+
+// ── Where the master secret is read from at startup ──
+pub trait SecretProvider {
+    // Human-readable source, for the startup log
+    fn name(&self) -> &'static str;
+    fn fetch(&self) -> Result<String, String>;
+}
+
+// ── Pick the provider named by SECRET_SOURCE ──
+// Unset means the MASTER_SECRET env var, as before, or the file named by
+// MASTER_SECRET_FILE when that is set. An unknown value or a
+// missing setting for the chosen backend is an error rather than a silent
+// fallback, so a typo can never start the service on the default secret.
+pub fn provider_from_env() -> Result<Box<dyn SecretProvider>, String> {
+    match std::env::var("SECRET_SOURCE").ok().as_deref() {
+        None | Some("") | Some("env") => match std::env::var("MASTER_SECRET_FILE") {
+            Ok(path) if !path.is_empty() => Ok(Box::new(FileSecret { path })),
+            _ => Ok(Box::new(EnvSecret)),
+        },
+        Some("vault") => Ok(Box::new(VaultSecret {
+            addr: required("VAULT_ADDR")?,
+            token: required("VAULT_TOKEN")?,
+            path: required("MASTER_SECRET_PATH")?,
+            field: std::env::var("MASTER_SECRET_FIELD")
+                .unwrap_or_else(|_| "master_secret".to_string()),
+        })),
+        Some("aws") => Ok(Box::new(AwsSecret {
+            region: required("AWS_REGION")?,
+            secret_id: required("MASTER_SECRET_ARN")?,
+            access_key_id: required("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: required("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })),
+        Some("keyring") => Ok(Box::new(KeyringSecret {
+            service: std::env::var("KEYRING_SERVICE").unwrap_or_else(|_| "freecord".to_string()),
+            account: std::env::var("KEYRING_ACCOUNT")
+                .unwrap_or_else(|_| "master_secret".to_string()),
+        })),
+        Some(other) => Err(format!(
+            "Unknown SECRET_SOURCE {:?}; expected env, vault, aws or keyring",
+            other
+        )),
+    }
+}
+
+// ── Per-tenant master secrets from TENANT_SECRETS_FILE ──
+// A JSON object of org id to secret, e.g. `{"42": "..."}`, mounted like
+// MASTER_SECRET_FILE. Channels under a listed org derive their keys from its
+// own secret, so a leaked tenant secret exposes no other tenant. Unset means
+// every org shares the master secret.
+pub fn tenant_secrets_from_env() -> Result<HashMap<i64, String>, String> {
+    let Some(path) = std::env::var("TENANT_SECRETS_FILE").ok().filter(|p| !p.is_empty()) else {
+        return Ok(HashMap::new());
+    };
+    read_tenant_secrets(&path)
+}
+
+fn read_tenant_secrets(path: &str) -> Result<HashMap<i64, String>, String> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read TENANT_SECRETS_FILE {}: {}", path, e))?;
+    let by_org: HashMap<String, String> = serde_json::from_str(&raw)
+        .map_err(|e| format!("TENANT_SECRETS_FILE {} is not a JSON object: {}", path, e))?;
+    by_org
+        .into_iter()
+        .map(|(org, secret)| {
+            let org_id = org
+                .parse()
+                .map_err(|_| format!("TENANT_SECRETS_FILE key {:?} is not an org id", org))?;
+            if secret.is_empty() || is_default(&secret) {
+                return Err(format!("TENANT_SECRETS_FILE secret for org {} is unset", org_id));
+            }
+            Ok((org_id, secret))
+        })
+        .collect()
+}
+
+fn required(var: &str) -> Result<String, String> {
+    std::env::var(var).map_err(|_| format!("{} must be set for this SECRET_SOURCE", var))
+}
+
+// ── MASTER_SECRET from the environment ──
+pub struct EnvSecret;
+
+impl SecretProvider for EnvSecret {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn fetch(&self) -> Result<String, String> {
+        Ok(std::env::var("MASTER_SECRET").unwrap_or_else(|_| DEFAULT_SECRET.to_string()))
+    }
+}
+
+// ── MASTER_SECRET_FILE: a mounted secret file, e.g. a Docker or K8s secret ──
+// Unlike an env var, the secret never shows up in /proc/<pid>/environ.
+pub struct FileSecret {
+    path: String,
+}
+
+impl SecretProvider for FileSecret {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn fetch(&self) -> Result<String, String> {
+        let contents = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("Cannot read MASTER_SECRET_FILE {}: {}", self.path, e))?;
+        // Editors and `echo` leave a trailing newline that isn't part of the secret
+        let secret = contents.strip_suffix('\n').unwrap_or(&contents);
+        let secret = secret.strip_suffix('\r').unwrap_or(secret);
+        if secret.is_empty() {
+            return Err(format!("MASTER_SECRET_FILE {} is empty", self.path));
+        }
+        Ok(secret.to_string())
+    }
+}
+
+// ── The OS keyring: Keychain, Windows Credential Manager or Linux keyutils ──
+// For single-node deployments. The entry is read, never created; store it
+// once out of band under KEYRING_SERVICE / KEYRING_ACCOUNT.
+pub struct KeyringSecret {
+    service: String,
+    account: String,
+}
+
+impl SecretProvider for KeyringSecret {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn fetch(&self) -> Result<String, String> {
+        let entry = keyring::Entry::new(&self.service, &self.account)
+            .map_err(|e| format!("OS keyring unavailable: {}", e))?;
+        self.read(&entry)
+    }
+}
+
+impl KeyringSecret {
+    // ── The secret stored in `entry`; apart from `fetch` so tests can mock it ──
+    fn read(&self, entry: &keyring::Entry) -> Result<String, String> {
+        match entry.get_password() {
+            Ok(secret) if !secret.is_empty() => Ok(secret),
+            Ok(_) => Err(format!("Keyring entry {}/{} is empty", self.service, self.account)),
+            Err(keyring::Error::NoEntry) => Err(format!(
+                "No keyring entry for service {:?}, account {:?}",
+                self.service, self.account
+            )),
+            Err(e @ (keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_))) => {
+                Err(format!("OS keyring unavailable: {}", e))
+            }
+            Err(e) => Err(format!("Keyring read failed: {}", e)),
+        }
+    }
+}
+
+// ── HashiCorp Vault, KV v1 or v2 ──
+pub struct VaultSecret {
+    addr: String,
+    token: String,
+    // e.g. `secret/data/freecord` for KV v2
+    path: String,
+    field: String,
+}
+
+impl SecretProvider for VaultSecret {
+    fn name(&self) -> &'static str {
+        "vault"
+    }
+
+    fn fetch(&self) -> Result<String, String> {
+        let url = format!("{}/v1/{}", self.addr.trim_end_matches('/'), self.path);
+        let body: serde_json::Value = ureq::get(&url)
+            .set("X-Vault-Token", &self.token)
+            .call()
+            .map_err(|e| format!("Vault request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Vault response was not JSON: {}", e))?;
+
+        // KV v2 nests the secret one level deeper than v1
+        let data = &body["data"];
+        data["data"][&self.field]
+            .as_str()
+            .or_else(|| data[&self.field].as_str())
+            .map(str::to_string)
+            .ok_or_else(|| format!("Vault secret {} has no string field {:?}", self.path, self.field))
+    }
+}
+
+// ── AWS Secrets Manager GetSecretValue, signed with SigV4 ──
+pub struct AwsSecret {
+    region: String,
+    secret_id: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl SecretProvider for AwsSecret {
+    fn name(&self) -> &'static str {
+        "aws"
+    }
+
+    fn fetch(&self) -> Result<String, String> {
+        let host = format!("secretsmanager.{}.amazonaws.com", self.region);
+        let payload = serde_json::json!({ "SecretId": self.secret_id }).to_string();
+
+        let mut request = ureq::post(&format!("https://{}/", host));
+        for (name, value) in self.sign(&host, &payload, SystemTime::now()) {
+            request = request.set(name, &value);
+        }
+        let body: serde_json::Value = request
+            .send_string(&payload)
+            .map_err(|e| format!("Secrets Manager request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Secrets Manager response was not JSON: {}", e))?;
+
+        body["SecretString"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("Secret {} has no SecretString", self.secret_id))
+    }
+}
+
+impl AwsSecret {
+    // ── All request headers, including the SigV4 Authorization ──
+    fn sign(&self, host: &str, payload: &str, now: SystemTime) -> Vec<(&'static str, String)> {
+        let (amz_date, date) = amz_timestamps(now);
+
+        // Canonical headers must be lowercase and sorted by name
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.push(("x-amz-target", "secretsmanager.GetSecretValue".to_string()));
+
+        let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let canonical_headers: String =
+            headers.iter().map(|(k, v)| format!("{}:{}\n", k, v)).collect();
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            hex(&Sha256::digest(payload.as_bytes())),
+        );
+
+        let scope = format!("{}/{}/secretsmanager/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes())),
+        );
+
+        let mut key = hmac(format!("AWS4{}", self.secret_access_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "secretsmanager", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, signed_headers, signature,
+        );
+
+        headers.push(("authorization", authorization));
+        headers
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// ── `YYYYMMDDTHHMMSSZ` and `YYYYMMDD` in UTC ──
+fn amz_timestamps(now: SystemTime) -> (String, String) {
+    let secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!("{:02}{:02}{:02}", rem / 3600, rem % 3600 / 60, rem % 60);
+    (format!("{}T{}Z", date, time), date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn aws(session_token: Option<&str>) -> AwsSecret {
+        AwsSecret {
+            region: "eu-west-1".into(),
+            secret_id: "freecord/master".into(),
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY".into(),
+            session_token: session_token.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn amz_timestamps_are_utc_civil_dates() {
+        assert_eq!(amz_timestamps(at(0)), ("19700101T000000Z".into(), "19700101".into()));
+        assert_eq!(amz_timestamps(at(1_700_000_000)).0, "20231114T221320Z");
+        // Leap days, including the century rule
+        assert_eq!(amz_timestamps(at(951_782_400)).1, "20000229");
+        assert_eq!(amz_timestamps(at(1_709_164_800)).1, "20240229");
+        assert_eq!(amz_timestamps(at(1_709_251_199)).0, "20240229T235959Z");
+    }
+
+    // Expected signature computed outside the service from the SigV4 spec
+    #[test]
+    fn sigv4_signs_the_canonical_request() {
+        let host = "secretsmanager.eu-west-1.amazonaws.com";
+        let headers = aws(None).sign(host, r#"{"SecretId":"freecord/master"}"#, at(1_700_000_000));
+        let (name, authorization) = headers.last().unwrap();
+        assert_eq!(*name, "authorization");
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 \
+             Credential=AKIDEXAMPLE/20231114/eu-west-1/secretsmanager/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date;x-amz-target, \
+             Signature=c92e040b69393579280f205410309061b7463414f172dca25bdf65f7efceba50",
+        );
+    }
+
+    #[test]
+    fn a_session_token_is_signed_too() {
+        let headers = aws(Some("token")).sign("host", "{}", at(1_700_000_000));
+        assert!(headers.contains(&("x-amz-security-token", "token".into())));
+        let authorization = &headers.last().unwrap().1;
+        assert!(authorization
+            .contains("SignedHeaders=content-type;host;x-amz-date;x-amz-security-token;"));
+    }
+
+    fn secret_file(name: &str, contents: &str) -> FileSecret {
+        let file = format!("freecord-{}-secret-{}", std::process::id(), name);
+        let path = std::env::temp_dir().join(file);
+        std::fs::write(&path, contents).unwrap();
+        FileSecret { path: path.to_str().unwrap().to_owned() }
+    }
+
+    #[test]
+    fn a_secret_file_loses_only_its_trailing_newline() {
+        for (name, contents) in [("lf", "s3cret\n"), ("crlf", "s3cret\r\n"), ("bare", "s3cret")] {
+            let file = secret_file(name, contents);
+            assert_eq!(file.fetch().unwrap(), "s3cret");
+            std::fs::remove_file(&file.path).unwrap();
+        }
+        let file = secret_file("spaces", " s3cret \n\n");
+        assert_eq!(file.fetch().unwrap(), " s3cret \n");
+        std::fs::remove_file(&file.path).unwrap();
+    }
+
+    #[test]
+    fn an_empty_or_missing_secret_file_is_an_error() {
+        let file = secret_file("empty", "\n");
+        assert!(file.fetch().unwrap_err().contains("is empty"));
+        std::fs::remove_file(&file.path).unwrap();
+        assert!(file.fetch().unwrap_err().starts_with("Cannot read MASTER_SECRET_FILE"));
+    }
+
+    #[test]
+    fn only_the_builtin_secret_is_the_default() {
+        assert!(is_default(DEFAULT_SECRET));
+        assert!(!is_default("default-secret-change-me "));
+    }
+
+    // Each case writes its own file and returns what reading it gave
+    fn tenant_secrets(name: &str, contents: &str) -> Result<HashMap<i64, String>, String> {
+        let file = format!("freecord-{}-tenants-{}.json", std::process::id(), name);
+        let path = std::env::temp_dir().join(file);
+        std::fs::write(&path, contents).unwrap();
+        let read = read_tenant_secrets(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        read
+    }
+
+    #[test]
+    fn tenant_secrets_are_keyed_by_org_id() {
+        let read = tenant_secrets("ok", r#"{"42": "forty-two", "7": "seven"}"#).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[&42], "forty-two");
+        assert_eq!(read[&7], "seven");
+        assert!(tenant_secrets("empty", "{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_tenant_secrets_files_are_refused() {
+        let not_json = tenant_secrets("list", r#"["42"]"#).unwrap_err();
+        assert!(not_json.contains("is not a JSON object"));
+        let bad_org = tenant_secrets("org", r#"{"acme": "s3cret"}"#).unwrap_err();
+        assert!(bad_org.contains("is not an org id"));
+        for (name, secret) in [("blank", ""), ("default", DEFAULT_SECRET)] {
+            let unset = tenant_secrets(name, &json!({ "42": secret }).to_string()).unwrap_err();
+            assert!(unset.contains("for org 42 is unset"));
+        }
+        let missing = read_tenant_secrets("/nonexistent/freecord-tenants.json").unwrap_err();
+        assert!(missing.starts_with("Cannot read TENANT_SECRETS_FILE"));
+    }
+
+    fn keyring() -> (KeyringSecret, keyring::Entry) {
+        let credential = Box::new(keyring::mock::MockCredential::default());
+        let secret = KeyringSecret { service: "freecord".into(), account: "master_secret".into() };
+        (secret, keyring::Entry::new_with_credential(credential))
+    }
+
+    #[test]
+    fn the_keyring_entry_is_the_secret() {
+        let (secret, entry) = keyring();
+        entry.set_password("s3cret").unwrap();
+        assert_eq!(secret.read(&entry).unwrap(), "s3cret");
+    }
+
+    #[test]
+    fn keyring_failures_are_named() {
+        let (secret, entry) = keyring();
+        let missing = secret.read(&entry).unwrap_err();
+        assert!(missing.starts_with("No keyring entry for service \"freecord\""), "{}", missing);
+
+        entry.set_password("").unwrap();
+        assert!(secret.read(&entry).unwrap_err().contains("is empty"));
+
+        let mock: &keyring::mock::MockCredential = entry.get_credential().downcast_ref().unwrap();
+        mock.set_error(keyring::Error::NoStorageAccess("locked".into()));
+        assert!(secret.read(&entry).unwrap_err().starts_with("OS keyring unavailable"));
+    }
+}
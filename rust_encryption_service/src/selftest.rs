@@ -0,0 +1,135 @@
+use aes_gcm::aead::Payload;
+use serde::Serialize;
+
+use crate::crypto::{Algorithm, ChannelCipher};
+
+// ── Known-answer self-test of both AEADs ──
+// Run once at startup through ChannelCipher, the same path every message
+// takes; any mismatch and the service refuses to start. The result is kept
+// for GET /admin/crypto-selftest.
+
+// Every byte field in hex
+struct Vector {
+    name: &'static str,
+    algorithm: Algorithm,
+    key: &'static str,
+    nonce: &'static str,
+    aad: &'static str,
+    plaintext: &'static str,
+    // Ciphertext followed by the 16-byte tag
+    sealed: &'static str,
+}
+
+const VECTORS: &[Vector] = &[
+    // NIST CAVP gcmEncryptExtIV256.rsp
+    Vector {
+        name: "nist-gcm-256-empty",
+        algorithm: Algorithm::Aes256gcm,
+        key: "b52c505a37d78eda5dd34f20c22540ea1b58963cf8e5bf8ffa85f9f2492505b4",
+        nonce: "516c33929df5a3284ff463d7",
+        aad: "",
+        plaintext: "",
+        sealed: "bdc1ac884d332457a1d2664f168c76f0",
+    },
+    Vector {
+        name: "nist-gcm-256-plaintext",
+        algorithm: Algorithm::Aes256gcm,
+        key: "31bdadd96698c204aa9ce1448ea94ae1fb4a9a0b3c9d773b51bb1822666b8f22",
+        nonce: "0d18e06c7c725ac9e362e1ce",
+        aad: "",
+        plaintext: "2db5168e932556f8089a0622981d017d",
+        sealed: "fa4362189661d163fcd6a56d8bf0405ad636ac1bbedd5cc3ee727dc2ab4a9489",
+    },
+    Vector {
+        name: "nist-gcm-256-aad",
+        algorithm: Algorithm::Aes256gcm,
+        key: "92e11dcdaa866f5ce790fd24501f92509aacf4cb8b1339d50c9c1240935dd08b",
+        nonce: "ac93a1a6145299bde902f21a",
+        aad: "1e0889016f67601c8ebea4943bc23ad6",
+        plaintext: "2d71bcfa914e4ac045b2aa60955fad24",
+        sealed: "8995ae2e6df3dbf96fac7b7137bae67feca5aa77d51d4a0a14d9c51e1da474ab",
+    },
+    // RFC 8439 section 2.8.2
+    Vector {
+        name: "rfc8439-2.8.2",
+        algorithm: Algorithm::Chacha20poly1305,
+        key: "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+        nonce: "070000004041424344454647",
+        aad: "50515253c0c1c2c3c4c5c6c7",
+        // "Ladies and Gentlemen of the class of '99: If I could offer you only
+        // one tip for the future, sunscreen would be it."
+        plaintext: "4c616469657320616e642047656e746c656d656e206f662074686520636c6173\
+            73206f66202739393a204966204920636f756c64206f6666657220796f75206f\
+            6e6c79206f6e652074697020666f7220746865206675747572652c2073756e73\
+            637265656e20776f756c642062652069742e",
+        sealed: "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6\
+            3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36\
+            92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+            3ff4def08e4b7a9de576d26586cec64b6116\
+            1ae10b594f09e26a7e902ecbd0600691",
+    },
+];
+
+#[derive(Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub vectors: Vec<VectorResult>,
+}
+
+#[derive(Serialize)]
+pub struct VectorResult {
+    pub name: &'static str,
+    pub algorithm: Algorithm,
+    // Sealing gave the published bytes, and opening them gave the plaintext back
+    pub passed: bool,
+}
+
+pub fn run() -> SelfTestReport {
+    let vectors: Vec<VectorResult> = VECTORS
+        .iter()
+        .map(|v| VectorResult { name: v.name, algorithm: v.algorithm, passed: check(v) })
+        .collect();
+    SelfTestReport { passed: vectors.iter().all(|v| v.passed), vectors }
+}
+
+fn check(v: &Vector) -> bool {
+    let decoded = [v.key, v.nonce, v.aad, v.plaintext, v.sealed].map(hex::decode);
+    let [Ok(key), Ok(nonce), Ok(aad), Ok(plaintext), Ok(expected)] = decoded else {
+        return false;
+    };
+    let cipher = ChannelCipher::new(v.algorithm, &key);
+    let sealed = cipher.encrypt(&nonce, Payload { msg: &plaintext, aad: &aad });
+    let opened = cipher.decrypt(&nonce, Payload { msg: &expected, aad: &aad });
+    sealed.is_ok_and(|sealed| sealed == expected) && opened.is_ok_and(|opened| opened == plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_vector_passes() {
+        let report = run();
+        let names: Vec<_> = report.vectors.iter().filter(|v| v.passed).map(|v| v.name).collect();
+        assert_eq!(names, VECTORS.iter().map(|v| v.name).collect::<Vec<_>>());
+        assert!(report.passed);
+    }
+
+    #[test]
+    fn a_wrong_tag_or_plaintext_fails() {
+        let good = &VECTORS[2];
+        // The last byte of the tag changed
+        let sealed = "8995ae2e6df3dbf96fac7b7137bae67feca5aa77d51d4a0a14d9c51e1da474ac";
+        let bad_tag = Vector { sealed, ..*good };
+        assert!(!check(&bad_tag));
+        let bad_plaintext = Vector { plaintext: "2d71bcfa914e4ac045b2aa60955fad25", ..*good };
+        assert!(!check(&bad_plaintext));
+        let bad_aad = Vector { aad: "", ..*good };
+        assert!(!check(&bad_aad));
+    }
+
+    #[test]
+    fn a_malformed_vector_fails() {
+        assert!(!check(&Vector { key: "not hex", ..VECTORS[0] }));
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use actix_web::web;
+use serde::Serialize;
+
+use crate::AppState;
+
+// ── One structured line on the way out, whatever the cause ──
+// Logged once, as "Shutdown: {json}", when main returns (a signal stopped
+// the server, or startup, binding or the server itself failed) or when the
+// main thread panics. A panic caught in a handler does not stop the process
+// and is not reported here.
+pub struct ShutdownDiagnostics {
+    started: Instant,
+    phase: AtomicU8,
+    state: OnceLock<web::Data<AppState>>,
+    reported: AtomicBool,
+}
+
+// How far startup got, so a failure can be told apart from a bind failure
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum Phase {
+    Starting,
+    Binding,
+    Serving,
+}
+
+#[derive(Serialize)]
+struct ShutdownReport<'a> {
+    reason: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    uptime_secs: f64,
+    // Messages and files sealed, and decrypts attempted, over the process' life
+    encrypts: u64,
+    decrypts: u64,
+}
+
+impl ShutdownDiagnostics {
+    pub fn new() -> Arc<Self> {
+        let diagnostics = Arc::new(ShutdownDiagnostics {
+            started: Instant::now(),
+            phase: AtomicU8::new(Phase::Starting as u8),
+            state: OnceLock::new(),
+            reported: AtomicBool::new(false),
+        });
+        let on_panic = diagnostics.clone();
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // Worker threads are restarted; only the main thread takes the process down
+            if std::thread::current().name() == Some("main") {
+                on_panic.report("panic", Some(info.to_string()));
+            }
+            default_hook(info);
+        }));
+        diagnostics
+    }
+
+    pub fn set_phase(&self, phase: Phase) {
+        self.phase.store(phase as u8, Ordering::Release);
+    }
+
+    // Counts come from the metrics; before this is called they read zero
+    pub fn attach(&self, state: web::Data<AppState>) {
+        let _ = self.state.set(state);
+    }
+
+    // ── Log the line for how `main` ended ──
+    pub fn finish(&self, result: &std::io::Result<()>) {
+        let (reason, error) = self.outcome(result);
+        self.report(reason, error);
+    }
+
+    fn outcome(&self, result: &std::io::Result<()>) -> (&'static str, Option<String>) {
+        let Err(e) = result else {
+            // The server only stops on its own after SIGINT, SIGTERM or SIGQUIT
+            return ("signal", None);
+        };
+        let reason = match self.phase.load(Ordering::Acquire) {
+            p if p == Phase::Starting as u8 => "startup_failed",
+            p if p == Phase::Binding as u8 => "bind_failed",
+            _ => "server_error",
+        };
+        (reason, Some(e.to_string()))
+    }
+
+    fn report(&self, reason: &str, error: Option<String>) {
+        let Some(report) = self.take_report(reason, error) else {
+            return;
+        };
+        match serde_json::to_string(&report) {
+            Ok(json) => log::info!("Shutdown: {}", json),
+            Err(e) => log::warn!("Failed to encode shutdown diagnostics: {}", e),
+        }
+        log::logger().flush();
+    }
+
+    // None once a report was taken, so a panic during shutdown logs one line
+    fn take_report<'a>(
+        &self,
+        reason: &'a str,
+        error: Option<String>,
+    ) -> Option<ShutdownReport<'a>> {
+        if self.reported.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        let (encrypts, decrypts) = self.state.get().map_or((0, 0), |s| s.metrics.operations());
+        Some(ShutdownReport {
+            reason,
+            error,
+            uptime_secs: self.started.elapsed().as_secs_f64(),
+            encrypts,
+            decrypts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Without `new`, which would install the panic hook
+    fn diagnostics(phase: Phase) -> ShutdownDiagnostics {
+        ShutdownDiagnostics {
+            started: Instant::now(),
+            phase: AtomicU8::new(phase as u8),
+            state: OnceLock::new(),
+            reported: AtomicBool::new(false),
+        }
+    }
+
+    fn failed() -> std::io::Result<()> {
+        Err(std::io::Error::new(std::io::ErrorKind::AddrInUse, "address in use"))
+    }
+
+    #[test]
+    fn a_clean_stop_was_a_signal() {
+        assert_eq!(diagnostics(Phase::Serving).outcome(&Ok(())), ("signal", None));
+    }
+
+    #[test]
+    fn a_failure_is_named_by_phase() {
+        for (phase, reason) in [
+            (Phase::Starting, "startup_failed"),
+            (Phase::Binding, "bind_failed"),
+            (Phase::Serving, "server_error"),
+        ] {
+            let (got, error) = diagnostics(phase).outcome(&failed());
+            assert_eq!(got, reason);
+            assert_eq!(error.as_deref(), Some("address in use"));
+        }
+        let moved = diagnostics(Phase::Starting);
+        moved.set_phase(Phase::Binding);
+        assert_eq!(moved.outcome(&failed()).0, "bind_failed");
+    }
+
+    #[test]
+    fn only_one_report_is_taken() {
+        let diagnostics = diagnostics(Phase::Serving);
+        let report = diagnostics.take_report("signal", None).unwrap();
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["reason"], "signal");
+        assert!(json.get("error").is_none());
+        assert_eq!((json["encrypts"].as_u64(), json["decrypts"].as_u64()), (Some(0), Some(0)));
+        assert!(diagnostics.take_report("panic", Some("boom".into())).is_none());
+    }
+}
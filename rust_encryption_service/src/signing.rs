@@ -0,0 +1,150 @@
+use actix_web::body::{self, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use sha2::Sha256;
+
+pub const SIGNATURE_HEADER: HeaderName = HeaderName::from_static("x-signature");
+
+// ── Shared key clients use to verify response bodies ──
+pub struct SigningKey(pub Vec<u8>);
+
+// ── Middleware: X-Signature = base64(HMAC-SHA256(key, body)) ──
+// App-layer integrity that survives a TLS-terminating proxy. Only the body
+// is covered; status and headers are not. Responses pass through unsigned
+// when no SigningKey is registered.
+pub async fn sign_response(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let key = req.app_data::<web::Data<SigningKey>>().cloned();
+    let res = next.call(req).await?;
+    let Some(key) = key else {
+        return Ok(res.map_into_boxed_body());
+    };
+
+    let (req, res) = res.into_parts();
+    let (mut head, body) = res.into_parts();
+    let body = body::to_bytes(body).await.map_err(|e| ErrorInternalServerError(e.into()))?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key.0).expect("HMAC accepts keys of any length");
+    mac.update(&body);
+    let signature = BASE64.encode(mac.finalize().into_bytes());
+    head.headers_mut().insert(
+        SIGNATURE_HEADER,
+        HeaderValue::from_str(&signature).expect("base64 is a valid header value"),
+    );
+
+    Ok(ServiceResponse::new(req, head.set_body(body).map_into_boxed_body()))
+}
+
+// ── Ed25519 key that signs archival envelopes ──
+// ARCHIVE_SIGNING_KEY is a base64 32-byte Ed25519 seed. Unlike X-Signature,
+// the signature travels in the body and is checked against the public key
+// alone, so an envelope stored for years can still be verified offline.
+pub struct ArchiveSigner(Ed25519KeyPair);
+
+impl ArchiveSigner {
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Ok(encoded) = std::env::var("ARCHIVE_SIGNING_KEY") else {
+            return Ok(None);
+        };
+        let seed = BASE64
+            .decode(encoded.trim())
+            .map_err(|_| "ARCHIVE_SIGNING_KEY must be base64".to_string())?;
+        ArchiveSigner::from_seed(&seed)
+            .map(Some)
+            .ok_or_else(|| "ARCHIVE_SIGNING_KEY must decode to a 32-byte Ed25519 seed".to_string())
+    }
+
+    pub fn from_seed(seed: &[u8]) -> Option<Self> {
+        Ed25519KeyPair::from_seed_unchecked(seed).ok().map(ArchiveSigner)
+    }
+
+    // Base64 of the raw 32-byte public key
+    pub fn public_key(&self) -> String {
+        BASE64.encode(self.0.public_key().as_ref())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> String {
+        BASE64.encode(self.0.sign(message).as_ref())
+    }
+}
+
+// ── The bytes an archival envelope signature covers ──
+// A domain tag, then each field on its own line; none of them can hold a
+// newline, so no two envelopes share a message.
+pub fn archive_message(
+    ciphertext: &str,
+    generation: &str,
+    algorithm: &str,
+    produced_at: u64,
+) -> Vec<u8> {
+    format!("freecord/archive/v1\n{ciphertext}\n{generation}\n{algorithm}\n{produced_at}\n")
+        .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{middleware, App, HttpResponse};
+
+    async fn signed(key: Option<&[u8]>) -> (Option<String>, web::Bytes) {
+        let mut app = App::new();
+        if let Some(key) = key {
+            app = app.app_data(web::Data::new(SigningKey(key.to_vec())));
+        }
+        let app = init_service(
+            app.wrap(middleware::from_fn(sign_response))
+                .route("/", web::get().to(|| async { HttpResponse::Ok().body("hello") })),
+        )
+        .await;
+        let resp = call_service(&app, TestRequest::get().to_request()).await;
+        let signature = resp
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .map(|v| v.to_str().unwrap().to_owned());
+        (signature, read_body(resp).await)
+    }
+
+    #[actix_web::test]
+    async fn signature_is_hmac_sha256_of_the_body() {
+        let (signature, body) = signed(Some(b"signing-key")).await;
+        assert_eq!(body, "hello");
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"signing-key").unwrap();
+        mac.update(b"hello");
+        let expected = BASE64.encode(mac.finalize().into_bytes());
+        assert_eq!(signature, Some(expected));
+    }
+
+    #[actix_web::test]
+    async fn without_a_key_responses_pass_unsigned() {
+        let (signature, body) = signed(None).await;
+        assert_eq!(signature, None);
+        assert_eq!(body, "hello");
+    }
+
+    #[test]
+    fn archive_signatures_verify_under_the_public_key() {
+        let signer = ArchiveSigner::from_seed(&[7; 32]).unwrap();
+        let message = archive_message("blob", "0816 b95e", "aes256gcm", 1_700_000_000);
+        let signature = BASE64.decode(signer.sign(&message)).unwrap();
+        let public_key = BASE64.decode(signer.public_key()).unwrap();
+        let key = ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key);
+        assert!(key.verify(&message, &signature).is_ok());
+        let other = archive_message("blob", "0816 b95e", "aes256gcm", 1_700_000_001);
+        assert!(key.verify(&other, &signature).is_err());
+    }
+
+    #[test]
+    fn archive_message_puts_each_field_on_its_own_line() {
+        let message = archive_message("blob", "gen", "aes256gcm", 5);
+        assert_eq!(message, b"freecord/archive/v1\nblob\ngen\naes256gcm\n5\n");
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+// ── Rolling decrypt success ratio, with an optional alert webhook ──
+// Counts authenticated opens against authentication failures over the last
+// SLO_WINDOW_SECS; malformed requests say nothing about the keys and are not
+// counted. With SLO_ALERT_WEBHOOK set, the ratio falling below
+// SLO_DECRYPT_SUCCESS_THRESHOLD posts one "firing" alert, and climbing back
+// one "resolved". Windows with fewer than SLO_MIN_DECRYPTS decrypts never alert.
+pub struct SloMonitor {
+    pub window_secs: u64,
+    pub threshold: f64,
+    min_decrypts: u64,
+    webhook: Option<String>,
+    // One (unix second, successes, failures) per second with decrypts, oldest first
+    seconds: Mutex<VecDeque<(u64, u64, u64)>>,
+    firing: AtomicBool,
+}
+
+#[derive(Serialize)]
+struct Alert<'a> {
+    status: &'a str,
+    decrypt_success_ratio: f64,
+    threshold: f64,
+    window_secs: u64,
+    decrypts: u64,
+    failures: u64,
+    at: u64,
+}
+
+fn env<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+// The SLO_* settings when unset
+const DEFAULT_WINDOW_SECS: u64 = 300;
+const DEFAULT_THRESHOLD: f64 = 0.99;
+const DEFAULT_MIN_DECRYPTS: u64 = 20;
+
+impl Default for SloMonitor {
+    fn default() -> Self {
+        SloMonitor::new(DEFAULT_WINDOW_SECS, DEFAULT_THRESHOLD, DEFAULT_MIN_DECRYPTS, None)
+    }
+}
+
+impl SloMonitor {
+    pub fn from_env() -> Self {
+        SloMonitor::new(
+            env("SLO_WINDOW_SECS", DEFAULT_WINDOW_SECS),
+            env("SLO_DECRYPT_SUCCESS_THRESHOLD", DEFAULT_THRESHOLD),
+            env("SLO_MIN_DECRYPTS", DEFAULT_MIN_DECRYPTS),
+            std::env::var("SLO_ALERT_WEBHOOK").ok().filter(|url| !url.is_empty()),
+        )
+    }
+
+    pub fn new(
+        window_secs: u64,
+        threshold: f64,
+        min_decrypts: u64,
+        webhook: Option<String>,
+    ) -> Self {
+        SloMonitor {
+            window_secs: window_secs.max(1),
+            threshold,
+            min_decrypts,
+            webhook,
+            seconds: Mutex::new(VecDeque::new()),
+            firing: AtomicBool::new(false),
+        }
+    }
+
+    pub fn webhook_enabled(&self) -> bool {
+        self.webhook.is_some()
+    }
+
+    // ── Count one decrypt that authenticated, or failed to ──
+    pub fn record(&self, success: bool, now: u64) {
+        let (successes, failures) = {
+            let mut seconds = self.seconds.lock().unwrap();
+            match seconds.back_mut() {
+                Some((second, ok, failed)) if *second == now => {
+                    *if success { ok } else { failed } += 1;
+                }
+                _ => seconds.push_back((now, success as u64, !success as u64)),
+            }
+            self.totals(&mut seconds, now)
+        };
+        self.check(successes, failures, now);
+    }
+
+    // ── Success ratio over the window; None with no decrypts in it ──
+    pub fn ratio(&self, now: u64) -> Option<f64> {
+        let (successes, failures) = self.totals(&mut self.seconds.lock().unwrap(), now);
+        let total = successes + failures;
+        (total > 0).then(|| successes as f64 / total as f64)
+    }
+
+    // Drops seconds that left the window and sums the rest
+    fn totals(&self, seconds: &mut VecDeque<(u64, u64, u64)>, now: u64) -> (u64, u64) {
+        while seconds.front().is_some_and(|&(second, _, _)| second + self.window_secs <= now) {
+            seconds.pop_front();
+        }
+        seconds.iter().fold((0, 0), |(ok, failed), &(_, s, f)| (ok + s, failed + f))
+    }
+
+    fn check(&self, successes: u64, failures: u64, now: u64) {
+        let Some(url) = &self.webhook else {
+            return;
+        };
+        let decrypts = successes + failures;
+        if decrypts < self.min_decrypts {
+            return;
+        }
+        let ratio = successes as f64 / decrypts as f64;
+        let breached = ratio < self.threshold;
+        // Only a change of state posts, so a sustained breach pages once
+        if self.firing.swap(breached, Ordering::AcqRel) == breached {
+            return;
+        }
+        let alert = Alert {
+            status: if breached { "firing" } else { "resolved" },
+            decrypt_success_ratio: ratio,
+            threshold: self.threshold,
+            window_secs: self.window_secs,
+            decrypts,
+            failures,
+            at: now,
+        };
+        log::warn!(
+            "Decrypt success ratio {:.4} over {}s is {} (threshold {})",
+            ratio, self.window_secs, alert.status, self.threshold
+        );
+        let Ok(body) = serde_json::to_value(&alert) else {
+            return;
+        };
+        // ureq blocks, and this runs on the request path
+        let url = url.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = ureq::post(&url).send_json(body) {
+                log::error!("SLO alert webhook failed: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(webhook: Option<&str>) -> SloMonitor {
+        SloMonitor::new(60, 0.9, 10, webhook.map(str::to_owned))
+    }
+
+    #[test]
+    fn ratio_covers_only_the_window() {
+        let slo = monitor(None);
+        assert_eq!(slo.ratio(1_000), None);
+        slo.record(false, 1_000);
+        slo.record(true, 1_030);
+        slo.record(true, 1_030);
+        slo.record(true, 1_030);
+        assert_eq!(slo.ratio(1_030), Some(0.75));
+        // The failure leaves the window a minute after it happened
+        assert_eq!(slo.ratio(1_060), Some(1.0));
+        assert_eq!(slo.ratio(1_090), None);
+        assert!(slo.seconds.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn one_entry_per_second() {
+        let slo = monitor(None);
+        for _ in 0..5 {
+            slo.record(true, 1_000);
+        }
+        slo.record(false, 1_001);
+        let seconds = slo.seconds.lock().unwrap();
+        assert_eq!(seconds.iter().copied().collect::<Vec<_>>(), [(1_000, 5, 0), (1_001, 0, 1)]);
+    }
+
+    #[test]
+    fn a_breach_fires_once_and_then_resolves() {
+        // Nothing listens on the discard port, so each alert's post just fails
+        let slo = monitor(Some("http://127.0.0.1:9/alerts"));
+        // Too few decrypts to judge, however badly they went
+        for _ in 0..9 {
+            slo.record(false, 1_000);
+        }
+        assert!(!slo.firing.load(Ordering::Acquire));
+        slo.record(false, 1_000);
+        assert!(slo.firing.load(Ordering::Acquire));
+
+        // Climbing back to the threshold resolves it: 90 of 100
+        for _ in 0..89 {
+            slo.record(true, 1_001);
+        }
+        assert!(slo.firing.load(Ordering::Acquire));
+        slo.record(true, 1_001);
+        assert_eq!(slo.ratio(1_001), Some(0.9));
+        assert!(!slo.firing.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn without_a_webhook_nothing_fires() {
+        let slo = monitor(None);
+        for _ in 0..20 {
+            slo.record(false, 1_000);
+        }
+        assert!(!slo.webhook_enabled());
+        assert!(!slo.firing.load(Ordering::Acquire));
+    }
+}
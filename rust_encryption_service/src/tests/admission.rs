@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web;
+use serde_json::json;
+use tokio::sync::Semaphore;
+
+use super::support;
+use crate::admission::ChannelShares;
+use crate::crypto::KeyId;
+use crate::AppState;
+
+// ── Crypto admission ──
+// Past MAX_CONCURRENT_CRYPTO in flight, encrypts and decrypts are shed with
+// a 503 and Retry-After rather than queued without bound.
+
+fn one_slot() -> AppState {
+    let mut state = support::state();
+    state.crypto_permits = Arc::new(Semaphore::new(1));
+    state
+}
+
+#[actix_web::test]
+async fn busy_slots_shed_with_retry_after() {
+    let state = web::Data::new(one_slot());
+    let held = state.crypto_permits.clone().try_acquire_owned().unwrap();
+
+    let hi = json!({ "channel_id": 1, "message": "hi" });
+    let reply = support::post(&state, "/encrypt", hi).await;
+    assert_eq!(reply.status, 503);
+    assert_eq!(reply.code(), "overloaded");
+    assert_eq!(reply.header("Retry-After"), Some("1"));
+    assert_eq!(state.metrics.crypto_requests_shed.since_reset(), 1);
+
+    drop(held);
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+}
+
+#[actix_web::test]
+async fn queued_request_gets_a_freed_slot() {
+    let mut state = one_slot();
+    state.crypto_queue_timeout = Duration::from_secs(5);
+    let state = web::Data::new(state);
+    let held = state.crypto_permits.clone().try_acquire_owned().unwrap();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(held);
+    });
+
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(state.metrics.crypto_requests_shed.since_reset(), 0);
+}
+
+#[actix_web::test]
+async fn queue_timeout_sheds() {
+    let mut state = one_slot();
+    state.crypto_queue_timeout = Duration::from_millis(20);
+    let state = web::Data::new(state);
+    let _held = state.crypto_permits.clone().try_acquire_owned().unwrap();
+
+    let hi = json!({ "channel_id": 1, "message": "hi" });
+    let reply = support::post(&state, "/encrypt", hi).await;
+    assert_eq!(reply.status, 503);
+    assert_eq!(reply.code(), "overloaded");
+}
+
+#[actix_web::test]
+async fn slots_are_freed_after_each_request() {
+    let state = web::Data::new(one_slot());
+    for _ in 0..3 {
+        let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+        let reply = support::post(&state, "/decrypt", json!({ "channel_id": 1, "encrypted": blob }))
+            .await;
+        assert_eq!(reply.status, 200, "{:?}", reply.body);
+    }
+    assert_eq!(state.crypto_permits.available_permits(), 1);
+}
+
+// ── Per-channel shares ──
+// With MAX_CRYPTO_PER_CHANNEL set, a channel at its share is shed while the
+// others still get slots.
+
+#[actix_web::test]
+async fn a_channel_at_its_share_is_shed_alone() {
+    let mut state = support::state();
+    state.channel_shares = ChannelShares::new(Some(1));
+    let state = web::Data::new(state);
+    let held = state.crypto_permit(Some(KeyId::new(None, 1))).await;
+    assert!(held.is_ok());
+
+    let hi = json!({ "channel_id": 1, "message": "hi" });
+    let reply = support::post(&state, "/encrypt", hi).await;
+    assert_eq!(reply.status, 503);
+    assert_eq!(reply.code(), "channel_overloaded");
+    assert_eq!(reply.header("Retry-After"), Some("1"));
+    support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+
+    drop(held);
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+}
+
+// ── Fair queuing ──
+// Requests waiting for a slot are served a channel at a time, so a flood
+// queued on one channel does not hold back another's.
+
+#[actix_web::test]
+async fn queued_channels_take_turns() {
+    let mut state = one_slot();
+    state.crypto_queue_timeout = Duration::from_secs(5);
+    let state = web::Data::new(state);
+    let held = state.crypto_permits.clone().try_acquire_owned().unwrap();
+
+    let served = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut waiters = Vec::new();
+    for channel_id in [1, 1, 1, 2] {
+        let (state, served) = (state.clone(), served.clone());
+        waiters.push(tokio::spawn(async move {
+            let permit = state.crypto_permit(Some(KeyId::new(None, channel_id))).await;
+            assert!(permit.is_ok());
+            served.lock().unwrap().push(channel_id);
+        }));
+        // Let it take its place in line before the next one arrives
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    drop(held);
+    for waiter in waiters {
+        waiter.await.unwrap();
+    }
+    assert_eq!(*served.lock().unwrap(), [1, 2, 1, 1]);
+    assert!(state.channel_shares.is_idle());
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use actix_web::test::TestRequest;
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+
+use super::support;
+use crate::crypto::Algorithm;
+use crate::{choose_default_algorithm, AppState};
+
+// ── GET /algorithms ──
+// One entry per supported cipher, with the sizes a client needs to plan
+// around; the server's default is the one marked recommended.
+
+async fn algorithms(state: &web::Data<AppState>) -> Vec<Value> {
+    let reply = support::call(state, TestRequest::get().uri("/algorithms")).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json().as_array().unwrap().clone()
+}
+
+fn named<'a>(list: &'a [Value], name: &str) -> &'a Value {
+    list.iter().find(|a| a["name"] == name).unwrap()
+}
+
+#[actix_web::test]
+async fn aes_gcm_is_listed_with_its_sizes() {
+    let state = web::Data::new(support::state());
+    let list = algorithms(&state).await;
+    assert_eq!(list.len(), Algorithm::ALL.len());
+
+    let aes = named(&list, "aes256gcm");
+    assert_eq!(aes["id"], 1);
+    assert_eq!(aes["key_bytes"], 32);
+    assert_eq!(aes["nonce_bytes"], 12);
+    assert_eq!(aes["tag_bytes"], 16);
+    assert_eq!(aes["nonce_misuse_resistant"], false);
+}
+
+#[actix_web::test]
+async fn the_default_algorithm_is_recommended() {
+    let mut state = support::state();
+    state.default_algorithm = Algorithm::Chacha20poly1305;
+    let list = algorithms(&web::Data::new(state)).await;
+    assert_eq!(named(&list, "chacha20poly1305")["recommended"], true);
+    assert_eq!(named(&list, "aes256gcm")["recommended"], false);
+}
+
+#[actix_web::test]
+async fn listing_needs_no_token() {
+    let mut state = support::state();
+    support::enable_jwt(&mut state);
+    let state = web::Data::new(state);
+    assert!(!algorithms(&state).await.is_empty());
+}
+
+// ── Unknown algorithm ids ──
+// A header naming a cipher this build doesn't have is refused by name, never
+// opened as AES-GCM.
+
+#[actix_web::test]
+async fn unknown_algorithm_id_is_refused() {
+    let state = web::Data::new(support::state());
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let mut sealed = BASE64.decode(blob).unwrap();
+    // Magic, format version, then the algorithm id
+    assert!(Algorithm::from_id(0x7F).is_none());
+    sealed[2] = 0x7F;
+
+    let body = json!({ "channel_id": 1, "encrypted": BASE64.encode(&sealed) });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "unsupported_algorithm");
+    assert!(reply.json()["error"].as_str().unwrap().contains("127"));
+}
+
+// ── AUTO_SELECT_ALGORITHM ──
+// The benchmark is stubbed, so each host's outcome can be tested anywhere.
+
+fn timed(fastest: Algorithm) -> Vec<(Algorithm, Duration)> {
+    let slowest = Algorithm::ALL.into_iter().find(|&a| a != fastest).unwrap();
+    vec![(fastest, Duration::from_micros(100)), (slowest, Duration::from_micros(900))]
+}
+
+#[test]
+fn auto_selection_takes_the_fastest() {
+    for fastest in Algorithm::ALL {
+        let chosen = choose_default_algorithm(None, true, || timed(fastest));
+        assert!(chosen == Ok(fastest));
+    }
+}
+
+#[test]
+fn a_named_algorithm_wins_without_benchmarking() {
+    let named = Some("chacha20poly1305".to_string());
+    let chosen = choose_default_algorithm(named, true, || unreachable!("benchmark ran"));
+    assert!(chosen == Ok(Algorithm::Chacha20poly1305));
+
+    let unknown = choose_default_algorithm(Some("rot13".into()), true, || unreachable!());
+    assert!(unknown.is_err_and(|e| e.starts_with("Unknown DEFAULT_ALGORITHM")));
+}
+
+#[test]
+fn without_auto_selection_aes_gcm_stays() {
+    let chosen = choose_default_algorithm(None, false, || unreachable!("benchmark ran"));
+    assert!(chosen == Ok(Algorithm::Aes256gcm));
+}
@@ -0,0 +1,81 @@
+use actix_web::{test, web};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde_json::json;
+
+use super::support;
+use crate::signing::{archive_message, ArchiveSigner};
+use crate::AppState;
+
+// ── Signed archive envelopes ──
+// An /encrypt with `envelope` set returns the blob signed under the key at
+// /archive/public-key, so the result can be checked offline years later.
+
+fn signing_state() -> web::Data<AppState> {
+    let mut state = support::state();
+    state.archive_signer = ArchiveSigner::from_seed(&[7; 32]);
+    web::Data::new(state)
+}
+
+#[actix_web::test]
+async fn envelope_verifies_under_the_published_key() {
+    let state = signing_state();
+    let body = json!({ "channel_id": 1, "message": "keep", "envelope": true });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    let envelope = &reply.json()["envelope"];
+    assert_eq!(envelope["ciphertext"], reply.json()["encrypted"]);
+    assert_eq!(envelope["produced_at"], support::START);
+
+    let reply = support::call(&state, test::TestRequest::get().uri("/archive/public-key")).await;
+    assert_eq!(reply.json()["algorithm"], "ed25519");
+    let public_key = BASE64.decode(reply.json()["public_key"].as_str().unwrap()).unwrap();
+
+    let message = archive_message(
+        envelope["ciphertext"].as_str().unwrap(),
+        envelope["generation"].as_str().unwrap(),
+        envelope["algorithm"].as_str().unwrap(),
+        envelope["produced_at"].as_u64().unwrap(),
+    );
+    let signature = BASE64.decode(envelope["signature"].as_str().unwrap()).unwrap();
+    let key = UnparsedPublicKey::new(&ED25519, public_key);
+    assert!(key.verify(&message, &signature).is_ok());
+}
+
+#[actix_web::test]
+async fn generation_is_the_channel_key_fingerprint() {
+    let state = signing_state();
+    let body = json!({ "channel_id": 1, "message": "keep", "envelope": true });
+    let reply = support::post(&state, "/encrypt", body).await;
+    let generation = reply.json()["envelope"]["generation"].clone();
+
+    let req = test::TestRequest::get().uri("/channels/1/fingerprint");
+    let reply = support::call(&state, req).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["fingerprint"], generation);
+}
+
+#[actix_web::test]
+async fn envelope_needs_a_signing_key() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "keep", "envelope": true });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "archive_signing_disabled");
+
+    let reply = support::call(&state, test::TestRequest::get().uri("/archive/public-key")).await;
+    assert_eq!(reply.status, 404);
+    assert_eq!(reply.code(), "archive_signing_disabled");
+}
+
+#[actix_web::test]
+async fn envelope_is_refused_for_raw_output() {
+    let state = signing_state();
+    let req = test::TestRequest::post()
+        .uri("/encrypt")
+        .insert_header(("Accept", "application/octet-stream"))
+        .set_json(json!({ "channel_id": 1, "message": "keep", "envelope": true }));
+    let reply = support::call(&state, req).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "envelope_requires_json");
+}
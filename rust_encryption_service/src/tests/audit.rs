@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex};
+
+use actix_web::{test, web};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::json;
+
+use super::support;
+use crate::audit::{AuditAction, AuditEntry, CollectingAuditSink, FileAuditSink};
+use crate::AppState;
+
+// ── Audit log ──
+// Every use of a channel key reaches the sink, stamped by the state's clock;
+// failed attempts and raw caller keys do not.
+
+fn audited() -> (web::Data<AppState>, Arc<Mutex<Vec<AuditEntry>>>) {
+    let sink = CollectingAuditSink::default();
+    let entries = sink.entries.clone();
+    let mut state = support::state();
+    state.audit = Some(Box::new(sink));
+    (web::Data::new(state), entries)
+}
+
+fn actions(entries: &Mutex<Vec<AuditEntry>>) -> Vec<(AuditAction, i64)> {
+    entries.lock().unwrap().iter().map(|e| (e.action, e.channel_id)).collect()
+}
+
+#[actix_web::test]
+async fn encrypt_and_decrypt_are_recorded() {
+    let (state, entries) = audited();
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let reply = support::post(&state, "/decrypt", json!({ "channel_id": 1, "encrypted": blob }))
+        .await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+
+    assert!(actions(&entries) == [(AuditAction::Encrypt, 1), (AuditAction::Decrypt, 1)]);
+    assert!(entries.lock().unwrap().iter().all(|e| e.at == support::START));
+}
+
+#[actix_web::test]
+async fn a_failed_decrypt_is_not_recorded() {
+    let (state, entries) = audited();
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let reply = support::post(&state, "/decrypt", json!({ "channel_id": 2, "encrypted": blob }))
+        .await;
+    assert_eq!(reply.status, 400);
+
+    assert!(actions(&entries) == [(AuditAction::Encrypt, 1)]);
+}
+
+#[actix_web::test]
+async fn a_key_export_is_recorded() {
+    let (state, entries) = audited();
+    // The X25519 base point; any valid public key will do
+    let mut recipient = [0u8; 32];
+    recipient[0] = 9;
+    let recipient = BASE64.encode(recipient).replace('=', "%3D");
+    let uri = format!("/keys/3/export?recipient_key={}", recipient);
+    let reply = support::call(&state, support::admin(test::TestRequest::get().uri(&uri))).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+
+    assert!(actions(&entries) == [(AuditAction::Export, 3)]);
+}
+
+// ── GET /audit/export ──
+
+fn temp_log(name: &str) -> String {
+    let file = format!("freecord-{}-export-{}.log", std::process::id(), name);
+    let path = std::env::temp_dir().join(file);
+    let _ = std::fs::remove_file(&path);
+    path.to_str().unwrap().to_owned()
+}
+
+#[actix_web::test]
+async fn export_streams_the_matching_lines_as_written() {
+    let path = temp_log("filter");
+    let mut state = support::state();
+    let sink = FileAuditSink::open(&path, Some(b"chain-key".to_vec())).unwrap();
+    state.audit = Some(Box::new(sink));
+    let state = web::Data::new(state);
+    for channel_id in [1, 2, 1] {
+        support::encrypt(&state, json!({ "channel_id": channel_id, "message": "hi" })).await;
+    }
+
+    let req = test::TestRequest::get().uri("/audit/export?channel_id=1");
+    let reply = support::call(&state, support::admin(req)).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.header("Content-Type"), Some("application/x-ndjson"));
+    let file = std::fs::read_to_string(&path).unwrap();
+    let expected: String = file
+        .lines()
+        .filter(|line| line.contains(r#""channel_id":1"#))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    assert_eq!(reply.body, expected);
+    assert_eq!(expected.lines().count(), 2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[actix_web::test]
+async fn export_is_admin_only_and_needs_a_file_sink() {
+    let (state, _) = audited();
+    let req = test::TestRequest::get().uri("/audit/export");
+    let reply = support::call(&state, req).await;
+    assert_eq!(reply.status, 401);
+
+    let req = test::TestRequest::get().uri("/audit/export");
+    let reply = support::call(&state, support::admin(req)).await;
+    assert_eq!(reply.status, 404);
+    assert_eq!(reply.code(), "audit_export_unavailable");
+}
@@ -0,0 +1,137 @@
+use actix_web::{test, web};
+use serde_json::{json, Value};
+
+use super::support::{self, JwtIssuer};
+use crate::AppState;
+
+// ── JWT scopes and tenant isolation ──
+
+const TENANT: i64 = 7;
+
+fn tenant_state() -> AppState {
+    let mut state = support::state();
+    state.tenant_secrets.insert(TENANT, "tenant-seven-secret".into());
+    state
+}
+
+fn jwt_state(mut state: AppState) -> (web::Data<AppState>, JwtIssuer) {
+    let issuer = support::enable_jwt(&mut state);
+    (web::Data::new(state), issuer)
+}
+
+async fn encrypt_as(
+    state: &web::Data<AppState>,
+    token: Option<&str>,
+    body: Value,
+) -> support::Reply {
+    let req = test::TestRequest::post().uri("/encrypt").set_json(body);
+    let req = match token {
+        Some(token) => support::bearer(req, token),
+        None => req,
+    };
+    support::call(state, req).await
+}
+
+#[actix_web::test]
+async fn tenant_channels_are_refused_without_jwt_auth() {
+    let state = web::Data::new(tenant_state());
+    let tenant = json!({ "channel_id": 1, "org_id": TENANT, "message": "hi" });
+    let reply = encrypt_as(&state, None, tenant).await;
+    assert_eq!(reply.status, 403);
+    assert_eq!(reply.code(), "org_claim_required");
+
+    // Orgs without a secret of their own are unaffected
+    let other = json!({ "channel_id": 1, "org_id": 8, "message": "hi" });
+    assert_eq!(encrypt_as(&state, None, other).await.status, 200);
+}
+
+#[actix_web::test]
+async fn tenant_channels_need_a_matching_org_claim() {
+    let (state, issuer) = jwt_state(tenant_state());
+    let body = json!({ "channel_id": 1, "org_id": TENANT, "message": "hi" });
+
+    let no_org = issuer.token(json!({ "scope": "crypto:encrypt" }));
+    let reply = encrypt_as(&state, Some(&no_org), body.clone()).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (403, "org_claim_required".into()));
+
+    let wrong_org = issuer.token(json!({ "scope": "crypto:encrypt", "org_id": 8 }));
+    let reply = encrypt_as(&state, Some(&wrong_org), body.clone()).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (403, "org_not_allowed".into()));
+
+    let right_org = issuer.token(json!({ "scope": "crypto:encrypt", "org_id": TENANT }));
+    assert_eq!(encrypt_as(&state, Some(&right_org), body).await.status, 200);
+}
+
+#[actix_web::test]
+async fn tenant_decrypts_need_the_org_claim_too() {
+    let (state, issuer) = jwt_state(tenant_state());
+    let token = issuer.token(json!({ "scope": "crypto:encrypt crypto:decrypt", "org_id": TENANT }));
+    let sealed = encrypt_as(
+        &state,
+        Some(&token),
+        json!({ "channel_id": 1, "org_id": TENANT, "message": "hi" }),
+    )
+    .await
+    .json()["encrypted"]
+        .clone();
+    let body = json!({ "channel_id": 1, "org_id": TENANT, "encrypted": sealed });
+
+    let no_org = issuer.token(json!({ "scope": "crypto:decrypt" }));
+    let req = test::TestRequest::post().uri("/decrypt").set_json(body.clone());
+    assert_eq!(support::call(&state, support::bearer(req, &no_org)).await.status, 403);
+
+    let req = test::TestRequest::post().uri("/decrypt").set_json(body);
+    let reply = support::call(&state, support::bearer(req, &token)).await;
+    assert_eq!(reply.json()["message"], "hi");
+}
+
+#[actix_web::test]
+async fn jwt_is_required_once_enabled() {
+    let (state, _) = jwt_state(support::state());
+    let reply = encrypt_as(&state, None, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (401, "jwt_required".into()));
+
+    let reply =
+        encrypt_as(&state, Some("not.a.jwt"), json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (401, "jwt_invalid".into()));
+}
+
+#[actix_web::test]
+async fn expired_jwt_is_refused() {
+    let (state, issuer) = jwt_state(support::state());
+    let expired = support::unix_now() - 120;
+    let token = issuer.token(json!({ "scope": "crypto:encrypt", "exp": expired }));
+    let reply = encrypt_as(&state, Some(&token), json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (401, "jwt_expired".into()));
+}
+
+#[actix_web::test]
+async fn each_endpoint_needs_its_scope() {
+    let (state, issuer) = jwt_state(support::state());
+    let decrypt_only = issuer.token(json!({ "scope": "crypto:decrypt" }));
+    let reply =
+        encrypt_as(&state, Some(&decrypt_only), json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (403, "insufficient_scope".into()));
+
+    let encrypt_only = issuer.token(json!({ "scope": "crypto:encrypt" }));
+    let reply =
+        encrypt_as(&state, Some(&encrypt_only), json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(reply.status, 200);
+    let req = test::TestRequest::post()
+        .uri("/decrypt")
+        .set_json(json!({ "channel_id": 1, "encrypted": reply.json()["encrypted"] }));
+    let reply = support::call(&state, support::bearer(req, &encrypt_only)).await;
+    assert_eq!(reply.code(), "insufficient_scope");
+}
+
+#[actix_web::test]
+async fn channel_ids_claim_limits_the_token() {
+    let (state, issuer) = jwt_state(support::state());
+    let token = issuer.token(json!({ "scope": "crypto:encrypt", "channel_ids": [1, "2"] }));
+    for channel_id in [1, 2] {
+        let body = json!({ "channel_id": channel_id, "message": "hi" });
+        assert_eq!(encrypt_as(&state, Some(&token), body).await.status, 200);
+    }
+    let reply = encrypt_as(&state, Some(&token), json!({ "channel_id": 3, "message": "hi" })).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (403, "channel_not_allowed".into()));
+}
@@ -0,0 +1,66 @@
+use serde_json::Value;
+
+use super::support;
+use crate::banner::{EffectiveConfig, Startup};
+use crate::AppState;
+
+// ── Startup config banner ──
+// Every secret the state holds shows only as "[redacted]", or null unset.
+
+fn startup() -> Startup<'static> {
+    Startup {
+        bind: "0.0.0.0:8080",
+        admin_bind: None,
+        tls: false,
+        mtls: false,
+        secret_source: "env",
+        signing: true,
+        sentry: false,
+    }
+}
+
+fn banner(state: &AppState) -> (String, Value) {
+    let json = serde_json::to_string(&EffectiveConfig::new(state, startup())).unwrap();
+    let value = serde_json::from_str(&json).unwrap();
+    (json, value)
+}
+
+#[test]
+fn secrets_are_redacted() {
+    let mut state = support::state();
+    state.previous_master_secret = Some("the-previous-secret".into());
+    state.tenant_secrets.insert(7, "tenant-seven-secret".into());
+    let (json, config) = banner(&state);
+
+    for secret in [support::SECRET, "the-previous-secret", "tenant-seven-secret"] {
+        assert!(!json.contains(secret), "{} leaked into {}", secret, json);
+    }
+    assert!(!json.contains(state.admin_token.as_deref().unwrap()));
+    assert_eq!(config["master_secret"], "[redacted]");
+    assert_eq!(config["previous_master_secret"], "[redacted]");
+    assert_eq!(config["admin_token"], "[redacted]");
+    assert_eq!(config["response_signing_key"], "[redacted]");
+    assert_eq!(config["tenant_secrets"], 1);
+}
+
+#[test]
+fn unset_secrets_are_null() {
+    let mut state = support::state();
+    state.admin_token = None;
+    let (_, config) = banner(&state);
+    assert_eq!(config["previous_master_secret"], Value::Null);
+    assert_eq!(config["admin_token"], Value::Null);
+    assert_eq!(config["sentry_dsn"], Value::Null);
+    assert_eq!(config["audit_sink"], Value::Null);
+}
+
+#[test]
+fn settings_are_reported_as_configured() {
+    let (_, config) = banner(&support::state());
+    assert_eq!(config["bind"], "0.0.0.0:8080");
+    assert_eq!(config["secret_source"], "env");
+    assert_eq!(config["default_algorithm"], "aes256gcm");
+    assert_eq!(config["max_concurrent_crypto"], 16);
+    assert_eq!(config["metrics_enabled"], true);
+    assert_eq!(config["max_decrypted_bytes"], 1024 * 1024);
+}
@@ -0,0 +1,315 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::json;
+
+use super::support::{self, START};
+use crate::clock::MockClock;
+use crate::crypto::{Algorithm, KeyId};
+use crate::{get_cipher, AppState, KeyCacheOverflow, SecretVersion};
+
+// ── Cipher cache ──
+// A channel's cipher is built on first use, or ahead of it by
+// /keys/preload, and reused by every request after.
+
+fn fresh() -> web::Data<AppState> {
+    web::Data::new(support::state())
+}
+
+async fn preload(state: &web::Data<AppState>, channel_ids: serde_json::Value) -> u64 {
+    let body = json!({ "channel_ids": channel_ids });
+    let reply = support::admin_post(state, "/keys/preload", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()["preloaded"].as_u64().unwrap()
+}
+
+#[actix_web::test]
+async fn repeat_requests_reuse_the_cipher() {
+    let state = fresh();
+    for _ in 0..3 {
+        support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    }
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), 1);
+    assert_eq!(state.metrics.cipher_cache_hits.since_reset(), 2);
+}
+
+#[actix_web::test]
+async fn preload_counts_only_the_ciphers_it_built() {
+    let state = fresh();
+    assert_eq!(preload(&state, json!([1, 2, "3"])).await, 3);
+    assert_eq!(preload(&state, json!([3, 4])).await, 1);
+}
+
+#[actix_web::test]
+async fn a_preloaded_channel_starts_warm() {
+    let state = fresh();
+    preload(&state, json!([1])).await;
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), 0);
+    assert_eq!(state.metrics.cipher_cache_hits.since_reset(), 1);
+}
+
+#[actix_web::test]
+async fn preload_is_admin_only_and_capped() {
+    let state = fresh();
+    let reply = support::post(&state, "/keys/preload", json!({ "channel_ids": [1] })).await;
+    assert_eq!(reply.status, 401);
+
+    let channel_ids: Vec<i64> = (0..1001).collect();
+    let body = json!({ "channel_ids": channel_ids });
+    let reply = support::admin_post(&state, "/keys/preload", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "too_many_channels");
+    assert!(state.ciphers.lock().is_empty());
+}
+
+// ── Cold-start coalescing ──
+// Requests racing on a cold channel wait on one derivation between them.
+
+#[test]
+fn racing_cold_requests_derive_once() {
+    let state = support::state();
+    let start = std::sync::Barrier::new(8);
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                start.wait();
+                let (version, key_id) = (SecretVersion::Current, KeyId::channel(1));
+                assert!(get_cipher(&state, version, Algorithm::Aes256gcm, key_id).is_ok());
+            });
+        }
+    });
+    assert_eq!(state.metrics.key_derivation_seconds.get_sample_count(), 1);
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), 1);
+    assert_eq!(state.metrics.cipher_cache_hits.since_reset(), 7);
+}
+
+// ── What the cache is keyed on ──
+// Secret version, algorithm and the whole KeyId: changing any one of them
+// is a different key, so it must be a different entry.
+
+#[test]
+fn every_derivation_input_gets_its_own_entry() {
+    let mut state = support::state();
+    state.previous_master_secret = Some("old-master-secret".into());
+    let (current, aes) = (SecretVersion::Current, Algorithm::Aes256gcm);
+    let variants = [
+        (current, aes, KeyId::channel(1)),
+        (SecretVersion::Previous, aes, KeyId::channel(1)),
+        (current, Algorithm::Chacha20poly1305, KeyId::channel(1)),
+        (current, aes, KeyId::new(Some(5), 1)),
+        (current, aes, KeyId::shared(None, 1, 2)),
+    ];
+
+    let payload = || aes_gcm::aead::Payload { msg: &b"hi"[..], aad: &b""[..] };
+    let mut sealed = Vec::new();
+    for _ in 0..2 {
+        for (version, algorithm, key_id) in variants {
+            let cipher = get_cipher(&state, version, algorithm, key_id).ok().unwrap();
+            sealed.push(cipher.encrypt(&[0u8; 12], payload()).unwrap());
+        }
+    }
+    assert_eq!(state.ciphers.lock().len(), variants.len());
+    // Second pass served the same ciphers; within a pass, no two keys match
+    assert_eq!(sealed[..variants.len()], sealed[variants.len()..]);
+    let first_pass: std::collections::HashSet<_> = sealed[..variants.len()].iter().collect();
+    assert_eq!(first_pass.len(), variants.len());
+}
+
+// ── POST /keys/exists ──
+// A pure read: asking never builds or even reserves a cipher.
+
+async fn exists(state: &web::Data<AppState>, channel_ids: serde_json::Value) -> serde_json::Value {
+    let body = json!({ "channel_ids": channel_ids });
+    let reply = support::admin_post(state, "/keys/exists", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()
+}
+
+#[actix_web::test]
+async fn exists_reports_only_built_ciphers() {
+    let state = fresh();
+    support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+    let reply = exists(&state, json!([1, 2])).await;
+    assert_eq!(
+        reply,
+        json!([{ "channel_id": 1, "exists": false }, { "channel_id": 2, "exists": true }])
+    );
+}
+
+#[actix_web::test]
+async fn asking_leaves_the_cache_alone() {
+    let state = fresh();
+    exists(&state, json!([1, 2, 3])).await;
+    assert!(state.ciphers.lock().is_empty());
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), 0);
+    assert_eq!(exists(&state, json!([1])).await[0]["exists"], false);
+}
+
+#[actix_web::test]
+async fn asking_takes_the_admin_token() {
+    let state = fresh();
+    let reply = support::post(&state, "/keys/exists", json!({ "channel_ids": [1] })).await;
+    assert_eq!(reply.status, 401);
+}
+
+// ── POST /admin/flush-keys ──
+// Evicts every cached cipher; ratchet chains stay, as their keys cannot be
+// derived again.
+
+#[actix_web::test]
+async fn flush_evicts_every_cipher() {
+    let state = fresh();
+    for channel_id in [1, 2] {
+        support::encrypt(&state, json!({ "channel_id": channel_id, "message": "hi" })).await;
+    }
+    let reply = support::admin_post(&state, "/admin/flush-keys", json!({})).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["cleared"], 2);
+    assert!(state.ciphers.lock().is_empty());
+
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), 3);
+}
+
+#[actix_web::test]
+async fn flush_keeps_ratchet_chains() {
+    let state = fresh();
+    let body = json!({ "channel_id": 1, "message": "hi", "ratchet": true });
+    let sealed = support::encrypt(&state, body).await;
+    support::admin_post(&state, "/admin/flush-keys", json!({})).await;
+
+    let body = json!({ "channel_id": 1, "encrypted": sealed });
+    assert_eq!(support::post(&state, "/decrypt", body).await.json()["message"], "hi");
+}
+
+#[actix_web::test]
+async fn flush_is_admin_only() {
+    let state = fresh();
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(support::post(&state, "/admin/flush-keys", json!({})).await.status, 401);
+    assert_eq!(state.ciphers.lock().len(), 1);
+}
+
+// ── LOCK_TIMEOUT_MS ──
+// A request gives up on a stuck cache lock with a 503 instead of hanging.
+
+#[actix_web::test]
+async fn a_held_cache_lock_times_out() {
+    let mut state = support::state();
+    state.lock_timeout = std::time::Duration::from_millis(20);
+    let state = web::Data::new(state);
+    let body = json!({ "channel_id": 1, "message": "hi" });
+
+    // Another thread sits on the lock until told to let go
+    let locked = std::sync::Arc::new(std::sync::Barrier::new(2));
+    let (release, released) = std::sync::mpsc::channel::<()>();
+    let holder = {
+        let (state, locked) = (state.clone(), locked.clone());
+        std::thread::spawn(move || {
+            let _held = state.ciphers.lock();
+            locked.wait();
+            let _ = released.recv();
+        })
+    };
+    locked.wait();
+    let reply = support::post(&state, "/encrypt", body.clone()).await;
+    assert_eq!(reply.status, 503);
+    assert_eq!(reply.code(), "lock_timeout");
+    release.send(()).unwrap();
+    holder.join().unwrap();
+
+    assert_eq!(support::post(&state, "/encrypt", body).await.status, 200);
+}
+
+// ── Key cache footprint ──
+// /health and /metrics estimate the cache as entries times a fixed size.
+
+async fn health(state: &web::Data<AppState>) -> (u64, u64) {
+    let reply = support::call(state, TestRequest::get().uri("/health")).await;
+    let body = reply.json();
+    (body["cached_keys"].as_u64().unwrap(), body["key_cache_bytes"].as_u64().unwrap())
+}
+
+#[actix_web::test]
+async fn the_footprint_grows_linearly_with_cached_keys() {
+    let state = fresh();
+    assert_eq!(health(&state).await, (0, 0));
+
+    preload(&state, json!([1])).await;
+    let (entries, per_key) = health(&state).await;
+    assert_eq!(entries, 1);
+    assert!(per_key > 32);
+
+    preload(&state, json!([2, 3, 4])).await;
+    assert_eq!(health(&state).await, (4, 4 * per_key));
+
+    let reply = support::call(&state, TestRequest::get().uri("/metrics")).await;
+    let text = String::from_utf8(reply.body.to_vec()).unwrap();
+    assert!(text.contains("freecord_cached_keys 4\n"), "{}", text);
+    assert!(text.contains(&format!("freecord_key_cache_bytes {}\n", 4 * per_key)), "{}", text);
+}
+
+// ── KEY_CACHE_OVERFLOW ──
+// What a cold channel gets once MAX_CACHED_KEYS ciphers are cached.
+
+// Two channels cached, channel 1 used last; the clock ticks between uses
+// so "least recently used" is unambiguous
+async fn full(
+    overflow: KeyCacheOverflow,
+    ttl: Option<Duration>,
+) -> (web::Data<AppState>, Arc<MockClock>) {
+    let clock = Arc::new(MockClock::new(START));
+    let mut state = support::state_with_clock(clock.clone());
+    state.max_cached_keys = Some(2);
+    state.key_cache_overflow = overflow;
+    state.key_cache_ttl = ttl;
+    let state = web::Data::new(state);
+    for channel_id in [1, 2, 1] {
+        support::encrypt(&state, json!({ "channel_id": channel_id, "message": "hi" })).await;
+        clock.advance(Duration::from_secs(1));
+    }
+    (state, clock)
+}
+
+async fn cached(state: &web::Data<AppState>) -> Vec<bool> {
+    let reply = exists(state, json!([1, 2, 3])).await;
+    reply.as_array().unwrap().iter().map(|c| c["exists"].as_bool().unwrap()).collect()
+}
+
+#[actix_web::test]
+async fn lru_evicts_the_least_recently_used() {
+    let (state, _clock) = full(KeyCacheOverflow::Lru, None).await;
+    support::encrypt(&state, json!({ "channel_id": 3, "message": "hi" })).await;
+    assert_eq!(cached(&state).await, [true, false, true]);
+}
+
+#[actix_web::test]
+async fn reject_refuses_a_cold_channel_at_capacity() {
+    let (state, _clock) = full(KeyCacheOverflow::Reject, None).await;
+    let body = json!({ "channel_id": 3, "message": "hi" });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert_eq!(reply.status, 503);
+    assert_eq!(reply.code(), "cache_full");
+    assert_eq!(cached(&state).await, [true, true, false]);
+
+    // Cached channels are still served
+    support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+}
+
+#[actix_web::test]
+async fn reject_still_makes_room_from_expired_ciphers() {
+    let (state, clock) = full(KeyCacheOverflow::Reject, Some(Duration::from_secs(60))).await;
+    clock.advance(Duration::from_secs(60));
+    support::encrypt(&state, json!({ "channel_id": 3, "message": "hi" })).await;
+    assert_eq!(cached(&state).await, [false, false, true]);
+}
+
+#[actix_web::test]
+async fn unbounded_ignores_the_limit() {
+    let (state, _clock) = full(KeyCacheOverflow::Unbounded, None).await;
+    support::encrypt(&state, json!({ "channel_id": 3, "message": "hi" })).await;
+    assert_eq!(cached(&state).await, [true, true, true]);
+}
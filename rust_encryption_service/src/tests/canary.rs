@@ -0,0 +1,39 @@
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+use crate::purge_ciphers;
+
+// ── Master canary ──
+// A read-only check: it must open samples without filling the cipher cache
+// or moving its hit and miss counters.
+
+#[actix_web::test]
+async fn canary_bypasses_the_cipher_cache() {
+    let mut state = support::state();
+    state.previous_master_secret = Some("old-master-secret".into());
+    let state = web::Data::new(state);
+    let plain = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let ratchet = json!({ "channel_id": 2, "message": "hi", "ratchet": true });
+    let ratchet = support::encrypt(&state, ratchet).await;
+    assert!(purge_ciphers(&state).is_ok());
+    let hits = state.metrics.cipher_cache_hits.since_reset();
+    let misses = state.metrics.cipher_cache_misses.since_reset();
+
+    let samples = json!({ "samples": [
+        { "channel_id": 1, "encrypted": plain },
+        { "channel_id": 2, "encrypted": ratchet },
+        { "channel_id": 3, "encrypted": plain },
+    ] });
+    let reply = support::admin_post(&state, "/admin/master-canary", samples).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    let results = &reply.json()["results"];
+    assert_eq!(results[0]["current"], true);
+    assert_eq!(results[0]["previous"], false);
+    assert_eq!(results[1]["current"], true);
+    assert_eq!(results[2]["current"], false);
+
+    assert!(state.ciphers.lock().is_empty());
+    assert_eq!(state.metrics.cipher_cache_hits.since_reset(), hits);
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), misses);
+}
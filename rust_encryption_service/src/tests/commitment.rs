@@ -0,0 +1,64 @@
+use actix_web::web;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── Key commitment ──
+// A committed blob names the key it was sealed under, so opening it under
+// another one fails before the AEAD is tried, and DEBUG_ERRORS can say why.
+
+fn state(debug_errors: bool) -> web::Data<AppState> {
+    let mut state = support::state();
+    state.debug_errors = debug_errors;
+    web::Data::new(state)
+}
+
+async fn sealed(state: &web::Data<AppState>, commit: bool) -> String {
+    support::encrypt(state, json!({ "channel_id": 1, "message": "hi", "commit": commit })).await
+}
+
+async fn decrypt_code(state: &web::Data<AppState>, channel_id: i64, encrypted: &str) -> String {
+    let body = json!({ "channel_id": channel_id, "encrypted": encrypted });
+    support::post(state, "/decrypt", body).await.code()
+}
+
+#[actix_web::test]
+async fn committed_blob_opens_under_its_own_key() {
+    let state = state(false);
+    let blob = sealed(&state, true).await;
+    let body = json!({ "channel_id": 1, "encrypted": blob, "verbose": true });
+    let reply = support::admin_post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "hi");
+    assert_eq!(reply.json()["commitment_verified"], true);
+}
+
+#[actix_web::test]
+async fn wrong_channel_is_named_only_with_debug_errors() {
+    let quiet = state(false);
+    let blob = sealed(&quiet, true).await;
+    assert_eq!(decrypt_code(&quiet, 2, &blob).await, "decryption_failed");
+
+    let debug = state(true);
+    let blob = sealed(&debug, true).await;
+    assert_eq!(decrypt_code(&debug, 2, &blob).await, "possible_wrong_channel");
+}
+
+#[actix_web::test]
+async fn without_a_commitment_a_wrong_channel_is_a_plain_failure() {
+    let state = state(true);
+    let blob = sealed(&state, false).await;
+    assert_eq!(decrypt_code(&state, 2, &blob).await, "decryption_failed");
+}
+
+#[actix_web::test]
+async fn a_changed_commitment_fails() {
+    let state = state(false);
+    let mut blob = BASE64.decode(sealed(&state, true).await).unwrap();
+    // The commitment follows the 4-byte fixed header
+    blob[4] ^= 1;
+    assert_eq!(decrypt_code(&state, 1, &BASE64.encode(blob)).await, "decryption_failed");
+}
@@ -0,0 +1,64 @@
+use actix_web::web;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::json;
+
+use super::support;
+
+// ── Context subkeys ──
+
+#[actix_web::test]
+async fn an_edited_context_fails_to_open() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "hi", "context": "thread-7" });
+    let mut blob = BASE64.decode(support::encrypt(&state, body).await).unwrap();
+    // Fixed header, u16 length, then the context itself
+    assert_eq!(&blob[6..14], b"thread-7");
+    blob[13] = b'8';
+
+    let body = json!({ "channel_id": 1, "encrypted": BASE64.encode(&blob) });
+    assert_eq!(support::post(&state, "/decrypt", body).await.code(), "decryption_failed");
+}
+
+#[actix_web::test]
+async fn a_context_blob_opens_only_on_its_channel() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "hi", "context": "thread-7" });
+    let sealed = support::encrypt(&state, body).await;
+    let body = json!({ "channel_id": 2, "encrypted": sealed });
+    assert_eq!(support::post(&state, "/decrypt", body).await.code(), "decryption_failed");
+}
+
+// ── Attachment hashes ──
+// A message bound to an attachment opens only with that attachment's hash.
+
+async fn decrypt_code(state: &web::Data<crate::AppState>, body: serde_json::Value) -> String {
+    let reply = support::post(state, "/decrypt", body).await;
+    if reply.status == 200 {
+        return "ok".into();
+    }
+    reply.code()
+}
+
+#[actix_web::test]
+async fn a_bound_message_needs_its_attachment_hash() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "see file", "attachment_hash": "abc" });
+    let sealed = support::encrypt(&state, body).await;
+
+    let with = |hash: &str| {
+        json!({ "channel_id": 1, "encrypted": sealed, "attachment_hash": hash })
+    };
+    assert_eq!(decrypt_code(&state, with("abc")).await, "ok");
+    assert_eq!(decrypt_code(&state, with("abd")).await, "decryption_failed");
+    let without = json!({ "channel_id": 1, "encrypted": sealed });
+    assert_eq!(decrypt_code(&state, without).await, "attachment_hash_required");
+}
+
+#[actix_web::test]
+async fn a_hash_for_an_unbound_message_is_refused() {
+    let state = web::Data::new(support::state());
+    let sealed = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": sealed, "attachment_hash": "abc" });
+    assert_eq!(decrypt_code(&state, body).await, "attachment_hash_not_bound");
+}
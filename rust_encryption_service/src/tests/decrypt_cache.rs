@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+use crate::response_cache::ResponseCache;
+use crate::AppState;
+
+// ── Decrypt cache ──
+// A repeated decrypt is served from the cache; verbose decrypts skip it, and
+// /admin/flush-keys empties it along with the cipher cache.
+
+fn cached_state() -> web::Data<AppState> {
+    let mut state = support::state();
+    let cache = ResponseCache::new(16, Duration::from_secs(60), state.clock.clone());
+    state.decrypt_cache = Some(cache);
+    web::Data::new(state)
+}
+
+#[actix_web::test]
+async fn repeated_decrypt_is_a_hit() {
+    let state = cached_state();
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "pinned" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": blob });
+
+    let first = support::post(&state, "/decrypt", body.clone()).await;
+    let second = support::post(&state, "/decrypt", body).await;
+    assert_eq!(first.status, 200, "{:?}", first.body);
+    assert_eq!(second.json()["message"], "pinned");
+    assert_eq!(state.metrics.decrypt_cache_misses.since_reset(), 1);
+    assert_eq!(state.metrics.decrypt_cache_hits.since_reset(), 1);
+}
+
+#[actix_web::test]
+async fn a_hit_still_counts_as_a_use() {
+    let state = cached_state();
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "pinned" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": blob });
+
+    for _ in 0..2 {
+        assert_eq!(support::post(&state, "/decrypt", body.clone()).await.status, 200);
+    }
+    assert_eq!(state.metrics.decrypt_cache_hits.since_reset(), 1);
+    let usage = support::call(&state, TestRequest::get().uri("/channels/1/usage")).await;
+    // The encrypt, then both decrypts
+    assert_eq!(usage.json()["use_count"], 3);
+}
+
+#[actix_web::test]
+async fn another_channel_is_not_served_from_the_cache() {
+    let state = cached_state();
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "pinned" })).await;
+    let reply = support::post(&state, "/decrypt", json!({ "channel_id": 1, "encrypted": blob }))
+        .await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+
+    let reply = support::post(&state, "/decrypt", json!({ "channel_id": 2, "encrypted": blob }))
+        .await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(state.metrics.decrypt_cache_hits.since_reset(), 0);
+}
+
+#[actix_web::test]
+async fn verbose_decrypts_skip_the_cache() {
+    let state = cached_state();
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "pinned" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": blob, "verbose": true });
+
+    for _ in 0..2 {
+        let reply = support::admin_post(&state, "/decrypt", body.clone()).await;
+        assert_eq!(reply.status, 200, "{:?}", reply.body);
+    }
+    assert_eq!(state.metrics.decrypt_cache_hits.since_reset(), 0);
+    assert_eq!(state.metrics.decrypt_cache_misses.since_reset(), 0);
+}
+
+#[actix_web::test]
+async fn flush_keys_empties_the_cache() {
+    let state = cached_state();
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "pinned" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": blob });
+    support::post(&state, "/decrypt", body.clone()).await;
+
+    let reply = support::admin_post(&state, "/admin/flush-keys", json!({})).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    support::post(&state, "/decrypt", body).await;
+    assert_eq!(state.metrics.decrypt_cache_hits.since_reset(), 0);
+    assert_eq!(state.metrics.decrypt_cache_misses.since_reset(), 2);
+}
@@ -0,0 +1,161 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+
+use super::support::{self, SECRET};
+use crate::crypto::{
+    derive_channel_key, derive_context_key, derive_key, derive_key_len, derive_signing_key,
+    key_commitment, key_fingerprint, KeyId, KeyPurpose,
+};
+
+// ── Key derivation ──
+// Here rather than in crypto.rs, which the cipher_reuse bench also compiles.
+// Expected keys were computed outside the service from the documented
+// derivations, under support::SECRET.
+
+#[test]
+fn bare_channel_key_is_sha256_of_secret_and_id() {
+    assert_eq!(
+        hex::encode(derive_key(SECRET, KeyId::channel(1))),
+        "45e5e2809bfaf39ceddb66b689a0c50004c663777aa9fa3a0215bc10fe149c96",
+    );
+    assert_eq!(derive_key(SECRET, KeyId::channel(1)), derive_channel_key(SECRET, 1));
+}
+
+#[test]
+fn org_channel_key_is_domain_separated() {
+    assert_eq!(
+        hex::encode(derive_key(SECRET, KeyId::new(Some(5), 1))),
+        "150a45e4192a7fed7a36e6354fe0df7c4e48168c34e34604fe8e1602df3fa157",
+    );
+    let keys = [
+        derive_key(SECRET, KeyId::channel(1)),
+        derive_key(SECRET, KeyId::new(Some(5), 1)),
+        derive_key(SECRET, KeyId::new(Some(1), 5)),
+        derive_key(SECRET, KeyId::new(Some(0), 1)),
+    ];
+    for (i, a) in keys.iter().enumerate() {
+        assert!(keys[i + 1..].iter().all(|b| a != b), "key {} repeats", i);
+    }
+}
+
+#[test]
+fn shared_key_is_hkdf_over_both_channel_keys() {
+    let shared = derive_key(SECRET, KeyId::shared(None, 2, 1));
+    assert_eq!(
+        hex::encode(&shared),
+        "064542015fdaa602a27661c410d988f29340f980b1b1b8e5b7c825dbc7291eb2",
+    );
+    // Whichever order the pair is given in, and whichever way it is spelled
+    assert_eq!(shared, derive_key(SECRET, KeyId::shared(None, 1, 2)));
+    assert_eq!(shared, derive_key(SECRET, KeyId::channel(2).with_shared(Some(1))));
+    assert_ne!(shared, derive_key(SECRET, KeyId::channel(1)));
+    assert_ne!(shared, derive_key(SECRET, KeyId::channel(2)));
+    assert_ne!(shared, derive_key(SECRET, KeyId::shared(Some(5), 1, 2)));
+}
+
+#[test]
+fn other_key_lengths_expand_the_channel_key() {
+    let key_id = KeyId::channel(1);
+    assert_eq!(derive_key_len(SECRET, key_id, 32), derive_key(SECRET, key_id));
+    let short = derive_key_len(SECRET, key_id, 16);
+    assert_eq!(hex::encode(&short), "db27ce27a21b0976668352df5c8029a2");
+    // The length is bound in, so no length is a prefix of another
+    let long = derive_key_len(SECRET, key_id, 64);
+    assert_eq!(long.len(), 64);
+    assert_ne!(&long[..16], &short[..]);
+}
+
+#[test]
+fn context_subkey_is_hkdf_under_the_context_label() {
+    let channel_key = derive_key(SECRET, KeyId::channel(1));
+    assert_eq!(
+        hex::encode(derive_context_key(&channel_key, b"thread-7")),
+        "6191f80c98b2d6d859a590705e378c73e91d54ec194ccb9504f0a1fe9290ba40",
+    );
+    assert_ne!(
+        derive_context_key(&channel_key, b"thread-7"),
+        derive_context_key(&channel_key, b"thread-8"),
+    );
+}
+
+#[test]
+fn commitment_is_hkdf_under_the_commit_label() {
+    let key = derive_key(SECRET, KeyId::channel(1));
+    assert_eq!(
+        hex::encode(key_commitment(&key)),
+        "97c4652cfc05185ce26ccffb9dc83f14ac54d896c9e6968d574b686528ddb9aa",
+    );
+    assert_ne!(key_commitment(&key), key_commitment(&derive_key(SECRET, KeyId::channel(2))));
+}
+
+#[test]
+fn fingerprint_is_four_hex_groups_of_a_tagged_sha256() {
+    let key = derive_key(SECRET, KeyId::channel(1));
+    assert_eq!(key_fingerprint(&key), "0816 b95e 8348 c8cf");
+}
+
+#[actix_web::test]
+async fn fingerprint_endpoint_reports_the_current_key() {
+    let state = web::Data::new(support::state());
+    let get = |uri: &str| support::call(&state, TestRequest::get().uri(uri));
+    let bare = get("/channels/1/fingerprint").await.json();
+    assert_eq!(bare["fingerprint"], "0816 b95e 8348 c8cf");
+    assert!(bare.get("org_id").is_none());
+
+    let org = get("/channels/1/fingerprint?org_id=5").await.json();
+    assert_eq!(org["org_id"], 5);
+    let org_key = derive_key(SECRET, KeyId::new(Some(5), 1));
+    assert_eq!(org["fingerprint"], key_fingerprint(&org_key));
+}
+
+#[test]
+fn signing_key_is_seeded_by_hkdf_under_the_ed25519_label() {
+    use ring::signature::KeyPair;
+    let pair = derive_signing_key(&derive_key(SECRET, KeyId::channel(1)));
+    assert_eq!(
+        hex::encode(pair.public_key()),
+        "5a764d2b17aba798df322a6a1ce3cbc6da5ca55cd720cc6b584c01624ba4fec5",
+    );
+    let other = derive_signing_key(&derive_key(SECRET, KeyId::channel(2)));
+    assert_ne!(other.public_key().as_ref(), pair.public_key().as_ref());
+}
+
+// Every purpose; the match in `no_label_is_a_prefix_of_another` stops
+// compiling when one is added without being listed here
+const PURPOSES: [KeyPurpose; 11] = [
+    KeyPurpose::Shared,
+    KeyPurpose::KeyLength,
+    KeyPurpose::Context,
+    KeyPurpose::Commitment,
+    KeyPurpose::DecryptToken,
+    KeyPurpose::Index,
+    KeyPurpose::RatchetInit,
+    KeyPurpose::RatchetStep,
+    KeyPurpose::RatchetMessage,
+    KeyPurpose::KeyExport,
+    KeyPurpose::Signing,
+];
+
+#[test]
+fn no_label_is_a_prefix_of_another() {
+    match PURPOSES[0] {
+        KeyPurpose::Shared
+        | KeyPurpose::KeyLength
+        | KeyPurpose::Context
+        | KeyPurpose::Commitment
+        | KeyPurpose::DecryptToken
+        | KeyPurpose::Index
+        | KeyPurpose::RatchetInit
+        | KeyPurpose::RatchetStep
+        | KeyPurpose::RatchetMessage
+        | KeyPurpose::KeyExport
+        | KeyPurpose::Signing => {}
+    }
+    // Context and KeyLength append to their label, so a prefix could collide
+    for (i, a) in PURPOSES.iter().enumerate() {
+        for (j, b) in PURPOSES.iter().enumerate() {
+            let (a, b) = (String::from_utf8_lossy(a.info()), String::from_utf8_lossy(b.info()));
+            assert!(i == j || !b.starts_with(&*a), "{} prefixes {}", a, b);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+
+use super::support;
+use crate::monobit_passes;
+
+// ── GET /diag/rng ──
+// FIPS 140-2 monobit bounds over 20,000 bits: 9,725 to 10,275 ones.
+
+// A 2,500-byte sample with exactly `ones` bits set
+fn sample(ones: usize) -> [u8; 2500] {
+    let mut sample = [0u8; 2500];
+    for bit in 0..ones {
+        sample[bit % 2500] |= 1 << (bit / 2500);
+    }
+    sample
+}
+
+#[test]
+fn monobit_bounds_are_inclusive() {
+    assert!(monobit_passes(&[0x55; 2500]));
+    assert!(monobit_passes(&sample(9725)));
+    assert!(monobit_passes(&sample(10275)));
+    assert!(!monobit_passes(&sample(9724)));
+    assert!(!monobit_passes(&sample(10276)));
+    assert!(!monobit_passes(&[0; 2500]));
+    assert!(!monobit_passes(&[0xff; 2500]));
+}
+
+#[actix_web::test]
+async fn the_os_rng_reports_healthy_to_an_admin() {
+    let state = web::Data::new(support::state());
+    let get = || TestRequest::get().uri("/diag/rng");
+    assert_eq!(support::call(&state, get()).await.status, 401);
+
+    let reply = support::call(&state, support::admin(get())).await;
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.json(), serde_json::json!({ "source": "os", "healthy": true }));
+}
@@ -0,0 +1,174 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── Blob encodings on the wire ──
+// /encrypt returns base64 unless `output` or an octet-stream Accept says
+// otherwise; /decrypt takes each back, raw blobs as the body itself.
+
+fn fresh() -> web::Data<AppState> {
+    web::Data::new(support::state())
+}
+
+async fn opened(state: &web::Data<AppState>, body: serde_json::Value) -> String {
+    let reply = support::post(state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()["message"].as_str().unwrap().to_owned()
+}
+
+#[actix_web::test]
+async fn hex_output_decrypts_as_hex_input() {
+    let state = fresh();
+    let body = json!({ "channel_id": 1, "message": "hi", "output": "hex" });
+    let sealed = support::encrypt(&state, body).await;
+    assert!(sealed.bytes().all(|b| b.is_ascii_hexdigit()));
+
+    let body = json!({ "channel_id": 1, "encrypted": sealed, "input": "hex" });
+    assert_eq!(opened(&state, body).await, "hi");
+    let not_hex = json!({ "channel_id": 1, "encrypted": "zz", "input": "hex" });
+    assert_eq!(support::post(&state, "/decrypt", not_hex).await.code(), "invalid_hex");
+}
+
+#[actix_web::test]
+async fn octet_stream_accept_gets_raw_bytes_back() {
+    let state = fresh();
+    let req = TestRequest::post()
+        .uri("/encrypt")
+        .insert_header(("Accept", "application/octet-stream"))
+        .set_json(json!({ "channel_id": 1, "message": "hi" }));
+    let reply = support::call(&state, req).await;
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Content-Type"), Some("application/octet-stream"));
+
+    let req = TestRequest::post()
+        .uri("/decrypt?channel_id=1")
+        .insert_header(("Content-Type", "application/octet-stream"))
+        .set_payload(reply.body.clone());
+    let reply = support::call(&state, req).await;
+    assert_eq!(reply.json()["message"], "hi");
+}
+
+fn raw_decrypt(body: impl Into<web::Bytes>) -> TestRequest {
+    TestRequest::post()
+        .uri("/decrypt?channel_id=1")
+        .insert_header(("Content-Type", "application/octet-stream"))
+        .set_payload(body.into())
+}
+
+#[actix_web::test]
+async fn a_raw_body_too_short_to_be_sealed_is_refused() {
+    let state = fresh();
+    let reply = support::call(&state, raw_decrypt(vec![1, 2, 3])).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (400, "ciphertext_too_short".into()));
+}
+
+#[actix_web::test]
+async fn raw_output_opens_as_a_raw_body() {
+    let state = fresh();
+    let body = json!({ "channel_id": 1, "message": "sealed raw", "output": "raw" });
+    let sealed = support::post(&state, "/encrypt", body).await;
+    assert_eq!(sealed.header("Content-Type"), Some("application/octet-stream"));
+
+    let reply = support::call(&state, raw_decrypt(sealed.body.clone())).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "sealed raw");
+    // The same bytes under another channel's key do not open
+    let req = raw_decrypt(sealed.body).uri("/decrypt?channel_id=2");
+    assert_eq!(support::call(&state, req).await.status, 400);
+}
+
+#[actix_web::test]
+async fn raw_input_must_come_as_the_body() {
+    let state = fresh();
+    let body = json!({ "channel_id": 1, "encrypted": "AAAA", "input": "raw" });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (400, "invalid_input_encoding".into()));
+}
+
+// ── Base64 padding ──
+// Input decodes padded or not, standard or URL-safe; output stays padded
+// standard unless `output` is base64nopad.
+
+#[actix_web::test]
+async fn padded_and_unpadded_base64_both_decrypt() {
+    let state = fresh();
+    let padded = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert!(padded.ends_with('='));
+    let unpadded = padded.trim_end_matches('=');
+    let url_safe = unpadded.replace('+', "-").replace('/', "_");
+
+    for encrypted in [padded.as_str(), unpadded, &url_safe] {
+        let body = json!({ "channel_id": 1, "encrypted": encrypted });
+        assert_eq!(opened(&state, body).await, "hi", "{}", encrypted);
+    }
+}
+
+#[actix_web::test]
+async fn base64nopad_output_drops_the_padding() {
+    let state = fresh();
+    let body = json!({ "channel_id": 1, "message": "hi", "output": "base64nopad" });
+    let sealed = support::encrypt(&state, body).await;
+    assert!(!sealed.contains('='));
+    assert_eq!(opened(&state, json!({ "channel_id": 1, "encrypted": sealed })).await, "hi");
+}
+
+#[actix_web::test]
+async fn mixed_alphabets_or_stray_padding_are_refused() {
+    let state = fresh();
+    for encrypted in ["AA+-AAAA", "A===", "AAAA="] {
+        let body = json!({ "channel_id": 1, "encrypted": encrypted });
+        let reply = support::post(&state, "/decrypt", body).await;
+        assert_eq!(reply.code(), "invalid_base64", "{}", encrypted);
+    }
+}
+
+// ── Line-wrapped base64 ──
+// ASCII whitespace is dropped before decoding, so PEM-style blobs open.
+
+#[actix_web::test]
+async fn wrapped_base64_opens_like_the_unwrapped_blob() {
+    let state = fresh();
+    let message = "x".repeat(100);
+    let sealed = support::encrypt(&state, json!({ "channel_id": 1, "message": message })).await;
+    let lines: Vec<String> =
+        sealed.as_bytes().chunks(64).map(|l| String::from_utf8(l.to_vec()).unwrap()).collect();
+    assert!(lines.len() > 1);
+
+    let spaced = format!(" {}\t", lines.join(" "));
+    for wrapped in [lines.join("\n"), lines.join("\r\n") + "\n", spaced] {
+        let body = json!({ "channel_id": 1, "encrypted": wrapped });
+        assert_eq!(opened(&state, body).await, message, "{:?}", wrapped);
+    }
+}
+
+// ── "binary": true on /encrypt ──
+// The message is base64 of the bytes to seal; anything else is refused up
+// front. Text that happens to be valid base64 reads as text without it.
+
+#[actix_web::test]
+async fn binary_messages_must_be_base64() {
+    let state = fresh();
+    for message in ["not base64!", "A===", "caf\u{e9}", "AAAA=AAA"] {
+        let body = json!({ "channel_id": 1, "message": message, "binary": true });
+        let reply = support::post(&state, "/encrypt", body).await;
+        assert_eq!(reply.status, 400, "{}", message);
+        assert_eq!(reply.code(), "encoding_mismatch", "{}", message);
+    }
+    assert!(state.ciphers.lock().is_empty());
+}
+
+#[actix_web::test]
+async fn the_binary_flag_decides_how_base64_text_is_read() {
+    let state = fresh();
+    // "aGk=" is the base64 of "hi"
+    let binary = json!({ "channel_id": 1, "message": "aGk=", "binary": true });
+    let sealed = support::encrypt(&state, binary).await;
+    assert_eq!(opened(&state, json!({ "channel_id": 1, "encrypted": sealed })).await, "hi");
+
+    let text = json!({ "channel_id": 1, "message": "aGk=" });
+    let sealed = support::encrypt(&state, text).await;
+    assert_eq!(opened(&state, json!({ "channel_id": 1, "encrypted": sealed })).await, "aGk=");
+}
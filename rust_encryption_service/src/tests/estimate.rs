@@ -0,0 +1,76 @@
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+
+use super::support;
+use crate::AppState;
+
+// ── /encrypt/estimate ──
+// The estimate is exact: every size it gives must match what /encrypt seals
+// for the same options.
+
+// The estimate for `options`, next to the blob /encrypt returns for them
+async fn estimate_and_blob(state: &web::Data<AppState>, options: Value) -> (Value, String) {
+    let mut body = json!({ "channel_id": 1, "message": "the quarterly numbers are in" });
+    body.as_object_mut().unwrap().extend(options.as_object().unwrap().clone());
+
+    let reply = support::post(state, "/encrypt/estimate", body.clone()).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    (reply.json(), support::encrypt(state, body).await)
+}
+
+#[actix_web::test]
+async fn estimate_matches_the_sealed_blob() {
+    let state = web::Data::new(support::state());
+    let cases = [
+        json!({}),
+        json!({ "context": "thread:42" }),
+        json!({ "metadata": { "author": 7 } }),
+        json!({ "commit": true, "timestamp": true }),
+        json!({ "ratchet": true }),
+        json!({ "sequence": true }),
+    ];
+    for options in cases {
+        let (estimate, blob) = estimate_and_blob(&state, options.clone()).await;
+        let sealed = BASE64.decode(&blob).unwrap();
+        assert_eq!(estimate["ciphertext_bytes"], sealed.len(), "{}", options);
+        assert_eq!(estimate["encoded_bytes"], blob.len(), "{}", options);
+    }
+}
+
+#[actix_web::test]
+async fn estimate_follows_the_output_encoding() {
+    let state = web::Data::new(support::state());
+    for output in ["base64", "base64nopad", "hex"] {
+        let (estimate, blob) = estimate_and_blob(&state, json!({ "output": output })).await;
+        assert_eq!(estimate["encoded_bytes"], blob.len(), "{}", output);
+    }
+}
+
+#[actix_web::test]
+async fn length_estimates_like_the_message() {
+    let state = web::Data::new(support::state());
+    let by_message = json!({ "message": "héllo" });
+    let by_length = json!({ "length": "héllo".len() });
+    let a = support::post(&state, "/encrypt/estimate", by_message).await.json();
+    let b = support::post(&state, "/encrypt/estimate", by_length).await.json();
+    assert_eq!(a, b);
+    assert_eq!(a["plaintext_bytes"], 6);
+}
+
+#[actix_web::test]
+async fn estimate_needs_exactly_one_of_message_or_length() {
+    let state = web::Data::new(support::state());
+    for body in [json!({}), json!({ "message": "hi", "length": 2 })] {
+        let reply = support::post(&state, "/encrypt/estimate", body).await;
+        assert_eq!(reply.status, 400);
+        assert_eq!(reply.code(), "invalid_estimate");
+    }
+}
+
+#[actix_web::test]
+async fn estimate_touches_no_keys() {
+    let state = web::Data::new(support::state());
+    support::post(&state, "/encrypt/estimate", json!({ "channel_id": 1, "length": 10 })).await;
+    assert!(state.ciphers.lock().is_empty());
+}
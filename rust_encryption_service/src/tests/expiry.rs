@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{test, web};
+use serde_json::json;
+
+use super::support::{self, START};
+use crate::clock::MockClock;
+use crate::lockout::DecryptLockout;
+use crate::{evict_expired_ciphers, AppState};
+
+// ── Time-based behavior, driven by MockClock instead of sleeping ──
+
+fn state_at(clock: &Arc<MockClock>, setup: impl FnOnce(&mut AppState)) -> web::Data<AppState> {
+    let mut state = support::state_with_clock(clock.clone());
+    setup(&mut state);
+    web::Data::new(state)
+}
+
+#[actix_web::test]
+async fn cached_cipher_expires_after_its_ttl() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = state_at(&clock, |s| s.key_cache_ttl = Some(Duration::from_secs(60)));
+    let body = json!({ "channel_id": 1, "message": "hi" });
+
+    support::encrypt(&state, body.clone()).await;
+    support::encrypt(&state, body.clone()).await;
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), 1);
+    assert_eq!(state.metrics.cipher_cache_hits.since_reset(), 1);
+
+    clock.advance(Duration::from_secs(59));
+    assert_eq!(evict_expired_ciphers(&state).ok(), Some(0));
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(evict_expired_ciphers(&state).ok(), Some(1));
+
+    support::encrypt(&state, body).await;
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), 2);
+}
+
+#[actix_web::test]
+async fn expired_cipher_is_rebuilt_without_the_sweeper() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = state_at(&clock, |s| s.key_cache_ttl = Some(Duration::from_secs(60)));
+    let body = json!({ "channel_id": 1, "message": "hi" });
+
+    support::encrypt(&state, body.clone()).await;
+    clock.advance(Duration::from_secs(61));
+    support::encrypt(&state, body).await;
+    assert_eq!(state.metrics.cipher_cache_misses.since_reset(), 2);
+    assert_eq!(state.metrics.cipher_cache_hits.since_reset(), 0);
+}
+
+async fn decrypt_from(state: &web::Data<AppState>, encrypted: &str) -> support::Reply {
+    let req = test::TestRequest::post()
+        .uri("/decrypt")
+        .peer_addr("192.0.2.1:4000".parse().unwrap())
+        .set_json(json!({ "channel_id": 1, "encrypted": encrypted }));
+    support::call(state, req).await
+}
+
+#[actix_web::test]
+async fn lockout_lifts_once_its_time_is_up() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = state_at(&clock, |s| s.decrypt_lockout = Some(DecryptLockout::new(2, 60, 300)));
+    let good = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let bad = support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+
+    assert_eq!(decrypt_from(&state, &bad).await.code(), "decryption_failed");
+    assert_eq!(decrypt_from(&state, &bad).await.code(), "decryption_failed");
+    let locked = decrypt_from(&state, &good).await;
+    assert_eq!(locked.status, 429);
+    assert_eq!(locked.code(), "too_many_failures");
+    assert_eq!(locked.json()["resets_at"], START + 300);
+
+    clock.advance(Duration::from_secs(299));
+    assert_eq!(decrypt_from(&state, &good).await.status, 429);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(decrypt_from(&state, &good).await.json()["message"], "hi");
+}
+
+#[actix_web::test]
+async fn failures_spread_past_the_window_never_lock() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = state_at(&clock, |s| s.decrypt_lockout = Some(DecryptLockout::new(2, 60, 300)));
+    let bad = support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+
+    for _ in 0..3 {
+        assert_eq!(decrypt_from(&state, &bad).await.code(), "decryption_failed");
+        clock.advance(Duration::from_secs(61));
+    }
+}
+
+#[actix_web::test]
+async fn decrypt_token_expires() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = state_at(&clock, |_| {});
+    let encrypted = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let issued =
+        support::admin_post(&state, "/tokens/issue", json!({ "channel_id": 1, "ttl_secs": 60 }))
+            .await;
+    assert_eq!(issued.json()["expires_at"], START + 60);
+    let token = issued.json()["token"].as_str().unwrap().to_owned();
+
+    let decrypt = |token: String| {
+        let req = test::TestRequest::post()
+            .uri("/decrypt")
+            .insert_header(("x-decrypt-token", token))
+            .set_json(json!({ "channel_id": 1, "encrypted": encrypted }));
+        support::call(&state, req)
+    };
+    assert_eq!(decrypt(token.clone()).await.json()["message"], "hi");
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(decrypt(token).await.code(), "token_expired");
+}
+
+#[actix_web::test]
+async fn idempotent_replay_ends_with_its_ttl() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = state_at(&clock, |_| {});
+    let encrypt = || {
+        let req = test::TestRequest::post()
+            .uri("/encrypt")
+            .insert_header(("Idempotency-Key", "retry-1"))
+            .set_json(json!({ "channel_id": 1, "message": "hi" }));
+        support::call(&state, req)
+    };
+
+    let first = encrypt().await.json()["encrypted"].clone();
+    assert_eq!(encrypt().await.json()["encrypted"], first);
+    clock.advance(Duration::from_secs(301));
+    assert_ne!(encrypt().await.json()["encrypted"], first);
+}
@@ -0,0 +1,125 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::Aes256Gcm;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD};
+use base64::Engine;
+use hkdf::Hkdf;
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use super::support;
+use crate::AppState;
+
+// ── GET /keys/{channel_id}/export ──
+// Each key the channel has under a held master secret, wrapped for the
+// recipient. The unwrapping here follows the documented scheme, not the
+// service's own code.
+
+const OLD_SECRET: &str = "old-master-secret";
+
+struct Recipient {
+    private: EphemeralPrivateKey,
+    public: Vec<u8>,
+}
+
+fn recipient() -> Recipient {
+    let rng = ring::rand::SystemRandom::new();
+    let private = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+    let public = private.compute_public_key().unwrap().as_ref().to_vec();
+    Recipient { private, public }
+}
+
+async fn export(state: &web::Data<AppState>, channel_id: i64, recipient: &[u8]) -> Value {
+    let uri = format!(
+        "/keys/{}/export?recipient_key={}",
+        channel_id,
+        URL_SAFE_NO_PAD.encode(recipient)
+    );
+    let reply = support::call(state, support::admin(TestRequest::get().uri(&uri))).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()
+}
+
+// Every exported key in the clear, by secret version
+fn unwrap_keys(recipient: Recipient, export: &Value) -> Vec<(String, Vec<u8>)> {
+    let ephemeral = BASE64.decode(export["ephemeral_public_key"].as_str().unwrap()).unwrap();
+    let peer = UnparsedPublicKey::new(&X25519, &ephemeral);
+    let salt = [&ephemeral[..], &recipient.public[..]].concat();
+    let wrapping_key = agreement::agree_ephemeral(recipient.private, &peer, |shared| {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(&salt), shared)
+            .expand(b"freecord/key-export/v1", &mut key)
+            .unwrap();
+        key
+    })
+    .unwrap();
+    let unwrapper = Aes256Gcm::new_from_slice(&wrapping_key).unwrap();
+
+    let channel_id = export["channel_id"].as_i64().unwrap();
+    let keys = export["keys"].as_array().unwrap();
+    keys.iter()
+        .map(|key| {
+            let version = key["secret_version"].as_str().unwrap().to_owned();
+            let wrapped = BASE64.decode(key["wrapped"].as_str().unwrap()).unwrap();
+            let (nonce, sealed) = wrapped.split_at(12);
+            let aad = format!("freecord/key-export/v1:{}::{}", channel_id, version);
+            let payload = Payload { msg: sealed, aad: aad.as_bytes() };
+            (version, unwrapper.decrypt(nonce.into(), payload).unwrap())
+        })
+        .collect()
+}
+
+async fn opens(state: &web::Data<AppState>, encrypted: &str, key: &[u8]) -> bool {
+    let body = json!({ "channel_id": 1, "encrypted": encrypted, "key": BASE64.encode(key) });
+    support::admin_post(state, "/decrypt/raw", body).await.status == 200
+}
+
+#[actix_web::test]
+async fn an_unwrapped_key_opens_the_channel() {
+    let state = web::Data::new(support::state());
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+
+    let recipient = recipient();
+    let export = export(&state, 1, &recipient.public).await;
+    assert_eq!(export["scheme"], "x25519-hkdf-sha256-aes256gcm");
+    let keys = unwrap_keys(recipient, &export);
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].0, "current");
+    assert!(opens(&state, &blob, &keys[0].1).await);
+}
+
+#[actix_web::test]
+async fn every_held_generation_is_exported() {
+    let mut old = support::state();
+    old.master_secret = OLD_SECRET.into();
+    let old = web::Data::new(old);
+    let old_blob = support::encrypt(&old, json!({ "channel_id": 1, "message": "hi" })).await;
+
+    let mut state = support::state();
+    state.previous_master_secret = Some(OLD_SECRET.into());
+    let state = web::Data::new(state);
+    let new_blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+
+    let recipient = recipient();
+    let export = export(&state, 1, &recipient.public).await;
+    let keys = unwrap_keys(recipient, &export);
+    let key = |version: &str| &keys.iter().find(|(v, _)| v == version).unwrap().1;
+    assert!(opens(&state, &new_blob, key("current")).await);
+    assert!(opens(&state, &old_blob, key("previous")).await);
+    assert!(!opens(&state, &old_blob, key("current")).await);
+}
+
+#[actix_web::test]
+async fn a_bad_recipient_or_no_admin_is_refused() {
+    let state = web::Data::new(support::state());
+    let req = TestRequest::get().uri("/keys/1/export?recipient_key=AAAA");
+    let reply = support::call(&state, support::admin(req)).await;
+    assert_eq!(reply.code(), "invalid_recipient_key");
+
+    let recipient = URL_SAFE_NO_PAD.encode(recipient().public);
+    let uri = format!("/keys/1/export?recipient_key={}", recipient);
+    let reply = support::call(&state, TestRequest::get().uri(&uri)).await;
+    assert_eq!(reply.status, 401);
+}
@@ -0,0 +1,80 @@
+use actix_web::web;
+use aes_gcm::aead::Payload;
+use serde_json::json;
+
+use super::support;
+use crate::crypto::{derive_key, Algorithm, ChannelCipher, KeyId};
+use crate::envelope::{FORMAT_VERSION, MAGIC};
+
+// ── Decrypt fallbacks ──
+// Readings and keys /decrypt tries after the obvious one fails.
+
+// Headerless v0 blob: nonce || ciphertext, no associated data
+fn legacy_blob(secret: &str, channel_id: i64, nonce: [u8; 12], message: &[u8]) -> String {
+    let key = derive_key(secret, KeyId::channel(channel_id));
+    let cipher = ChannelCipher::new(Algorithm::Aes256gcm, &key);
+    let sealed = cipher.encrypt(&nonce, Payload { msg: message, aad: &[] }).unwrap();
+    hex::encode([&nonce[..], &sealed].concat())
+}
+
+fn decrypt_body(channel_id: i64, encrypted: &str) -> serde_json::Value {
+    json!({ "channel_id": channel_id, "encrypted": encrypted, "input": "hex" })
+}
+
+#[actix_web::test]
+async fn legacy_nonce_that_looks_like_a_header_still_opens() {
+    let state = web::Data::new(support::state());
+    // The random nonce starts with a valid empty header
+    let nonce = [MAGIC, FORMAT_VERSION, Algorithm::Aes256gcm.id(), 0, 4, 5, 6, 7, 8, 9, 10, 11];
+    let blob = legacy_blob(support::SECRET, 1, nonce, b"hello");
+
+    let mut body = decrypt_body(1, &blob);
+    body["verbose"] = json!(true);
+    // Verbose details are admin only
+    let reply = support::admin_post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "hello");
+    assert!(reply.json()["format_version"].is_null());
+}
+
+// ── MASTER_SECRET_PREVIOUS ──
+
+const OLD_SECRET: &str = "old-master-secret";
+const NONCE: [u8; 12] = [9; 12];
+
+fn with_previous() -> web::Data<crate::AppState> {
+    let mut state = support::state();
+    state.previous_master_secret = Some(OLD_SECRET.into());
+    web::Data::new(state)
+}
+
+#[actix_web::test]
+async fn previous_secret_opens_what_the_current_one_cannot() {
+    let state = with_previous();
+    let old = legacy_blob(OLD_SECRET, 1, NONCE, b"old");
+    let reply = support::post(&state, "/decrypt", decrypt_body(1, &old)).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "old");
+    assert_eq!(reply.json()["secret_version"], "previous");
+
+    let current = legacy_blob(support::SECRET, 1, NONCE, b"new");
+    let reply = support::post(&state, "/decrypt", decrypt_body(1, &current)).await;
+    assert_eq!(reply.json()["secret_version"], "current");
+}
+
+#[actix_web::test]
+async fn without_a_previous_secret_old_data_fails() {
+    let state = web::Data::new(support::state());
+    let old = legacy_blob(OLD_SECRET, 1, NONCE, b"old");
+    let reply = support::post(&state, "/decrypt", decrypt_body(1, &old)).await;
+    assert_eq!(reply.code(), "decryption_failed");
+}
+
+#[actix_web::test]
+async fn encrypts_always_use_the_current_secret() {
+    let state = with_previous();
+    let sealed = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": sealed });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.json()["secret_version"], "current");
+}
@@ -0,0 +1,202 @@
+use actix_web::{test, web};
+
+use super::support;
+use crate::AppState;
+
+// ── File endpoints ──
+// Uploads go through /encrypt/file as multipart; the chunked envelope it
+// streams back is what /decrypt/file and /decrypt/range take as the body.
+
+const BOUNDARY: &str = "freecord-test-boundary";
+
+// A multipart body with a `channel_id` field and then the `file` part
+fn upload(channel_id: i64, file: &[u8]) -> test::TestRequest {
+    let mut body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"channel_id\"\r\n\r\n{id}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n",
+        b = BOUNDARY,
+        id = channel_id,
+    )
+    .into_bytes();
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+    test::TestRequest::post()
+        .uri("/encrypt/file")
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={}", BOUNDARY)))
+        .set_payload(body)
+}
+
+pub(super) async fn encrypt_file(
+    state: &web::Data<AppState>,
+    channel_id: i64,
+    file: &[u8],
+) -> Vec<u8> {
+    let reply = support::call(state, upload(channel_id, file)).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.body.to_vec()
+}
+
+async fn decrypt(state: &web::Data<AppState>, uri: &str, sealed: Vec<u8>) -> support::Reply {
+    support::call(state, test::TestRequest::post().uri(uri).set_payload(sealed)).await
+}
+
+// Long enough to span several 64 KiB chunks
+fn contents() -> Vec<u8> {
+    (0..200_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+#[actix_web::test]
+async fn file_round_trips() {
+    let state = web::Data::new(support::state());
+    let file = contents();
+    let sealed = encrypt_file(&state, 1, &file).await;
+    assert!(sealed.len() > file.len());
+
+    let reply = decrypt(&state, "/decrypt/file?channel_id=1", sealed).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.body.as_ref(), file.as_slice());
+}
+
+#[actix_web::test]
+async fn file_for_another_channel_fails_up_front() {
+    let state = web::Data::new(support::state());
+    let sealed = encrypt_file(&state, 1, b"meeting notes").await;
+
+    let reply = decrypt(&state, "/decrypt/file?channel_id=2", sealed).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
+
+#[actix_web::test]
+async fn cut_off_file_fails() {
+    let state = web::Data::new(support::state());
+    let mut sealed = encrypt_file(&state, 1, b"meeting notes").await;
+    sealed.truncate(sealed.len() - 1);
+
+    let reply = decrypt(&state, "/decrypt/file?channel_id=1", sealed).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
+
+#[actix_web::test]
+async fn not_an_envelope_is_rejected() {
+    let state = web::Data::new(support::state());
+    let reply = decrypt(&state, "/decrypt/file?channel_id=1", b"plain bytes".to_vec()).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "invalid_file");
+}
+
+#[actix_web::test]
+async fn upload_needs_a_channel_first() {
+    let state = web::Data::new(support::state());
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a\"\r\n\r\n\
+         data\r\n--{b}--\r\n",
+        b = BOUNDARY,
+    );
+    let req = test::TestRequest::post()
+        .uri("/encrypt/file")
+        .insert_header(("Content-Type", format!("multipart/form-data; boundary={}", BOUNDARY)))
+        .set_payload(body);
+    let reply = support::call(&state, req).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "missing_channel_id");
+}
+
+// ── best_effort ──
+// A damaged file gives back its intact leading chunks, with X-Truncated
+// saying whether anything was lost.
+
+#[actix_web::test]
+async fn best_effort_keeps_the_chunks_before_the_damage() {
+    let state = web::Data::new(support::state());
+    let file = contents();
+    let mut sealed = encrypt_file(&state, 1, &file).await;
+    // Flip a byte well inside the second chunk
+    let at = sealed.len() - file.len() + 64 * 1024 + 100;
+    sealed[at] ^= 1;
+
+    let reply = decrypt(&state, "/decrypt/file?channel_id=1&best_effort=true", sealed).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.header("X-Truncated"), Some("true"));
+    assert_eq!(reply.body.as_ref(), &file[..64 * 1024]);
+}
+
+#[actix_web::test]
+async fn best_effort_on_a_whole_file_is_not_truncated() {
+    let state = web::Data::new(support::state());
+    let file = contents();
+    let sealed = encrypt_file(&state, 1, &file).await;
+
+    let reply = decrypt(&state, "/decrypt/file?channel_id=1&best_effort=true", sealed).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.header("X-Truncated"), Some("false"));
+    assert_eq!(reply.body.as_ref(), file.as_slice());
+}
+
+#[actix_web::test]
+async fn best_effort_with_a_bad_first_chunk_fails() {
+    let state = web::Data::new(support::state());
+    let sealed = encrypt_file(&state, 1, b"meeting notes").await;
+
+    let reply = decrypt(&state, "/decrypt/file?channel_id=2&best_effort=true", sealed).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
+
+// ── /decrypt/range ──
+
+#[actix_web::test]
+async fn range_spanning_a_chunk_boundary() {
+    let state = web::Data::new(support::state());
+    let file = contents();
+    let sealed = encrypt_file(&state, 1, &file).await;
+
+    let start = 64 * 1024 - 10;
+    let uri = format!("/decrypt/range?channel_id=1&start={}&length=20", start);
+    let reply = decrypt(&state, &uri, sealed).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.body.as_ref(), &file[start..start + 20]);
+}
+
+#[actix_web::test]
+async fn range_is_cut_at_the_end_or_refused_past_it() {
+    let state = web::Data::new(support::state());
+    let file = contents();
+    let sealed = encrypt_file(&state, 1, &file).await;
+
+    let uri = format!("/decrypt/range?channel_id=1&start={}&length=100", file.len() - 5);
+    let reply = decrypt(&state, &uri, sealed.clone()).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.body.as_ref(), &file[file.len() - 5..]);
+
+    let uri = format!("/decrypt/range?channel_id=1&start={}&length=1", file.len() + 10);
+    let reply = decrypt(&state, &uri, sealed).await;
+    assert_eq!(reply.status, 416);
+    assert_eq!(reply.code(), "range_not_satisfiable");
+}
+
+// ── /decrypt/stream ──
+
+#[actix_web::test]
+async fn stream_serves_the_file_as_text() {
+    let state = web::Data::new(support::state());
+    let text: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+    let sealed = encrypt_file(&state, 1, text.as_bytes()).await;
+
+    let reply = decrypt(&state, "/decrypt/stream?channel_id=1", sealed).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.header("Content-Type"), Some("text/plain; charset=utf-8"));
+    assert_eq!(reply.body.as_ref(), text.as_bytes());
+}
+
+#[actix_web::test]
+async fn stream_for_another_channel_fails() {
+    let state = web::Data::new(support::state());
+    let sealed = encrypt_file(&state, 1, b"one\ntwo\n").await;
+
+    let reply = decrypt(&state, "/decrypt/stream?channel_id=2", sealed).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
@@ -0,0 +1,76 @@
+use actix_web::{test, web};
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── Idempotency-Key on /encrypt ──
+// A repeat of the same request replays the first response byte for byte;
+// the key never returns another request's ciphertext.
+
+async fn encrypt_with(
+    state: &web::Data<AppState>,
+    key: &str,
+    body: serde_json::Value,
+) -> support::Reply {
+    let req = test::TestRequest::post()
+        .uri("/encrypt")
+        .insert_header(("Idempotency-Key", key))
+        .set_json(body);
+    support::call(state, req).await
+}
+
+#[actix_web::test]
+async fn a_repeat_replays_the_first_response() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "hi" });
+    let first = encrypt_with(&state, "send-42", body.clone()).await;
+    let second = encrypt_with(&state, "send-42", body.clone()).await;
+    assert_eq!(first.status, 200, "{:?}", first.body);
+    assert_eq!(second.body, first.body);
+
+    // Without the header every encrypt draws a fresh nonce
+    let fresh = support::encrypt(&state, body).await;
+    assert_ne!(fresh, first.json()["encrypted"]);
+}
+
+#[actix_web::test]
+async fn a_different_body_under_the_same_key_is_sealed_anew() {
+    let state = web::Data::new(support::state());
+    let first = encrypt_with(&state, "send-42", json!({ "channel_id": 1, "message": "hi" })).await;
+    let other = encrypt_with(&state, "send-42", json!({ "channel_id": 2, "message": "hi" })).await;
+    assert_eq!(other.status, 200, "{:?}", other.body);
+    assert_ne!(other.body, first.body);
+
+    let reply = support::post(&state, "/decrypt", json!({
+        "channel_id": 2,
+        "encrypted": other.json()["encrypted"],
+    }))
+    .await;
+    assert_eq!(reply.json()["message"], "hi");
+}
+
+#[actix_web::test]
+async fn a_replay_is_not_charged_again() {
+    let mut state = support::state();
+    state.limits.daily_encrypt_quota = Some(1);
+    let state = web::Data::new(state);
+    let body = json!({ "channel_id": 1, "message": "hi" });
+    let first = encrypt_with(&state, "send-42", body.clone()).await;
+    let second = encrypt_with(&state, "send-42", body.clone()).await;
+    assert_eq!(first.status, 200, "{:?}", first.body);
+    assert_eq!(second.status, 200, "{:?}", second.body);
+
+    let third = encrypt_with(&state, "send-43", body).await;
+    assert_eq!(third.status, 429);
+}
+
+#[actix_web::test]
+async fn an_empty_or_overlong_key_is_rejected() {
+    let state = web::Data::new(support::state());
+    for key in [String::new(), "k".repeat(256)] {
+        let reply = encrypt_with(&state, &key, json!({ "channel_id": 1, "message": "hi" })).await;
+        assert_eq!(reply.status, 400);
+        assert_eq!(reply.code(), "invalid_idempotency_key");
+    }
+}
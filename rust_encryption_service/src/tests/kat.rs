@@ -0,0 +1,375 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use crypto_secretbox::XSalsa20Poly1305;
+use serde_json::{json, Value};
+
+use super::support::{self, START};
+use crate::chunked::ChunkSealer;
+use crate::crypto::{derive_key, Algorithm, ChannelCipher, KeyId};
+use crate::nonce::MockNonceStrategy;
+use crate::{envelope, ratchet, NONCE_LEN};
+
+// ── Known-answer vectors ──
+// Primitive vectors are published ones. Envelope vectors were computed
+// outside the service from the documented layout (envelope.rs, chunked.rs)
+// with an independent AES-GCM and HKDF, for channel 42 under
+// support::SECRET, the message "hello" and the nonce below.
+
+const NONCE: [u8; NONCE_LEN] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const CHANNEL: i64 = 42;
+
+fn unhex(s: &str) -> Vec<u8> {
+    hex::decode(s).unwrap()
+}
+
+// ── Primitives ──
+
+// GCM specification (McGrew & Viega), test case 16
+#[test]
+fn aes_256_gcm() {
+    let key = unhex("feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308");
+    let nonce = unhex("cafebabefacedbaddecaf888");
+    let aad = unhex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+    let plaintext = unhex(
+        "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+         1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+    );
+    let sealed = unhex(
+        "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+         8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662\
+         76fc6ece0f4e1768cddf8853bb2d551b",
+    );
+    let cipher = ChannelCipher::new(Algorithm::Aes256gcm, &key);
+    assert_eq!(cipher.encrypt(&nonce, Payload { msg: &plaintext, aad: &aad }).unwrap(), sealed);
+    assert_eq!(cipher.decrypt(&nonce, Payload { msg: &sealed, aad: &aad }).unwrap(), plaintext);
+}
+
+// RFC 8439 section 2.8.2
+#[test]
+fn chacha20_poly1305() {
+    let key = unhex("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+    let nonce = unhex("070000004041424344454647");
+    let aad = unhex("50515253c0c1c2c3c4c5c6c7");
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only \
+        one tip for the future, sunscreen would be it.";
+    let sealed = unhex(
+        "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6\
+         3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36\
+         92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+         3ff4def08e4b7a9de576d26586cec64b6116\
+         1ae10b594f09e26a7e902ecbd0600691",
+    );
+    let cipher = ChannelCipher::new(Algorithm::Chacha20poly1305, &key);
+    assert_eq!(cipher.encrypt(&nonce, Payload { msg: plaintext, aad: &aad }).unwrap(), sealed);
+    assert_eq!(cipher.decrypt(&nonce, Payload { msg: &sealed, aad: &aad }).unwrap(), plaintext);
+}
+
+// NaCl tests/secretbox.c, opened the way a secretbox-format /decrypt is
+#[test]
+fn xsalsa20_poly1305() {
+    let key = unhex("1b27556473e985d462cd51197a9a46c76009549eac6474f206c4ee0844f68389");
+    let nonce = unhex("69696ee955b62b73cd62bda875fc73d68219e0036b7a0b37");
+    let plaintext = unhex(
+        "be075fc53c81f2d5cf141316ebeb0c7b5228c52a4c62cbd44b66849b64244ffc\
+         e5ecbaaf33bd751a1ac728d45e6c61296cdc3c01233561f41db66cce314adb31\
+         0e3be8250c46f06dceea3a7fa1348057e2f6556ad6b1318a024a838f21af1fde\
+         048977eb48f59ffd4924ca1c60902e52f0a089bc76897040e082f93776384864\
+         5e0705",
+    );
+    let sealed = unhex(
+        "f3ffc7703f9400e52a7dfb4b3d3305d98e993b9f48681273c29650ba32fc76ce\
+         48332ea7164d96a4476fb8c531a1186ac0dfc17c98dce87b4da7f011ec48c972\
+         71d2c20f9b928fe2270d6fb863d51738b48eeee314a7cc8ab932164548e526ae\
+         90224368517acfeabd6bb3732bc0e9da99832b61ca01b6de56244a9e88d5f9b3\
+         7973f622a43d14a6599b1f654cb45a74e355a5",
+    );
+    let cipher = XSalsa20Poly1305::new(crypto_secretbox::Key::from_slice(&key));
+    let nonce = crypto_secretbox::Nonce::from_slice(&nonce);
+    assert_eq!(cipher.encrypt(nonce, &plaintext[..]).unwrap(), sealed);
+    assert_eq!(cipher.decrypt(nonce, &sealed[..]).unwrap(), plaintext);
+}
+
+#[test]
+fn startup_selftest_passes() {
+    assert!(crate::selftest::run().passed);
+}
+
+#[actix_web::test]
+async fn selftest_report_is_admin_only() {
+    let state = web::Data::new(support::state());
+    let get = || TestRequest::get().uri("/admin/crypto-selftest");
+    assert_eq!(support::call(&state, get()).await.status, 401);
+
+    let report = support::call(&state, support::admin(get())).await.json();
+    assert_eq!(report["passed"], true);
+    let vectors = report["vectors"].as_array().unwrap();
+    assert_eq!(vectors.len(), 4);
+    assert_eq!(vectors[3], json!({
+        "name": "rfc8439-2.8.2",
+        "algorithm": "chacha20poly1305",
+        "passed": true,
+    }));
+}
+
+// ── Envelope versions ──
+
+fn fixed_nonce_state() -> web::Data<crate::AppState> {
+    let mut state = support::state();
+    state.nonces = Box::new(MockNonceStrategy::new([NONCE]));
+    web::Data::new(state)
+}
+
+fn request(extra: Value) -> Value {
+    let mut body = json!({ "channel_id": CHANNEL, "message": "hello", "output": "hex" });
+    body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    body
+}
+
+// ── Seal `extra` on top of the base request and check the exact bytes ──
+// The expected blob is then decrypted again, so each vector also covers
+// the open side of its flag.
+async fn check(extra: Value, expected: &str) -> Value {
+    let state = fixed_nonce_state();
+    let encrypted = support::encrypt(&state, request(extra.clone())).await;
+    assert_eq!(encrypted, expected);
+
+    let mut body = json!({ "channel_id": CHANNEL, "encrypted": expected, "input": "hex" });
+    if let Some(hash) = extra.get("attachment_hash") {
+        body["attachment_hash"] = hash.clone();
+    }
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    let opened = reply.json();
+    assert_eq!(opened["message"], "hello");
+    opened
+}
+
+#[actix_web::test]
+async fn v2_aes_256_gcm() {
+    check(
+        json!({}),
+        "fc020100000102030405060708090a0b42df1cbebb2a7612b15c823a7cb2b9ccc1d16925d0",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn v2_chacha20_poly1305() {
+    check(
+        json!({ "algorithm": "chacha20poly1305" }),
+        "fc020200000102030405060708090a0bdd259e7f32398bf122e66935e0a594fbb212035891",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn v2_framed() {
+    let opened = check(
+        json!({ "metadata": { "k": "v" } }),
+        "fc020101000102030405060708090a0b2aba70d7bc910a497f2e83e2f6c9e150\
+         3b6d29447e6488ec5f641b83ac6046d6d5e6",
+    )
+    .await;
+    assert_eq!(opened["metadata"], json!({ "k": "v" }));
+}
+
+#[actix_web::test]
+async fn v2_context() {
+    let opened = check(
+        json!({ "context": "thread-1" }),
+        "fc02010200087468726561642d31000102030405060708090a0bd85e4550845a\
+         414126283a6a781ddafbcbe304eed7",
+    )
+    .await;
+    assert_eq!(opened["context"], "thread-1");
+}
+
+#[actix_web::test]
+async fn v2_commit() {
+    check(
+        json!({ "commit": true }),
+        "fc0201086681577747fb6ca68be8ee1d24e177b1e0698faace4bb6b11544ac04\
+         65cfe852000102030405060708090a0b42df1cbebbbeb766bb3f3ef6e7ce7c91\
+         616bab8f9e",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn v2_attachment() {
+    check(
+        json!({ "attachment_hash": "abc" }),
+        "fc020110000102030405060708090a0b42df1cbebbd93fdf2a377dc6fdc0872981dd9d869a",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn v2_sequence() {
+    let opened = check(
+        json!({ "sequence": true }),
+        "fc0201400000000000000000000102030405060708090a0b42df1cbebb150245\
+         d4d08cdd8050bca4d15606efb5",
+    )
+    .await;
+    assert_eq!(opened["sequence"], 0);
+}
+
+// The header's time comes from the state's MockClock, fixed at START
+#[actix_web::test]
+async fn v2_produced_at() {
+    let opened = check(
+        json!({ "timestamp": true }),
+        "fc02018001000000006553f100000102030405060708090a0b42df1cbebbc06f\
+         65ced281003a0d382bdf56926c88",
+    )
+    .await;
+    assert_eq!(opened["produced_at"], START);
+}
+
+// Ratchet keys come from a random root by design, so only the layout is
+// fixed: the flag, then the chain's first index ahead of the nonce
+#[actix_web::test]
+async fn v2_ratchet() {
+    let state = fixed_nonce_state();
+    let encrypted = support::encrypt(&state, request(json!({ "ratchet": true }))).await;
+    let blob = unhex(&encrypted);
+    let flags = envelope::FLAG_RATCHET as u8;
+    let version = envelope::FORMAT_VERSION;
+    assert_eq!(blob[..4], [envelope::MAGIC, version, Algorithm::Aes256gcm.id(), flags]);
+    assert_eq!(blob[4..12], ratchet::first_index(START).to_be_bytes());
+    assert_eq!(blob[12..24], NONCE);
+
+    let body = json!({ "channel_id": CHANNEL, "encrypted": encrypted, "input": "hex" });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.json()["message"], "hello");
+}
+
+// StreamBE32 chunks of four bytes under nonce prefix 00..06
+#[test]
+fn v2_chunked() {
+    let key = derive_key(support::SECRET, KeyId::channel(CHANNEL));
+    let cipher = ChannelCipher::new(Algorithm::Aes256gcm, &key);
+    let header = envelope::Header::new(Algorithm::Aes256gcm.id()).with_chunk_size(4).encode();
+    let mut sealer = ChunkSealer::new(cipher, header, [0, 1, 2, 3, 4, 5, 6], 4);
+    let mut blob = sealer.preamble();
+    blob.extend(sealer.push(b"hello world").unwrap());
+    blob.extend(sealer.finish().unwrap());
+    assert_eq!(
+        hex::encode(blob),
+        "fc020120000000040001020304050642fbd27fc69d5c03683d343e91326278b5\
+         98166dc6c2a4b24410a94b7799f3ea81c080f1258510b965a68d68fa9b3d6515\
+         a2f81dad416e23ff27e9",
+    );
+}
+
+// ── Legacy formats, which are only ever opened ──
+
+// v1 headers, as sealed before produced_at moved to v2
+const V1: &[(&str, &str)] = &[
+    ("", "fc010100000102030405060708090a0b42df1cbebb35cfe70fc7ee0f2ce8b03e244329b809"),
+    ("", "fc010200000102030405060708090a0bdd259e7f3217aa3c7f16f668380b9dd466960320ca"),
+    (
+        "",
+        "fc010101000102030405060708090a0b2aba70d7bc910a497f2e83e2f6c9e150\
+         3b6df2431b02535044eedf445e5dc695ff89",
+    ),
+    (
+        "",
+        "fc01010200087468726561642d31000102030405060708090a0bd85e4550845e\
+         d86be4db2a6989a2caacb09c724101",
+    ),
+    (
+        "",
+        "fc0101086681577747fb6ca68be8ee1d24e177b1e0698faace4bb6b11544ac04\
+         65cfe852000102030405060708090a0b42df1cbebb03d0cb0e4f9de8d52d066f\
+         ecedb7553b",
+    ),
+    ("abc", "fc010110000102030405060708090a0b42df1cbebbc6862a94ac11f3ad9a8edb644fdd1b43"),
+    (
+        "",
+        "fc0101400000000000000000000102030405060708090a0b42df1cbebb0abbb0\
+         6a4be0e8d00ab55634c446726c",
+    ),
+];
+
+async fn open_v1(
+    state: &web::Data<crate::AppState>,
+    hash: &str,
+    encrypted: &str,
+) -> support::Reply {
+    let mut body = json!({ "channel_id": CHANNEL, "encrypted": encrypted, "input": "hex" });
+    if !hash.is_empty() {
+        body["attachment_hash"] = json!(hash);
+    }
+    support::post(state, "/decrypt", body).await
+}
+
+#[actix_web::test]
+async fn legacy_v1() {
+    let state = fixed_nonce_state();
+    for (hash, encrypted) in V1 {
+        let reply = open_v1(&state, hash, encrypted).await;
+        assert_eq!(reply.status, 200, "{}: {:?}", encrypted, reply.body);
+        assert_eq!(reply.json()["message"], "hello");
+    }
+}
+
+// 0x80 was never a v1 flag, so a v1 header claiming produced_at is refused
+#[actix_web::test]
+async fn legacy_v1_has_no_produced_at() {
+    let state = fixed_nonce_state();
+    let encrypted = "fc010180000000006553f100000102030405060708090a0b42df1cbebb2868cb\
+                     c70e40d2f51e83a86b2ae5c657";
+    let reply = open_v1(&state, "", encrypted).await;
+    assert_eq!(reply.status, 400, "{:?}", reply.body);
+}
+
+#[actix_web::test]
+async fn legacy_v1_chunked() {
+    let state = fixed_nonce_state();
+    let sealed = unhex(
+        "fc010120000000040001020304050642fbd27fd924a9bdf351016ecb3b909d27\
+         d88bb4c6c2a4b25ba95cf5ecf5c6badbc97214b7c58d6065a68d77436e83fe79\
+         97a847a4b38bb1bfba30",
+    );
+    let uri = format!("/decrypt/file?channel_id={}", CHANNEL);
+    let reply = support::call(&state, TestRequest::post().uri(&uri).set_payload(sealed)).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(&reply.body[..], b"hello world");
+}
+
+// Headerless v0: nonce || ciphertext, no associated data
+#[actix_web::test]
+async fn legacy_headerless() {
+    let state = fixed_nonce_state();
+    let body = json!({
+        "channel_id": CHANNEL,
+        "encrypted": "000102030405060708090a0b42df1cbebb59202f6e0edec2c6c226483635629fe4",
+        "input": "hex",
+    });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "hello");
+}
+
+// libsodium crypto_secretbox_easy under the channel key
+#[actix_web::test]
+async fn legacy_secretbox() {
+    let state = fixed_nonce_state();
+    let key = derive_key(support::SECRET, KeyId::channel(CHANNEL));
+    let nonce = [7u8; 24];
+    let sealed = XSalsa20Poly1305::new(crypto_secretbox::Key::from_slice(&key))
+        .encrypt(crypto_secretbox::Nonce::from_slice(&nonce), &b"hello"[..])
+        .unwrap();
+    let body = json!({
+        "channel_id": CHANNEL,
+        "encrypted": hex::encode([&nonce[..], &sealed].concat()),
+        "input": "hex",
+        "format": "secretbox",
+    });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "hello");
+}
@@ -0,0 +1,132 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── MAX_DECRYPTED_BYTES ──
+// An oversized blob is refused from its length alone, before any plaintext
+// buffer is allocated for it.
+
+fn capped(max_decrypted_bytes: usize) -> web::Data<AppState> {
+    let mut state = support::state();
+    state.limits.max_decrypted_bytes = max_decrypted_bytes;
+    web::Data::new(state)
+}
+
+#[actix_web::test]
+async fn plaintext_over_the_ceiling_is_refused() {
+    let state = capped(64);
+    let long = json!({ "channel_id": 1, "message": "x".repeat(65) });
+    let long = support::encrypt(&state, long).await;
+
+    let body = json!({ "channel_id": 1, "encrypted": long });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 413);
+    assert_eq!(reply.code(), "plaintext_too_large");
+}
+
+#[actix_web::test]
+async fn plaintext_under_the_ceiling_opens() {
+    let state = capped(64);
+    let short = json!({ "channel_id": 1, "message": "x".repeat(32) });
+    let short = support::encrypt(&state, short).await;
+
+    let body = json!({ "channel_id": 1, "encrypted": short });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "x".repeat(32));
+}
+
+// ── KEY_BYTE_LIMIT ──
+// Once a channel key has sealed its share of plaintext, encrypts under it are
+// refused until a master secret rotation hands the channel a fresh key.
+
+fn key_limited(key_byte_limit: u64) -> web::Data<AppState> {
+    let mut state = support::state();
+    state.limits.key_byte_limit = key_byte_limit;
+    web::Data::new(state)
+}
+
+#[actix_web::test]
+async fn crossing_the_key_limit_refuses_encrypts() {
+    let state = key_limited(10);
+    let first = support::encrypt(&state, json!({ "channel_id": 1, "message": "123456" })).await;
+
+    let body = json!({ "channel_id": 1, "message": "12345" });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert_eq!(reply.status, 409);
+    assert_eq!(reply.code(), "key_exhausted");
+
+    // Refused bytes aren't counted, so what fits is still let through
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "1234" })).await;
+    let reply = support::call(&state, TestRequest::get().uri("/channels/1/usage")).await;
+    assert_eq!(reply.json()["bytes_sealed"], 10);
+    assert_eq!(reply.json()["byte_limit"], 10);
+
+    // Other channels have keys of their own, and decrypts are never refused
+    support::encrypt(&state, json!({ "channel_id": 2, "message": "123456" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": first });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+}
+
+#[actix_web::test]
+async fn rotating_the_master_secret_gives_a_fresh_key() {
+    let state = key_limited(10);
+    let old = support::encrypt(&state, json!({ "channel_id": 1, "message": "1234567890" })).await;
+    let reply = support::post(&state, "/encrypt", json!({ "channel_id": 1, "message": "1" })).await;
+    assert_eq!(reply.code(), "key_exhausted");
+
+    // The restart after a rotation: new MASTER_SECRET, old one kept as previous
+    let mut rotated = support::state();
+    rotated.limits.key_byte_limit = 10;
+    rotated.previous_master_secret = Some(rotated.master_secret.clone());
+    rotated.master_secret = "rotated-master-secret".into();
+    let rotated = web::Data::new(rotated);
+
+    support::encrypt(&rotated, json!({ "channel_id": 1, "message": "1234567890" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": old });
+    let reply = support::post(&rotated, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+}
+
+// ── MAX_AAD_BYTES ──
+// The header, context included, plus any attachment hash must fit; the
+// fixed header is 4 bytes and a context adds 2 for its length.
+
+fn aad_capped(max_aad_bytes: usize) -> web::Data<AppState> {
+    let mut state = support::state();
+    state.limits.max_aad_bytes = Some(max_aad_bytes);
+    web::Data::new(state)
+}
+
+#[actix_web::test]
+async fn aad_up_to_the_limit_is_sealed() {
+    let state = aad_capped(16);
+    let body = json!({ "channel_id": 1, "message": "hi", "context": "x".repeat(10) });
+    support::encrypt(&state, body).await;
+
+    let body = json!({ "channel_id": 1, "message": "hi", "context": "x".repeat(11) });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "aad_too_large");
+}
+
+#[actix_web::test]
+async fn an_attachment_hash_counts_toward_the_aad() {
+    let state = aad_capped(16);
+    let body = json!({ "channel_id": 1, "message": "hi", "attachment_hash": "x".repeat(12) });
+    support::encrypt(&state, body).await;
+
+    let body = json!({ "channel_id": 1, "message": "hi", "attachment_hash": "x".repeat(13) });
+    assert_eq!(support::post(&state, "/encrypt", body).await.code(), "aad_too_large");
+}
+
+#[actix_web::test]
+async fn without_a_limit_only_the_context_length_field_caps_aad() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "hi", "context": "x".repeat(4096) });
+    support::encrypt(&state, body).await;
+}
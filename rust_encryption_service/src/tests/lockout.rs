@@ -0,0 +1,118 @@
+use actix_web::{test, web};
+use serde_json::json;
+
+use super::{files, support};
+use crate::lockout::DecryptLockout;
+use crate::proxy::TrustedProxies;
+use crate::AppState;
+
+// ── Decrypt lockout per client ──
+// Failures count against the channel and the caller together, so one
+// client guessing at a channel never locks out another.
+
+fn locking_state() -> web::Data<AppState> {
+    let mut state = support::state();
+    state.decrypt_lockout = Some(DecryptLockout::new(2, 60, 300));
+    web::Data::new(state)
+}
+
+fn decrypt_as(peer: &str, encrypted: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/decrypt")
+        .peer_addr(peer.parse().unwrap())
+        .set_json(json!({ "channel_id": 1, "encrypted": encrypted }))
+}
+
+#[actix_web::test]
+async fn a_locked_out_client_leaves_others_alone() {
+    let state = locking_state();
+    let good = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let bad = support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+    for _ in 0..2 {
+        support::call(&state, decrypt_as("192.0.2.1:4000", &bad)).await;
+    }
+
+    let locked = support::call(&state, decrypt_as("192.0.2.1:4001", &good)).await;
+    assert_eq!(locked.status, 429);
+    assert_eq!(locked.code(), "too_many_failures");
+    let other = support::call(&state, decrypt_as("192.0.2.2:4000", &good)).await;
+    assert_eq!(other.json()["message"], "hi");
+}
+
+#[actix_web::test]
+async fn a_success_resets_the_count() {
+    let state = locking_state();
+    let good = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let bad = support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+    for blob in [&bad, &good, &bad, &good] {
+        let reply = support::call(&state, decrypt_as("192.0.2.1:4000", blob)).await;
+        assert_ne!(reply.status, 429);
+    }
+}
+
+// ── Behind a trusted proxy ──
+// The lockout follows the client the proxy names, not the proxy itself.
+
+#[actix_web::test]
+async fn the_forwarded_client_is_locked_out_not_the_proxy() {
+    let mut state = support::state();
+    state.decrypt_lockout = Some(DecryptLockout::new(2, 60, 300));
+    state.trusted_proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+    let state = web::Data::new(state);
+    let good = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let bad = support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+    let via_proxy = |client: &'static str, blob: &str| {
+        decrypt_as("10.0.0.1:4000", blob).insert_header(("X-Forwarded-For", client))
+    };
+    for _ in 0..2 {
+        support::call(&state, via_proxy("198.51.100.7", &bad)).await;
+    }
+
+    let locked = support::call(&state, via_proxy("198.51.100.7", &good)).await;
+    assert_eq!(locked.status, 429);
+    let other = support::call(&state, via_proxy("198.51.100.8", &good)).await;
+    assert_eq!(other.json()["message"], "hi");
+    let direct = support::call(&state, decrypt_as("10.0.0.1:4000", &good)).await;
+    assert_eq!(direct.json()["message"], "hi");
+}
+
+// ── Across decrypt routes ──
+// /decrypt, /decrypt/file and /decrypt/range share one count per client.
+
+fn file_as(peer: &str, uri: &str, sealed: Vec<u8>) -> test::TestRequest {
+    test::TestRequest::post().uri(uri).peer_addr(peer.parse().unwrap()).set_payload(sealed)
+}
+
+#[actix_web::test]
+async fn a_lockout_from_decrypt_blocks_files() {
+    let state = locking_state();
+    let bad = support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+    for _ in 0..2 {
+        support::call(&state, decrypt_as("192.0.2.1:4000", &bad)).await;
+    }
+
+    let sealed = files::encrypt_file(&state, 1, b"meeting notes").await;
+    for uri in ["/decrypt/file?channel_id=1", "/decrypt/stream?channel_id=1"] {
+        let reply = support::call(&state, file_as("192.0.2.1:4000", uri, sealed.clone())).await;
+        assert_eq!(reply.status, 429, "{}", uri);
+    }
+    let range = "/decrypt/range?channel_id=1&start=0&length=4";
+    let reply = support::call(&state, file_as("192.0.2.1:4000", range, sealed.clone())).await;
+    assert_eq!(reply.status, 429);
+    let other = file_as("192.0.2.2:4000", "/decrypt/file?channel_id=1", sealed);
+    assert_eq!(support::call(&state, other).await.body.as_ref(), b"meeting notes");
+}
+
+#[actix_web::test]
+async fn failed_file_decrypts_count_toward_decrypt() {
+    let state = locking_state();
+    let sealed = files::encrypt_file(&state, 2, b"meeting notes").await;
+    let file = file_as("192.0.2.1:4000", "/decrypt/file?channel_id=1", sealed.clone());
+    assert_eq!(support::call(&state, file).await.code(), "decryption_failed");
+    let range = file_as("192.0.2.1:4000", "/decrypt/range?channel_id=1&start=0&length=4", sealed);
+    assert_eq!(support::call(&state, range).await.code(), "decryption_failed");
+
+    let good = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let locked = support::call(&state, decrypt_as("192.0.2.1:4000", &good)).await;
+    assert_eq!(locked.status, 429);
+}
@@ -0,0 +1,24 @@
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+
+// ── POST /admin/log-level ──
+// A spec is checked before the logger is touched, so a typo never leaves
+// the service logging nothing.
+
+#[actix_web::test]
+async fn a_bad_level_is_refused() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "level": "info,actix_web=loud" });
+    let reply = support::admin_post(&state, "/admin/log-level", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "invalid_log_level");
+}
+
+#[actix_web::test]
+async fn changing_the_level_needs_the_admin_token() {
+    let state = web::Data::new(support::state());
+    let reply = support::post(&state, "/admin/log-level", json!({ "level": "debug" })).await;
+    assert_eq!(reply.status, 401);
+}
@@ -0,0 +1,49 @@
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── Maintenance mode ──
+// Freezes everything that seals new data; decrypts keep serving.
+
+async fn set(state: &web::Data<AppState>, enabled: bool) {
+    let body = json!({ "enabled": enabled });
+    let reply = support::admin_post(state, "/admin/maintenance", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["maintenance"], enabled);
+}
+
+#[actix_web::test]
+async fn writes_freeze_while_decrypts_serve() {
+    let state = web::Data::new(support::state());
+    let sealed = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    set(&state, true).await;
+
+    let body = json!({ "channel_id": 1, "message": "hi" });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert_eq!(reply.status, 503);
+    assert_eq!(reply.code(), "maintenance_mode");
+    let job = json!({ "channel_id": 1, "items": [] });
+    let reply = support::admin_post(&state, "/jobs/reencrypt", job).await;
+    assert_eq!(reply.code(), "maintenance_mode");
+
+    let body = json!({ "channel_id": 1, "encrypted": sealed });
+    assert_eq!(support::post(&state, "/decrypt", body).await.json()["message"], "hi");
+}
+
+#[actix_web::test]
+async fn turning_it_off_resumes_encrypts() {
+    let state = web::Data::new(support::state());
+    set(&state, true).await;
+    set(&state, false).await;
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+}
+
+#[actix_web::test]
+async fn the_toggle_is_admin_only() {
+    let state = web::Data::new(support::state());
+    let reply = support::post(&state, "/admin/maintenance", json!({ "enabled": true })).await;
+    assert_eq!(reply.status, 401);
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+}
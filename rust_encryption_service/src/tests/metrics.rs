@@ -0,0 +1,99 @@
+use actix_web::{test, web};
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── Metrics endpoints ──
+// /metrics/reset moves the snapshot's baseline; the scraped counters keep
+// counting up, so Prometheus never sees a reset it didn't expect.
+
+async fn admin_get(state: &web::Data<AppState>, path: &str) -> support::Reply {
+    support::call(state, support::admin(test::TestRequest::get().uri(path))).await
+}
+
+async fn scrape(state: &web::Data<AppState>) -> String {
+    let reply = support::call(state, test::TestRequest::get().uri("/metrics")).await;
+    assert_eq!(reply.status, 200);
+    String::from_utf8(reply.body.to_vec()).unwrap()
+}
+
+#[actix_web::test]
+async fn reset_zeroes_the_snapshot_but_not_the_scrape() {
+    let state = web::Data::new(support::state());
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let before = admin_get(&state, "/metrics/snapshot").await.json();
+    assert_eq!(before["freecord_cipher_cache_misses_total"], 1);
+
+    let reset = support::admin_post(&state, "/metrics/reset", json!({})).await;
+    assert_eq!(reset.status, 200);
+    assert_eq!(reset.json()["freecord_cipher_cache_misses_total"], 0);
+    assert!(scrape(&state).await.contains("freecord_cipher_cache_misses_total 1"));
+
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let after = admin_get(&state, "/metrics/snapshot").await.json();
+    assert_eq!(after["freecord_cipher_cache_hits_total"], 1);
+    assert_eq!(after["freecord_cipher_cache_misses_total"], 0);
+}
+
+#[actix_web::test]
+async fn snapshot_and_reset_need_the_admin_token() {
+    let state = web::Data::new(support::state());
+    let get = test::TestRequest::get().uri("/metrics/snapshot");
+    assert_eq!(support::call(&state, get).await.status, 401);
+    let reset = support::post(&state, "/metrics/reset", json!({})).await;
+    assert_eq!(reset.status, 401);
+}
+
+#[actix_web::test]
+async fn metrics_can_be_turned_off() {
+    let mut state = support::state();
+    state.metrics_enabled = false;
+    let state = web::Data::new(state);
+    let get = test::TestRequest::get().uri("/metrics");
+    assert_eq!(support::call(&state, get).await.status, 404);
+    assert_eq!(admin_get(&state, "/metrics/snapshot").await.status, 404);
+}
+
+// ── Algorithm and outcome labels ──
+
+#[actix_web::test]
+async fn operations_are_labeled_by_algorithm_and_outcome() {
+    let state = web::Data::new(support::state());
+    let aes = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let chacha = json!({ "channel_id": 1, "message": "hi", "algorithm": "chacha20poly1305" });
+    let chacha = support::encrypt(&state, chacha).await;
+    for (channel_id, blob) in [(1, &aes), (1, &chacha), (2, &chacha)] {
+        let body = json!({ "channel_id": channel_id, "encrypted": blob });
+        support::post(&state, "/decrypt", body).await;
+    }
+
+    let text = scrape(&state).await;
+    for line in [
+        r#"freecord_encrypt_total{algorithm="aes256gcm"} 1"#,
+        r#"freecord_encrypt_total{algorithm="chacha20poly1305"} 1"#,
+        r#"freecord_decrypt_total{algorithm="aes256gcm",outcome="success"} 1"#,
+        r#"freecord_decrypt_total{algorithm="chacha20poly1305",outcome="success"} 1"#,
+        r#"freecord_decrypt_total{algorithm="chacha20poly1305",outcome="auth_failure"} 1"#,
+    ] {
+        assert!(text.contains(line), "{} missing from\n{}", line, text);
+    }
+}
+
+// ── Cold-start timing ──
+// Only a request that builds the channel cipher observes the histograms.
+
+#[actix_web::test]
+async fn only_a_cold_key_is_timed() {
+    let state = web::Data::new(support::state());
+    for _ in 0..3 {
+        support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    }
+    assert_eq!(state.metrics.key_derivation_seconds.get_sample_count(), 1);
+    assert_eq!(state.metrics.cipher_init_seconds.get_sample_count(), 1);
+
+    support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+    let text = scrape(&state).await;
+    assert!(text.contains("freecord_key_derivation_seconds_count 2"), "{}", text);
+    assert!(text.contains("freecord_cipher_init_seconds_count 2"), "{}", text);
+}
@@ -0,0 +1,57 @@
+// ── In-crate tests ──
+// The service is a binary with no library target, so handler tests live
+// here and reach the crate's private items directly. `support` builds a
+// deterministic AppState and runs requests through the real route table.
+
+mod admission;
+mod algorithms;
+mod archive;
+mod audit;
+mod auth;
+mod banner;
+mod cache;
+mod canary;
+mod commitment;
+mod context;
+mod decrypt_cache;
+mod derive;
+mod diag;
+mod encodings;
+mod estimate;
+mod expiry;
+mod export;
+mod fallback;
+mod files;
+mod idempotency;
+mod kat;
+mod limits;
+mod lockout;
+mod logging;
+mod maintenance;
+mod metrics;
+mod nonces;
+mod normalize;
+mod offload;
+mod plaintext;
+mod policy;
+mod quota;
+mod ratchet;
+mod raw_key;
+mod reencrypt;
+mod rotations;
+mod routes;
+mod search;
+mod secretbox;
+mod secrets;
+mod sequence;
+mod shared;
+mod signatures;
+mod slo;
+mod stats;
+mod support;
+mod test_secret;
+mod timestamps;
+mod tokens;
+mod unicode;
+mod usage;
+mod verbose;
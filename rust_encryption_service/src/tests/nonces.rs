@@ -0,0 +1,29 @@
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+use crate::nonce::MockNonceStrategy;
+
+// ── Nonce reuse tripwire ──
+// In debug builds a NonceStrategy that repeats itself under one channel key
+// fails the request loudly instead of sealing a second message.
+
+#[cfg(debug_assertions)]
+#[actix_web::test]
+#[should_panic(expected = "nonce reused")]
+async fn a_repeated_nonce_panics_the_encrypt() {
+    let mut state = support::state();
+    state.nonces = Box::new(MockNonceStrategy::new([[7; 12]]));
+    let state = web::Data::new(state);
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "one" })).await;
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "two" })).await;
+}
+
+#[actix_web::test]
+async fn one_nonce_across_channels_is_allowed() {
+    let mut state = support::state();
+    state.nonces = Box::new(MockNonceStrategy::new([[7; 12]]));
+    let state = web::Data::new(state);
+    support::encrypt(&state, json!({ "channel_id": 1, "message": "one" })).await;
+    support::encrypt(&state, json!({ "channel_id": 2, "message": "two" })).await;
+}
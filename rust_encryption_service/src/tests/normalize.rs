@@ -0,0 +1,60 @@
+use actix_web::web;
+use serde_json::{json, Value};
+
+use super::support;
+use crate::AppState;
+
+// ── "normalize" on /encrypt ──
+// Under NFC the composed and decomposed spellings of a text seal the same
+// bytes, and the response says the message was normalized.
+
+const COMPOSED: &str = "caf\u{e9}";
+const DECOMPOSED: &str = "cafe\u{301}";
+
+// The sealed message as /decrypt gives it back, and the /encrypt response
+async fn sealed(state: &web::Data<AppState>, message: &str, normalize: Value) -> (String, Value) {
+    let mut body = json!({ "channel_id": 1, "message": message });
+    if !normalize.is_null() {
+        body["normalize"] = normalize;
+    }
+    let reply = support::post(state, "/encrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    let encrypted = reply.json();
+
+    let body = json!({ "channel_id": 1, "encrypted": encrypted["encrypted"] });
+    let opened = support::post(state, "/decrypt", body).await.json();
+    (opened["message"].as_str().unwrap().to_owned(), encrypted)
+}
+
+#[actix_web::test]
+async fn nfc_seals_equivalent_spellings_alike() {
+    let state = web::Data::new(support::state());
+    let (composed, reply) = sealed(&state, COMPOSED, json!("nfc")).await;
+    let (decomposed, other) = sealed(&state, DECOMPOSED, json!("nfc")).await;
+    assert_eq!(composed, COMPOSED);
+    assert_eq!(decomposed, COMPOSED);
+    assert_eq!(reply["normalized"], "nfc");
+
+    // Same plaintext, so only the random nonce tells the blobs apart
+    let len = |reply: &Value| reply["encrypted"].as_str().unwrap().len();
+    assert_eq!(len(&reply), len(&other));
+}
+
+#[actix_web::test]
+async fn without_normalize_the_bytes_are_kept() {
+    let state = web::Data::new(support::state());
+    for normalize in [Value::Null, json!("none")] {
+        let (decomposed, reply) = sealed(&state, DECOMPOSED, normalize).await;
+        assert_eq!(decomposed, DECOMPOSED);
+        assert!(reply.get("normalized").is_none());
+    }
+}
+
+#[actix_web::test]
+async fn unknown_normalizations_are_refused() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": COMPOSED, "normalize": "nfkd" });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "invalid_json");
+}
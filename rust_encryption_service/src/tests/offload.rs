@@ -0,0 +1,63 @@
+use std::thread::{self, ThreadId};
+
+use actix_web::body::MessageBody;
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+
+use super::support;
+use crate::{offload, AppState};
+
+// ── OFFLOAD_THRESHOLD_BYTES ──
+// Crypto work on large inputs runs on the blocking pool, so the async worker
+// keeps serving other requests meanwhile; small work stays inline.
+
+fn threshold(bytes: usize) -> web::Data<AppState> {
+    let mut state = support::state();
+    state.limits.offload_threshold_bytes = bytes;
+    web::Data::new(state)
+}
+
+async fn ran_on(state: &web::Data<AppState>, len: usize) -> ThreadId {
+    offload(state, len, |_| Ok(thread::current().id())).await.ok().unwrap()
+}
+
+#[actix_web::test]
+async fn only_work_past_the_threshold_leaves_the_worker() {
+    let state = threshold(1024);
+    let worker = thread::current().id();
+    assert_eq!(ran_on(&state, 1023).await, worker);
+    assert_ne!(ran_on(&state, 1024).await, worker);
+}
+
+#[actix_web::test]
+async fn an_offloaded_error_comes_back_whole() {
+    let state = threshold(0);
+    let failed = offload(&state, 1, |_| -> Result<(), _> {
+        Err(HttpResponse::Conflict().insert_header(("X-Reason", "test")).body("no"))
+    });
+    let resp = failed.await.unwrap_err();
+    assert_eq!(resp.status(), 409);
+    assert_eq!(resp.headers().get("X-Reason").unwrap(), "test");
+    assert_eq!(resp.into_body().try_into_bytes().ok().unwrap(), "no");
+}
+
+#[actix_web::test]
+async fn a_big_encrypt_does_not_hold_up_a_small_one() {
+    let state = threshold(64 * 1024);
+    let big = json!({ "channel_id": 1, "message": "x".repeat(1536 * 1024) });
+    let small = json!({ "channel_id": 2, "message": "hi" });
+
+    // Both share this one worker thread; the big one is polled first, so
+    // the small one finishes first only if the big seal left the worker
+    let order = std::sync::Mutex::new(Vec::new());
+    let run = |body, name| {
+        let (state, order) = (&state, &order);
+        async move {
+            let reply = support::post(state, "/encrypt", body).await;
+            assert_eq!(reply.status, 200, "{:?}", reply.body);
+            order.lock().unwrap().push(name);
+        }
+    };
+    futures_util::join!(run(big, "big"), run(small, "small"));
+    assert_eq!(*order.lock().unwrap(), ["small", "big"]);
+}
@@ -0,0 +1,59 @@
+use actix_web::web;
+use serde_json::{json, Value};
+
+use super::support;
+use crate::AppState;
+
+// ── What /decrypt hands back ──
+// Text comes back as it went in; anything not UTF-8 as base64 with
+// `binary: true`, never lossily decoded.
+
+async fn round_trip(state: &web::Data<AppState>, body: Value) -> Value {
+    let encrypted = support::encrypt(state, body).await;
+    let body = json!({ "channel_id": 1, "encrypted": encrypted });
+    let reply = support::post(state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()
+}
+
+#[actix_web::test]
+async fn non_utf8_plaintext_comes_back_as_base64() {
+    let state = web::Data::new(support::state());
+    // 0xff 0xfe 0x00 is no UTF-8 sequence
+    let body = json!({ "channel_id": 1, "message": "//4A", "binary": true });
+    let opened = round_trip(&state, body).await;
+    assert_eq!(opened["message"], "//4A");
+    assert_eq!(opened["binary"], true);
+}
+
+#[actix_web::test]
+async fn text_plaintext_has_no_binary_flag() {
+    let state = web::Data::new(support::state());
+    let opened = round_trip(&state, json!({ "channel_id": 1, "message": "héllo" })).await;
+    assert_eq!(opened["message"], "héllo");
+    assert!(opened.get("binary").is_none());
+}
+
+// ── Empty plaintext ──
+// An authenticated empty message is flagged, so it can't pass for a failure.
+
+#[actix_web::test]
+async fn an_empty_message_round_trips_flagged() {
+    let state = web::Data::new(support::state());
+    let opened = round_trip(&state, json!({ "channel_id": 1, "message": "" })).await;
+    assert_eq!(opened["message"], "");
+    assert_eq!(opened["empty"], true);
+
+    let opened = round_trip(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert!(opened.get("empty").is_none());
+}
+
+#[actix_web::test]
+async fn an_empty_message_under_the_wrong_key_still_fails() {
+    let state = web::Data::new(support::state());
+    let encrypted = support::encrypt(&state, json!({ "channel_id": 1, "message": "" })).await;
+    let body = json!({ "channel_id": 2, "encrypted": encrypted });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
@@ -0,0 +1,75 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+
+// ── Per-key admin settings: algorithm pins and encrypt policies ──
+// A setting made with `?org_id=` or `?shared_with=` applies to that key only,
+// never to the bare channel with the same id.
+
+fn encrypt_with(body: serde_json::Value, algorithm: &str) -> serde_json::Value {
+    let mut body = body;
+    body["algorithm"] = json!(algorithm);
+    body
+}
+
+#[actix_web::test]
+async fn pin_applies_to_the_org_key_only() {
+    let state = web::Data::new(support::state());
+    let pin = json!({ "algorithm": "chacha20poly1305" });
+    let pinned = support::admin_post(&state, "/channels/1/algorithm?org_id=5", pin).await;
+    assert_eq!(pinned.status, 200, "{:?}", pinned.body);
+    assert_eq!(pinned.json()["org_id"], 5);
+
+    let org = json!({ "channel_id": 1, "org_id": 5, "message": "hi" });
+    let refused = support::post(&state, "/encrypt", encrypt_with(org, "aes256gcm")).await;
+    assert_eq!(refused.code(), "algorithm_mismatch");
+
+    let bare = json!({ "channel_id": 1, "message": "hi" });
+    support::encrypt(&state, encrypt_with(bare, "aes256gcm")).await;
+}
+
+#[actix_web::test]
+async fn pin_applies_to_the_shared_key_only() {
+    let state = web::Data::new(support::state());
+    let pin = json!({ "algorithm": "chacha20poly1305" });
+    // Either channel of the pair names the same key
+    let pinned = support::admin_post(&state, "/channels/2/algorithm?shared_with=1", pin).await;
+    assert_eq!(pinned.status, 200, "{:?}", pinned.body);
+    assert_eq!(pinned.json()["channel_id"], 1);
+    assert_eq!(pinned.json()["shared_with"], 2);
+
+    let shared = json!({ "channel_id": 1, "shared_with": 2, "message": "hi" });
+    let refused = support::post(&state, "/encrypt", encrypt_with(shared, "aes256gcm")).await;
+    assert_eq!(refused.code(), "algorithm_mismatch");
+
+    let bare = json!({ "channel_id": 2, "message": "hi" });
+    support::encrypt(&state, encrypt_with(bare, "aes256gcm")).await;
+}
+
+fn is_hex(blob: &str) -> bool {
+    blob.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+}
+
+#[actix_web::test]
+async fn policy_applies_to_the_org_key_only() {
+    let state = web::Data::new(support::state());
+    let policy = json!({ "output": "hex" });
+    let set = support::admin_post(&state, "/channels/1/policy?org_id=5", policy).await;
+    assert_eq!(set.status, 200, "{:?}", set.body);
+    assert_eq!(set.json()["org_id"], 5);
+
+    let get = |uri| support::admin(TestRequest::get().uri(uri));
+    let org = support::call(&state, get("/channels/1/policy?org_id=5")).await;
+    assert_eq!(org.json()["policy"]["output"], "hex");
+    let bare = support::call(&state, get("/channels/1/policy")).await;
+    assert!(bare.json()["policy"]["output"].is_null());
+
+    let org = json!({ "channel_id": 1, "org_id": 5, "message": "hi" });
+    let sealed = support::encrypt(&state, org).await;
+    assert!(is_hex(&sealed), "{}", sealed);
+    let bare = json!({ "channel_id": 1, "message": "hi" });
+    let sealed = support::encrypt(&state, bare).await;
+    assert!(!is_hex(&sealed), "{}", sealed);
+}
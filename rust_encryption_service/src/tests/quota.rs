@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web;
+use serde_json::json;
+
+use super::support::{self, START};
+use crate::clock::MockClock;
+use crate::AppState;
+
+// ── Daily encrypt quotas ──
+
+const DAY: u64 = 24 * 60 * 60;
+
+fn quota_state(clock: &Arc<MockClock>, quota: u64) -> web::Data<AppState> {
+    let mut state = support::state_with_clock(clock.clone());
+    state.limits.daily_encrypt_quota = Some(quota);
+    state.limits.max_aad_bytes = Some(16);
+    web::Data::new(state)
+}
+
+fn message(channel_id: i64) -> serde_json::Value {
+    json!({ "channel_id": channel_id, "message": "hi" })
+}
+
+#[actix_web::test]
+async fn quota_refuses_past_the_limit_until_the_next_day() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = quota_state(&clock, 2);
+    support::encrypt(&state, message(1)).await;
+    support::encrypt(&state, message(1)).await;
+
+    let refused = support::post(&state, "/encrypt", message(1)).await;
+    assert_eq!(refused.status, 429);
+    assert_eq!(refused.code(), "quota_exceeded");
+    let resets_at = (START / DAY + 1) * DAY;
+    assert_eq!(refused.json()["resets_at"], resets_at);
+
+    // Other channels count separately
+    support::encrypt(&state, message(2)).await;
+
+    clock.advance(Duration::from_secs(resets_at - START));
+    support::encrypt(&state, message(1)).await;
+}
+
+#[actix_web::test]
+async fn failed_seals_are_refunded() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = quota_state(&clock, 1);
+    let too_much_aad = json!({ "channel_id": 1, "message": "hi", "context": "x".repeat(32) });
+    for _ in 0..3 {
+        let reply = support::post(&state, "/encrypt", too_much_aad.clone()).await;
+        assert_eq!(reply.code(), "aad_too_large");
+    }
+    support::encrypt(&state, message(1)).await;
+    assert_eq!(support::post(&state, "/encrypt", message(1)).await.code(), "quota_exceeded");
+}
+
+#[actix_web::test]
+async fn refused_requests_before_the_seal_are_not_charged() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = quota_state(&clock, 1);
+    let not_base64 = json!({ "channel_id": 1, "message": "not base64!", "binary": true });
+    assert_eq!(support::post(&state, "/encrypt", not_base64).await.code(), "encoding_mismatch");
+    support::encrypt(&state, message(1)).await;
+}
+
+#[actix_web::test]
+async fn channel_quota_overrides_the_default() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = quota_state(&clock, 1);
+    let set = support::admin_post(&state, "/channels/1/quota", json!({ "daily_limit": 3 })).await;
+    assert_eq!(set.status, 200, "{:?}", set.body);
+    for _ in 0..3 {
+        support::encrypt(&state, message(1)).await;
+    }
+    assert_eq!(support::post(&state, "/encrypt", message(1)).await.code(), "quota_exceeded");
+}
+
+#[actix_web::test]
+async fn org_quota_leaves_the_bare_channel_alone() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = quota_state(&clock, 1);
+    let set = json!({ "daily_limit": 2 });
+    let set = support::admin_post(&state, "/channels/1/quota?org_id=5", set).await;
+    assert_eq!(set.status, 200, "{:?}", set.body);
+    assert_eq!(set.json()["org_id"], 5);
+
+    let org = json!({ "channel_id": 1, "org_id": 5, "message": "hi" });
+    support::encrypt(&state, org.clone()).await;
+    support::encrypt(&state, org.clone()).await;
+    assert_eq!(support::post(&state, "/encrypt", org).await.code(), "quota_exceeded");
+
+    support::encrypt(&state, message(1)).await;
+    assert_eq!(support::post(&state, "/encrypt", message(1)).await.code(), "quota_exceeded");
+}
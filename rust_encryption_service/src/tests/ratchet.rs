@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use actix_web::web;
+use serde_json::json;
+
+use super::support::{self, Reply, START};
+use crate::clock::MockClock;
+use crate::AppState;
+
+// ── Ratcheted encrypts ──
+// Every message gets its own key from the channel's chain, and only the
+// last RATCHET_WINDOW of them can still be opened.
+
+fn state(window: usize) -> web::Data<AppState> {
+    started_at(START, window)
+}
+
+fn started_at(unix_now: u64, window: usize) -> web::Data<AppState> {
+    let mut state = support::state_with_clock(Arc::new(MockClock::new(unix_now)));
+    state.ratchet_window = window;
+    web::Data::new(state)
+}
+
+async fn seal(state: &web::Data<AppState>, message: &str) -> String {
+    support::encrypt(state, json!({ "channel_id": 1, "message": message, "ratchet": true })).await
+}
+
+async fn open(state: &web::Data<AppState>, encrypted: &str) -> Reply {
+    support::post(state, "/decrypt", json!({ "channel_id": 1, "encrypted": encrypted })).await
+}
+
+#[actix_web::test]
+async fn messages_past_the_window_are_gone() {
+    let state = state(2);
+    let first = seal(&state, "one").await;
+    let second = seal(&state, "two").await;
+    assert_eq!(open(&state, &first).await.json()["message"], "one");
+
+    let third = seal(&state, "three").await;
+    let gone = open(&state, &first).await;
+    assert_eq!(gone.status, 410);
+    assert_eq!(gone.code(), "ratchet_window_exceeded");
+    assert_eq!(open(&state, &second).await.json()["message"], "two");
+    assert_eq!(open(&state, &third).await.json()["message"], "three");
+}
+
+#[actix_web::test]
+async fn a_restart_forgets_the_chain() {
+    let before = state(8);
+    let sealed = seal(&before, "hi").await;
+    // A later start never reuses an index, so the old blob finds no key
+    // rather than the wrong one
+    let after = started_at(START + 1, 8);
+    seal(&after, "new chain").await;
+    assert_eq!(open(&after, &sealed).await.code(), "ratchet_window_exceeded");
+}
+
+#[actix_web::test]
+async fn each_message_gets_its_own_ratchet_index() {
+    let state = state(8);
+    let verbose = |encrypted: String| {
+        json!({ "channel_id": 1, "encrypted": encrypted, "verbose": true })
+    };
+    let mut indices = Vec::new();
+    for _ in 0..3 {
+        let sealed = seal(&state, "hi").await;
+        let reply = support::admin_post(&state, "/decrypt", verbose(sealed)).await;
+        indices.push(reply.json()["ratchet_index"].as_u64().unwrap());
+    }
+    assert_eq!(indices[1], indices[0] + 1);
+    assert_eq!(indices[2], indices[1] + 1);
+}
+
+#[actix_web::test]
+async fn a_refused_encrypt_takes_no_ratchet_index() {
+    let mut capped = support::state_with_clock(Arc::new(MockClock::new(START)));
+    capped.ratchet_window = 1;
+    capped.limits.max_aad_bytes = Some(64);
+    let state = web::Data::new(capped);
+    let first = seal(&state, "one").await;
+
+    let context = "x".repeat(64);
+    let body = json!({ "channel_id": 1, "message": "hi", "ratchet": true, "context": context });
+    let refused = support::post(&state, "/encrypt", body).await;
+    assert_eq!(refused.code(), "aad_too_large");
+    // Still the newest index, so a one-message window still holds it
+    assert_eq!(open(&state, &first).await.json()["message"], "one");
+}
@@ -0,0 +1,55 @@
+use actix_web::web;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::json;
+
+use super::support::{self, SECRET};
+use crate::crypto::{derive_key, KeyId};
+use crate::AppState;
+
+// ── POST /decrypt/raw ──
+// Opens a blob under a key the caller supplies instead of deriving one;
+// admin-only, since the channel id then authorizes nothing.
+
+fn channel_key(channel_id: i64) -> String {
+    BASE64.encode(derive_key(SECRET, KeyId::channel(channel_id)))
+}
+
+async fn sealed(state: &web::Data<AppState>) -> String {
+    support::encrypt(state, json!({ "channel_id": 1, "message": "hi" })).await
+}
+
+#[actix_web::test]
+async fn the_channel_key_opens_its_blob() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "encrypted": sealed(&state).await, "key": channel_key(1) });
+    let reply = support::admin_post(&state, "/decrypt/raw", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "hi");
+}
+
+#[actix_web::test]
+async fn another_key_fails() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "encrypted": sealed(&state).await, "key": channel_key(2) });
+    let reply = support::admin_post(&state, "/decrypt/raw", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
+
+#[actix_web::test]
+async fn the_key_must_be_32_base64_bytes() {
+    let state = web::Data::new(support::state());
+    let blob = sealed(&state).await;
+    for (key, code) in [("not base64!", "invalid_key"), ("AAAA", "invalid_key_length")] {
+        let body = json!({ "channel_id": 1, "encrypted": blob, "key": key });
+        assert_eq!(support::admin_post(&state, "/decrypt/raw", body).await.code(), code);
+    }
+}
+
+#[actix_web::test]
+async fn raw_decrypts_are_admin_only() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "encrypted": sealed(&state).await, "key": channel_key(1) });
+    assert_eq!(support::post(&state, "/decrypt/raw", body).await.status, 401);
+}
@@ -0,0 +1,192 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::{json, Value};
+
+use super::support;
+use crate::AppState;
+
+// ── Re-encrypt jobs ──
+// Each call reseals one page of items under the current master secret and
+// hands back a cursor.
+
+const OLD_SECRET: &str = "old-master-secret";
+
+async fn sealed_under_old_secret(body: Value) -> String {
+    let mut state = support::state();
+    state.master_secret = OLD_SECRET.into();
+    support::encrypt(&web::Data::new(state), body).await
+}
+
+fn rolled_over() -> AppState {
+    let mut state = support::state();
+    state.previous_master_secret = Some(OLD_SECRET.into());
+    state
+}
+
+async fn open(state: &web::Data<AppState>, encrypted: &Value) -> Value {
+    let body = json!({ "channel_id": 1, "encrypted": encrypted, "verbose": true });
+    let reply = support::admin_post(state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()
+}
+
+#[actix_web::test]
+async fn items_move_onto_the_current_secret_with_their_options() {
+    let sealed = json!({ "channel_id": 1, "message": "hi", "context": "thread-7", "commit": true });
+    let old = sealed_under_old_secret(sealed).await;
+    let state = web::Data::new(rolled_over());
+
+    let job = json!({ "channel_id": 1, "items": [{ "id": 1, "encrypted": old }] });
+    let reply = support::admin_post(&state, "/jobs/reencrypt", job).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["reencrypted"], 1);
+
+    let opened = open(&state, &reply.json()["results"][0]["encrypted"]).await;
+    assert_eq!(opened["message"], "hi");
+    assert_eq!(opened["context"], "thread-7");
+    assert_eq!(opened["secret_version"], "current");
+    assert_eq!(opened["commitment_verified"], true);
+}
+
+#[actix_web::test]
+async fn pages_resume_from_the_cursor() {
+    let mut state = rolled_over();
+    state.limits.reencrypt_batch_size = 2;
+    let state = web::Data::new(state);
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let items: Vec<Value> =
+        [5, 1, 3].iter().map(|id| json!({ "id": id, "encrypted": blob })).collect();
+
+    let job = json!({ "channel_id": 1, "items": items });
+    let page = support::admin_post(&state, "/jobs/reencrypt", job).await.json();
+    assert_eq!(page["results"][0]["id"], 1);
+    assert_eq!(page["results"][1]["id"], 3);
+    assert_eq!((page["remaining"].clone(), page["next_cursor"].clone()), (json!(1), json!(3)));
+
+    let job = json!({ "channel_id": 1, "cursor": 3, "items": items });
+    let page = support::admin_post(&state, "/jobs/reencrypt", job).await.json();
+    assert_eq!(page["results"].as_array().unwrap().len(), 1);
+    assert_eq!(page["results"][0]["id"], 5);
+    assert_eq!(page["remaining"], 0);
+    assert_eq!(page["next_cursor"], Value::Null);
+}
+
+#[actix_web::test]
+async fn a_bad_item_fails_alone() {
+    let state = web::Data::new(rolled_over());
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let other = support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+    let items = json!([
+        { "id": 1, "encrypted": blob },
+        { "id": 2, "encrypted": other },
+        { "id": 3, "encrypted": "not base64!" },
+    ]);
+
+    let job = json!({ "channel_id": 1, "items": items });
+    let reply = support::admin_post(&state, "/jobs/reencrypt", job).await.json();
+    assert_eq!((reply["reencrypted"].clone(), reply["failed"].clone()), (json!(1), json!(2)));
+    assert!(reply["results"][0]["encrypted"].is_string());
+    assert_eq!(reply["results"][1]["code"], "decryption_failed");
+    assert_eq!(reply["results"][2]["code"], "invalid_base64");
+}
+
+// ── Parallel batches ──
+// Past PARALLEL_REENCRYPT_MIN items a page runs on rayon's pool; results
+// still come back in id order, and failures stay with their own item.
+
+#[actix_web::test]
+async fn a_parallel_batch_keeps_its_order() {
+    let state = web::Data::new(rolled_over());
+    let old = sealed_under_old_secret(json!({ "channel_id": 1, "message": "hi" })).await;
+    let items: Vec<Value> = (0..crate::PARALLEL_REENCRYPT_MIN as i64 * 2)
+        .rev()
+        .map(|id| json!({ "id": id, "encrypted": if id % 10 == 0 { "bad" } else { &old } }))
+        .collect();
+
+    let job = json!({ "channel_id": 1, "items": items });
+    let reply = support::admin_post(&state, "/jobs/reencrypt", job).await.json();
+    let results = reply["results"].as_array().unwrap();
+    assert_eq!(results.len(), items.len());
+    for (id, result) in results.iter().enumerate() {
+        assert_eq!(result["id"], id);
+        assert_eq!(result["encrypted"].is_string(), id % 10 != 0, "{}", result);
+    }
+    assert_eq!(reply["failed"], items.len().div_ceil(10));
+    assert_eq!(open(&state, &results[1]["encrypted"]).await["secret_version"], "current");
+}
+
+// ── NDJSON ──
+// One result line per item line, in arrival order; a line that is not an
+// item gets its own error line and the rest carry on.
+
+#[actix_web::test]
+async fn ndjson_streams_a_line_per_item() {
+    let state = web::Data::new(rolled_over());
+    let old = sealed_under_old_secret(json!({ "channel_id": 1, "message": "hi" })).await;
+    let body = format!(
+        "{}\n\nnot an item\n{}\n{}",
+        json!({ "id": 1, "encrypted": old }),
+        json!({ "id": 2, "encrypted": old }),
+        json!({ "id": 3, "encrypted": old }),
+    );
+    let req = TestRequest::post()
+        .uri("/jobs/reencrypt?channel_id=1&cursor=1")
+        .insert_header(("Content-Type", "application/x-ndjson"))
+        .set_payload(body);
+    let reply = support::call(&state, support::admin(req)).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+
+    let lines: Vec<Value> = std::str::from_utf8(&reply.body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["code"], "invalid_item");
+    assert_eq!(lines[1]["id"], 2);
+    assert_eq!(lines[2]["id"], 3);
+    assert_eq!(open(&state, &lines[2]["encrypted"]).await["secret_version"], "current");
+}
+
+// ── Algorithm migration ──
+// Naming an `algorithm` reseals under it instead of the channel's own; that
+// needs the admin token, and a channel pinned elsewhere refuses it.
+
+#[actix_web::test]
+async fn items_move_onto_the_named_algorithm() {
+    let state = web::Data::new(support::state());
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(open(&state, &json!(blob)).await["algorithm"], "aes256gcm");
+
+    let job = json!({
+        "channel_id": 1,
+        "algorithm": "chacha20poly1305",
+        "items": [{ "id": 1, "encrypted": blob }],
+    });
+    let reply = support::admin_post(&state, "/jobs/reencrypt", job).await;
+    assert_eq!(reply.json()["reencrypted"], 1, "{:?}", reply.body);
+    let opened = open(&state, &reply.json()["results"][0]["encrypted"]).await;
+    assert_eq!(opened["algorithm"], "chacha20poly1305");
+    assert_eq!(opened["message"], "hi");
+}
+
+#[actix_web::test]
+async fn migration_needs_the_admin_token() {
+    let state = web::Data::new(support::state());
+    let job = json!({ "channel_id": 1, "algorithm": "chacha20poly1305", "items": [] });
+    let reply = support::post(&state, "/jobs/reencrypt", job).await;
+    assert_eq!(reply.status, 401);
+}
+
+#[actix_web::test]
+async fn a_pinned_channel_refuses_another_algorithm() {
+    let state = web::Data::new(support::state());
+    let pin = json!({ "algorithm": "aes256gcm" });
+    let pinned = support::admin_post(&state, "/channels/1/algorithm", pin).await;
+    assert_eq!(pinned.status, 200, "{:?}", pinned.body);
+
+    let job = json!({ "channel_id": 1, "algorithm": "chacha20poly1305", "items": [] });
+    let reply = support::admin_post(&state, "/jobs/reencrypt", job).await;
+    assert_eq!(reply.status, 409);
+    assert_eq!(reply.code(), "algorithm_mismatch");
+}
@@ -0,0 +1,205 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+
+use super::support;
+use crate::audit::{AuditAction, CollectingAuditSink};
+use crate::crypto::{derive_key, KeyId};
+use crate::AppState;
+
+// ── Rotation history ──
+// A re-encrypt job that moves a channel off MASTER_SECRET_PREVIOUS records
+// one entry per new key generation.
+
+const OLD_SECRET: &str = "old-master-secret";
+
+// Seal under the secret the service used before the rollover
+async fn sealed_under_old_secret(channel_id: i64) -> String {
+    let mut state = support::state();
+    state.master_secret = OLD_SECRET.into();
+    let state = web::Data::new(state);
+    support::encrypt(&state, json!({ "channel_id": channel_id, "message": "hi" })).await
+}
+
+fn rolled_over() -> web::Data<AppState> {
+    let mut state = support::state();
+    state.previous_master_secret = Some(OLD_SECRET.into());
+    web::Data::new(state)
+}
+
+async fn rotations(state: &web::Data<AppState>, channel_id: i64) -> Value {
+    let uri = format!("/channels/{}/rotations", channel_id);
+    let reply = support::call(state, support::admin(TestRequest::get().uri(&uri))).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()["rotations"].clone()
+}
+
+async fn fingerprints(state: &web::Data<AppState>, channel_id: i64) -> Value {
+    let uri = format!("/channels/{}/fingerprints", channel_id);
+    support::call(state, support::admin(TestRequest::get().uri(&uri))).await.json()
+}
+
+#[actix_web::test]
+async fn reencrypt_migration_appends_one_entry() {
+    let old = sealed_under_old_secret(1).await;
+    let state = rolled_over();
+    assert_eq!(rotations(&state, 1).await, json!([]));
+
+    let job = json!({ "channel_id": 1, "items": [{ "id": 1, "encrypted": old }] });
+    for _ in 0..2 {
+        let reply = support::admin_post(&state, "/jobs/reencrypt", job.clone()).await;
+        assert_eq!(reply.json()["reencrypted"], 1, "{:?}", reply.body);
+    }
+
+    let history = rotations(&state, 1).await;
+    let keys = fingerprints(&state, 1).await;
+    assert_eq!(history.as_array().unwrap().len(), 1, "{}", history);
+    assert_eq!(history[0]["reason"], "manual");
+    assert_eq!(history[0]["generation"], keys["current"]);
+    assert_eq!(history[0]["previous_generation"], keys["previous"]);
+    assert_eq!(history[0]["rotated_at"], support::START);
+    assert_eq!(rotations(&state, 2).await, json!([]));
+}
+
+#[actix_web::test]
+async fn reencrypt_records_the_reason_given() {
+    let old = sealed_under_old_secret(1).await;
+    let state = rolled_over();
+    let item = json!({ "id": 1, "encrypted": old });
+    let job = json!({ "channel_id": 1, "reason": "incident", "items": [item] });
+    support::admin_post(&state, "/jobs/reencrypt", job).await;
+    assert_eq!(rotations(&state, 1).await[0]["reason"], "incident");
+}
+
+#[actix_web::test]
+async fn streaming_reencrypt_records_the_rotation() {
+    let old = sealed_under_old_secret(1).await;
+    let state = rolled_over();
+    let line = |id| json!({ "id": id, "encrypted": old }).to_string();
+    let body = format!("{}\n{}\n", line(1), line(2));
+    let req = TestRequest::post()
+        .uri("/jobs/reencrypt?channel_id=1&reason=scheduled")
+        .insert_header(("content-type", "application/x-ndjson"))
+        .set_payload(body);
+    let reply = support::call(&state, support::admin(req)).await;
+    assert_eq!(reply.status, 200);
+
+    let history = rotations(&state, 1).await;
+    assert_eq!(history.as_array().unwrap().len(), 1, "{}", history);
+    assert_eq!(history[0]["reason"], "scheduled");
+}
+
+#[actix_web::test]
+async fn data_already_current_is_no_rotation() {
+    let state = rolled_over();
+    let current = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let job = json!({ "channel_id": 1, "items": [{ "id": 1, "encrypted": current }] });
+    let reply = support::admin_post(&state, "/jobs/reencrypt", job).await;
+    assert_eq!(reply.json()["reencrypted"], 1);
+    assert_eq!(rotations(&state, 1).await, json!([]));
+}
+
+#[actix_web::test]
+async fn rotation_goes_to_the_audit_sink() {
+    let old = sealed_under_old_secret(1).await;
+    let sink = CollectingAuditSink::default();
+    let entries = sink.entries.clone();
+    let mut state = support::state();
+    state.previous_master_secret = Some(OLD_SECRET.into());
+    state.audit = Some(Box::new(sink));
+    let state = web::Data::new(state);
+
+    let job = json!({ "channel_id": 1, "items": [{ "id": 1, "encrypted": old }] });
+    support::admin_post(&state, "/jobs/reencrypt", job).await;
+    let entries = entries.lock().unwrap();
+    let rotations = entries.iter().filter(|e| e.action == AuditAction::Rotate).count();
+    assert_eq!(rotations, 1);
+}
+
+// ── GET /channels/{id}/fingerprints ──
+// The channel key under both master secrets, as fingerprints only.
+
+#[actix_web::test]
+async fn fingerprints_differ_across_different_secrets() {
+    let state = rolled_over();
+    let keys = fingerprints(&state, 1).await;
+    assert_ne!(keys["current"], keys["previous"]);
+    assert_eq!(keys["changed"], true);
+    assert_eq!(keys["channel_id"], 1);
+
+    // Neither key itself is in the response, in either encoding
+    let body = keys.to_string();
+    for secret in [support::SECRET, OLD_SECRET] {
+        let key = derive_key(secret, KeyId::new(None, 1));
+        assert!(!body.contains(&hex::encode(&key)));
+        assert!(!body.contains(&BASE64.encode(&key)));
+    }
+}
+
+#[actix_web::test]
+async fn fingerprints_match_when_the_secret_is_unchanged() {
+    let mut state = support::state();
+    state.previous_master_secret = Some(support::SECRET.into());
+    let keys = fingerprints(&web::Data::new(state), 1).await;
+    assert_eq!(keys["current"], keys["previous"]);
+    assert_eq!(keys["changed"], false);
+}
+
+#[actix_web::test]
+async fn without_a_previous_secret_there_is_nothing_to_compare() {
+    let state = web::Data::new(support::state());
+    let keys = fingerprints(&state, 1).await;
+    assert!(keys["current"].is_string());
+    assert_eq!((&keys["previous"], &keys["changed"]), (&Value::Null, &Value::Null));
+
+    let req = TestRequest::get().uri("/channels/1/fingerprints");
+    assert_eq!(support::call(&state, req).await.status, 401);
+}
+
+// ── POST /keys/fingerprints ──
+// Current-generation fingerprints in bulk, for comparing replicas.
+
+async fn bulk_fingerprints(state: &web::Data<AppState>, body: Value) -> Vec<Value> {
+    let reply = support::post(state, "/keys/fingerprints", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json().as_array().unwrap().clone()
+}
+
+#[actix_web::test]
+async fn replicas_sharing_a_secret_agree() {
+    let (one, two) = (web::Data::new(support::state()), web::Data::new(support::state()));
+    let body = json!({ "channel_ids": [3, 1, 2] });
+    let from_one = bulk_fingerprints(&one, body.clone()).await;
+    assert_eq!(from_one, bulk_fingerprints(&two, body.clone()).await);
+    let order: Vec<_> = from_one.iter().map(|f| f["channel_id"].as_i64().unwrap()).collect();
+    assert_eq!(order, [3, 1, 2]);
+
+    // A replica that missed a rotation stands out
+    let mut stale = support::state();
+    stale.master_secret = OLD_SECRET.into();
+    let from_stale = bulk_fingerprints(&web::Data::new(stale), body).await;
+    for (ours, theirs) in from_one.iter().zip(&from_stale) {
+        assert_ne!(ours["fingerprint"], theirs["fingerprint"]);
+    }
+}
+
+#[actix_web::test]
+async fn bulk_fingerprints_match_the_single_channel_ones() {
+    let state = web::Data::new(support::state());
+    let bulk = bulk_fingerprints(&state, json!({ "channel_ids": [1], "org_id": 5 })).await;
+    let req = TestRequest::get().uri("/channels/1/fingerprint?org_id=5");
+    let single = support::call(&state, req).await.json();
+    assert_eq!(bulk, [single]);
+    assert_eq!(bulk[0]["org_id"], 5);
+}
+
+#[actix_web::test]
+async fn bulk_fingerprints_are_capped() {
+    let state = web::Data::new(support::state());
+    let channel_ids: Vec<i64> = (0..1001).collect();
+    let body = json!({ "channel_ids": channel_ids });
+    let reply = support::post(&state, "/keys/fingerprints", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "too_many_channels");
+}
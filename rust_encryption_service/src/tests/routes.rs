@@ -0,0 +1,138 @@
+use actix_web::test;
+use actix_web::web;
+
+use super::support;
+use crate::{admin_routes, public_routes};
+
+// ── With ADMIN_PORT set, each server carries only its own routes ──
+
+// (method, path) of every route that must stay off the public port
+const ADMIN_ONLY: &[(&str, &str)] = &[
+    ("GET", "/metrics"),
+    ("POST", "/decrypt/raw"),
+    ("POST", "/jobs/reencrypt"),
+    ("POST", "/index-tokens"),
+    ("POST", "/tokens/issue"),
+    ("GET", "/metrics/snapshot"),
+    ("POST", "/metrics/reset"),
+    ("POST", "/channels/1/algorithm"),
+    ("GET", "/channels/1/policy"),
+    ("POST", "/channels/1/quota"),
+    ("GET", "/channels/1/fingerprints"),
+    ("GET", "/channels/1/rotations"),
+    ("GET", "/channels/1/usage"),
+    ("POST", "/keys/preload"),
+    ("POST", "/keys/exists"),
+    ("POST", "/keys/fingerprints"),
+    ("GET", "/keys/1/export"),
+    ("GET", "/diag/rng"),
+    ("GET", "/admin/crypto-selftest"),
+    ("GET", "/audit/export"),
+    ("POST", "/admin/log-level"),
+    ("POST", "/admin/maintenance"),
+    ("POST", "/admin/flush-keys"),
+    ("POST", "/admin/master-canary"),
+    ("POST", "/admin/test-secret"),
+];
+
+fn request(method: &str, path: &str) -> test::TestRequest {
+    let req = match method {
+        "GET" => test::TestRequest::get(),
+        _ => test::TestRequest::post().set_json(serde_json::json!({})),
+    };
+    req.uri(path)
+}
+
+#[actix_web::test]
+async fn admin_routes_are_not_public() {
+    let state = web::Data::new(support::state());
+    for &(method, path) in ADMIN_ONLY {
+        let public = support::call_on(&state, public_routes, request(method, path)).await;
+        assert_eq!(public.code(), "not_found", "{} {} is public", method, path);
+        let admin = support::call_on(&state, admin_routes, request(method, path)).await;
+        assert_ne!(admin.code(), "not_found", "{} {} is not on the admin port", method, path);
+    }
+}
+
+#[actix_web::test]
+async fn ndjson_reencrypt_is_admin_only() {
+    let state = web::Data::new(support::state());
+    let req = || {
+        test::TestRequest::post()
+            .uri("/jobs/reencrypt")
+            .insert_header(("content-type", "application/x-ndjson"))
+            .set_payload("")
+    };
+    assert_eq!(support::call_on(&state, public_routes, req()).await.code(), "not_found");
+    assert_ne!(support::call_on(&state, admin_routes, req()).await.code(), "not_found");
+}
+
+#[actix_web::test]
+async fn encrypt_and_decrypt_stay_public() {
+    let state = web::Data::new(support::state());
+    let encrypt = test::TestRequest::post()
+        .uri("/encrypt")
+        .set_json(serde_json::json!({ "channel_id": 1, "message": "hi" }));
+    let reply = support::call_on(&state, public_routes, encrypt).await;
+    assert_eq!(reply.status, 200);
+    let decrypt = test::TestRequest::post().uri("/decrypt").set_json(serde_json::json!({
+        "channel_id": 1,
+        "encrypted": reply.json()["encrypted"],
+    }));
+    assert_eq!(support::call_on(&state, public_routes, decrypt).await.json()["message"], "hi");
+}
+
+// ── Unknown routes and methods ──
+// Both answer with an ErrorResponse body; a known path also names, in Allow,
+// the methods it takes.
+
+#[actix_web::test]
+async fn an_unknown_route_is_a_json_404() {
+    let state = web::Data::new(support::state());
+    let reply = support::call(&state, test::TestRequest::get().uri("/no/such/route")).await;
+    assert_eq!(reply.status, 404);
+    assert_eq!(reply.code(), "not_found");
+}
+
+#[actix_web::test]
+async fn a_wrong_method_is_a_405_naming_the_right_ones() {
+    let state = web::Data::new(support::state());
+    let reply = support::call(&state, test::TestRequest::get().uri("/encrypt")).await;
+    assert_eq!(reply.status, 405);
+    assert_eq!(reply.code(), "method_not_allowed");
+    assert_eq!(reply.header("Allow"), Some("POST"));
+
+    let delete = test::TestRequest::delete().uri("/channels/1/policy");
+    let reply = support::call(&state, support::admin(delete)).await;
+    assert_eq!(reply.status, 405);
+    assert_eq!(reply.header("Allow"), Some("GET, POST"));
+}
+
+// ── Request body and query rejections ──
+
+#[actix_web::test]
+async fn a_non_json_body_names_the_content_type() {
+    let state = web::Data::new(support::state());
+    let req = test::TestRequest::post()
+        .uri("/encrypt")
+        .insert_header(("content-type", "text/plain"))
+        .set_payload(r#"{"channel_id": 1, "message": "hi"}"#);
+    let reply = support::call(&state, req).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "invalid_content_type");
+}
+
+#[actix_web::test]
+async fn malformed_json_and_queries_are_structured_400s() {
+    let state = web::Data::new(support::state());
+    let req = test::TestRequest::post()
+        .uri("/encrypt")
+        .insert_header(("content-type", "application/json"))
+        .set_payload(r#"{"channel_id": 1,"#);
+    let reply = support::call(&state, req).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (400, "invalid_json".into()));
+
+    let req = test::TestRequest::post().uri("/decrypt/file?channel_id=one").set_payload("x");
+    let reply = support::call(&state, req).await;
+    assert_eq!((reply.status.as_u16(), reply.code()), (400, "invalid_query".into()));
+}
@@ -0,0 +1,34 @@
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── POST /index-tokens ──
+
+async fn tokens_for(state: &web::Data<AppState>, channel_id: i64, message: &str) -> Vec<String> {
+    let body = json!({ "channel_id": channel_id, "message": message });
+    let sealed = support::encrypt(state, body).await;
+    let body = json!({ "channel_id": channel_id, "encrypted": sealed });
+    let reply = support::admin_post(state, "/index-tokens", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    serde_json::from_value(reply.json()["tokens"].clone()).unwrap()
+}
+
+#[actix_web::test]
+async fn equal_terms_give_equal_tokens_within_a_channel_only() {
+    let state = web::Data::new(support::state());
+    let a = tokens_for(&state, 1, "Meet at noon").await;
+    assert_eq!(a.len(), 3);
+    assert_eq!(tokens_for(&state, 1, "noon? MEET at noon.").await, a);
+    assert!(tokens_for(&state, 2, "Meet at noon").await.iter().all(|t| !a.contains(t)));
+}
+
+#[actix_web::test]
+async fn binary_messages_have_no_terms() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "/w==", "binary": true });
+    let sealed = support::encrypt(&state, body).await;
+    let body = json!({ "channel_id": 1, "encrypted": sealed });
+    assert_eq!(support::admin_post(&state, "/index-tokens", body).await.code(), "not_text");
+}
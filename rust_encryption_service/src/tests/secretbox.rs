@@ -0,0 +1,63 @@
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use crypto_secretbox::aead::{Aead, KeyInit};
+use crypto_secretbox::XSalsa20Poly1305;
+use serde_json::json;
+
+use super::support::{self, SECRET};
+use crate::crypto::{derive_key, KeyId};
+
+// ── Legacy libsodium secretbox ──
+// crypto_secretbox_easy output under the channel key, as a libsodium client
+// wrote it: 24-byte nonce, then MAC and ciphertext. The plain round trip
+// is in kat.rs.
+
+fn secretbox(channel_id: i64, message: &[u8]) -> String {
+    let key = derive_key(SECRET, KeyId::channel(channel_id));
+    let nonce = [3u8; 24];
+    let sealed = XSalsa20Poly1305::new(crypto_secretbox::Key::from_slice(&key))
+        .encrypt(crypto_secretbox::Nonce::from_slice(&nonce), message)
+        .unwrap();
+    BASE64.encode([&nonce[..], &sealed].concat())
+}
+
+#[actix_web::test]
+async fn verbose_secretbox_reports_its_algorithm() {
+    let state = web::Data::new(support::state());
+    let body = json!({
+        "channel_id": 1,
+        "encrypted": secretbox(1, b"from libsodium"),
+        "format": "secretbox",
+        "verbose": true,
+    });
+    let reply = support::admin_post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "from libsodium");
+    assert_eq!(reply.json()["algorithm"], "xsalsa20poly1305");
+}
+
+#[actix_web::test]
+async fn secretbox_for_another_channel_fails() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 2, "encrypted": secretbox(1, b"hi"), "format": "secretbox" });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
+
+#[actix_web::test]
+async fn secretbox_needs_its_format_named() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "encrypted": secretbox(1, b"hi") });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 400);
+}
+
+#[actix_web::test]
+async fn a_cut_off_secretbox_is_rejected() {
+    let state = web::Data::new(support::state());
+    let short = BASE64.encode([3u8; 30]);
+    let body = json!({ "channel_id": 1, "encrypted": short, "format": "secretbox" });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 400);
+}
@@ -0,0 +1,170 @@
+use actix_web::{test, web};
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── Default master secret ──
+// Running on the built-in secret is the likeliest reason an old blob won't
+// open, so DEBUG_ERRORS says so ahead of any other hint.
+
+fn state(default_secret_active: bool) -> web::Data<AppState> {
+    let mut state = support::state();
+    state.debug_errors = true;
+    state.default_secret_active = default_secret_active;
+    web::Data::new(state)
+}
+
+async fn decrypt_code(state: &web::Data<AppState>, channel_id: i64, encrypted: &str) -> String {
+    let body = json!({ "channel_id": channel_id, "encrypted": encrypted });
+    support::post(state, "/decrypt", body).await.code()
+}
+
+#[actix_web::test]
+async fn failed_decrypt_names_the_default_secret() {
+    let state = state(true);
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(decrypt_code(&state, 2, &blob).await, "default_secret_active");
+}
+
+#[actix_web::test]
+async fn a_set_secret_gets_the_usual_hint() {
+    let state = state(false);
+    let body = json!({ "channel_id": 1, "message": "hi", "commit": true });
+    let blob = support::encrypt(&state, body).await;
+    assert_eq!(decrypt_code(&state, 2, &blob).await, "possible_wrong_channel");
+}
+
+#[actix_web::test]
+async fn a_good_blob_still_opens_on_the_default_secret() {
+    let state = state(true);
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    let body = json!({ "channel_id": 1, "encrypted": blob });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["message"], "hi");
+}
+
+// ── Tenant master secrets ──
+// A tenant's channels derive from its own secret, so the same channel and
+// org sealed under two tenant secrets open under neither's key but its own.
+
+const TENANT: i64 = 7;
+
+fn tenant_state(secret: &str) -> (web::Data<AppState>, String) {
+    let mut state = support::state();
+    state.tenant_secrets.insert(TENANT, secret.into());
+    let issuer = support::enable_jwt(&mut state);
+    let token = issuer.token(json!({ "scope": "crypto:encrypt crypto:decrypt", "org_id": TENANT }));
+    (web::Data::new(state), token)
+}
+
+async fn as_tenant(
+    state: &web::Data<AppState>,
+    token: &str,
+    uri: &str,
+    body: serde_json::Value,
+) -> support::Reply {
+    let req = test::TestRequest::post().uri(uri).set_json(body);
+    support::call(state, support::bearer(req, token)).await
+}
+
+async fn tenant_blob(state: &web::Data<AppState>, token: &str) -> String {
+    let body = json!({ "channel_id": 1, "org_id": TENANT, "message": "hi" });
+    let reply = as_tenant(state, token, "/encrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()["encrypted"].as_str().unwrap().to_owned()
+}
+
+#[actix_web::test]
+async fn tenant_secrets_are_not_interchangeable() {
+    let (first, first_token) = tenant_state("tenant-secret-one");
+    let (second, second_token) = tenant_state("tenant-secret-two");
+    let first_blob = tenant_blob(&first, &first_token).await;
+    let second_blob = tenant_blob(&second, &second_token).await;
+
+    for (state, token, own, other) in [
+        (&first, &first_token, &first_blob, &second_blob),
+        (&second, &second_token, &second_blob, &first_blob),
+    ] {
+        let body = json!({ "channel_id": 1, "org_id": TENANT, "encrypted": own });
+        let reply = as_tenant(state, token, "/decrypt", body).await;
+        assert_eq!(reply.json()["message"], "hi");
+
+        let body = json!({ "channel_id": 1, "org_id": TENANT, "encrypted": other });
+        let reply = as_tenant(state, token, "/decrypt", body).await;
+        assert_eq!(reply.status, 400);
+        assert_eq!(reply.code(), "decryption_failed");
+    }
+}
+
+#[actix_web::test]
+async fn a_tenant_secret_is_not_the_master_secret() {
+    let (tenant, token) = tenant_state("tenant-secret-one");
+    let blob = tenant_blob(&tenant, &token).await;
+
+    // The same channel and org under the shared master secret
+    let shared = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "org_id": TENANT, "encrypted": blob });
+    let reply = support::post(&shared, "/decrypt", body).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
+
+// ── Reads of a tenant's channel keys ──
+// Fingerprints, usage and Ed25519 verification all derive from the channel
+// key, so each takes a token for the channel's org like a decrypt does.
+
+const OTHER_TENANT: i64 = 8;
+
+async fn get_as_tenant(state: &web::Data<AppState>, token: &str, uri: &str) -> support::Reply {
+    support::call(state, support::bearer(test::TestRequest::get().uri(uri), token)).await
+}
+
+fn assert_org_refused(reply: &support::Reply) {
+    assert_eq!(reply.status, 403, "{:?}", reply.body);
+    assert_eq!(reply.code(), "org_not_allowed");
+}
+
+#[actix_web::test]
+async fn a_channel_fingerprint_takes_the_tenants_token() {
+    let (state, token) = tenant_state("tenant-secret-one");
+    let own = format!("/channels/1/fingerprint?org_id={}", TENANT);
+    assert_eq!(get_as_tenant(&state, &token, &own).await.status, 200);
+    let other = format!("/channels/1/fingerprint?org_id={}", OTHER_TENANT);
+    assert_org_refused(&get_as_tenant(&state, &token, &other).await);
+}
+
+#[actix_web::test]
+async fn usage_takes_the_tenants_token() {
+    let (state, token) = tenant_state("tenant-secret-one");
+    let own = format!("/channels/1/usage?org_id={}", TENANT);
+    assert_eq!(get_as_tenant(&state, &token, &own).await.status, 200);
+    let other = format!("/channels/1/usage?org_id={}", OTHER_TENANT);
+    assert_org_refused(&get_as_tenant(&state, &token, &other).await);
+}
+
+#[actix_web::test]
+async fn bulk_fingerprints_take_the_tenants_token() {
+    let (state, token) = tenant_state("tenant-secret-one");
+    let own = json!({ "channel_ids": [1, 2], "org_id": TENANT });
+    assert_eq!(as_tenant(&state, &token, "/keys/fingerprints", own).await.status, 200);
+    let other = json!({ "channel_ids": [1, 2], "org_id": OTHER_TENANT });
+    assert_org_refused(&as_tenant(&state, &token, "/keys/fingerprints", other).await);
+}
+
+#[actix_web::test]
+async fn ed25519_verify_takes_the_tenants_token() {
+    let (state, token) = tenant_state("tenant-secret-one");
+    let body = json!({ "channel_id": 1, "org_id": TENANT, "message": "hi" });
+    let signed = as_tenant(&state, &token, "/sign/ed25519", body).await.json();
+
+    let own = json!({
+        "channel_id": 1, "org_id": TENANT, "message": "hi", "signature": signed["signature"]
+    });
+    assert_eq!(as_tenant(&state, &token, "/verify/ed25519", own).await.json()["valid"], true);
+    let other = json!({
+        "channel_id": 1, "org_id": OTHER_TENANT, "message": "hi", "signature": signed["signature"]
+    });
+    assert_org_refused(&as_tenant(&state, &token, "/verify/ed25519", other).await);
+}
@@ -0,0 +1,76 @@
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+
+use super::support;
+use crate::AppState;
+
+// ── "sequence": true ──
+// Each sequenced encrypt takes its channel's next number; it rides in the
+// authenticated header and comes back from /decrypt.
+
+async fn sequenced(state: &web::Data<AppState>, channel_id: i64) -> (Value, String) {
+    let body = json!({ "channel_id": channel_id, "message": "hi", "sequence": true });
+    let reply = support::post(state, "/encrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    let reply = reply.json();
+    (reply["sequence"].clone(), reply["encrypted"].as_str().unwrap().to_owned())
+}
+
+async fn opened(state: &web::Data<AppState>, channel_id: i64, encrypted: &str) -> support::Reply {
+    let body = json!({ "channel_id": channel_id, "encrypted": encrypted });
+    support::post(state, "/decrypt", body).await
+}
+
+#[actix_web::test]
+async fn successive_encrypts_count_up() {
+    let state = web::Data::new(support::state());
+    for expected in 0..3 {
+        let (sequence, blob) = sequenced(&state, 1).await;
+        assert_eq!(sequence, expected);
+        assert_eq!(opened(&state, 1, &blob).await.json()["sequence"], expected);
+    }
+}
+
+#[actix_web::test]
+async fn each_channel_counts_on_its_own() {
+    let state = web::Data::new(support::state());
+    sequenced(&state, 1).await;
+    sequenced(&state, 1).await;
+    assert_eq!(sequenced(&state, 2).await.0, 0);
+
+    // Unsequenced encrypts neither take a number nor report one
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert!(opened(&state, 1, &blob).await.json().get("sequence").is_none());
+    assert_eq!(sequenced(&state, 1).await.0, 2);
+}
+
+#[actix_web::test]
+async fn a_rewritten_sequence_fails_to_open() {
+    let state = web::Data::new(support::state());
+    let (_, blob) = sequenced(&state, 1).await;
+    let mut sealed = BASE64.decode(blob).unwrap();
+    // The big-endian u64 right after the 4-byte fixed header
+    sealed[11] ^= 1;
+
+    let reply = opened(&state, 1, &BASE64.encode(&sealed)).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
+
+#[actix_web::test]
+async fn a_refused_encrypt_takes_no_number() {
+    let mut limited = support::state();
+    limited.limits.key_byte_limit = 4;
+    limited.limits.max_aad_bytes = Some(64);
+    let state = web::Data::new(limited);
+    assert_eq!(sequenced(&state, 1).await.0, 0);
+
+    let context = "x".repeat(64);
+    let too_long =
+        json!({ "channel_id": 1, "message": "hi", "sequence": true, "context": context });
+    assert_eq!(support::post(&state, "/encrypt", too_long).await.code(), "aad_too_large");
+    let exhausting = json!({ "channel_id": 1, "message": "hello", "sequence": true });
+    assert_eq!(support::post(&state, "/encrypt", exhausting).await.code(), "key_exhausted");
+    assert_eq!(sequenced(&state, 1).await.0, 1);
+}
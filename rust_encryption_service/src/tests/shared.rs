@@ -0,0 +1,80 @@
+use actix_web::{test, web};
+use serde_json::json;
+
+use super::support;
+
+// ── Keys shared by two channels ──
+
+#[actix_web::test]
+async fn shared_key_is_order_independent_and_opens_from_either_side() {
+    let state = web::Data::new(support::state());
+    let forward = support::post(&state, "/channels/derive-shared", json!({ "channel_ids": [5, 3] }))
+        .await
+        .json();
+    let reverse = support::post(&state, "/channels/derive-shared", json!({ "channel_ids": [3, 5] }))
+        .await
+        .json();
+    assert_eq!(forward, reverse);
+    assert_eq!(forward["channel_id"], 3);
+    assert_eq!(forward["shared_with"], 5);
+
+    let sealed =
+        support::encrypt(&state, json!({ "channel_id": 5, "shared_with": 3, "message": "hi" }))
+            .await;
+    let body = json!({ "channel_id": 3, "shared_with": 5, "encrypted": sealed });
+    assert_eq!(support::post(&state, "/decrypt", body).await.json()["message"], "hi");
+    let alone = json!({ "channel_id": 3, "encrypted": sealed });
+    assert_eq!(support::post(&state, "/decrypt", alone).await.code(), "decryption_failed");
+}
+
+#[actix_web::test]
+async fn a_channel_cannot_share_with_itself() {
+    let state = web::Data::new(support::state());
+    for ids in [json!([1, 1]), json!([1]), json!([1, 2, 3])] {
+        let reply = support::post(&state, "/channels/derive-shared", json!({ "channel_ids": ids }))
+            .await;
+        assert_eq!(reply.code(), "invalid_channel_ids");
+    }
+}
+
+async fn derive_as(state: &web::Data<crate::AppState>, token: &str) -> support::Reply {
+    let req = test::TestRequest::post()
+        .uri("/channels/derive-shared")
+        .set_json(json!({ "channel_ids": [1, 2] }));
+    support::call(state, support::bearer(req, token)).await
+}
+
+#[actix_web::test]
+async fn deriving_needs_both_scopes_on_both_channels() {
+    let mut state = support::state();
+    let issuer = support::enable_jwt(&mut state);
+    let state = web::Data::new(state);
+    let both = "crypto:encrypt crypto:decrypt";
+
+    let reply = derive_as(&state, &issuer.token(json!({ "scope": "crypto:encrypt" }))).await;
+    assert_eq!(reply.code(), "insufficient_scope");
+    let one_channel = issuer.token(json!({ "scope": both, "channel_ids": [1] }));
+    assert_eq!(derive_as(&state, &one_channel).await.code(), "channel_not_allowed");
+    let other_channel = issuer.token(json!({ "scope": both, "channel_ids": [2] }));
+    assert_eq!(derive_as(&state, &other_channel).await.code(), "channel_not_allowed");
+
+    let token = issuer.token(json!({ "scope": both, "channel_ids": [1, 2] }));
+    assert_eq!(derive_as(&state, &token).await.status, 200);
+}
+
+#[actix_web::test]
+async fn using_a_shared_key_needs_both_channels_in_the_token() {
+    let mut state = support::state();
+    let issuer = support::enable_jwt(&mut state);
+    let state = web::Data::new(state);
+    let body = json!({ "channel_id": 1, "shared_with": 2, "message": "hi" });
+
+    let token = issuer.token(json!({ "scope": "crypto:encrypt", "channel_ids": [1] }));
+    let req = test::TestRequest::post().uri("/encrypt").set_json(body.clone());
+    let reply = support::call(&state, support::bearer(req, &token)).await;
+    assert_eq!(reply.code(), "channel_not_allowed");
+
+    let token = issuer.token(json!({ "scope": "crypto:encrypt", "channel_ids": [1, 2] }));
+    let req = test::TestRequest::post().uri("/encrypt").set_json(body);
+    assert_eq!(support::call(&state, support::bearer(req, &token)).await.status, 200);
+}
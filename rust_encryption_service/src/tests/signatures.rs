@@ -0,0 +1,50 @@
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+
+// ── Ed25519 message signatures ──
+// /sign/ed25519 signs under a key derived from the channel's; anyone with
+// the public key can check the result at /verify/ed25519.
+
+// Ed25519 is deterministic: channel 1's signature over "hello", computed
+// outside the service from its derived seed
+const HELLO_SIGNATURE: &str =
+    "SBoWu69lSHOVKXtUmO59QbEEzxu7zzXz9NJUbjdyuHap/bnfQKk73pPLFd+dcS+iPwqY/0beYvMQBTyn3DfKBQ==";
+
+#[actix_web::test]
+async fn sign_is_deterministic_per_channel() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "hello" });
+    let reply = support::post(&state, "/sign/ed25519", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    assert_eq!(reply.json()["signature"], HELLO_SIGNATURE);
+    assert_eq!(reply.json()["public_key"], "WnZNKxerp5jfMipqHOPLxtpcpVzXIMxrWEwBYkuk/sU=");
+}
+
+#[actix_web::test]
+async fn verify_accepts_only_the_signed_message_and_channel() {
+    let state = web::Data::new(support::state());
+    let check = |channel_id: i64, message: &str| {
+        json!({ "channel_id": channel_id, "message": message, "signature": HELLO_SIGNATURE })
+    };
+    let cases = [(check(1, "hello"), true), (check(1, "hellO"), false), (check(2, "hello"), false)];
+    for (body, valid) in cases {
+        let reply = support::post(&state, "/verify/ed25519", body).await;
+        assert_eq!(reply.status, 200, "{:?}", reply.body);
+        assert_eq!(reply.json()["valid"], valid);
+    }
+}
+
+#[actix_web::test]
+async fn verify_needs_a_base64_signature() {
+    let state = web::Data::new(support::state());
+    for body in [
+        json!({ "channel_id": 1, "message": "hello" }),
+        json!({ "channel_id": 1, "message": "hello", "signature": "not base64!" }),
+    ] {
+        let reply = support::post(&state, "/verify/ed25519", body).await;
+        assert_eq!(reply.status, 400);
+        assert_eq!(reply.code(), "invalid_signature");
+    }
+}
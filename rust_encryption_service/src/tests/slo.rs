@@ -0,0 +1,40 @@
+use actix_web::{test, web};
+use serde_json::json;
+
+use super::support;
+use crate::AppState;
+
+// ── Decrypt success SLO ──
+// Only decrypts that reached the AEAD count toward the ratio; a request
+// too malformed to try says nothing about the keys.
+
+async fn decrypt(state: &web::Data<AppState>, channel_id: i64, encrypted: &str) -> u16 {
+    let body = json!({ "channel_id": channel_id, "encrypted": encrypted });
+    support::post(state, "/decrypt", body).await.status.as_u16()
+}
+
+#[actix_web::test]
+async fn ratio_counts_authenticated_opens_against_failures() {
+    let state = web::Data::new(support::state());
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(state.slo.ratio(support::START), None);
+
+    assert_eq!(decrypt(&state, 1, &blob).await, 200);
+    assert_eq!(decrypt(&state, 2, &blob).await, 400);
+    assert_eq!(decrypt(&state, 1, "not base64!").await, 400);
+    assert_eq!(state.slo.ratio(support::START), Some(0.5));
+}
+
+#[actix_web::test]
+async fn metrics_report_the_ratio() {
+    let state = web::Data::new(support::state());
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    for channel_id in [1, 1, 1, 2] {
+        decrypt(&state, channel_id, &blob).await;
+    }
+
+    let reply = support::call(&state, test::TestRequest::get().uri("/metrics")).await;
+    assert_eq!(reply.status, 200);
+    let text = String::from_utf8(reply.body.to_vec()).unwrap();
+    assert!(text.contains("freecord_decrypt_success_ratio 0.75"), "{}", text);
+}
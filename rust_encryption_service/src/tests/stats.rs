@@ -0,0 +1,32 @@
+use actix_web::web;
+use base64::Engine;
+use serde_json::json;
+
+use super::support;
+
+// ── Verbose encrypt stats ──
+
+#[actix_web::test]
+async fn verbose_encrypt_reports_sizes() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "hello", "verbose": true });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    let reply = reply.json();
+
+    let blob = base64::engine::general_purpose::STANDARD
+        .decode(reply["encrypted"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(reply["plaintext_bytes"], 5);
+    assert_eq!(reply["ciphertext_bytes"], blob.len());
+    // Nothing is compressed, so there is no ratio to report
+    assert!(reply.get("compression_ratio").is_none());
+}
+
+#[actix_web::test]
+async fn stats_are_only_sent_when_asked_for() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "channel_id": 1, "message": "hi" });
+    let reply = support::post(&state, "/encrypt", body).await;
+    assert!(reply.json().get("plaintext_bytes").is_none());
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use actix_web::http::header::HeaderMap;
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use tokio::sync::Semaphore;
+
+use crate::admission::ChannelShares;
+use crate::clock::MockClock;
+use crate::crypto::Algorithm;
+use crate::nonce::RandomNonce;
+use crate::nonce_guard::NonceGuard;
+use crate::proxy::TrustedProxies;
+use crate::response_cache::ResponseCache;
+use crate::slo::SloMonitor;
+use crate::{
+    all_routes, json_error, not_found, query_error, selftest, AppState, KeyCacheOverflow, Limits,
+    Metrics,
+};
+
+pub const SECRET: &str = "test-master-secret";
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+// 2023-11-14T22:13:20Z
+pub const START: u64 = 1_700_000_000;
+
+// ── AppState with every setting at its default and nothing read from env ──
+pub fn state() -> AppState {
+    state_with_clock(Arc::new(MockClock::new(START)))
+}
+
+pub fn state_with_clock(clock: Arc<MockClock>) -> AppState {
+    AppState {
+        ciphers: parking_lot::Mutex::new(HashMap::new()),
+        lock_timeout: Duration::from_millis(1000),
+        key_cache_ttl: None,
+        max_cached_keys: None,
+        key_cache_overflow: KeyCacheOverflow::Lru,
+        key_meta: Mutex::new(HashMap::new()),
+        key_usage: RwLock::new(HashMap::new()),
+        pin_on_first_use: false,
+        default_algorithm: Algorithm::Aes256gcm,
+        encrypt_timestamps: false,
+        reject_invalid_unicode: true,
+        ratchets: Mutex::new(HashMap::new()),
+        ratchet_window: 64,
+        master_secret: SECRET.into(),
+        previous_master_secret: None,
+        tenant_secrets: HashMap::new(),
+        limits: Limits {
+            max_decrypted_bytes: 1024 * 1024,
+            reencrypt_batch_size: 500,
+            max_upload_bytes: 100 * 1024 * 1024,
+            daily_encrypt_quota: None,
+            key_byte_limit: 64 << 30,
+            offload_threshold_bytes: 64 * 1024,
+            max_aad_bytes: None,
+        },
+        metrics: Metrics::new(),
+        metrics_enabled: true,
+        admin_token: Some(ADMIN_TOKEN.into()),
+        jwt: None,
+        require_decrypt_token: false,
+        debug_errors: false,
+        default_secret_active: false,
+        maintenance: AtomicBool::new(false),
+        nonce_guard: NonceGuard::new(),
+        crypto_selftest: selftest::run(),
+        nonces: Box::new(RandomNonce),
+        crypto_permits: Arc::new(Semaphore::new(16)),
+        crypto_queue_timeout: Duration::ZERO,
+        channel_shares: ChannelShares::new(None),
+        decrypt_cache: None,
+        decrypt_lockout: None,
+        slo: SloMonitor::default(),
+        trusted_proxies: TrustedProxies::default(),
+        archive_signer: None,
+        idempotency: ResponseCache::new(1024, Duration::from_secs(300), clock.clone()),
+        clock,
+        audit: None,
+    }
+}
+
+pub struct Reply {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: web::Bytes,
+}
+
+impl Reply {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap_or(serde_json::Value::Null)
+    }
+
+    // The `code` of an ErrorResponse body
+    pub fn code(&self) -> String {
+        self.json()["code"].as_str().unwrap_or_default().to_owned()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+}
+
+// ── Run one request through `routes`, configured as `serve` does ──
+pub async fn call_on(
+    state: &web::Data<AppState>,
+    routes: fn(&mut web::ServiceConfig),
+    req: test::TestRequest,
+) -> Reply {
+    let app = test::init_service(
+        App::new()
+            .app_data(state.clone())
+            .app_data(web::JsonConfig::default().error_handler(json_error))
+            .app_data(web::QueryConfig::default().error_handler(query_error))
+            .configure(routes)
+            .default_service(web::to(not_found)),
+    )
+    .await;
+    let resp = test::call_service(&app, req.to_request()).await;
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let body = test::read_body(resp).await;
+    Reply { status, headers, body }
+}
+
+pub async fn call(state: &web::Data<AppState>, req: test::TestRequest) -> Reply {
+    call_on(state, all_routes, req).await
+}
+
+pub async fn post(state: &web::Data<AppState>, path: &str, body: serde_json::Value) -> Reply {
+    call(state, test::TestRequest::post().uri(path).set_json(body)).await
+}
+
+pub fn admin(req: test::TestRequest) -> test::TestRequest {
+    req.insert_header(("Authorization", format!("Bearer {}", ADMIN_TOKEN)))
+}
+
+pub async fn admin_post(state: &web::Data<AppState>, path: &str, body: serde_json::Value) -> Reply {
+    call(state, admin(test::TestRequest::post().uri(path).set_json(body))).await
+}
+
+// ── Signs JWTs that a state's JwtAuth accepts ──
+pub struct JwtIssuer(jsonwebtoken::EncodingKey);
+
+pub const AUDIENCE: &str = "freecord-test";
+
+// Turns JWT auth on for `state` and returns what signs its tokens
+pub fn enable_jwt(state: &mut AppState) -> JwtIssuer {
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+    let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    state.jwt = Some(crate::auth::JwtAuth::ed25519(pair.public_key().as_ref(), AUDIENCE));
+    JwtIssuer(jsonwebtoken::EncodingKey::from_ed_der(pkcs8.as_ref()))
+}
+
+impl JwtIssuer {
+    // A token valid for an hour carrying `claims` on top of `aud` and `exp`
+    pub fn token(&self, claims: serde_json::Value) -> String {
+        let mut full = serde_json::json!({ "aud": AUDIENCE, "exp": unix_now() + 3600 });
+        full.as_object_mut().unwrap().extend(claims.as_object().unwrap().clone());
+        let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::EdDSA);
+        jsonwebtoken::encode(&header, &full, &self.0).unwrap()
+    }
+}
+
+// jsonwebtoken checks `exp` against the real clock, not the state's
+pub fn unix_now() -> u64 {
+    crate::clock::Clock::unix_now(&crate::clock::SystemClock)
+}
+
+pub fn bearer(req: test::TestRequest, token: &str) -> test::TestRequest {
+    req.insert_header(("Authorization", format!("Bearer {}", token)))
+}
+
+// ── Encrypt `body` and return the blob, failing the test on any error ──
+pub async fn encrypt(state: &web::Data<AppState>, body: serde_json::Value) -> String {
+    let reply = post(state, "/encrypt", body).await;
+    assert_eq!(reply.status, StatusCode::OK, "{:?}", reply.body);
+    reply.json()["encrypted"].as_str().unwrap().to_owned()
+}
@@ -0,0 +1,83 @@
+use actix_web::web;
+use serde_json::{json, Value};
+
+use super::support;
+use crate::AppState;
+
+// ── POST /admin/test-secret ──
+// Whether a candidate master secret opens each sample, tried under keys
+// derived from it alone.
+
+const CANDIDATE: &str = "next-master-secret";
+
+// A sample sealed under `secret`, as a /decrypt body
+async fn sample(secret: &str, channel_id: i64) -> Value {
+    let mut state = support::state();
+    state.master_secret = secret.into();
+    let state = web::Data::new(state);
+    let body = json!({ "channel_id": channel_id, "message": "hi" });
+    json!({ "channel_id": channel_id, "encrypted": support::encrypt(&state, body).await })
+}
+
+async fn test_secret(state: &web::Data<AppState>, candidate: &str, samples: Value) -> Value {
+    let body = json!({ "candidate": candidate, "samples": samples });
+    let reply = support::admin_post(state, "/admin/test-secret", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()
+}
+
+#[actix_web::test]
+async fn the_right_candidate_opens_its_samples() {
+    let state = web::Data::new(support::state());
+    let samples = json!([sample(CANDIDATE, 1).await, sample(CANDIDATE, 2).await]);
+
+    let tested = test_secret(&state, CANDIDATE, samples.clone()).await;
+    assert_eq!(tested["matches_current"], false);
+    assert_eq!(
+        tested["results"],
+        json!([{ "index": 0, "opens": true }, { "index": 1, "opens": true }])
+    );
+
+    let tested = test_secret(&state, "wrong-master-secret", samples).await;
+    assert_eq!(tested["results"][0]["opens"], false);
+    assert_eq!(tested["results"][1]["opens"], false);
+}
+
+#[actix_web::test]
+async fn the_current_secret_is_recognised() {
+    let state = web::Data::new(support::state());
+    let samples = json!([sample(support::SECRET, 1).await]);
+    let tested = test_secret(&state, support::SECRET, samples).await;
+    assert_eq!(tested["matches_current"], true);
+    assert_eq!(tested["results"][0]["opens"], true);
+}
+
+#[actix_web::test]
+async fn a_candidate_leaves_the_cipher_cache_alone() {
+    let state = web::Data::new(support::state());
+    test_secret(&state, CANDIDATE, json!([sample(CANDIDATE, 1).await])).await;
+    assert!(state.ciphers.lock().is_empty());
+}
+
+#[actix_web::test]
+async fn undecodable_samples_are_skipped() {
+    let state = web::Data::new(support::state());
+    let samples = json!([{ "channel_id": 1, "encrypted": "not base64!" }]);
+    let tested = test_secret(&state, CANDIDATE, samples).await;
+    assert_eq!(
+        tested["results"],
+        json!([{ "index": 0, "opens": false, "code": "invalid_encoding" }])
+    );
+}
+
+#[actix_web::test]
+async fn bad_requests_are_refused() {
+    let state = web::Data::new(support::state());
+    let body = json!({ "candidate": "", "samples": [] });
+    let reply = support::admin_post(&state, "/admin/test-secret", body).await;
+    assert_eq!(reply.code(), "invalid_candidate");
+
+    let body = json!({ "candidate": CANDIDATE, "samples": [] });
+    let reply = support::post(&state, "/admin/test-secret", body).await;
+    assert_eq!(reply.status, 401);
+}
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+
+use super::support::{self, START};
+use crate::clock::MockClock;
+use crate::AppState;
+
+// ── Encrypt timestamps ──
+// `produced_at` comes from the service clock, rides in the authenticated
+// header and is returned by both /encrypt and /decrypt.
+
+fn clocked(encrypt_timestamps: bool) -> (web::Data<AppState>, Arc<MockClock>) {
+    let clock = Arc::new(MockClock::new(START));
+    let mut state = support::state_with_clock(clock.clone());
+    state.encrypt_timestamps = encrypt_timestamps;
+    (web::Data::new(state), clock)
+}
+
+async fn sealed(state: &web::Data<AppState>, timestamp: Option<bool>) -> Value {
+    let mut body = json!({ "channel_id": 1, "message": "hi" });
+    if let Some(timestamp) = timestamp {
+        body["timestamp"] = json!(timestamp);
+    }
+    let reply = support::post(state, "/encrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()
+}
+
+async fn opened(state: &web::Data<AppState>, encrypted: &str) -> support::Reply {
+    let body = json!({ "channel_id": 1, "encrypted": encrypted });
+    support::post(state, "/decrypt", body).await
+}
+
+#[actix_web::test]
+async fn the_encrypt_time_is_recovered() {
+    let (state, clock) = clocked(false);
+    let reply = sealed(&state, Some(true)).await;
+    assert_eq!(reply["produced_at"], START);
+
+    clock.advance(Duration::from_secs(3600));
+    let opened = opened(&state, reply["encrypted"].as_str().unwrap()).await;
+    assert_eq!(opened.json()["produced_at"], START);
+}
+
+#[actix_web::test]
+async fn encrypt_timestamps_sets_the_default() {
+    let (state, _clock) = clocked(true);
+    assert_eq!(sealed(&state, None).await["produced_at"], START);
+    assert!(sealed(&state, Some(false)).await.get("produced_at").is_none());
+
+    let (state, _clock) = clocked(false);
+    let reply = sealed(&state, None).await;
+    assert!(reply.get("produced_at").is_none());
+    let opened = opened(&state, reply["encrypted"].as_str().unwrap()).await;
+    assert!(opened.json().get("produced_at").is_none());
+}
+
+#[actix_web::test]
+async fn an_altered_timestamp_fails_to_open() {
+    let (state, _clock) = clocked(false);
+    let reply = sealed(&state, Some(true)).await;
+    let mut blob = BASE64.decode(reply["encrypted"].as_str().unwrap()).unwrap();
+    // The big-endian u64 after the fixed header and its second flags byte;
+    // a day earlier
+    assert_eq!(blob[5..13], START.to_be_bytes());
+    let forged = (START - 86_400).to_be_bytes();
+    blob[5..13].copy_from_slice(&forged);
+
+    let reply = opened(&state, &BASE64.encode(&blob)).await;
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.code(), "decryption_failed");
+}
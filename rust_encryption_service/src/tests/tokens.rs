@@ -0,0 +1,88 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::json;
+
+use super::support::{self, Reply};
+use crate::AppState;
+
+// ── Decrypt tokens on /decrypt ──
+
+const OLD_SECRET: &str = "old-master-secret";
+
+async fn issue(state: &web::Data<AppState>, channel_id: i64) -> String {
+    let body = json!({ "channel_id": channel_id });
+    let issued = support::admin_post(state, "/tokens/issue", body).await;
+    assert_eq!(issued.status, 200, "{:?}", issued.body);
+    issued.json()["token"].as_str().unwrap().to_owned()
+}
+
+async fn decrypt(
+    state: &web::Data<AppState>,
+    token: Option<&str>,
+    channel_id: i64,
+    encrypted: &str,
+) -> Reply {
+    let mut req = TestRequest::post()
+        .uri("/decrypt")
+        .set_json(json!({ "channel_id": channel_id, "encrypted": encrypted }));
+    if let Some(token) = token {
+        req = req.insert_header(("x-decrypt-token", token));
+    }
+    support::call(state, req).await
+}
+
+fn requiring_tokens() -> web::Data<AppState> {
+    let mut state = support::state();
+    state.require_decrypt_token = true;
+    web::Data::new(state)
+}
+
+#[actix_web::test]
+async fn a_missing_token_is_refused_only_when_required() {
+    let state = requiring_tokens();
+    let sealed = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(decrypt(&state, None, 1, &sealed).await.code(), "token_required");
+    let token = issue(&state, 1).await;
+    assert_eq!(decrypt(&state, Some(&token), 1, &sealed).await.json()["message"], "hi");
+
+    let state = web::Data::new(support::state());
+    let sealed = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(decrypt(&state, None, 1, &sealed).await.json()["message"], "hi");
+}
+
+#[actix_web::test]
+async fn a_presented_token_is_always_checked() {
+    let state = web::Data::new(support::state());
+    let sealed = support::encrypt(&state, json!({ "channel_id": 2, "message": "hi" })).await;
+    let token = issue(&state, 1).await;
+    assert_eq!(decrypt(&state, Some(&token), 2, &sealed).await.code(), "token_wrong_scope");
+    assert_eq!(decrypt(&state, Some("not-a-token"), 2, &sealed).await.code(), "token_invalid");
+}
+
+#[actix_web::test]
+async fn tokens_outlive_a_rollover_while_the_previous_secret_is_set() {
+    let mut old = support::state();
+    old.master_secret = OLD_SECRET.into();
+    let old = web::Data::new(old);
+    let token = issue(&old, 1).await;
+
+    let mut rolled = support::state();
+    rolled.require_decrypt_token = true;
+    rolled.previous_master_secret = Some(OLD_SECRET.into());
+    let rolled = web::Data::new(rolled);
+    let sealed = support::encrypt(&rolled, json!({ "channel_id": 1, "message": "hi" })).await;
+    assert_eq!(decrypt(&rolled, Some(&token), 1, &sealed).await.json()["message"], "hi");
+
+    // Once the previous secret is retired, so are its tokens
+    let retired = requiring_tokens();
+    assert_eq!(decrypt(&retired, Some(&token), 1, &sealed).await.code(), "token_invalid");
+}
+
+#[actix_web::test]
+async fn issuing_needs_the_admin_token() {
+    let state = web::Data::new(support::state());
+    let reply = support::post(&state, "/tokens/issue", json!({ "channel_id": 1 })).await;
+    assert_eq!(reply.status, 401);
+    let no_ttl = json!({ "channel_id": 1, "ttl_secs": 0 });
+    assert_eq!(support::admin_post(&state, "/tokens/issue", no_ttl).await.code(), "invalid_ttl");
+}
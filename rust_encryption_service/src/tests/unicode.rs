@@ -0,0 +1,47 @@
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::json;
+
+use super::support;
+
+// ── Lone surrogates ──
+// A `\u` escape for half a surrogate pair names no character. By default
+// /encrypt refuses it; with REJECT_INVALID_UNICODE=false it seals U+FFFD
+// in its place.
+
+fn surrogate_encrypt(message: &str) -> TestRequest {
+    TestRequest::post()
+        .uri("/encrypt")
+        .insert_header(("content-type", "application/json"))
+        .set_payload(format!(r#"{{"channel_id": 1, "message": {}}}"#, message))
+}
+
+#[actix_web::test]
+async fn a_lone_surrogate_escape_is_refused() {
+    let state = web::Data::new(support::state());
+    for message in [r#""\ud800""#, r#""x\udfffy""#, r#""\ud800A""#] {
+        let reply = support::call(&state, surrogate_encrypt(message)).await;
+        assert_eq!(reply.status, 400, "{}", message);
+        assert_eq!(reply.code(), "invalid_unicode", "{}", message);
+    }
+    assert!(state.ciphers.lock().is_empty());
+
+    // A full pair is one character and seals fine
+    let reply = support::call(&state, surrogate_encrypt(r#""\ud83d\ude00""#)).await;
+    assert_eq!(reply.status, 200);
+}
+
+#[actix_web::test]
+async fn accepting_seals_the_replacement_character() {
+    let mut accepting = support::state();
+    accepting.reject_invalid_unicode = false;
+    let state = web::Data::new(accepting);
+    let message = r#""x\udfff\ud800y\ud83d\ude00""#;
+    let reply = support::call(&state, surrogate_encrypt(message)).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+
+    let body = json!({ "channel_id": 1, "encrypted": reply.json()["encrypted"] });
+    let opened = support::post(&state, "/decrypt", body).await;
+    // Two halves in the wrong order are two lone ones; the pair after is kept
+    assert_eq!(opened.json()["message"], "x\u{FFFD}\u{FFFD}y\u{1F600}");
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::test::TestRequest;
+use actix_web::web;
+use serde_json::{json, Value};
+
+use super::support::{self, START};
+use crate::audit::AuditAction;
+use crate::clock::MockClock;
+use crate::crypto::KeyId;
+use crate::{record_use, AppState};
+
+// ── GET /channels/{id}/usage ──
+// Successful encrypts and decrypts under each key, counted with atomics so
+// concurrent requests on one channel never wait on each other.
+
+async fn usage(state: &web::Data<AppState>, uri: &str) -> Value {
+    let reply = support::call(state, TestRequest::get().uri(uri)).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    reply.json()
+}
+
+#[actix_web::test]
+async fn uses_are_counted_and_stamped() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = web::Data::new(support::state_with_clock(clock.clone()));
+    assert_eq!(usage(&state, "/channels/1/usage").await["use_count"], 0);
+    assert_eq!(usage(&state, "/channels/1/usage").await["last_used_at"], Value::Null);
+
+    let sealed = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+    clock.advance(Duration::from_secs(30));
+    support::post(&state, "/decrypt", json!({ "channel_id": 1, "encrypted": sealed })).await;
+    // A failed decrypt used no key
+    support::post(&state, "/decrypt", json!({ "channel_id": 2, "encrypted": sealed })).await;
+
+    let one = usage(&state, "/channels/1/usage").await;
+    assert_eq!(one["use_count"], 2);
+    assert_eq!(one["last_used_at"], START + 30);
+    assert_eq!(usage(&state, "/channels/2/usage").await["use_count"], 0);
+}
+
+#[actix_web::test]
+async fn org_keys_are_counted_apart() {
+    let state = web::Data::new(support::state());
+    support::encrypt(&state, json!({ "channel_id": 1, "org_id": 5, "message": "hi" })).await;
+    assert_eq!(usage(&state, "/channels/1/usage").await["use_count"], 0);
+    let org = usage(&state, "/channels/1/usage?org_id=5").await;
+    assert_eq!((org["org_id"].as_i64(), org["use_count"].as_u64()), (Some(5), Some(1)));
+}
+
+#[test]
+fn concurrent_uses_are_all_counted() {
+    let state = support::state();
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    record_use(&state, KeyId::channel(1), AuditAction::Encrypt);
+                }
+            });
+        }
+    });
+    let usage = state.key_usage.read().unwrap()[&KeyId::channel(1)].clone();
+    assert_eq!(usage.use_count.load(std::sync::atomic::Ordering::Relaxed), 800);
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use actix_web::web;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::json;
+
+use super::support::{self, START};
+use crate::clock::MockClock;
+use crate::envelope::FORMAT_VERSION;
+use crate::ratchet;
+
+// ── Verbose decrypts ──
+// With `"verbose": true` an admin gets back how the blob was sealed; the
+// normal path reports none of it.
+
+#[actix_web::test]
+async fn verbose_details_describe_the_seal() {
+    let clock = Arc::new(MockClock::new(START));
+    let state = web::Data::new(support::state_with_clock(clock));
+    let body = json!({
+        "channel_id": 1,
+        "message": "hi",
+        "algorithm": "chacha20poly1305",
+        "ratchet": true,
+    });
+    let blob = support::encrypt(&state, body).await;
+
+    let body = json!({ "channel_id": 1, "encrypted": blob, "verbose": true });
+    let reply = support::admin_post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    let details = reply.json();
+    assert_eq!(details["message"], "hi");
+    assert_eq!(details["authenticated"], true);
+    assert_eq!(details["algorithm"], "chacha20poly1305");
+    assert_eq!(details["secret_version"], "current");
+    assert_eq!(details["format_version"], FORMAT_VERSION);
+    assert_eq!(details["header_authenticated"], true);
+    assert_eq!(details["attachment_bound"], false);
+    assert_eq!(details["commitment_verified"], false);
+    assert_eq!(details["ratchet_index"], ratchet::first_index(START));
+
+    // The nonce reported is the one stored right after the header
+    let nonce = BASE64.decode(details["nonce"].as_str().unwrap()).unwrap();
+    let sealed = BASE64.decode(&blob).unwrap();
+    assert_eq!(nonce.len(), 12);
+    assert!(sealed.windows(nonce.len()).any(|w| w == nonce.as_slice()));
+}
+
+#[actix_web::test]
+async fn plain_decrypts_carry_no_details() {
+    let state = web::Data::new(support::state());
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+
+    let body = json!({ "channel_id": 1, "encrypted": blob });
+    let reply = support::admin_post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 200, "{:?}", reply.body);
+    for field in ["authenticated", "algorithm", "nonce", "format_version"] {
+        assert!(reply.json().get(field).is_none(), "{}", field);
+    }
+}
+
+#[actix_web::test]
+async fn verbose_is_admin_only() {
+    let state = web::Data::new(support::state());
+    let blob = support::encrypt(&state, json!({ "channel_id": 1, "message": "hi" })).await;
+
+    let body = json!({ "channel_id": 1, "encrypted": blob, "verbose": true });
+    let reply = support::post(&state, "/decrypt", body).await;
+    assert_eq!(reply.status, 401);
+}
@@ -0,0 +1,78 @@
+use std::ops::Deref;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+// ── Message text, with lone surrogate escapes replaced ──
+// serde_json refuses `\uD800`, or any other half of a surrogate pair, in a
+// String, which would make such a message a parse error before
+// REJECT_INVALID_UNICODE could decide. Read as bytes, serde_json keeps the
+// half as WTF-8 instead; each one becomes U+FFFD here, and `replaced` says
+// so for the caller to refuse.
+pub struct Text {
+    pub text: String,
+    pub replaced: bool,
+}
+
+impl Text {
+    pub fn from_wtf8(bytes: &[u8]) -> Text {
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut replaced = false;
+        let mut rest = bytes;
+        // A surrogate encodes as 0xED then 0xA0..=0xBF; 0xED is never a
+        // continuation byte, so this cannot start mid-character
+        while let Some(at) = rest.windows(2).position(|w| w[0] == 0xED && w[1] >= 0xA0) {
+            out.extend_from_slice(&rest[..at]);
+            out.extend_from_slice(char::REPLACEMENT_CHARACTER.encode_utf8(&mut [0; 4]).as_bytes());
+            rest = rest.get(at + 3..).unwrap_or_default();
+            replaced = true;
+        }
+        out.extend_from_slice(rest);
+        match String::from_utf8(out) {
+            Ok(text) => Text { text, replaced },
+            // Raw bytes that were never UTF-8 to begin with
+            Err(e) => {
+                let text = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                Text { text, replaced: true }
+            }
+        }
+    }
+}
+
+impl Deref for Text {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Serialize for Text {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+struct TextVisitor;
+
+impl Visitor<'_> for TextVisitor {
+    type Value = Text;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Text, E> {
+        Ok(Text { text: v.to_owned(), replaced: false })
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Text, E> {
+        Ok(Text::from_wtf8(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for Text {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(TextVisitor)
+    }
+}
@@ -0,0 +1,145 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+// ── Optional TLS, and mTLS on top ──
+// TLS_CERT_PATH and TLS_KEY_PATH (PEM) switch the listener to HTTPS. With
+// CLIENT_CA_PATH as well, every client must present a certificate issued by
+// that CA; anything else fails the handshake and never reaches a handler.
+
+// ── Verified identity of an mTLS client: its certificate's CN, else first SAN ──
+// Handlers read it with `req.conn_data::<ClientIdentity>()`.
+#[derive(Clone)]
+pub struct ClientIdentity(pub String);
+
+// ── Server config from the environment; None serves plain HTTP ──
+pub fn server_config() -> Result<Option<ServerConfig>, String> {
+    let path = |var: &str| std::env::var(var).ok().filter(|p| !p.is_empty());
+    let (cert_path, key_path, ca_path) =
+        (path("TLS_CERT_PATH"), path("TLS_KEY_PATH"), path("CLIENT_CA_PATH"));
+    let (cert_path, key_path) = match (cert_path, key_path) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) if ca_path.is_none() => return Ok(None),
+        _ => return Err("TLS needs both TLS_CERT_PATH and TLS_KEY_PATH".into()),
+    };
+    load(&cert_path, &key_path, ca_path.as_deref()).map(Some)
+}
+
+fn load(cert_path: &str, key_path: &str, ca_path: Option<&str>) -> Result<ServerConfig, String> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Cannot read TLS_CERT_PATH {}: {}", cert_path, e))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("Cannot read TLS_KEY_PATH {}: {}", key_path, e))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("TLS setup failed: {}", e))?;
+    let builder = match ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            let cas = CertificateDer::pem_file_iter(ca_path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| format!("Cannot read CLIENT_CA_PATH {}: {}", ca_path, e))?;
+            for ca in cas {
+                roots.add(ca).map_err(|e| format!("Bad CA in CLIENT_CA_PATH: {}", e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| format!("Client certificate verifier failed: {}", e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("TLS certificate and key do not match: {}", e))
+}
+
+// ── HttpServer::on_connect hook: record the verified client's identity ──
+// The handshake has already checked the chain, so this only reads names.
+pub fn record_client(conn: &dyn Any, ext: &mut Extensions) {
+    let Some(stream) = conn.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let (_, session) = stream.get_ref();
+    let identity = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(|cert| X509Certificate::from_der(cert).ok())
+        .and_then(|(_, cert)| identity(&cert));
+    if let Some(identity) = identity {
+        ext.insert(ClientIdentity(identity));
+    }
+}
+
+fn identity(cert: &X509Certificate) -> Option<String> {
+    let cn = cert.subject().iter_common_name().next().and_then(|cn| cn.as_str().ok());
+    if let Some(cn) = cn {
+        return Some(cn.to_owned());
+    }
+    let san = cert.subject_alternative_name().ok().flatten()?;
+    san.value.general_names.iter().find_map(|name| match name {
+        GeneralName::DNSName(name) | GeneralName::URI(name) | GeneralName::RFC822Name(name) => {
+            Some((*name).to_owned())
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // See testdata/tls/README for what each file holds
+    fn testdata(name: &str) -> String {
+        format!("{}/testdata/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn identity_of(name: &str) -> Option<String> {
+        let cert = CertificateDer::from_pem_file(testdata(name)).unwrap();
+        let (_, cert) = X509Certificate::from_der(&cert).unwrap();
+        identity(&cert)
+    }
+
+    #[test]
+    fn identity_is_the_common_name() {
+        assert_eq!(identity_of("cn.pem").as_deref(), Some("bot.freecord.test"));
+    }
+
+    #[test]
+    fn without_a_common_name_the_first_named_san_is_used() {
+        // The IP entry ahead of it names no one
+        assert_eq!(identity_of("san.pem").as_deref(), Some("worker.freecord.test"));
+    }
+
+    #[test]
+    fn a_certificate_and_its_key_load() {
+        let config = load(&testdata("cn.pem"), &testdata("cn.key"), None);
+        assert!(config.is_ok());
+        let config = load(&testdata("cn.pem"), &testdata("cn.key"), Some(&testdata("ca.pem")));
+        assert!(config.is_ok());
+    }
+
+    #[test]
+    fn bad_tls_files_are_refused() {
+        let err = |result: Result<ServerConfig, String>| result.err().unwrap();
+        let mismatched = err(load(&testdata("cn.pem"), &testdata("san.key"), None));
+        assert!(mismatched.starts_with("TLS certificate and key do not match"), "{}", mismatched);
+        let missing = err(load(&testdata("none.pem"), &testdata("cn.key"), None));
+        assert!(missing.starts_with("Cannot read TLS_CERT_PATH"), "{}", missing);
+        let no_ca = Some(testdata("none.pem"));
+        let no_ca = err(load(&testdata("cn.pem"), &testdata("cn.key"), no_ca.as_deref()));
+        assert!(no_ca.starts_with("Cannot read CLIENT_CA_PATH"), "{}", no_ca);
+    }
+}
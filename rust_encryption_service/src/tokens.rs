@@ -0,0 +1,141 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::crypto::KeyId;
+
+// ── Decrypt tokens: admin-issued capabilities for one key until an expiry ──
+// token = base64url(claims JSON) "." base64url(HMAC-SHA256(token key, claims))
+// The token key is derived from the master secret (see derive_token_key), so
+// tokens need no storage and stop verifying once that secret is retired.
+
+#[derive(Serialize, Deserialize)]
+pub struct Claims {
+    pub channel_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_with: Option<i64>,
+    // Unix seconds after which the token is refused
+    pub exp: u64,
+}
+
+pub enum TokenError {
+    Invalid,
+    Expired,
+    WrongScope,
+}
+
+fn mac(key: &[u8]) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length")
+}
+
+pub fn issue(key: &[u8], claims: &Claims) -> String {
+    let payload = BASE64URL.encode(serde_json::to_vec(claims).expect("claims serialize"));
+    let mut mac = mac(key);
+    mac.update(payload.as_bytes());
+    let signature = BASE64URL.encode(mac.finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+// ── Check a token against the key it is presented for ──
+// Accepted when it verifies under any of `keys`, so tokens issued before a
+// secret rotation keep working while the previous secret is configured.
+pub fn verify(keys: &[[u8; 32]], token: &str, key_id: KeyId, now: u64) -> Result<(), TokenError> {
+    let (payload, signature) = token.split_once('.').ok_or(TokenError::Invalid)?;
+    let signature = BASE64URL.decode(signature).map_err(|_| TokenError::Invalid)?;
+    let authentic = keys.iter().any(|key| {
+        let mut mac = mac(key);
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    });
+    if !authentic {
+        return Err(TokenError::Invalid);
+    }
+
+    let claims: Claims = BASE64URL
+        .decode(payload)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or(TokenError::Invalid)?;
+    if claims.exp <= now {
+        return Err(TokenError::Expired);
+    }
+    let scope = KeyId {
+        org_id: claims.org_id,
+        channel_id: claims.channel_id,
+        shared_with: claims.shared_with,
+    };
+    if scope != key_id {
+        return Err(TokenError::WrongScope);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [1; 32];
+    const NOW: u64 = 1_000;
+
+    fn token_for(key_id: KeyId, exp: u64) -> String {
+        let claims = Claims {
+            channel_id: key_id.channel_id,
+            org_id: key_id.org_id,
+            shared_with: key_id.shared_with,
+            exp,
+        };
+        issue(&KEY, &claims)
+    }
+
+    #[test]
+    fn a_token_verifies_for_its_key_until_it_expires() {
+        let key_id = KeyId::new(Some(3), 1);
+        let token = token_for(key_id, NOW + 1);
+        assert!(verify(&[KEY], &token, key_id, NOW).is_ok());
+        assert!(matches!(verify(&[KEY], &token, key_id, NOW + 1), Err(TokenError::Expired)));
+    }
+
+    #[test]
+    fn a_token_names_exactly_one_key() {
+        let token = token_for(KeyId::shared(Some(3), 1, 2), NOW + 60);
+        for other in [
+            KeyId::channel(1),
+            KeyId::new(Some(3), 1),
+            KeyId::shared(None, 1, 2),
+            KeyId::shared(Some(3), 1, 4),
+        ] {
+            assert!(matches!(verify(&[KEY], &token, other, NOW), Err(TokenError::WrongScope)));
+        }
+        assert!(verify(&[KEY], &token, KeyId::shared(Some(3), 2, 1), NOW).is_ok());
+    }
+
+    #[test]
+    fn any_configured_key_verifies() {
+        let key_id = KeyId::channel(1);
+        let token = token_for(key_id, NOW + 60);
+        assert!(verify(&[[2; 32], KEY], &token, key_id, NOW).is_ok());
+        assert!(matches!(verify(&[[2; 32]], &token, key_id, NOW), Err(TokenError::Invalid)));
+    }
+
+    #[test]
+    fn a_changed_or_malformed_token_is_invalid() {
+        let key_id = KeyId::channel(1);
+        let token = token_for(key_id, NOW + 60);
+        let (payload, signature) = token.split_once('.').unwrap();
+        // Stretch the expiry without re-signing
+        let forged = BASE64URL.encode(br#"{"channel_id":1,"exp":99999999}"#);
+        for bad in [
+            format!("{}.{}", forged, signature),
+            format!("{}.{}", payload, &signature[1..]),
+            payload.to_owned(),
+            format!("{}.!!", payload),
+            String::new(),
+        ] {
+            let verified = verify(&[KEY], &bad, key_id, NOW);
+            assert!(matches!(verified, Err(TokenError::Invalid)), "{}", bad);
+        }
+    }
+}